@@ -0,0 +1,323 @@
+use crate::client::{twin::SharedTwin, IotHubClient};
+use anyhow::{Context, Result};
+use azure_iot_sdk_sys::*;
+use bytes::Bytes;
+use log::{debug, info, warn};
+use std::{
+    ffi::{c_char, c_void, CStr, CString},
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    sync::mpsc,
+};
+
+// block size used by default and maximum block size accepted by azure-sdk-c
+static DEFAULT_BLOCK_SIZE: usize = 4 * 1024 * 1024;
+// azure storage accepts at most 50000 blocks per blob
+static MAX_BLOCKS: u32 = 50000;
+
+/// Progress of a [`BlobUpload`]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct UploadProgress {
+    /// bytes uploaded so far
+    pub bytes: u64,
+    /// blocks uploaded so far
+    pub blocks: u32,
+}
+
+extern "C" {
+    // strings allocated by azure-sdk-c must be released by the C allocator
+    fn free(ptr: *mut c_void);
+}
+
+// client of azure storage created by azure-sdk-c for a single blob
+struct StorageClient(IOTHUB_CLIENT_LL_AZURE_STORAGE_CLIENT_HANDLE);
+
+// the storage client is independent of the client handle and only used by one thread at a time
+unsafe impl Send for StorageClient {}
+
+impl Drop for StorageClient {
+    fn drop(&mut self) {
+        unsafe { IoTHubDeviceClient_AzureStorageDestroyClient(self.0) }
+    }
+}
+
+/// Streaming upload of a blob to the azure storage linked to iothub, see [`IotHubClient::upload_to_blob`].
+///
+/// The source is read and uploaded in blocks of 4MiB by default, so that large files, e.g. log archives, don't
+/// have to be buffered in memory. If uploading a block fails, the block is kept and
+/// [`BlobUpload::upload_from`] can be called again with the same source in order to resume the upload.
+/// The blob becomes visible once [`BlobUpload::complete`] commits all uploaded blocks.
+pub struct BlobUpload {
+    twin: Arc<SharedTwin>,
+    storage: Arc<Mutex<StorageClient>>,
+    correlation_id: CString,
+    block_size: usize,
+    // block read from the source but not yet uploaded
+    pending: Option<Bytes>,
+    progress: UploadProgress,
+    tx_progress: Option<mpsc::Sender<UploadProgress>>,
+}
+
+impl BlobUpload {
+    /// Set the size of blocks read from the source, which is clamped to 1 byte up to 4MiB
+    pub fn block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size.clamp(1, DEFAULT_BLOCK_SIZE);
+        self
+    }
+
+    /// Set a channel signaling the progress after each uploaded block. Progress is dropped if the channel is
+    /// full, since the upload doesn't wait for the receiver.
+    pub fn progress_observer(mut self, tx_progress: mpsc::Sender<UploadProgress>) -> Self {
+        self.tx_progress = Some(tx_progress);
+        self
+    }
+
+    /// progress of the upload so far
+    pub fn progress(&self) -> UploadProgress {
+        self.progress
+    }
+
+    /// Read `source` until its end and upload it block by block. On error the upload can be resumed by
+    /// calling this function again with the same source, starting with the block that failed. Returns the
+    /// progress of the whole upload.
+    pub async fn upload_from<R: AsyncRead + Unpin>(
+        &mut self,
+        source: &mut R,
+    ) -> Result<UploadProgress> {
+        loop {
+            let block = match self.pending.clone() {
+                Some(block) => block,
+                None => {
+                    let mut block = Vec::with_capacity(self.block_size);
+
+                    (&mut *source)
+                        .take(self.block_size as u64)
+                        .read_to_end(&mut block)
+                        .await
+                        .context("upload_from: cannot read source")?;
+
+                    if block.is_empty() {
+                        return Ok(self.progress);
+                    }
+
+                    let block = Bytes::from(block);
+                    self.pending = Some(block.clone());
+                    block
+                }
+            };
+
+            anyhow::ensure!(
+                self.progress.blocks < MAX_BLOCKS,
+                "upload_from: blob exceeds {MAX_BLOCKS} blocks, increase block size"
+            );
+
+            self.put_block(self.progress.blocks, block.clone()).await?;
+
+            self.pending = None;
+            self.progress.blocks += 1;
+            self.progress.bytes += block.len() as u64;
+
+            debug!("upload_from: {:?}", self.progress);
+
+            if let Some(tx) = &self.tx_progress {
+                if tx.try_send(self.progress).is_err() {
+                    debug!("upload_from: progress dropped");
+                }
+            }
+        }
+    }
+
+    /// Commit all uploaded blocks to the blob and notify iothub of the completed upload
+    pub async fn complete(self) -> Result<()> {
+        let storage = self.storage.clone();
+
+        let committed = tokio::task::spawn_blocking(move || {
+            let storage = storage
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+            if IOTHUB_CLIENT_RESULT_TAG_IOTHUB_CLIENT_OK
+                != unsafe { IoTHubDeviceClient_AzureStoragePutBlockList(storage.0) }
+            {
+                anyhow::bail!("error while calling IoTHubDeviceClient_AzureStoragePutBlockList()");
+            }
+
+            Ok(())
+        })
+        .await?;
+
+        match committed {
+            Ok(()) => {
+                self.notify(true).await?;
+                info!("complete: uploaded {:?}", self.progress);
+                Ok(())
+            }
+            Err(e) => {
+                if let Err(notify) = self.notify(false).await {
+                    warn!("complete: {notify:#}");
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Give up the upload and notify iothub of the failed upload
+    pub async fn abort(self) -> Result<()> {
+        self.notify(false).await
+    }
+
+    async fn put_block(&self, number: u32, block: Bytes) -> Result<()> {
+        let storage = self.storage.clone();
+
+        // azure-sdk-c blocks until the block is uploaded
+        tokio::task::spawn_blocking(move || {
+            let storage = storage
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+            if IOTHUB_CLIENT_RESULT_TAG_IOTHUB_CLIENT_OK
+                != unsafe {
+                    IoTHubDeviceClient_AzureStoragePutBlock(
+                        storage.0,
+                        number,
+                        block.as_ptr(),
+                        block.len(),
+                    )
+                }
+            {
+                anyhow::bail!(
+                    "error while calling IoTHubDeviceClient_AzureStoragePutBlock({number})"
+                );
+            }
+
+            Ok(())
+        })
+        .await?
+    }
+
+    async fn notify(&self, success: bool) -> Result<()> {
+        let twin = self.twin.clone();
+        let correlation_id = self.correlation_id.clone();
+        let (status, message) = if success {
+            (200, CString::new("upload succeeded")?)
+        } else {
+            (500, CString::new("upload failed")?)
+        };
+
+        tokio::task::spawn_blocking(move || {
+            let borrowed = twin.borrow()?;
+            let handle = borrowed.raw_handle();
+
+            if IOTHUB_CLIENT_RESULT_TAG_IOTHUB_CLIENT_OK
+                != unsafe {
+                    IoTHubDeviceClient_AzureStorageNotifyBlobUploadCompletion(
+                        handle as IOTHUB_DEVICE_CLIENT_HANDLE,
+                        correlation_id.as_ptr(),
+                        success,
+                        status,
+                        message.as_ptr(),
+                    )
+                }
+            {
+                anyhow::bail!(
+                    "error while calling IoTHubDeviceClient_AzureStorageNotifyBlobUploadCompletion()"
+                );
+            }
+
+            Ok(())
+        })
+        .await?
+    }
+}
+
+impl IotHubClient {
+    /// Call this function to start a streaming upload of a blob named `destination` to the azure storage
+    /// linked to iothub. The client handle isn't locked while iothub is requested for the storage location,
+    /// thus other operations aren't stalled. Uploading the blocks doesn't involve the client handle.<br>
+    /// ***Note***: this function is only available for device clients, since iothub doesn't support blob
+    /// uploads of modules.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     let (tx_progress, mut rx_progress) = tokio::sync::mpsc::channel(10);
+    ///     let mut archive = tokio::fs::File::open("/var/log/archive.tar.gz").await.unwrap();
+    ///     let mut upload = client
+    ///         .upload_to_blob("logs/archive.tar.gz")
+    ///         .await
+    ///         .unwrap()
+    ///         .progress_observer(tx_progress);
+    ///
+    ///     tokio::spawn(async move {
+    ///         while let Some(progress) = rx_progress.recv().await {
+    ///             println!("uploaded {} bytes", progress.bytes);
+    ///         }
+    ///     });
+    ///
+    ///     // resume after failed blocks
+    ///     for _ in 0..3 {
+    ///         if upload.upload_from(&mut archive).await.is_ok() {
+    ///             upload.complete().await.unwrap();
+    ///             return;
+    ///         }
+    ///     }
+    ///
+    ///     upload.abort().await.unwrap();
+    /// }
+    /// ```
+    pub async fn upload_to_blob(&self, destination: &str) -> Result<BlobUpload> {
+        let twin = self.twin.clone();
+        let destination = CString::new(destination)?;
+
+        let (correlation_id, storage) = tokio::task::spawn_blocking(move || {
+            let borrowed = twin.borrow()?;
+            let handle = borrowed.raw_handle() as IOTHUB_DEVICE_CLIENT_HANDLE;
+            let mut correlation_id: *mut c_char = std::ptr::null_mut();
+            let mut sas_uri: *mut c_char = std::ptr::null_mut();
+
+            unsafe {
+                if IOTHUB_CLIENT_RESULT_TAG_IOTHUB_CLIENT_OK
+                    != IoTHubDeviceClient_AzureStorageInitializeBlobUpload(
+                        handle,
+                        destination.as_ptr(),
+                        &mut correlation_id,
+                        &mut sas_uri,
+                    )
+                {
+                    anyhow::bail!(
+                        "error while calling IoTHubDeviceClient_AzureStorageInitializeBlobUpload()"
+                    );
+                }
+
+                let owned_correlation_id = CStr::from_ptr(correlation_id).to_owned();
+                let storage = IoTHubDeviceClient_AzureStorageCreateClient(handle, sas_uri);
+
+                free(correlation_id as *mut c_void);
+                free(sas_uri as *mut c_void);
+
+                if storage.is_null() {
+                    anyhow::bail!(
+                        "error while calling IoTHubDeviceClient_AzureStorageCreateClient()"
+                    );
+                }
+
+                Ok((owned_correlation_id, StorageClient(storage)))
+            }
+        })
+        .await??;
+
+        Ok(BlobUpload {
+            twin: self.twin.clone(),
+            storage: Arc::new(Mutex::new(storage)),
+            correlation_id,
+            block_size: DEFAULT_BLOCK_SIZE,
+            pending: None,
+            progress: UploadProgress::default(),
+            tx_progress: None,
+        })
+    }
+}
@@ -0,0 +1,258 @@
+use crate::client::{
+    event_hubs, idempotency::IDEMPOTENCY_KEY_PROPERTY, message::urlencode, IotMessage,
+};
+use anyhow::Result;
+use bytes::Bytes;
+use log::warn;
+use std::{
+    collections::{HashMap, VecDeque},
+    ffi::CString,
+};
+
+/// message property containing the id of a chunked transfer
+pub static TRANSFER_ID_PROPERTY: &str = "transfer-id";
+/// message property containing the zero based index of a chunk
+pub static CHUNK_INDEX_PROPERTY: &str = "chunk-index";
+/// message property containing the number of chunks of a transfer
+pub static CHUNK_COUNT_PROPERTY: &str = "chunk-count";
+static MAX_PENDING_TRANSFERS: usize = 64;
+// chunks of 192 KiB add up to 12 GiB
+static MAX_CHUNK_COUNT: usize = 65536;
+
+/// splits `body` into D2C messages of at most `chunk_size` bytes. all chunks carry the transfer id as
+/// correlation id as well as chunk index and count as properties, so a consumer can reassemble the body.
+/// chunks share the buffer of `body`.
+pub(crate) fn chunk_messages(
+    transfer_id: &str,
+    body: &Bytes,
+    chunk_size: usize,
+    content_type: &str,
+) -> Result<Vec<IotMessage>> {
+    let chunk_count = body.len().div_ceil(chunk_size);
+
+    (0..chunk_count)
+        .map(|index| {
+            let start = index * chunk_size;
+
+            IotMessage::builder()
+                .set_body(body.slice(start..body.len().min(start + chunk_size)))
+                .set_correlation_id(transfer_id)
+                .set_content_type(content_type)
+                .set_property(TRANSFER_ID_PROPERTY, transfer_id)
+                .set_property(CHUNK_INDEX_PROPERTY, index.to_string())
+                .set_property(CHUNK_COUNT_PROPERTY, chunk_count.to_string())
+                .build()
+        })
+        .collect()
+}
+
+/// splits the body of an oversized `message` into chunks of at most `chunk_size` bytes that keep all
+/// properties of `message`. message id and idempotency key are suffixed by the chunk index, so that
+/// duplicate detection doesn't drop chunks.
+pub(crate) fn chunk_message(
+    transfer_id: &str,
+    message: &IotMessage,
+    chunk_size: usize,
+) -> Result<Vec<IotMessage>> {
+    let chunk_count = message.body.len().div_ceil(chunk_size);
+    let message_id = CString::new("$.mid")?;
+    let idempotency_key = CString::new(IDEMPOTENCY_KEY_PROPERTY)?;
+
+    (0..chunk_count)
+        .map(|index| {
+            let start = index * chunk_size;
+            let mut properties = message.properties.clone();
+            let mut system_properties = message.system_properties.clone();
+            let suffixed = |value: &CString| -> Result<CString> {
+                Ok(CString::new(format!(
+                    "{}-{index}",
+                    value.to_string_lossy()
+                ))?)
+            };
+
+            if let Some(key) = properties.get_mut(&idempotency_key) {
+                *key = suffixed(key)?;
+            }
+
+            if let Some(id) = system_properties.get_mut(&message_id) {
+                *id = suffixed(id)?;
+            }
+
+            for (key, value) in [
+                (TRANSFER_ID_PROPERTY, transfer_id.to_string()),
+                (CHUNK_INDEX_PROPERTY, index.to_string()),
+                (CHUNK_COUNT_PROPERTY, chunk_count.to_string()),
+            ] {
+                properties.insert(CString::new(key)?, CString::new(urlencode(value))?);
+            }
+
+            let mut chunk = IotMessage::outgoing(
+                message
+                    .body
+                    .slice(start..message.body.len().min(start + chunk_size)),
+                message.output_queue.clone(),
+                properties,
+                system_properties,
+            );
+
+            chunk.confirmation_timeout = message.confirmation_timeout;
+
+            Ok(chunk)
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+struct Transfer {
+    chunks: Vec<Option<Bytes>>,
+    received: usize,
+}
+
+/// Reassembly of bodies sent as chunked transfer, i.e. messages split into chunks by
+/// [`crate::client::IotHubClientBuilder::chunk_oversized_messages`] or large direct method responses sent by
+/// [`crate::client::IotHubClient::large_direct_method_response`].
+///
+/// Chunks are identified by their properties [`TRANSFER_ID_PROPERTY`], [`CHUNK_INDEX_PROPERTY`] and
+/// [`CHUNK_COUNT_PROPERTY`] and may arrive in any order. Duplicated chunks are ignored. Messages without these
+/// properties are passed through. At most 64 incomplete transfers are kept, the oldest is discarded if another
+/// one starts.
+/// ```rust
+/// use azure_iot_sdk::client::*;
+/// use std::collections::HashMap;
+///
+/// let chunk = |index: usize| {
+///     HashMap::from([
+///         (TRANSFER_ID_PROPERTY.to_string(), "frame-42".to_string()),
+///         (CHUNK_INDEX_PROPERTY.to_string(), index.to_string()),
+///         (CHUNK_COUNT_PROPERTY.to_string(), "2".to_string()),
+///     ])
+/// };
+/// let mut assembler = ChunkAssembler::new();
+///
+/// assert_eq!(assembler.add(&chunk(1), &b"world"[..]).unwrap(), None);
+/// assert_eq!(assembler.pending(), 1);
+/// assert_eq!(
+///     assembler.add(&chunk(0), &b"hello "[..]).unwrap(),
+///     Some(Bytes::from("hello world"))
+/// );
+/// assert_eq!(assembler.pending(), 0);
+///
+/// // messages that aren't chunked are passed through
+/// assert_eq!(
+///     assembler.add(&HashMap::new(), &b"plain"[..]).unwrap(),
+///     Some(Bytes::from("plain"))
+/// );
+/// ```
+#[derive(Debug, Default)]
+pub struct ChunkAssembler {
+    transfers: HashMap<String, Transfer>,
+    // transfer ids in the order of their first chunk
+    order: VecDeque<String>,
+}
+
+impl ChunkAssembler {
+    /// Get an assembler without pending transfers
+    pub fn new() -> Self {
+        ChunkAssembler::default()
+    }
+
+    /// Add a chunk with its decoded application `properties`, e.g. as received by an Event Hubs consumer.
+    /// Returns the reassembled body if the chunk completes its transfer and `None` if chunks are missing.
+    pub fn add(
+        &mut self,
+        properties: &HashMap<String, String>,
+        body: impl Into<Bytes>,
+    ) -> Result<Option<Bytes>> {
+        let Some(transfer_id) = properties.get(TRANSFER_ID_PROPERTY) else {
+            return Ok(Some(body.into()));
+        };
+        let number = |property: &str| -> Result<usize> {
+            let Some(value) = properties.get(property) else {
+                anyhow::bail!("chunk of transfer {transfer_id} has no property {property}");
+            };
+
+            value.parse().map_err(|_| {
+                anyhow::anyhow!("chunk of transfer {transfer_id} has invalid {property} {value}")
+            })
+        };
+        let index = number(CHUNK_INDEX_PROPERTY)?;
+        let count = number(CHUNK_COUNT_PROPERTY)?;
+
+        anyhow::ensure!(
+            index < count,
+            "chunk {index} of transfer {transfer_id} exceeds chunk count {count}"
+        );
+        anyhow::ensure!(
+            count <= MAX_CHUNK_COUNT,
+            "transfer {transfer_id} exceeds {MAX_CHUNK_COUNT} chunks"
+        );
+
+        if !self.transfers.contains_key(transfer_id) {
+            if self.order.len() == MAX_PENDING_TRANSFERS {
+                if let Some(oldest) = self.order.pop_front() {
+                    warn!("discard incomplete chunked transfer {oldest}");
+                    self.transfers.remove(&oldest);
+                }
+            }
+
+            self.order.push_back(transfer_id.clone());
+            self.transfers.insert(
+                transfer_id.clone(),
+                Transfer {
+                    chunks: vec![None; count],
+                    received: 0,
+                },
+            );
+        }
+
+        let transfer = self.transfers.get_mut(transfer_id).expect("no transfer");
+
+        anyhow::ensure!(
+            transfer.chunks.len() == count,
+            "chunk {index} of transfer {transfer_id} has chunk count {count} instead of {}",
+            transfer.chunks.len()
+        );
+
+        if transfer.chunks[index].is_none() {
+            transfer.chunks[index] = Some(body.into());
+            transfer.received += 1;
+        }
+
+        if transfer.received < count {
+            return Ok(None);
+        }
+
+        let transfer = self.transfers.remove(transfer_id).expect("no transfer");
+
+        self.order.retain(|id| id != transfer_id);
+
+        Ok(Some(
+            transfer
+                .chunks
+                .into_iter()
+                .flatten()
+                .flatten()
+                .collect::<Vec<u8>>()
+                .into(),
+        ))
+    }
+
+    /// Add `message`, e.g. a C2D message or a D2C message read back, see [`ChunkAssembler::add`]
+    pub fn add_message(&mut self, message: &IotMessage) -> Result<Option<Bytes>> {
+        self.add(
+            &event_hubs::application_properties(message)?,
+            message.body.clone(),
+        )
+    }
+
+    /// number of incomplete transfers
+    pub fn pending(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Discard the incomplete transfer `transfer_id`. Returns false if there is no such transfer.
+    pub fn discard(&mut self, transfer_id: &str) -> bool {
+        self.order.retain(|id| id != transfer_id);
+        self.transfers.remove(transfer_id).is_some()
+    }
+}
@@ -0,0 +1,77 @@
+use log::info;
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// deviation between system time and clock that is considered a wall clock sync, e.g. by ntp
+static SYNC_THRESHOLD_IN_SECS: u64 = 2;
+
+/// wall clock that is anchored to the system time and afterwards only advanced by the monotonic clock.
+/// thus timestamps don't jump on small adjustments of the system time. if the system time deviates by more
+/// than `SYNC_THRESHOLD_IN_SECS`, the wall clock was synced, e.g. after boot, and the clock is re-anchored.
+#[derive(Debug)]
+pub(crate) struct MonotonicClock {
+    anchor: Mutex<(SystemTime, Instant)>,
+}
+
+impl MonotonicClock {
+    pub(crate) fn new() -> Self {
+        MonotonicClock {
+            anchor: Mutex::new((SystemTime::now(), Instant::now())),
+        }
+    }
+
+    pub(crate) fn now(&self) -> SystemTime {
+        let mut anchor = self
+            .anchor
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let now = anchor.0 + anchor.1.elapsed();
+        let system = SystemTime::now();
+        let deviation = match system.duration_since(now) {
+            Ok(ahead) => ahead,
+            Err(behind) => behind.duration(),
+        };
+
+        if deviation < Duration::from_secs(SYNC_THRESHOLD_IN_SECS) {
+            return now;
+        }
+
+        info!("clock: system time deviates by {deviation:?}, re-anchor to synced wall clock");
+
+        *anchor = (system, Instant::now());
+
+        system
+    }
+
+    /// current time as RFC 3339 UTC timestamp with milliseconds, e.g. "2024-01-31T12:30:00.123Z"
+    pub(crate) fn now_rfc3339(&self) -> String {
+        rfc3339(self.now())
+    }
+}
+
+pub(crate) fn rfc3339(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO);
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+    // civil from days: http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
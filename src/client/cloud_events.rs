@@ -0,0 +1,68 @@
+//! Envelope of D2C messages according to [CloudEvents 1.0](https://github.com/cloudevents/spec) for
+//! consumers that require CloudEvents, e.g. Event Grid subscribers.
+//!
+//! Messages are built in structured content mode: the body is a JSON object with the context attributes
+//! `specversion`, `id`, `source`, `type`, `time` and `datacontenttype` and the payload as `data`. The content
+//! type of the message is `application/cloudevents+json`.<br>
+//! ***Note***: iothub message routing only queries bodies of content type `application/json`, thus routing
+//! queries on the body don't match CloudEvents messages. Route on application properties instead.
+//! ```rust
+//! use azure_iot_sdk::client::{cloud_events, event_hubs};
+//! use serde_json::json;
+//!
+//! let msg = cloud_events::message("/devices/sensor-1", "com.example.temperature", &json!({"celsius": 21.5}))
+//!     .unwrap()
+//!     .set_output_queue("telemetry")
+//!     .build()
+//!     .unwrap();
+//!
+//! let event: serde_json::Value = serde_json::from_slice(&msg.body).unwrap();
+//! assert_eq!(event["specversion"], cloud_events::SPEC_VERSION);
+//! assert_eq!(event["source"], "/devices/sensor-1");
+//! assert_eq!(event["type"], "com.example.temperature");
+//! assert_eq!(event["data"]["celsius"], 21.5);
+//!
+//! let system_properties = event_hubs::system_properties(&msg).unwrap();
+//! assert_eq!(system_properties["content-type"], cloud_events::CONTENT_TYPE_CLOUD_EVENTS_JSON);
+//! assert_eq!(system_properties["message-id"], event["id"]);
+//! ```
+use super::{
+    clock,
+    event_hubs::{CONTENT_ENCODING_UTF8, CONTENT_TYPE_JSON},
+    message::{IotMessage, IotMessageBuilder},
+};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::json;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+/// version of the CloudEvents specification messages comply with
+pub static SPEC_VERSION: &str = "1.0";
+/// content type of messages in structured content mode
+pub static CONTENT_TYPE_CLOUD_EVENTS_JSON: &str = "application/cloudevents+json";
+
+/// Get a message builder with `data` wrapped in a CloudEvent of `event_type` originating from `source`. The
+/// event gets a random UUID as `id`, which is also set as message id, and the current time as `time`.
+pub fn message<T: Serialize + ?Sized>(
+    source: &str,
+    event_type: &str,
+    data: &T,
+) -> Result<IotMessageBuilder> {
+    let id = Uuid::new_v4().to_string();
+    let event = json!({
+        "specversion": SPEC_VERSION,
+        "id": id,
+        "source": source,
+        "type": event_type,
+        "time": clock::rfc3339(SystemTime::now()),
+        "datacontenttype": CONTENT_TYPE_JSON,
+        "data": serde_json::to_value(data).context("cloud_events: cannot serialize data")?,
+    });
+
+    Ok(IotMessage::builder()
+        .set_body(serde_json::to_vec(&event).context("cloud_events: cannot serialize event")?)
+        .set_id(id)
+        .set_content_type(CONTENT_TYPE_CLOUD_EVENTS_JSON)
+        .set_content_encoding(CONTENT_ENCODING_UTF8))
+}
@@ -0,0 +1,95 @@
+use crate::client::IotMessage;
+use anyhow::{Context, Result};
+use flate2::{read::GzDecoder, write::GzEncoder};
+use std::{
+    ffi::CString,
+    io::{Read, Write},
+};
+
+/// content encoding of gzip compressed bodies
+pub static CONTENT_ENCODING_GZIP: &str = "gzip";
+/// bodies smaller than this are not compressed by default, since they hardly shrink
+pub(crate) static DEFAULT_COMPRESSION_THRESHOLD: usize = 1024;
+/// decompressed bodies larger than this are refused, e.g. to withstand gzip bombs
+pub(crate) static MAX_DECOMPRESSED_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Compression of D2C message bodies applied by [`crate::client::IotMessageBuilder::compress`].
+/// The content encoding of compressed messages is set accordingly, so that consumers can decompress them by
+/// [`Compression::decompress`]. Since iothub message routing cannot query compressed bodies, only properties
+/// of compressed messages can be used for routing. Incoming C2D messages with content encoding "gzip" are
+/// decompressed before they are handed over to the observer.<br>
+/// ***Note***: compression is only available with "gzip" feature enabled.
+/// ```rust
+/// use azure_iot_sdk::client::*;
+///
+/// let body = br#"{"temperature": 21.5}"#.repeat(100);
+/// let compressed = Compression::Gzip.compress(&body).unwrap();
+///
+/// assert!(compressed.len() < body.len() / 10);
+/// assert_eq!(Compression::Gzip.decompress(&compressed).unwrap(), body);
+/// assert_eq!(Compression::Gzip.content_encoding(), CONTENT_ENCODING_GZIP);
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Compression {
+    /// [gzip](https://www.rfc-editor.org/rfc/rfc1952) with default level
+    Gzip,
+}
+
+impl Compression {
+    /// content encoding set for compressed bodies
+    pub fn content_encoding(&self) -> &'static str {
+        match self {
+            Compression::Gzip => CONTENT_ENCODING_GZIP,
+        }
+    }
+
+    /// compresses `body`
+    pub fn compress(&self, body: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+
+                encoder.write_all(body).context("cannot compress body")?;
+                encoder.finish().context("cannot compress body")
+            }
+        }
+    }
+
+    /// decompresses `body`. fails if the decompressed body exceeds 16MiB.
+    pub fn decompress(&self, body: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Compression::Gzip => {
+                let mut decompressed = Vec::new();
+
+                GzDecoder::new(body)
+                    .take(MAX_DECOMPRESSED_SIZE + 1)
+                    .read_to_end(&mut decompressed)
+                    .context("cannot decompress body")?;
+
+                anyhow::ensure!(
+                    decompressed.len() as u64 <= MAX_DECOMPRESSED_SIZE,
+                    "decompressed body exceeds {MAX_DECOMPRESSED_SIZE} bytes"
+                );
+
+                Ok(decompressed)
+            }
+        }
+    }
+}
+
+/// decompresses the body of an incoming `message` if its content encoding is gzip. the content encoding is
+/// removed afterwards, since it doesn't apply to the decompressed body anymore.
+pub(crate) fn decompress_incoming(message: &mut IotMessage) -> Result<()> {
+    let content_encoding = CString::new("$.ce")?;
+
+    if message
+        .system_properties
+        .get(&content_encoding)
+        .is_some_and(|encoding| encoding.to_string_lossy().trim() == CONTENT_ENCODING_GZIP)
+    {
+        message.body = Compression::Gzip.decompress(&message.body)?.into();
+        message.system_properties.remove(&content_encoding);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,380 @@
+use crate::client::{
+    ClientType, IotHubClient, ModelId, RetryPolicy, Transport, UnsupportedModelIdPolicy,
+};
+use anyhow::Result;
+use log::{error, info};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{env, ffi::CString, fs, path::Path, time::Duration};
+
+pub(crate) static AZURE_SDK_LOGGING: &str = "AZURE_SDK_LOGGING";
+pub(crate) static AZURE_SDK_DO_WORK_FREQUENCY_IN_MS: &str = "AZURE_SDK_DO_WORK_FREQUENCY_IN_MS";
+pub(crate) static DO_WORK_FREQUENCY_RANGE_IN_MS: std::ops::RangeInclusive<u64> = 0..=100;
+pub(crate) static DO_WORK_FREQUENCY_DEFAULT_IN_MS: u64 = 100;
+pub(crate) static AZURE_SDK_CONFIRMATION_TIMEOUT_IN_SECS: &str =
+    "AZURE_SDK_CONFIRMATION_TIMEOUT_IN_SECS";
+pub(crate) static CONFIRMATION_TIMEOUT_DEFAULT_IN_SECS: u64 = 30;
+
+/// Typed configuration of an [`IotHubClient`] that can be validated as a whole and applied by
+/// [`crate::client::IotHubClientBuilder::config`]. Settings that are `None` aren't applied, i.e. the builder keeps
+/// its own settings, which finally fall back to defaults. Only the do_work frequency, the confirmation timeout and
+/// logging additionally fall back to environment variables, since no other setting is configurable by environment.
+/// ```rust, no_run
+/// use azure_iot_sdk::client::*;
+/// use std::time::Duration;
+///
+/// let config = IotHubClientConfig {
+///     transport: Some(Transport::MqttWebSocket),
+///     http_proxy: Some(HttpProxyConfig {
+///         host_address: "my-proxy".to_string(),
+///         port: 8080,
+///         credentials: None,
+///     }),
+///     do_work_freq_ms: Some(10),
+///     confirmation_timeout_secs: Some(60),
+///     ..IotHubClientConfig::from_env()
+/// };
+///
+/// config.validate().unwrap();
+///
+/// #[cfg(feature = "edge_client")]
+/// let builder = IotHubClient::builder().config(config);
+/// ```
+#[derive(Clone, Default)]
+pub struct IotHubClientConfig {
+    /// [`Transport`] used to connect to iothub
+    pub transport: Option<Transport>,
+    /// [`RetryPolicy`] and retry timeout in seconds
+    pub retry_policy: Option<(RetryPolicy, u32)>,
+    /// sas token lifetime and renewal margin
+    pub sas_token_lifetime: Option<(Duration, Duration)>,
+    /// http batching and minimum polling time. Only applicable to [`Transport::Http`].
+    pub http_settings: Option<(bool, Duration)>,
+    /// [`HttpProxyConfig`]
+    pub http_proxy: Option<HttpProxyConfig>,
+    /// trusted CA certificates in PEM format
+    pub trusted_certs: Option<String>,
+    /// Azure IoT Plug & Play model id
+    pub model_id: Option<String>,
+    /// [`UnsupportedModelIdPolicy`]
+    pub unsupported_model_id_policy: Option<UnsupportedModelIdPolicy>,
+    /// azure-sdk-c do_work frequency in ms in range of 0...100ms.
+    /// Falls back to `AZURE_SDK_DO_WORK_FREQUENCY_IN_MS` or 100ms.
+    pub do_work_freq_ms: Option<u64>,
+    /// timeout to wait for confirmations of D2C messages and reported properties.
+    /// Falls back to `AZURE_SDK_CONFIRMATION_TIMEOUT_IN_SECS` or 30s.
+    pub confirmation_timeout_secs: Option<u64>,
+    /// enables azure-sdk-c logging. Falls back to existence of `AZURE_SDK_LOGGING`.
+    pub logging: Option<bool>,
+}
+
+/// Http proxy part of [`IotHubClientConfig`]
+#[derive(Clone)]
+pub struct HttpProxyConfig {
+    /// host address of the proxy
+    pub host_address: String,
+    /// port of the proxy
+    pub port: u16,
+    /// optional username and password
+    pub credentials: Option<(String, String)>,
+}
+
+impl std::fmt::Debug for HttpProxyConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpProxyConfig")
+            .field("host_address", &self.host_address)
+            .field("port", &self.port)
+            .field(
+                "username",
+                &self.credentials.as_ref().map(|(username, _)| username),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+impl std::fmt::Debug for IotHubClientConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IotHubClientConfig")
+            .field("transport", &self.transport)
+            .field("retry_policy", &self.retry_policy)
+            .field("sas_token_lifetime", &self.sas_token_lifetime)
+            .field("http_settings", &self.http_settings)
+            .field("http_proxy", &self.http_proxy)
+            .field("trusted_certs", &self.trusted_certs.is_some())
+            .field("model_id", &self.model_id)
+            .field(
+                "unsupported_model_id_policy",
+                &self.unsupported_model_id_policy,
+            )
+            .field("do_work_freq_ms", &self.do_work_freq_ms)
+            .field("confirmation_timeout_secs", &self.confirmation_timeout_secs)
+            .field("logging", &self.logging)
+            .finish()
+    }
+}
+
+impl IotHubClientConfig {
+    /// Call this function to get a config with all settings taken from environment variables.
+    /// Invalid values are logged and ignored.
+    pub fn from_env() -> Self {
+        let mut config = IotHubClientConfig::default();
+
+        if let Ok(freq) = env::var(AZURE_SDK_DO_WORK_FREQUENCY_IN_MS) {
+            match freq.parse::<u64>() {
+                Ok(freq) if DO_WORK_FREQUENCY_RANGE_IN_MS.contains(&freq) => {
+                    config.do_work_freq_ms = Some(freq)
+                }
+                _ => error!("ignore do_work frequency {freq} since not in range of {DO_WORK_FREQUENCY_RANGE_IN_MS:?}ms"),
+            };
+        }
+
+        if let Ok(timeout_secs) = env::var(AZURE_SDK_CONFIRMATION_TIMEOUT_IN_SECS) {
+            match timeout_secs.parse::<u64>() {
+                Ok(timeout_secs) => config.confirmation_timeout_secs = Some(timeout_secs),
+                _ => error!("ignore invalid confirmation timeout {timeout_secs}"),
+            };
+        }
+
+        if env::var(AZURE_SDK_LOGGING).is_ok() {
+            config.logging = Some(true);
+        }
+
+        config
+    }
+
+    /// Call this function to load a config from a JSON file, e.g. in order to manage settings of embedded
+    /// deployments declaratively. Only JSON is supported, thus files without `.json` extension are rejected.
+    /// Settings not contained in the file are `None`.
+    /// Unknown settings and values of the wrong type are rejected, conflicting settings are reported by
+    /// [`IotHubClientConfig::validate`].
+    /// ```json
+    /// {
+    ///     "transport": "MqttWebSocket",
+    ///     "retry_policy": { "policy": "ExponentialBackoffWithJitter", "timeout_secs": 0 },
+    ///     "sas_token_lifetime": { "lifetime_secs": 3600, "renewal_margin_secs": 300 },
+    ///     "http_settings": { "batching": true, "min_polling_time_secs": 10 },
+    ///     "http_proxy": { "host_address": "my-proxy", "port": 8080, "username": "my-user", "password": "my-password" },
+    ///     "trusted_certs_path": "/etc/ssl/certs/my-root-ca.pem",
+    ///     "model_id": "dtmi:com:example:Thermostat;1",
+    ///     "unsupported_model_id_policy": "Warn",
+    ///     "do_work_freq_ms": 10,
+    ///     "confirmation_timeout_secs": 60,
+    ///     "logging": false
+    /// }
+    /// ```
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+
+        anyhow::ensure!(
+            path.extension()
+                .is_some_and(|extension| extension == "json"),
+            "unsupported config file {}: only JSON files with .json extension are supported",
+            path.display()
+        );
+
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("cannot read config file {}: {e}", path.display()))?;
+        let value: Value = serde_json::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("cannot parse config file {}: {e}", path.display()))?;
+
+        IotHubClientConfig::from_json(&value)
+    }
+
+    /// Call this function to get a config from a JSON document as described in [`IotHubClientConfig::from_file`].
+    pub fn from_json(value: &Value) -> Result<Self> {
+        let document = ConfigDocument::deserialize(value)
+            .map_err(|e| anyhow::anyhow!("invalid client config: {e}"))?;
+        let mut config = IotHubClientConfig {
+            transport: document.transport,
+            unsupported_model_id_policy: document.unsupported_model_id_policy,
+            model_id: document.model_id,
+            do_work_freq_ms: document.do_work_freq_ms,
+            confirmation_timeout_secs: document.confirmation_timeout_secs,
+            logging: document.logging,
+            ..IotHubClientConfig::default()
+        };
+
+        if let Some(retry_policy) = document.retry_policy {
+            config.retry_policy = Some((retry_policy.policy, retry_policy.timeout_secs));
+        }
+
+        if let Some(sas_token_lifetime) = document.sas_token_lifetime {
+            config.sas_token_lifetime = Some((
+                Duration::from_secs(sas_token_lifetime.lifetime_secs),
+                Duration::from_secs(sas_token_lifetime.renewal_margin_secs),
+            ));
+        }
+
+        if let Some(http_settings) = document.http_settings {
+            config.http_settings = Some((
+                http_settings.batching,
+                Duration::from_secs(http_settings.min_polling_time_secs),
+            ));
+        }
+
+        if let Some(http_proxy) = document.http_proxy {
+            let credentials = match (http_proxy.username, http_proxy.password) {
+                (Some(username), Some(password)) => Some((username, password)),
+                (None, None) => None,
+                _ => anyhow::bail!(
+                    "invalid client config: http_proxy: username and password must be set together"
+                ),
+            };
+
+            config.http_proxy = Some(HttpProxyConfig {
+                host_address: http_proxy.host_address,
+                port: http_proxy.port,
+                credentials,
+            });
+        }
+
+        if let Some(path) = document.trusted_certs_path {
+            config.trusted_certs = Some(fs::read_to_string(&path).map_err(|e| {
+                anyhow::anyhow!(
+                    "invalid client config: trusted_certs_path: cannot read {path}: {e}"
+                )
+            })?);
+        }
+
+        Ok(config)
+    }
+
+    /// Call this function to validate the config. All invalid or conflicting settings are reported at once.
+    pub fn validate(&self) -> Result<()> {
+        let mut errors = vec![];
+        let transport = self.transport.unwrap_or_default();
+
+        if transport == Transport::Http && IotHubClient::client_type() != ClientType::Device {
+            errors.push(format!(
+                "transport {transport:?} is only supported by device clients"
+            ));
+        }
+
+        if let Some((lifetime, renewal_margin)) = self.sas_token_lifetime {
+            if renewal_margin >= lifetime {
+                errors.push("sas token renewal margin must be smaller than lifetime".to_string());
+            }
+        }
+
+        if self.http_settings.is_some() && transport != Transport::Http {
+            errors.push(format!(
+                "http settings require transport {:?}, but transport is {transport:?}",
+                Transport::Http
+            ));
+        }
+
+        if let Some(http_proxy) = &self.http_proxy {
+            if http_proxy.host_address.is_empty() {
+                errors.push("http proxy host address must not be empty".to_string());
+            }
+
+            if matches!(transport, Transport::Mqtt | Transport::Amqp) {
+                errors.push(format!(
+                    "http proxy requires a websocket or http transport, but transport is {transport:?}"
+                ));
+            }
+        }
+
+        if let Some(trusted_certs) = &self.trusted_certs {
+            if CString::new(trusted_certs.as_str()).is_err() {
+                errors.push("trusted certs must not contain nul bytes".to_string());
+            }
+        }
+
+        if let Some(model_id) = &self.model_id {
+            if let Err(e) = model_id.parse::<ModelId>() {
+                errors.push(e.to_string());
+            }
+        }
+
+        if let Some(freq) = self.do_work_freq_ms {
+            if !DO_WORK_FREQUENCY_RANGE_IN_MS.contains(&freq) {
+                errors.push(format!(
+                    "do_work frequency {freq}ms not in range of {DO_WORK_FREQUENCY_RANGE_IN_MS:?}ms"
+                ));
+            }
+        }
+
+        if self.confirmation_timeout_secs == Some(0) {
+            errors.push("confirmation timeout must not be 0".to_string());
+        }
+
+        if !errors.is_empty() {
+            anyhow::bail!("invalid client config: {}", errors.join("; "));
+        }
+
+        Ok(())
+    }
+
+    /// effective do_work frequency in ms
+    pub(crate) fn do_work_freq_ms(&self) -> u64 {
+        let freq = self
+            .do_work_freq_ms
+            .unwrap_or(DO_WORK_FREQUENCY_DEFAULT_IN_MS);
+        info!("set do_work frequency {freq}ms");
+        freq
+    }
+
+    /// effective confirmation timeout in seconds
+    pub(crate) fn confirmation_timeout_secs(&self) -> u64 {
+        let timeout_secs = self
+            .confirmation_timeout_secs
+            .unwrap_or(CONFIRMATION_TIMEOUT_DEFAULT_IN_SECS);
+        info!("set confirmation timeout to {timeout_secs}s");
+        timeout_secs
+    }
+}
+
+/// JSON document of [`IotHubClientConfig::from_file`], settings that are missing aren't changed
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigDocument {
+    transport: Option<Transport>,
+    retry_policy: Option<RetryPolicyDocument>,
+    sas_token_lifetime: Option<SasTokenLifetimeDocument>,
+    http_settings: Option<HttpSettingsDocument>,
+    http_proxy: Option<HttpProxyDocument>,
+    trusted_certs_path: Option<String>,
+    model_id: Option<String>,
+    unsupported_model_id_policy: Option<UnsupportedModelIdPolicy>,
+    do_work_freq_ms: Option<u64>,
+    confirmation_timeout_secs: Option<u64>,
+    logging: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RetryPolicyDocument {
+    policy: RetryPolicy,
+    timeout_secs: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SasTokenLifetimeDocument {
+    lifetime_secs: u64,
+    renewal_margin_secs: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HttpSettingsDocument {
+    batching: bool,
+    min_polling_time_secs: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HttpProxyDocument {
+    host_address: String,
+    port: u16,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+/// parses `{ "policy": "<RetryPolicy>", "timeout_secs": <u32> }`
+pub(crate) fn retry_policy_from_json(value: &Value) -> Result<(RetryPolicy, u32)> {
+    let retry_policy = RetryPolicyDocument::deserialize(value)?;
+
+    Ok((retry_policy.policy, retry_policy.timeout_secs))
+}
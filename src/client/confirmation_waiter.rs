@@ -0,0 +1,133 @@
+use crate::client::{diagnostics::Diagnostics, trace_id::Trace};
+use anyhow::Result;
+use log::{debug, error, warn};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    thread,
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+/// wait for a confirmation including the conclusion of its outcome
+pub(crate) type ConfirmationWait = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// single thread with a runtime of its own that waits for confirmations the runtime of the client cannot
+/// wait for, since it is gone or shutting down. the thread is started on first use and stops as soon as
+/// [`ConfirmationWaiter::shutdown`] is called and all waits are done.
+#[derive(Debug, Default)]
+pub(crate) struct ConfirmationWaiter {
+    tx: Mutex<Option<mpsc::UnboundedSender<ConfirmationWait>>>,
+    stopped: AtomicBool,
+}
+
+impl ConfirmationWaiter {
+    fn tx(&self) -> MutexGuard<'_, Option<mpsc::UnboundedSender<ConfirmationWait>>> {
+        self.tx
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// runs `wait` on the waiter thread, which is started if not running yet
+    pub(crate) fn spawn(&self, wait: ConfirmationWait) -> Result<()> {
+        let mut tx = self.tx();
+
+        anyhow::ensure!(
+            !self.stopped.load(Ordering::Relaxed),
+            "confirmation waiter stopped"
+        );
+
+        if tx.as_ref().map_or(true, |tx| tx.is_closed()) {
+            *tx = Some(start()?);
+        }
+
+        match tx.as_ref() {
+            Some(tx) => tx
+                .send(wait)
+                .map_err(|_| anyhow::anyhow!("confirmation waiter stopped")),
+            None => anyhow::bail!("confirmation waiter not started"),
+        }
+    }
+
+    /// stops the waiter thread once all waits are done, no waits are accepted anymore
+    pub(crate) fn shutdown(&self) {
+        let mut tx = self.tx();
+
+        self.stopped.store(true, Ordering::Relaxed);
+        tx.take();
+    }
+}
+
+/// wait spawned on the runtime of the client. if the runtime drops it without ever polling it, i.e. since
+/// the runtime is shutting down, the wait is handed over to the [`ConfirmationWaiter`]. waits that were
+/// cancelled, i.e. aren't pending anymore, aren't handed over.
+pub(crate) struct Handover {
+    pub(crate) wait: Option<ConfirmationWait>,
+    pub(crate) trace: Trace,
+    pub(crate) diagnostics: Arc<Mutex<Diagnostics>>,
+    pub(crate) waiter: Arc<ConfirmationWaiter>,
+}
+
+impl Drop for Handover {
+    fn drop(&mut self) {
+        let Some(wait) = self.wait.take() else {
+            return;
+        };
+
+        let pending = match self.diagnostics.lock() {
+            Ok(diagnostics) => diagnostics.is_pending_confirmation(self.trace),
+            Err(poisoned) => poisoned.into_inner().is_pending_confirmation(self.trace),
+        };
+
+        if !pending {
+            return;
+        }
+
+        warn!(
+            "confirmation({}): runtime shutting down, wait on waiter thread",
+            self.trace
+        );
+
+        if let Err(e) = self.waiter.spawn(wait) {
+            error!(
+                "confirmation({}): cannot wait for confirmation: {e}",
+                self.trace
+            );
+        }
+    }
+}
+
+fn start() -> Result<mpsc::UnboundedSender<ConfirmationWait>> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()?;
+    let (tx, mut rx) = mpsc::unbounded_channel::<ConfirmationWait>();
+
+    thread::Builder::new()
+        .name("confirmation-waiter".to_string())
+        .spawn(move || {
+            debug!("confirmation waiter: started");
+
+            runtime.block_on(async move {
+                let mut waits: Vec<JoinHandle<()>> = vec![];
+
+                while let Some(wait) = rx.recv().await {
+                    waits.retain(|wait| !wait.is_finished());
+                    waits.push(tokio::spawn(wait));
+                }
+
+                for wait in waits {
+                    if let Err(e) = wait.await {
+                        error!("confirmation waiter: {e}");
+                    }
+                }
+            });
+
+            debug!("confirmation waiter: stopped");
+        })?;
+
+    Ok(tx)
+}
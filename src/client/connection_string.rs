@@ -0,0 +1,371 @@
+use crate::client::diagnostics;
+use anyhow::{Context, Result};
+use std::{fmt, str::FromStr};
+
+const HOST_NAME: &str = "HostName";
+const DEVICE_ID: &str = "DeviceId";
+const MODULE_ID: &str = "ModuleId";
+const SHARED_ACCESS_KEY: &str = "SharedAccessKey";
+const SHARED_ACCESS_SIGNATURE: &str = "SharedAccessSignature";
+const X509: &str = "x509";
+const GATEWAY_HOST_NAME: &str = "GatewayHostName";
+
+/// Parsed and validated iothub connection string.
+/// ```rust
+/// use azure_iot_sdk::client::ConnectionString;
+///
+/// let connection_string: ConnectionString =
+///     "HostName=my-hub.azure-devices.net;DeviceId=my-device;SharedAccessKey=c2VjcmV0"
+///         .parse()
+///         .unwrap();
+///
+/// assert_eq!(connection_string.host_name(), "my-hub.azure-devices.net");
+/// assert_eq!(connection_string.device_id(), "my-device");
+/// assert_eq!(connection_string.module_id(), None);
+///
+/// let connection_string = connection_string.with_gateway_host_name("my-edge-gateway.local");
+///
+/// assert_eq!(
+///     connection_string.to_string(),
+///     "HostName=my-hub.azure-devices.net;DeviceId=my-device;SharedAccessKey=c2VjcmV0;GatewayHostName=my-edge-gateway.local"
+/// );
+/// ```
+#[derive(Clone, Eq, PartialEq)]
+pub struct ConnectionString {
+    host_name: String,
+    device_id: String,
+    module_id: Option<String>,
+    shared_access_key: Option<String>,
+    gateway_host_name: Option<String>,
+    // further parts, e.g. SharedAccessSignature or x509, are kept in order to rebuild the string
+    others: Vec<(String, String)>,
+}
+
+impl ConnectionString {
+    /// Creates a new instance of [`ConnectionString`] without any credentials
+    pub fn new(host_name: impl Into<String>, device_id: impl Into<String>) -> Self {
+        ConnectionString {
+            host_name: host_name.into(),
+            device_id: device_id.into(),
+            module_id: None,
+            shared_access_key: None,
+            gateway_host_name: None,
+            others: vec![],
+        }
+    }
+
+    /// Sets the module id
+    pub fn with_module_id(mut self, module_id: impl Into<String>) -> Self {
+        self.module_id = Some(module_id.into());
+        self
+    }
+
+    /// Sets the shared access key
+    pub fn with_shared_access_key(mut self, shared_access_key: impl Into<String>) -> Self {
+        self.shared_access_key = Some(shared_access_key.into());
+        self
+    }
+
+    /// Sets the host name of an iot edge gateway
+    pub fn with_gateway_host_name(mut self, gateway_host_name: impl Into<String>) -> Self {
+        self.gateway_host_name = Some(gateway_host_name.into());
+        self
+    }
+
+    /// host name of iothub
+    pub fn host_name(&self) -> &str {
+        &self.host_name
+    }
+
+    /// device id
+    pub fn device_id(&self) -> &str {
+        &self.device_id
+    }
+
+    /// module id
+    pub fn module_id(&self) -> Option<&str> {
+        self.module_id.as_deref()
+    }
+
+    /// shared access key
+    pub fn shared_access_key(&self) -> Option<&str> {
+        self.shared_access_key.as_deref()
+    }
+
+    /// host name of iot edge gateway
+    pub fn gateway_host_name(&self) -> Option<&str> {
+        self.gateway_host_name.as_deref()
+    }
+
+    /// value of any other part, e.g. "SharedAccessSignature"
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.others
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl FromStr for ConnectionString {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut host_name = None;
+        let mut device_id = None;
+        let mut module_id = None;
+        let mut shared_access_key = None;
+        let mut gateway_host_name = None;
+        let mut others = vec![];
+
+        for part in s.split(';').filter(|part| !part.trim().is_empty()) {
+            let (key, value) = part.split_once('=').with_context(|| {
+                format!(
+                    "connection string: invalid part {}",
+                    diagnostics::redact(part)
+                )
+            })?;
+
+            let (key, value) = (key.trim(), value.trim());
+
+            if key.is_empty() {
+                anyhow::bail!(
+                    "connection string: empty key in part {}",
+                    diagnostics::redact(part)
+                );
+            }
+
+            if value.is_empty() {
+                anyhow::bail!("connection string: empty value for {key}");
+            }
+
+            let field = match key {
+                HOST_NAME => &mut host_name,
+                DEVICE_ID => &mut device_id,
+                MODULE_ID => &mut module_id,
+                SHARED_ACCESS_KEY => &mut shared_access_key,
+                GATEWAY_HOST_NAME => &mut gateway_host_name,
+                _ => {
+                    if others.iter().any(|(k, _)| k == key) {
+                        anyhow::bail!("connection string: duplicate {key}");
+                    }
+                    others.push((key.to_string(), value.to_string()));
+                    continue;
+                }
+            };
+
+            if field.replace(value.to_string()).is_some() {
+                anyhow::bail!("connection string: duplicate {key}");
+            }
+        }
+
+        let connection_string = ConnectionString {
+            host_name: host_name
+                .with_context(|| format!("connection string: missing {HOST_NAME}"))?,
+            device_id: device_id
+                .with_context(|| format!("connection string: missing {DEVICE_ID}"))?,
+            module_id,
+            shared_access_key,
+            gateway_host_name,
+            others,
+        };
+
+        if connection_string.shared_access_key.is_none()
+            && connection_string.get(SHARED_ACCESS_SIGNATURE).is_none()
+            && connection_string.get(X509).is_none()
+        {
+            anyhow::bail!(
+                "connection string: one of {SHARED_ACCESS_KEY}, {SHARED_ACCESS_SIGNATURE} or {X509} is required"
+            );
+        }
+
+        Ok(connection_string)
+    }
+}
+
+impl fmt::Display for ConnectionString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{HOST_NAME}={};{DEVICE_ID}={}",
+            self.host_name, self.device_id
+        )?;
+
+        if let Some(module_id) = &self.module_id {
+            write!(f, ";{MODULE_ID}={module_id}")?;
+        }
+
+        if let Some(shared_access_key) = &self.shared_access_key {
+            write!(f, ";{SHARED_ACCESS_KEY}={shared_access_key}")?;
+        }
+
+        for (key, value) in &self.others {
+            write!(f, ";{key}={value}")?;
+        }
+
+        if let Some(gateway_host_name) = &self.gateway_host_name {
+            write!(f, ";{GATEWAY_HOST_NAME}={gateway_host_name}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ConnectionString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&diagnostics::redact(&self.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static SHARED_ACCESS_KEY_VALUE: &str = "c2VjcmV0LWtleQ==";
+
+    fn parse(s: &str) -> Result<ConnectionString> {
+        s.parse()
+    }
+
+    fn error(s: &str) -> String {
+        parse(s).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn device() {
+        let connection_string = parse(&format!(
+            "HostName=my-hub.azure-devices.net;DeviceId=my-device;SharedAccessKey={SHARED_ACCESS_KEY_VALUE}"
+        ))
+        .unwrap();
+
+        assert_eq!(connection_string.host_name(), "my-hub.azure-devices.net");
+        assert_eq!(connection_string.device_id(), "my-device");
+        assert_eq!(connection_string.module_id(), None);
+        assert_eq!(connection_string.gateway_host_name(), None);
+    }
+
+    #[test]
+    fn equal_signs_inside_value() {
+        let connection_string = parse(&format!(
+            "HostName=my-hub;DeviceId=my-device;SharedAccessKey={SHARED_ACCESS_KEY_VALUE}"
+        ))
+        .unwrap();
+
+        assert_eq!(
+            connection_string.shared_access_key(),
+            Some(SHARED_ACCESS_KEY_VALUE)
+        );
+
+        let connection_string = parse(
+            "HostName=my-hub;DeviceId=my-device;SharedAccessSignature=SharedAccessSignature sr=my-hub&sig=abc%3D&se=1",
+        )
+        .unwrap();
+
+        assert_eq!(
+            connection_string.get(SHARED_ACCESS_SIGNATURE),
+            Some("SharedAccessSignature sr=my-hub&sig=abc%3D&se=1")
+        );
+    }
+
+    #[test]
+    fn module_and_gateway() {
+        let s = format!(
+            "HostName=my-hub;DeviceId=my-device;ModuleId=my-module;SharedAccessKey={SHARED_ACCESS_KEY_VALUE};GatewayHostName=my-gateway"
+        );
+        let connection_string = parse(&s).unwrap();
+
+        assert_eq!(connection_string.module_id(), Some("my-module"));
+        assert_eq!(connection_string.gateway_host_name(), Some("my-gateway"));
+        assert_eq!(connection_string.to_string(), s);
+
+        // order of parts doesn't matter
+        let reordered = parse(&format!(
+            "GatewayHostName=my-gateway;ModuleId=my-module;SharedAccessKey={SHARED_ACCESS_KEY_VALUE};DeviceId=my-device;HostName=my-hub"
+        ))
+        .unwrap();
+
+        assert_eq!(reordered, connection_string);
+    }
+
+    #[test]
+    fn builder_round_trip() {
+        let connection_string = ConnectionString::new("my-hub", "my-device")
+            .with_module_id("my-module")
+            .with_shared_access_key(SHARED_ACCESS_KEY_VALUE)
+            .with_gateway_host_name("my-gateway");
+
+        assert_eq!(
+            parse(&connection_string.to_string()).unwrap(),
+            connection_string
+        );
+    }
+
+    #[test]
+    fn x509() {
+        let connection_string = parse("HostName=my-hub;DeviceId=my-device;x509=true").unwrap();
+
+        assert_eq!(connection_string.get(X509), Some("true"));
+        assert_eq!(connection_string.shared_access_key(), None);
+    }
+
+    #[test]
+    fn missing_keys() {
+        assert!(error(&format!(
+            "DeviceId=my-device;SharedAccessKey={SHARED_ACCESS_KEY_VALUE}"
+        ))
+        .contains("missing HostName"));
+        assert!(error(&format!(
+            "HostName=my-hub;SharedAccessKey={SHARED_ACCESS_KEY_VALUE}"
+        ))
+        .contains("missing DeviceId"));
+        assert!(error("HostName=my-hub;DeviceId=my-device").contains("is required"));
+        assert!(error("").contains("missing HostName"));
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        assert!(error(&format!(
+            "HostName=my-hub;HostName=other-hub;DeviceId=my-device;SharedAccessKey={SHARED_ACCESS_KEY_VALUE}"
+        ))
+        .contains("duplicate HostName"));
+        assert!(error(&format!(
+            "HostName=my-hub;DeviceId=my-device;ModuleId=a;ModuleId=b;SharedAccessKey={SHARED_ACCESS_KEY_VALUE}"
+        ))
+        .contains("duplicate ModuleId"));
+        assert!(
+            error("HostName=my-hub;DeviceId=my-device;x509=true;x509=true")
+                .contains("duplicate x509")
+        );
+    }
+
+    #[test]
+    fn empty_keys_and_values() {
+        assert!(error(&format!(
+            "HostName=my-hub;DeviceId=my-device;=value;SharedAccessKey={SHARED_ACCESS_KEY_VALUE}"
+        ))
+        .contains("empty key"));
+        assert!(error(&format!(
+            "HostName=my-hub;DeviceId=;SharedAccessKey={SHARED_ACCESS_KEY_VALUE}"
+        ))
+        .contains("empty value for DeviceId"));
+        assert!(error(&format!(
+            "HostName=my-hub;DeviceId=my-device;GatewayHostName= ;SharedAccessKey={SHARED_ACCESS_KEY_VALUE}"
+        ))
+        .contains("empty value for GatewayHostName"));
+    }
+
+    #[test]
+    fn part_without_value() {
+        assert!(error(&format!(
+            "HostName=my-hub;DeviceId;SharedAccessKey={SHARED_ACCESS_KEY_VALUE}"
+        ))
+        .contains("invalid part DeviceId"));
+    }
+
+    #[test]
+    fn empty_parts_are_ignored() {
+        assert!(parse(&format!(
+            ";HostName=my-hub;;DeviceId=my-device; ;SharedAccessKey={SHARED_ACCESS_KEY_VALUE};"
+        ))
+        .is_ok());
+    }
+}
@@ -0,0 +1,74 @@
+use crate::client::TraceId;
+use anyhow::Result;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    ffi::{CStr, CString},
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Default)]
+struct Sent {
+    // trace id per message id, `None` while the message is being sent
+    trace_ids: HashMap<CString, Option<TraceId>>,
+    // message ids in the order they were reserved
+    order: VecDeque<(Instant, CString)>,
+}
+
+/// message ids of D2C messages sent within a sliding window
+#[derive(Debug)]
+pub(crate) struct DedupWindow {
+    window: Duration,
+    sent: RefCell<Sent>,
+}
+
+impl DedupWindow {
+    pub(crate) fn new(window: Duration) -> Self {
+        DedupWindow {
+            window,
+            sent: RefCell::new(Sent::default()),
+        }
+    }
+
+    /// reserves `message_id` for a send. returns the trace id of the message already sent with the same id
+    /// within the window, if any. fails if it is still being sent.
+    pub(crate) fn reserve(&self, message_id: &CStr) -> Result<Option<TraceId>> {
+        let mut sent = self.sent.borrow_mut();
+        let now = Instant::now();
+
+        while let Some((reserved, _)) = sent.order.front() {
+            if now.duration_since(*reserved) < self.window {
+                break;
+            }
+
+            if let Some((_, id)) = sent.order.pop_front() {
+                sent.trace_ids.remove(&id);
+            }
+        }
+
+        match sent.trace_ids.get(message_id) {
+            Some(Some(trace_id)) => Ok(Some(*trace_id)),
+            Some(None) => anyhow::bail!("message id {message_id:?} is already being sent"),
+            None => {
+                sent.trace_ids.insert(message_id.to_owned(), None);
+                sent.order.push_back((now, message_id.to_owned()));
+                Ok(None)
+            }
+        }
+    }
+
+    /// records the trace id of the message sent with the reserved `message_id`
+    pub(crate) fn sent(&self, message_id: &CStr, trace_id: TraceId) {
+        if let Some(entry) = self.sent.borrow_mut().trace_ids.get_mut(message_id) {
+            *entry = Some(trace_id);
+        }
+    }
+
+    /// releases the reserved `message_id` of a message that couldn't be sent, so that it can be sent again
+    pub(crate) fn release(&self, message_id: &CStr) {
+        let mut sent = self.sent.borrow_mut();
+
+        sent.trace_ids.remove(message_id);
+        sent.order.retain(|(_, id)| id.as_c_str() != message_id);
+    }
+}
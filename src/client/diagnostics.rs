@@ -0,0 +1,426 @@
+use crate::client::{
+    trace_id::Trace, AuthenticationStatus, ConfirmationOutcome, DeliveryMetrics, DispositionResult,
+    ErrorObserver, IncomingMessageMetrics, PendingConfirmations, TraceId,
+};
+use log::debug;
+use serde_json::json;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::oneshot;
+
+static CONNECTION_HISTORY_CAPACITY: usize = 32;
+static LAST_ERRORS_CAPACITY: usize = 16;
+static AUDIT_RECORDS_CAPACITY: usize = 256;
+static QUALITY_WINDOW: usize = 32;
+static QUALITY_MIN_SAMPLES: usize = 8;
+static SECRET_KEYS: [&str; 3] = ["SharedAccessKey=", "SharedAccessSignature=", "sig="];
+
+/// Incoming command recorded in an [`AuditRecord`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum InboundCommand {
+    /// direct method
+    DirectMethod {
+        /// method name
+        name: String,
+        /// result code returned to iothub
+        result_code: i32,
+    },
+    /// cloud to device (C2D) message
+    IncomingMessage {
+        /// message id
+        message_id: Option<String>,
+        /// correlation id
+        correlation_id: Option<String>,
+        /// [`DispositionResult`] returned to iothub
+        disposition: DispositionResult,
+    },
+}
+
+/// Audit record of an incoming direct method or C2D message
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AuditRecord {
+    /// time the command was received
+    pub received: SystemTime,
+    /// [`InboundCommand`]
+    pub command: InboundCommand,
+    /// time until the command was handled
+    pub latency: Duration,
+}
+
+/// Non-fatal error signaled to the observer registered by [`crate::client::IotHubClientBuilder::observe_errors`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ErrorEvent {
+    /// incoming desired properties, direct method or C2D message cannot be parsed
+    ParseFailure(String),
+    /// confirmation of a D2C message or reported properties failed
+    ConfirmationFailed {
+        /// trace id of the D2C message or reported properties
+        trace_id: TraceId,
+    },
+    /// confirmation of a D2C message or reported properties wasn't received in time
+    ConfirmationTimedOut {
+        /// trace id of the D2C message or reported properties
+        trace_id: TraceId,
+    },
+    /// an option cannot be applied to the underlying handle, e.g. on reconnect
+    SetOptionFailure(String),
+    /// a message cannot be sent, e.g. to a secondary hub
+    SendFailure(String),
+    /// credentials cannot be renewed by identity service
+    CredentialRenewalFailure(String),
+    /// the underlying handle cannot be recreated by the connection watchdog
+    RecoveryFailure(String),
+    /// connection status or desired properties rejected since the observer channel is full,
+    /// see [`crate::client::IotHubClientBuilder::bounded_latency`]
+    ObserverOverflow(String),
+    /// connection status or desired properties dropped since the observer channel is closed
+    ObserverClosed(String),
+}
+
+impl std::fmt::Display for ErrorEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorEvent::ParseFailure(e) => write!(f, "parse failure: {e}"),
+            ErrorEvent::ConfirmationFailed { trace_id } => {
+                write!(f, "confirmation({trace_id}): failed")
+            }
+            ErrorEvent::ConfirmationTimedOut { trace_id } => {
+                write!(f, "confirmation({trace_id}): timed out")
+            }
+            ErrorEvent::SetOptionFailure(e) => write!(f, "set option failed: {e}"),
+            ErrorEvent::SendFailure(e) => write!(f, "send failed: {e}"),
+            ErrorEvent::CredentialRenewalFailure(e) => write!(f, "credential renewal failed: {e}"),
+            ErrorEvent::RecoveryFailure(e) => write!(f, "recovery failed: {e}"),
+            ErrorEvent::ObserverOverflow(e) => write!(f, "observer overflow: {e}"),
+            ErrorEvent::ObserverClosed(e) => write!(f, "observer closed: {e}"),
+        }
+    }
+}
+
+/// runtime information collected while the client is alive, e.g. to be shipped with a support bundle
+#[derive(Debug, Default)]
+pub(crate) struct Diagnostics {
+    connection_history: VecDeque<(u64, AuthenticationStatus)>,
+    // status of the current connection, reset if the underlying handle is destroyed
+    connection_status: Option<AuthenticationStatus>,
+    last_errors: VecDeque<(u64, String)>,
+    audit_records: VecDeque<AuditRecord>,
+    // send time of pending confirmations and whether they belong to a D2C message
+    pending_confirmations: HashMap<Trace, (Instant, bool)>,
+    // output queues of pending D2C confirmations, only tracked if outputs are declared
+    pending_outputs: HashMap<Trace, String>,
+    // senders of confirmation outcomes awaited by send_d2c_message_confirmed
+    confirmation_waiters: HashMap<Trace, oneshot::Sender<ConfirmationOutcome>>,
+    // outcomes of the last confirmations, true if succeeded
+    confirmation_outcomes: VecDeque<bool>,
+    // totals of (succeeded, not succeeded) outcomes
+    outcome_totals: (u64, u64),
+    pub(crate) error_observer: Option<ErrorObserver>,
+    pub(crate) d2c_metrics: DeliveryMetrics,
+    pub(crate) output_metrics: HashMap<String, DeliveryMetrics>,
+    pub(crate) c2d_metrics: IncomingMessageMetrics,
+    pub(crate) reported_properties_sent: u64,
+    pub(crate) confirmations_succeeded: u64,
+    pub(crate) confirmations_failed: u64,
+    pub(crate) confirmations_timed_out: u64,
+    // dead letters dropped since the observer channel was full or closed
+    pub(crate) dead_letters_dropped: u64,
+}
+
+impl Diagnostics {
+    /// diagnostics with all histories pre-allocated and room for `pending_confirmations`
+    pub(crate) fn preallocated(pending_confirmations: usize) -> Self {
+        Diagnostics {
+            connection_history: VecDeque::with_capacity(CONNECTION_HISTORY_CAPACITY),
+            last_errors: VecDeque::with_capacity(LAST_ERRORS_CAPACITY),
+            audit_records: VecDeque::with_capacity(AUDIT_RECORDS_CAPACITY),
+            pending_confirmations: HashMap::with_capacity(pending_confirmations),
+            confirmation_outcomes: VecDeque::with_capacity(QUALITY_WINDOW),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn add_connection_status(&mut self, status: AuthenticationStatus) {
+        if self.connection_history.len() == CONNECTION_HISTORY_CAPACITY {
+            self.connection_history.pop_front();
+        }
+
+        self.connection_history.push_back((now_secs(), status));
+        self.connection_status = Some(status);
+    }
+
+    pub(crate) fn last_connection_status(&self) -> Option<AuthenticationStatus> {
+        self.connection_status
+    }
+
+    pub(crate) fn reset_connection_status(&mut self) {
+        self.connection_status = None;
+    }
+
+    /// records `event` as last error and signals it to the error observer, if any
+    pub(crate) fn report(&mut self, event: ErrorEvent) {
+        self.add_error(event.to_string());
+
+        if let Some(observer) = &self.error_observer {
+            if let Err(e) = observer.try_send(event) {
+                debug!("cannot signal error event: {e}");
+            }
+        }
+    }
+
+    fn add_error(&mut self, error: impl Into<String>) {
+        if self.last_errors.len() == LAST_ERRORS_CAPACITY {
+            self.last_errors.pop_front();
+        }
+
+        self.last_errors
+            .push_back((now_secs(), redact(&error.into())));
+    }
+
+    pub(crate) fn add_audit_record(&mut self, record: AuditRecord) {
+        if self.audit_records.len() == AUDIT_RECORDS_CAPACITY {
+            self.audit_records.pop_front();
+        }
+
+        self.audit_records.push_back(record);
+    }
+
+    pub(crate) fn add_pending_confirmation(&mut self, trace: Trace, d2c: bool) {
+        self.pending_confirmations
+            .insert(trace, (Instant::now(), d2c));
+    }
+
+    /// tracks the `output` of the D2C message `trace`, which must be added before its confirmation
+    pub(crate) fn add_pending_output(&mut self, trace: Trace, output: String) {
+        self.output_metrics.entry(output.clone()).or_default().sent += 1;
+        self.pending_outputs.insert(trace, output);
+    }
+
+    pub(crate) fn is_pending_confirmation(&self, trace: Trace) -> bool {
+        self.pending_confirmations.contains_key(&trace)
+    }
+
+    pub(crate) fn remove_pending_confirmation(&mut self, trace: Trace) {
+        self.pending_confirmations.remove(&trace);
+        self.pending_outputs.remove(&trace);
+    }
+
+    /// removes the pending confirmation `trace` and records its `outcome`
+    pub(crate) fn conclude_pending_confirmation(
+        &mut self,
+        trace: Trace,
+        outcome: ConfirmationOutcome,
+    ) {
+        if let Some((sent, true)) = self.pending_confirmations.remove(&trace) {
+            let latency = sent.elapsed();
+
+            self.d2c_metrics.add_outcome(outcome, latency);
+
+            if let Some(metrics) = self
+                .pending_outputs
+                .remove(&trace)
+                .and_then(|output| self.output_metrics.get_mut(&output))
+            {
+                metrics.add_outcome(outcome, latency);
+            }
+        }
+
+        self.add_confirmation_outcome(outcome);
+    }
+
+    /// removes the pending confirmation `trace` and its waiter without outcome. returns false if it isn't
+    /// pending.
+    pub(crate) fn cancel_pending_confirmation(&mut self, trace: Trace) -> bool {
+        self.confirmation_waiters.remove(&trace);
+
+        match self.pending_confirmations.remove(&trace) {
+            Some((_, d2c)) => {
+                if d2c {
+                    self.d2c_metrics.cancelled += 1;
+                }
+
+                if let Some(metrics) = self
+                    .pending_outputs
+                    .remove(&trace)
+                    .and_then(|output| self.output_metrics.get_mut(&output))
+                {
+                    metrics.cancelled += 1;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn clear_pending_confirmations(&mut self) {
+        self.pending_confirmations.clear();
+        self.pending_outputs.clear();
+        self.confirmation_waiters.clear();
+    }
+
+    pub(crate) fn add_confirmation_waiter(
+        &mut self,
+        trace: Trace,
+        waiter: oneshot::Sender<ConfirmationOutcome>,
+    ) {
+        self.confirmation_waiters.insert(trace, waiter);
+    }
+
+    pub(crate) fn take_confirmation_waiter(
+        &mut self,
+        trace: Trace,
+    ) -> Option<oneshot::Sender<ConfirmationOutcome>> {
+        self.confirmation_waiters.remove(&trace)
+    }
+
+    fn add_confirmation_outcome(&mut self, outcome: ConfirmationOutcome) {
+        if self.confirmation_outcomes.len() == QUALITY_WINDOW {
+            self.confirmation_outcomes.pop_front();
+        }
+
+        self.confirmation_outcomes
+            .push_back(outcome == ConfirmationOutcome::Succeeded);
+
+        if outcome == ConfirmationOutcome::Succeeded {
+            self.outcome_totals.0 += 1;
+        } else {
+            self.outcome_totals.1 += 1;
+        }
+    }
+
+    /// totals of (succeeded, not succeeded) confirmation outcomes
+    pub(crate) fn outcome_totals(&self) -> (u64, u64) {
+        self.outcome_totals
+    }
+
+    /// share of succeeded confirmations of the last outcomes, `None` if there are too few samples
+    pub(crate) fn connection_quality(&self) -> Option<f64> {
+        let samples = self.confirmation_outcomes.len();
+
+        (samples >= QUALITY_MIN_SAMPLES).then(|| {
+            self.confirmation_outcomes.iter().filter(|s| **s).count() as f64 / samples as f64
+        })
+    }
+
+    /// (succeeded, samples) of the last outcomes
+    pub(crate) fn confirmation_samples(&self) -> (usize, usize) {
+        (
+            self.confirmation_outcomes.iter().filter(|s| **s).count(),
+            self.confirmation_outcomes.len(),
+        )
+    }
+
+    pub(crate) fn reset_connection_quality(&mut self) {
+        self.confirmation_outcomes.clear();
+    }
+
+    pub(crate) fn pending_confirmations(&self) -> PendingConfirmations {
+        PendingConfirmations {
+            count: self.pending_confirmations.len(),
+            oldest_age: self
+                .pending_confirmations
+                .values()
+                .map(|(sent, _)| sent)
+                .min()
+                .map(|sent| sent.elapsed()),
+        }
+    }
+
+    pub(crate) fn audit_records(&self) -> Vec<AuditRecord> {
+        self.audit_records.iter().cloned().collect()
+    }
+
+    pub(crate) fn audit_trail_json(&self) -> serde_json::Value {
+        self.audit_records
+            .iter()
+            .map(|record| {
+                let received = record
+                    .received
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default();
+                let latency_ms = record.latency.as_millis() as u64;
+
+                match &record.command {
+                    InboundCommand::DirectMethod { name, result_code } => json!({
+                        "timestamp": received,
+                        "direct_method": name,
+                        "result_code": result_code,
+                        "latency_ms": latency_ms,
+                    }),
+                    InboundCommand::IncomingMessage {
+                        message_id,
+                        correlation_id,
+                        disposition,
+                    } => json!({
+                        "timestamp": received,
+                        "message_id": message_id,
+                        "correlation_id": correlation_id,
+                        "disposition": format!("{disposition:?}"),
+                        "latency_ms": latency_ms,
+                    }),
+                }
+            })
+            .collect()
+    }
+
+    pub(crate) fn connection_history_json(&self) -> serde_json::Value {
+        self.connection_history
+            .iter()
+            .map(|(timestamp, status)| json!({"timestamp": timestamp, "status": format!("{status:?}")}))
+            .collect()
+    }
+
+    pub(crate) fn last_errors_json(&self) -> serde_json::Value {
+        self.last_errors
+            .iter()
+            .map(|(timestamp, error)| json!({"timestamp": timestamp, "error": error}))
+            .collect()
+    }
+
+    pub(crate) fn stats_json(&self) -> serde_json::Value {
+        json!({
+            "d2c_messages_sent": self.d2c_metrics.sent,
+            "reported_properties_sent": self.reported_properties_sent,
+            "confirmations_succeeded": self.confirmations_succeeded,
+            "confirmations_failed": self.confirmations_failed,
+            "confirmations_timed_out": self.confirmations_timed_out,
+            "c2d_messages_accepted": self.c2d_metrics.accepted,
+            "c2d_messages_rejected": self.c2d_metrics.rejected,
+            "c2d_messages_abandoned": self.c2d_metrics.abandoned,
+            "c2d_messages_async_acked": self.c2d_metrics.async_acked,
+            "dead_letters_dropped": self.dead_letters_dropped,
+        })
+    }
+}
+
+/// seconds since unix epoch
+pub(crate) fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// masks all values of well known secret keys, e.g. in connection strings or SAS tokens
+pub(crate) fn redact(input: &str) -> String {
+    let mut output = input.to_string();
+
+    for key in SECRET_KEYS {
+        let mut from = 0;
+
+        while let Some(pos) = output[from..].find(key) {
+            let start = from + pos + key.len();
+            let end = output[start..]
+                .find(|c: char| c == ';' || c == '&' || c.is_whitespace())
+                .map_or(output.len(), |len| start + len);
+
+            output.replace_range(start..end, "<redacted>");
+            from = start + "<redacted>".len();
+        }
+    }
+
+    output
+}
@@ -0,0 +1,314 @@
+use crate::client::{clock, message::urlencode};
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::{
+    env,
+    time::{Duration, SystemTime},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+    time::timeout,
+};
+
+static IOTEDGE_WORKLOADURI: &str = "IOTEDGE_WORKLOADURI";
+static IOTEDGE_MODULEID: &str = "IOTEDGE_MODULEID";
+static IOTEDGE_MODULEGENERATIONID: &str = "IOTEDGE_MODULEGENERATIONID";
+static IOTEDGE_APIVERSION: &str = "IOTEDGE_APIVERSION";
+static WORKLOAD_API_VERSION_DEFAULT: &str = "2019-01-30";
+static WORKLOAD_API_TIMEOUT_IN_SECS: u64 = 30;
+static READ_BUFFER_SIZE: usize = 4096;
+static BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Server certificate issued by the IoT Edge workload API and signed by the edge CA
+#[derive(Clone)]
+pub struct ServerCertificate {
+    /// certificate chain in PEM format
+    pub certificate: String,
+    /// private key in PEM format
+    pub private_key: String,
+    /// expiration as RFC 3339 timestamp
+    pub expiration: String,
+}
+
+impl std::fmt::Debug for ServerCertificate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServerCertificate")
+            .field("certificate", &self.certificate)
+            .field("expiration", &self.expiration)
+            .finish_non_exhaustive()
+    }
+}
+
+pub(crate) async fn server_certificate(
+    common_name: &str,
+    expiration: SystemTime,
+) -> Result<ServerCertificate> {
+    let module_id = env::var(IOTEDGE_MODULEID).context(IOTEDGE_MODULEID)?;
+    let generation_id = env::var(IOTEDGE_MODULEGENERATIONID).context(IOTEDGE_MODULEGENERATIONID)?;
+
+    let response = request(
+        "POST",
+        &format!(
+            "/modules/{}/genid/{}/certificate/server",
+            urlencode(module_id),
+            urlencode(generation_id)
+        ),
+        Some(json!({
+            "commonName": common_name,
+            "expiration": clock::rfc3339(expiration),
+        })),
+    )
+    .await?;
+
+    let field = |value: Option<&Value>, name: &str| {
+        value
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .with_context(|| format!("workload api response misses {name}"))
+    };
+
+    Ok(ServerCertificate {
+        certificate: field(response.get("certificate"), "certificate")?,
+        private_key: field(
+            response.get("privateKey").and_then(|key| key.get("bytes")),
+            "privateKey",
+        )?,
+        expiration: field(response.get("expiration"), "expiration")?,
+    })
+}
+
+/// signs `data` by HMAC-SHA256 with the identity key `key_id` of the module, e.g. "primary"
+pub(crate) async fn sign(key_id: &str, data: &[u8]) -> Result<Vec<u8>> {
+    let module_id = env::var(IOTEDGE_MODULEID).context(IOTEDGE_MODULEID)?;
+    let generation_id = env::var(IOTEDGE_MODULEGENERATIONID).context(IOTEDGE_MODULEGENERATIONID)?;
+
+    let response = request(
+        "POST",
+        &format!(
+            "/modules/{}/genid/{}/sign",
+            urlencode(module_id),
+            urlencode(generation_id)
+        ),
+        Some(json!({
+            "keyId": key_id,
+            "algo": "HMACSHA256",
+            "data": base64_encode(data),
+        })),
+    )
+    .await?;
+
+    base64_decode(
+        response
+            .get("digest")
+            .and_then(Value::as_str)
+            .context("workload api response misses digest")?,
+    )
+}
+
+pub(crate) async fn trust_bundle() -> Result<String> {
+    request("GET", "/trust-bundle", None)
+        .await?
+        .get("certificate")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .context("workload api response misses certificate")
+}
+
+/// sends a http request to the workload API via its unix socket
+async fn request(method: &str, path: &str, body: Option<Value>) -> Result<Value> {
+    let uri = env::var(IOTEDGE_WORKLOADURI).context(IOTEDGE_WORKLOADURI)?;
+    let socket = uri
+        .strip_prefix("unix://")
+        .with_context(|| format!("unsupported workload uri {uri}"))?;
+    let api_version =
+        env::var(IOTEDGE_APIVERSION).unwrap_or(WORKLOAD_API_VERSION_DEFAULT.to_string());
+    let body = body.map(|body| body.to_string()).unwrap_or_default();
+    let request = format!(
+        "{method} {path}?api-version={api_version} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+        body.len()
+    );
+
+    // a stuck edge runtime must not block the caller forever
+    let response = timeout(Duration::from_secs(WORKLOAD_API_TIMEOUT_IN_SECS), async {
+        let mut stream = UnixStream::connect(socket)
+            .await
+            .with_context(|| format!("cannot connect to workload api {socket}"))?;
+        stream.write_all(request.as_bytes()).await?;
+
+        read_response(&mut stream).await
+    })
+    .await
+    .with_context(|| format!("workload api {socket} timed out"))??;
+
+    parse_response(&response)
+}
+
+/// head of a http response
+struct Head {
+    status: u16,
+    content_length: Option<usize>,
+    chunked: bool,
+}
+
+/// splits `response` into its head and the body received so far, `None` if the head is incomplete
+fn split_head(response: &[u8]) -> Result<Option<(Head, &[u8])>> {
+    let Some(end) = response.windows(4).position(|w| w == b"\r\n\r\n") else {
+        return Ok(None);
+    };
+    let head = std::str::from_utf8(&response[..end])?;
+    let mut lines = head.lines();
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|status| status.parse().ok())
+        .context("invalid workload api status line")?;
+    let mut content_length = None;
+    let mut chunked = false;
+
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => {
+                content_length = Some(value.parse().context("invalid content-length")?)
+            }
+            "transfer-encoding" => chunked = value.to_ascii_lowercase().contains("chunked"),
+            _ => {}
+        }
+    }
+
+    Ok(Some((
+        Head {
+            status,
+            content_length,
+            chunked,
+        },
+        &response[end + 4..],
+    )))
+}
+
+/// reads until the body is complete according to its content-length or chunked encoding, otherwise until the
+/// connection is closed
+async fn read_response(stream: &mut UnixStream) -> Result<Vec<u8>> {
+    let mut response = vec![];
+    let mut buf = vec![0; READ_BUFFER_SIZE];
+
+    loop {
+        if let Some((head, body)) = split_head(&response)? {
+            let complete = match (head.chunked, head.content_length) {
+                (true, _) => dechunk(body)?.is_some(),
+                (false, Some(content_length)) => body.len() >= content_length,
+                (false, None) => false,
+            };
+
+            if complete {
+                return Ok(response);
+            }
+        }
+
+        let read = stream.read(&mut buf).await?;
+
+        if read == 0 {
+            return Ok(response);
+        }
+
+        response.extend_from_slice(&buf[..read]);
+    }
+}
+
+fn parse_response(response: &[u8]) -> Result<Value> {
+    let (head, body) = split_head(response)?.context("invalid workload api response")?;
+    let body = match (head.chunked, head.content_length) {
+        (true, _) => dechunk(body)?.context("incomplete chunked workload api response")?,
+        (false, Some(content_length)) => body
+            .get(..content_length)
+            .context("incomplete workload api response")?
+            .to_vec(),
+        (false, None) => body.to_vec(),
+    };
+    let body = String::from_utf8(body)?;
+    let status = head.status;
+
+    if !(200..300).contains(&status) {
+        anyhow::bail!("workload api responded with status {status}: {body}");
+    }
+
+    Ok(serde_json::from_str(&body)?)
+}
+
+/// decodes a chunked `body`, `None` if the last chunk wasn't received yet
+fn dechunk(mut body: &[u8]) -> Result<Option<Vec<u8>>> {
+    let mut decoded = vec![];
+
+    loop {
+        let Some(line_end) = body.windows(2).position(|w| w == b"\r\n") else {
+            return Ok(None);
+        };
+        let size = std::str::from_utf8(&body[..line_end])?;
+        let size = usize::from_str_radix(size.split(';').next().unwrap_or_default().trim(), 16)
+            .context("invalid chunk size")?;
+        let rest = &body[line_end + 2..];
+
+        if size == 0 {
+            return Ok(Some(decoded));
+        }
+
+        let (Some(chunk), Some(next)) = (rest.get(..size), rest.get(size + 2..)) else {
+            return Ok(None);
+        };
+
+        decoded.extend_from_slice(chunk);
+        body = next;
+    }
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[((bits >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+fn base64_decode(encoded: &str) -> Result<Vec<u8>> {
+    let mut decoded = vec![];
+    let mut bits = 0u32;
+    let mut count = 0;
+
+    for c in encoded.bytes().filter(|c| *c != b'=') {
+        let value = BASE64_ALPHABET
+            .iter()
+            .position(|a| *a == c)
+            .context("invalid base64")? as u32;
+
+        bits = (bits << 6) | value;
+        count += 6;
+
+        if count >= 8 {
+            count -= 8;
+            decoded.push((bits >> count) as u8);
+        }
+    }
+
+    Ok(decoded)
+}
@@ -0,0 +1,101 @@
+use crate::client::{event_hubs, message::urlencode, IotMessage};
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use anyhow::Result;
+use azure_iot_sdk_sys::*;
+use std::{ffi::CString, sync::Arc};
+
+static ALGORITHM_PROPERTY: &str = "enc-alg";
+static KEY_ID_PROPERTY: &str = "enc-kid";
+static ALGORITHM: &str = "AES256GCM";
+static NONCE_SIZE: usize = 12;
+
+/// Provides the keys used for end-to-end payload encryption, e.g. from a HSM, a TPM or a key vault.
+/// See [`crate::client::IotHubClientBuilder::encrypt_payloads`].
+///
+/// Encrypted messages carry the properties `enc-alg` with value `AES256GCM` and `enc-kid` with the key id.
+/// The body is a 12 byte random nonce followed by the AES-256-GCM ciphertext including the 16 byte tag.
+/// The key id is used as additional authenticated data. Backends must produce C2D messages in the same format.
+pub trait KeyProvider: Send + Sync {
+    /// id and 256 bit key of the key used to encrypt outgoing messages
+    fn current_key(&self) -> Result<(String, [u8; 32])>;
+    /// key with id `key_id` used to decrypt incoming messages
+    fn key(&self, key_id: &str) -> Result<[u8; 32]>;
+}
+
+/// encrypts the body of an outgoing message by the current key
+pub(crate) fn encrypt(provider: &dyn KeyProvider, message: &mut IotMessage) -> Result<()> {
+    let (key_id, key) = provider.current_key()?;
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: &message.body,
+                aad: key_id.as_bytes(),
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("cannot encrypt message with key {key_id}"))?;
+
+    message.body = [nonce.as_slice(), &ciphertext].concat().into();
+    message
+        .properties
+        .insert(CString::new(ALGORITHM_PROPERTY)?, CString::new(ALGORITHM)?);
+    message.properties.insert(
+        CString::new(KEY_ID_PROPERTY)?,
+        CString::new(urlencode(key_id))?,
+    );
+
+    Ok(())
+}
+
+/// decrypts the body of `message` received by `handle`. unencrypted messages are rejected.
+pub(crate) fn decrypt_incoming(
+    provider: &dyn KeyProvider,
+    handle: IOTHUB_MESSAGE_HANDLE,
+    message: &mut IotMessage,
+) -> Result<()> {
+    let algorithm = IotMessage::incoming_property(handle, &CString::new(ALGORITHM_PROPERTY)?);
+
+    if algorithm.as_deref() != Some(ALGORITHM) {
+        anyhow::bail!("message not encrypted by {ALGORITHM}: {algorithm:?}");
+    }
+
+    let Some(key_id) = IotMessage::incoming_property(handle, &CString::new(KEY_ID_PROPERTY)?)
+    else {
+        anyhow::bail!("encrypted message without key id");
+    };
+    let key_id = event_hubs::decode_property(&key_id);
+
+    if message.body.len() < NONCE_SIZE {
+        anyhow::bail!("encrypted message too short");
+    }
+
+    let (nonce, ciphertext) = message.body.split_at(NONCE_SIZE);
+    let key = provider.key(&key_id)?;
+
+    message.body = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: key_id.as_bytes(),
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("cannot decrypt message with key {key_id}"))?
+        .into();
+
+    Ok(())
+}
+
+/// key provider shared by the client and its callback contexts
+#[derive(Clone)]
+pub(crate) struct Encryption(pub(crate) Arc<dyn KeyProvider>);
+
+impl std::fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Encryption")
+    }
+}
@@ -0,0 +1,162 @@
+//! Interoperability with consumers of the Event Hubs-compatible endpoint of iothub.
+//!
+//! iothub forwards D2C messages to Event Hubs consumers (e.g. Azure Stream Analytics, Azure Functions or
+//! any AMQP Event Hubs client) as follows:
+//! - application properties, set by [`IotMessageBuilder::set_property`], are url-decoded by iothub and
+//!   show up as plain strings in the application properties of the event.
+//! - message id, correlation id, content type and content encoding show up as system properties named
+//!   `message-id`, `correlation-id`, `content-type` and `content-encoding`.
+//! - the body is passed through as is. Only with content type `application/json` and content encoding
+//!   `utf-8` it is decoded by iothub message routing and can be queried.
+//!
+//! The helpers of this module map an [`IotMessage`] to exactly what such a consumer receives.
+//! ```rust
+//! use azure_iot_sdk::client::{event_hubs, IotMessage};
+//! use serde_json::json;
+//!
+//! let msg = event_hubs::json_message(&json!({"temperature": 21.5}))
+//!     .unwrap()
+//!     .set_property("room name", "kitchen & hall")
+//!     .build()
+//!     .unwrap();
+//!
+//! let application_properties = event_hubs::application_properties(&msg).unwrap();
+//! assert_eq!(application_properties["room name"], "kitchen & hall");
+//!
+//! let system_properties = event_hubs::system_properties(&msg).unwrap();
+//! assert_eq!(system_properties["content-type"], event_hubs::CONTENT_TYPE_JSON);
+//! assert_eq!(system_properties["content-encoding"], event_hubs::CONTENT_ENCODING_UTF8);
+//! ```
+use super::message::{IotMessage, IotMessageBuilder};
+use anyhow::{Context, Result};
+use std::{collections::HashMap, ffi::CStr};
+
+/// content type that makes the message body decodable for iothub message routing and Event Hubs consumers
+pub static CONTENT_TYPE_JSON: &str = "application/json";
+/// content encoding that makes the message body decodable for iothub message routing and Event Hubs consumers
+pub static CONTENT_ENCODING_UTF8: &str = "utf-8";
+
+// iothub wire ids of system properties and their names on the Event Hubs-compatible endpoint
+static SYSTEM_PROPERTIES: [(&str, &str); 4] = [
+    ("$.mid", "message-id"),
+    ("$.cid", "correlation-id"),
+    ("$.ct", "content-type"),
+    ("$.ce", "content-encoding"),
+];
+
+/// Get a message builder with `body` serialized as JSON and content type and content encoding set,
+/// so that the body can be queried by iothub message routing and decoded by Event Hubs consumers.
+/// ```rust
+/// use azure_iot_sdk::client::event_hubs;
+/// use serde_json::json;
+///
+/// let msg = event_hubs::json_message(&json!({"temperature": 21.5}))
+///     .unwrap()
+///     .set_output_queue("telemetry")
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(&msg.body[..], br#"{"temperature":21.5}"#);
+/// ```
+pub fn json_message(body: &serde_json::Value) -> Result<IotMessageBuilder> {
+    Ok(IotMessage::builder()
+        .set_body(serde_json::to_vec(body).context("json_message: cannot serialize body")?)
+        .set_content_type(CONTENT_TYPE_JSON)
+        .set_content_encoding(CONTENT_ENCODING_UTF8))
+}
+
+/// Get the application properties of `message` as received by an Event Hubs consumer.
+/// ```rust
+/// use azure_iot_sdk::client::{event_hubs, IotMessage};
+///
+/// let msg = IotMessage::builder()
+///     .set_body(vec![])
+///     .set_property("unit", "°C")
+///     .set_property("a+b", "50% off")
+///     .build()
+///     .unwrap();
+///
+/// let properties = event_hubs::application_properties(&msg).unwrap();
+/// assert_eq!(properties["unit"], "°C");
+/// assert_eq!(properties["a+b"], "50% off");
+/// ```
+pub fn application_properties(message: &IotMessage) -> Result<HashMap<String, String>> {
+    message
+        .properties
+        .iter()
+        .map(|(key, value)| Ok((decode(key)?, decode(value)?)))
+        .collect()
+}
+
+/// Get the system properties of `message` as received by an Event Hubs consumer, keyed by their
+/// names on the Event Hubs-compatible endpoint, e.g. `content-type`.
+/// ```rust
+/// use azure_iot_sdk::client::{event_hubs, IotMessage};
+///
+/// let msg = IotMessage::builder()
+///     .set_body(vec![])
+///     .set_id("my msg id")
+///     .set_content_type("text/plain")
+///     .build()
+///     .unwrap();
+///
+/// let properties = event_hubs::system_properties(&msg).unwrap();
+/// assert_eq!(properties["message-id"], "my msg id");
+/// assert_eq!(properties["content-type"], "text/plain");
+/// assert!(!properties.contains_key("correlation-id"));
+/// ```
+pub fn system_properties(message: &IotMessage) -> Result<HashMap<&'static str, String>> {
+    SYSTEM_PROPERTIES
+        .iter()
+        .filter_map(|(wire_id, name)| {
+            message
+                .system_properties
+                .iter()
+                .find(|(key, _)| key.to_bytes() == wire_id.as_bytes())
+                .map(|(_, value)| Ok((*name, decode(value)?)))
+        })
+        .collect()
+}
+
+/// Decode a property key or value as encoded on the wire, e.g. one of [`IotMessage::properties`].
+/// ```rust
+/// use azure_iot_sdk::client::event_hubs;
+///
+/// assert_eq!(event_hubs::decode_property("kitchen%20%26%20hall"), "kitchen & hall");
+/// ```
+pub fn decode_property(value: &str) -> String {
+    let mut bytes = value.bytes();
+    let mut decoded = vec![];
+
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let hex = bytes.clone().take(2).collect::<Vec<u8>>();
+
+                match std::str::from_utf8(&hex)
+                    .ok()
+                    .filter(|hex| hex.len() == 2)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        bytes.nth(1);
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn decode(value: &CStr) -> Result<String> {
+    Ok(decode_property(
+        value
+            .to_str()
+            .context("event_hubs: property is not valid utf-8")?,
+    ))
+}
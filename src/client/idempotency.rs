@@ -0,0 +1,128 @@
+use anyhow::{Context, Result};
+use log::debug;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// name of the message property carrying the idempotency key of a D2C message
+pub static IDEMPOTENCY_KEY_PROPERTY: &str = "idempotency-key";
+static RESERVED_BLOCK_SIZE: u64 = 1000;
+
+#[derive(Debug)]
+struct Sequence {
+    next: u64,
+    reserved_until: u64,
+}
+
+/// Generator of idempotency keys `<prefix>-<sequence>` assigned to D2C messages, so that backend consumers
+/// can deduplicate messages sent more than once by device-side retries. See
+/// [`crate::client::IotHubClientBuilder::idempotency_keys`].
+///
+/// Keys of a persistent generator are unique across restarts: the sequence is reserved in blocks whose end
+/// is stored in a file, so that a restarted generator continues after the last reserved block.
+/// Keys of a non-persistent generator are only unique as long as the process lives, thus the prefix
+/// should be unique per process start, e.g. contain a boot id.
+/// ```rust, no_run
+/// use azure_iot_sdk::client::*;
+///
+/// let keys = IdempotencyKeys::persistent("my-device", "/var/lib/my-service/idempotency").unwrap();
+///
+/// let msg = IotMessage::builder()
+///     .set_body(b"my telemetry".to_vec())
+///     .set_idempotency_key(keys.next_key().unwrap())
+///     .build()
+///     .unwrap();
+///
+/// assert!(msg.idempotency_key().unwrap().starts_with("my-device-"));
+/// ```
+#[derive(Debug)]
+pub struct IdempotencyKeys {
+    prefix: String,
+    path: Option<PathBuf>,
+    sequence: Mutex<Sequence>,
+}
+
+impl IdempotencyKeys {
+    /// Get a generator whose sequence starts at 0 on every process start
+    pub fn new(prefix: impl Into<String>) -> Self {
+        IdempotencyKeys {
+            prefix: prefix.into(),
+            path: None,
+            sequence: Mutex::new(Sequence {
+                next: 0,
+                reserved_until: u64::MAX,
+            }),
+        }
+    }
+
+    /// Get a generator whose sequence is persisted in `path`. The file is created if it doesn't exist.
+    pub fn persistent(prefix: impl Into<String>, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let next = match fs::read_to_string(&path) {
+            Ok(content) => content
+                .trim()
+                .parse::<u64>()
+                .with_context(|| format!("invalid idempotency sequence in {path:?}"))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => {
+                return Err(e).with_context(|| format!("cannot read idempotency sequence {path:?}"))
+            }
+        };
+
+        let keys = IdempotencyKeys {
+            prefix: prefix.into(),
+            path: Some(path),
+            sequence: Mutex::new(Sequence {
+                next,
+                reserved_until: next,
+            }),
+        };
+
+        keys.reserve(&mut keys.lock())?;
+
+        Ok(keys)
+    }
+
+    /// Get the next key
+    pub fn next_key(&self) -> Result<String> {
+        let mut sequence = self.lock();
+
+        if sequence.next == sequence.reserved_until {
+            self.reserve(&mut sequence)?;
+        }
+
+        let key = format!("{}-{}", self.prefix, sequence.next);
+
+        sequence.next += 1;
+
+        Ok(key)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Sequence> {
+        self.sequence
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// stores the end of the next block of the sequence before keys of the block are handed out
+    fn reserve(&self, sequence: &mut Sequence) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let reserved_until = sequence.next.saturating_add(RESERVED_BLOCK_SIZE);
+        let tmp = path.with_extension("tmp");
+
+        fs::write(&tmp, reserved_until.to_string())
+            .and_then(|_| fs::rename(&tmp, path))
+            .with_context(|| format!("cannot persist idempotency sequence {path:?}"))?;
+
+        debug!("reserved idempotency sequence until {reserved_until}");
+
+        sequence.reserved_until = reserved_until;
+
+        Ok(())
+    }
+}
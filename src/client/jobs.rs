@@ -0,0 +1,311 @@
+use crate::client::{
+    diagnostics::{self, Diagnostics},
+    latency::{self, LatencyProfile},
+    managed_config,
+    twin::SharedTwin,
+    ErrorEvent,
+};
+use anyhow::Result;
+use log::{debug, warn};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::sync::mpsc;
+
+static JOBS_SECTION: &str = "jobs";
+static MAX_FINISHED_JOBS: usize = 10;
+static REPORT_RETRY_INTERVAL_IN_SECS: u64 = 10;
+
+/// State of a [`Job`] as tracked by the client and reported in the "jobs" section of the reported properties
+#[derive(Clone, Debug, PartialEq)]
+pub enum JobState {
+    /// job is still running
+    Running {
+        /// progress in percent
+        progress: u8,
+        /// optional application specific details
+        detail: Option<Value>,
+    },
+    /// job finished with a result
+    Succeeded(Value),
+    /// job failed with an error
+    Failed(String),
+}
+
+impl JobState {
+    fn is_finished(&self) -> bool {
+        !matches!(self, JobState::Running { .. })
+    }
+
+    fn to_json(&self, name: &str) -> Value {
+        let updated = diagnostics::now_secs();
+
+        match self {
+            JobState::Running { progress, detail } => json!({
+                "name": name,
+                "state": "running",
+                "progress": progress,
+                "detail": detail,
+                "updated": updated,
+            }),
+            JobState::Succeeded(result) => json!({
+                "name": name,
+                "state": "succeeded",
+                "progress": 100,
+                "result": result,
+                "updated": updated,
+            }),
+            JobState::Failed(error) => json!({
+                "name": name,
+                "state": "failed",
+                "error": error,
+                "updated": updated,
+            }),
+        }
+    }
+}
+
+/// Long-running job started by a call of a method registered by
+/// [`crate::client::IotHubClientBuilder::long_running_methods`]. The method call is already answered with
+/// status 202 and `{"job_id": "<id>"}`, so that progress and the final result are reported by the job.
+/// A job that is dropped without result is reported as failed.
+#[derive(Debug)]
+pub struct Job {
+    /// job id returned to the caller
+    pub id: String,
+    /// method name
+    pub name: String,
+    /// method payload
+    pub payload: Value,
+    reporter: JobReporter,
+    finished: bool,
+}
+
+impl Job {
+    /// Report the progress of the job in percent with optional application specific details
+    pub fn progress(&self, progress: u8, detail: Option<Value>) -> Result<()> {
+        self.reporter.update(
+            &self.id,
+            &self.name,
+            JobState::Running {
+                progress: progress.min(100),
+                detail,
+            },
+        )
+    }
+
+    /// Report the successful result of the job
+    pub fn succeed(mut self, result: Value) -> Result<()> {
+        self.finished = true;
+        self.reporter
+            .update(&self.id, &self.name, JobState::Succeeded(result))
+    }
+
+    /// Report the failure of the job
+    pub fn fail(mut self, error: &str) -> Result<()> {
+        self.finished = true;
+        self.reporter
+            .update(&self.id, &self.name, JobState::Failed(error.to_string()))
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        if !self.finished {
+            warn!("job {} dropped without result", self.id);
+
+            let _ = self.reporter.update(
+                &self.id,
+                &self.name,
+                JobState::Failed("job dropped without result".to_string()),
+            );
+        }
+    }
+}
+
+/// Sender used to signal a new [`Job`] to the iothub client consumer
+pub type JobObserver = mpsc::Sender<Job>;
+
+type JobReport = (String, Value);
+pub(crate) type JobReportReceiver = mpsc::UnboundedReceiver<JobReport>;
+
+/// states of all running and the latest finished jobs
+#[derive(Debug, Default)]
+pub(crate) struct JobRegistry {
+    states: HashMap<String, JobState>,
+    finished: VecDeque<String>,
+}
+
+impl JobRegistry {
+    pub(crate) fn state(&self, id: &str) -> Option<JobState> {
+        self.states.get(id).cloned()
+    }
+}
+
+/// updates the registry and forwards the resulting reports to the reporting task
+#[derive(Clone, Debug)]
+struct JobReporter {
+    registry: Arc<Mutex<JobRegistry>>,
+    tx_report: mpsc::UnboundedSender<JobReport>,
+}
+
+impl JobReporter {
+    fn update(&self, id: &str, name: &str, state: JobState) -> Result<()> {
+        let mut reports = vec![(id.to_string(), state.to_json(name))];
+        let mut registry = self
+            .registry
+            .lock()
+            .map_err(|_| anyhow::anyhow!("job {id}: registry poisoned"))?;
+
+        if state.is_finished() {
+            registry.finished.push_back(id.to_string());
+        }
+
+        registry.states.insert(id.to_string(), state);
+
+        // evicted jobs are removed from the reported properties
+        while registry.finished.len() > MAX_FINISHED_JOBS {
+            if let Some(evicted) = registry.finished.pop_front() {
+                registry.states.remove(&evicted);
+                reports.push((evicted, Value::Null));
+            }
+        }
+
+        for report in reports {
+            self.tx_report
+                .send(report)
+                .map_err(|_| anyhow::anyhow!("job {id}: client dropped"))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// long-running methods of the direct method context. clones share the job ids, so that ids stay unique if a
+/// renewed handle uses a clone.
+#[derive(Clone)]
+pub(crate) struct JobContext {
+    methods: HashSet<String>,
+    observer: JobObserver,
+    reporter: JobReporter,
+    next_id: Arc<AtomicU32>,
+}
+
+impl JobContext {
+    pub(crate) fn new(
+        methods: &[String],
+        observer: JobObserver,
+        registry: Arc<Mutex<JobRegistry>>,
+    ) -> (Self, JobReportReceiver) {
+        let (tx_report, rx_report) = mpsc::unbounded_channel();
+
+        (
+            JobContext {
+                methods: methods.iter().cloned().collect(),
+                observer,
+                reporter: JobReporter {
+                    registry,
+                    tx_report,
+                },
+                next_id: Arc::new(AtomicU32::new(0)),
+            },
+            rx_report,
+        )
+    }
+
+    pub(crate) fn is_long_running(&self, method_name: &str) -> bool {
+        self.methods.contains(method_name)
+    }
+
+    /// starts a job for a call of `method_name` and returns its id. blocks until the job is handed over,
+    /// unless a latency profile is applied.
+    pub(crate) fn start(
+        &mut self,
+        method_name: &str,
+        payload: Value,
+        profile: Option<&LatencyProfile>,
+    ) -> Result<String> {
+        let next_id = self.next_id.fetch_add(1, Ordering::Relaxed).wrapping_add(1);
+        let id = format!("{method_name}-{}-{next_id}", diagnostics::now_secs());
+
+        self.reporter.update(
+            &id,
+            method_name,
+            JobState::Running {
+                progress: 0,
+                detail: None,
+            },
+        )?;
+
+        // a job that cannot be handed over is dropped and thereby reported as failed
+        latency::deliver(
+            &self.observer,
+            Job {
+                id: id.clone(),
+                name: method_name.to_string(),
+                payload,
+                reporter: self.reporter.clone(),
+                finished: false,
+            },
+            profile,
+        )
+        .map_err(|e| anyhow::anyhow!("job {id}: cannot hand over: {e}"))?;
+
+        debug!("started job {id}");
+
+        Ok(id)
+    }
+}
+
+/// reports job states received from `rx` in the "jobs" section. reports that cannot be sent, e.g. while
+/// disconnected, are merged with subsequent ones and retried.
+pub(crate) async fn run(
+    mut rx: JobReportReceiver,
+    twin: Arc<SharedTwin>,
+    diagnostics: Arc<Mutex<Diagnostics>>,
+    confirmation_timeout: Duration,
+) {
+    let mut unreported = Map::new();
+
+    loop {
+        if unreported.is_empty() {
+            let Some((id, report)) = rx.recv().await else {
+                return;
+            };
+            unreported.insert(id, report);
+        } else if let Ok(next) = tokio::time::timeout(
+            Duration::from_secs(REPORT_RETRY_INTERVAL_IN_SECS),
+            rx.recv(),
+        )
+        .await
+        {
+            let Some((id, report)) = next else {
+                return;
+            };
+            unreported.insert(id, report);
+        }
+
+        while let Ok((id, report)) = rx.try_recv() {
+            unreported.insert(id, report);
+        }
+
+        let reported = json!({ JOBS_SECTION: unreported });
+
+        match managed_config::report(&twin, reported, confirmation_timeout).await {
+            Ok(()) => unreported.clear(),
+            Err(e) => {
+                warn!("jobs: cannot report job states, retry: {e}");
+
+                if let Ok(mut diagnostics) = diagnostics.lock() {
+                    diagnostics.report(ErrorEvent::SendFailure(format!("job states: {e}")));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use crate::client::diagnostics::{Diagnostics, ErrorEvent};
+use log::warn;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+    time::{Duration, Instant},
+};
+use tokio::sync::{
+    mpsc::{self, error::TrySendError},
+    oneshot::{self, error::TryRecvError},
+};
+
+/// Profile bounding the worst-case latency the client adds to the azure-sdk-c worker thread, e.g. for
+/// real-time gateways. See [`crate::client::IotHubClientBuilder::bounded_latency`].
+///
+/// With a profile applied
+/// - incoming work is handed over to observers without blocking: if an observer channel is full the work is
+///   rejected immediately, i.e. direct methods as [`crate::client::DeadLetterReason::ChannelFull`] and
+///   connection status or desired properties as [`crate::client::ErrorEvent::ObserverOverflow`]. C2D messages
+///   are handled according to [`crate::client::OverflowPolicy`], but rejected instead of blocking,
+/// - results of C2D messages and direct methods are awaited for at most `result_timeout`, otherwise the
+///   C2D message is abandoned, the direct method fails and both are dead lettered as
+///   [`crate::client::DeadLetterReason::TimedOut`],
+/// - D2C messages and reported properties are rejected immediately if `max_pending_confirmations` is reached,
+/// - diagnostics and the suspend buffer are pre-allocated, so that their capacity never grows.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LatencyProfile {
+    /// maximum time a C2D message or direct method callback waits for the result of the consumer
+    pub result_timeout: Duration,
+    /// maximum number of D2C messages and reported properties waiting for confirmation
+    pub max_pending_confirmations: usize,
+}
+
+impl Default for LatencyProfile {
+    fn default() -> Self {
+        LatencyProfile {
+            result_timeout: Duration::from_millis(100),
+            max_pending_confirmations: 256,
+        }
+    }
+}
+
+/// hands `value` over to `tx`. blocks while the channel is full, unless a profile is applied.
+pub(crate) fn deliver<T>(
+    tx: &mpsc::Sender<T>,
+    value: T,
+    profile: Option<&LatencyProfile>,
+) -> Result<(), TrySendError<T>> {
+    match profile {
+        Some(_) => tx.try_send(value),
+        None => tx
+            .blocking_send(value)
+            .map_err(|e| TrySendError::Closed(e.0)),
+    }
+}
+
+/// hands `value` over to an observer like [`deliver`] without ever failing, since it's called from azure-sdk-c
+/// callbacks. `value` is dropped if the channel is full or closed, which is logged and reported as
+/// [`ErrorEvent::ObserverOverflow`] or [`ErrorEvent::ObserverClosed`] together with `describe(&value)`.
+/// returns true if `value` was delivered.
+pub(crate) fn deliver_observed<T>(
+    tx: &mpsc::Sender<T>,
+    value: T,
+    profile: Option<&LatencyProfile>,
+    diagnostics: &Mutex<Diagnostics>,
+    describe: impl FnOnce(&T) -> String,
+) -> bool {
+    let event = match deliver(tx, value, profile) {
+        Ok(()) => return true,
+        Err(TrySendError::Full(value)) => ErrorEvent::ObserverOverflow(describe(&value)),
+        Err(TrySendError::Closed(value)) => ErrorEvent::ObserverClosed(describe(&value)),
+    };
+
+    warn!("cannot deliver to observer: {event}");
+
+    diagnostics
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .report(event);
+
+    false
+}
+
+/// unparks the thread waiting for a result as soon as it is sent
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// waits for the result sent to `rx`. waits at most `result_timeout` if a profile is applied,
+/// [`TryRecvError::Empty`] signals a timeout.
+pub(crate) fn await_result<T>(
+    mut rx: oneshot::Receiver<T>,
+    profile: Option<&LatencyProfile>,
+) -> Result<T, TryRecvError> {
+    let Some(profile) = profile else {
+        return rx.blocking_recv().map_err(|_| TryRecvError::Closed);
+    };
+
+    let deadline = Instant::now() + profile.result_timeout;
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    // the thread is parked until the result is sent or the deadline is reached, unparking might be spurious
+    loop {
+        if let Poll::Ready(result) = Pin::new(&mut rx).poll(&mut cx) {
+            return result.map_err(|_| TryRecvError::Closed);
+        }
+
+        let now = Instant::now();
+
+        if now >= deadline {
+            return Err(TryRecvError::Empty);
+        }
+
+        thread::park_timeout(deadline - now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // tolerance of thread scheduling when asserting the latency budget
+    static SLACK: Duration = Duration::from_millis(50);
+
+    fn profile(result_timeout: Duration) -> LatencyProfile {
+        LatencyProfile {
+            result_timeout,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn await_result_returns_sent_result() {
+        let (tx, rx) = oneshot::channel();
+
+        tx.send(42).unwrap();
+
+        assert_eq!(await_result(rx, Some(&LatencyProfile::default())), Ok(42));
+    }
+
+    #[test]
+    fn await_result_times_out_within_budget() {
+        let budget = Duration::from_millis(100);
+        let (_tx, rx) = oneshot::channel::<()>();
+        let start = Instant::now();
+
+        assert_eq!(
+            await_result(rx, Some(&profile(budget))),
+            Err(TryRecvError::Empty)
+        );
+
+        let elapsed = start.elapsed();
+
+        assert!(elapsed >= budget, "returned early after {elapsed:?}");
+        assert!(elapsed < budget + SLACK, "exceeded budget: {elapsed:?}");
+    }
+
+    #[test]
+    fn await_result_wakes_up_on_late_result() {
+        let (tx, rx) = oneshot::channel();
+        let start = Instant::now();
+        let sender = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            tx.send(42).unwrap();
+        });
+
+        assert_eq!(
+            await_result(rx, Some(&profile(Duration::from_secs(10)))),
+            Ok(42)
+        );
+        assert!(start.elapsed() < Duration::from_millis(20) + SLACK);
+
+        sender.join().unwrap();
+    }
+
+    #[test]
+    fn await_result_returns_closed_immediately() {
+        let (tx, rx) = oneshot::channel::<()>();
+        let start = Instant::now();
+
+        drop(tx);
+
+        assert_eq!(
+            await_result(rx, Some(&profile(Duration::from_secs(10)))),
+            Err(TryRecvError::Closed)
+        );
+        assert!(start.elapsed() < SLACK);
+    }
+
+    #[test]
+    fn deliver_rejects_immediately_if_full() {
+        let (tx, _rx) = mpsc::channel(1);
+        let start = Instant::now();
+
+        assert!(deliver(&tx, 1, Some(&LatencyProfile::default())).is_ok());
+        assert!(matches!(
+            deliver(&tx, 2, Some(&LatencyProfile::default())),
+            Err(TrySendError::Full(2))
+        ));
+        assert!(start.elapsed() < SLACK);
+    }
+
+    fn observed_diagnostics() -> (Mutex<Diagnostics>, mpsc::Receiver<ErrorEvent>) {
+        let (tx, rx) = mpsc::channel(10);
+        let diagnostics = Diagnostics {
+            error_observer: Some(tx),
+            ..Default::default()
+        };
+
+        (Mutex::new(diagnostics), rx)
+    }
+
+    #[test]
+    fn deliver_observed_delivers() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let (diagnostics, mut errors) = observed_diagnostics();
+
+        assert!(deliver_observed(&tx, 1, None, &diagnostics, |v| v.to_string()));
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert!(errors.try_recv().is_err());
+    }
+
+    #[test]
+    fn deliver_observed_reports_full_channel() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let (diagnostics, mut errors) = observed_diagnostics();
+        let profile = LatencyProfile::default();
+        let start = Instant::now();
+
+        assert!(deliver_observed(
+            &tx,
+            1,
+            Some(&profile),
+            &diagnostics,
+            |v| v.to_string()
+        ));
+        assert!(!deliver_observed(
+            &tx,
+            2,
+            Some(&profile),
+            &diagnostics,
+            |v| v.to_string()
+        ));
+        assert!(start.elapsed() < SLACK);
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert!(matches!(
+            errors.try_recv(),
+            Ok(ErrorEvent::ObserverOverflow(value)) if value == "2"
+        ));
+    }
+
+    #[test]
+    fn deliver_observed_reports_closed_channel() {
+        for profile in [None, Some(LatencyProfile::default())] {
+            let (tx, rx) = mpsc::channel(1);
+            let (diagnostics, mut errors) = observed_diagnostics();
+
+            drop(rx);
+
+            // returns instead of panicking, regardless of the profile
+            assert!(!deliver_observed(
+                &tx,
+                1,
+                profile.as_ref(),
+                &diagnostics,
+                |v| v.to_string()
+            ));
+            assert!(matches!(
+                errors.try_recv(),
+                Ok(ErrorEvent::ObserverClosed(value)) if value == "1"
+            ));
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use crate::client::{
+    config::{self, DO_WORK_FREQUENCY_RANGE_IN_MS},
+    diagnostics::Diagnostics,
+    twin::SharedTwin,
+    ConfirmationResult, ErrorEvent, IotHubClient, TraceId, TwinUpdate, TwinUpdateState,
+};
+use anyhow::Result;
+use azure_iot_sdk_sys::*;
+use log::{debug, error, info, warn, LevelFilter};
+use serde_json::{json, Map, Value};
+use std::{
+    ffi::{c_void, CString},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::{mpsc, oneshot};
+
+/// managed configuration section of a desired update
+#[derive(Debug)]
+pub(crate) struct ManagedSettings {
+    settings: Value,
+    desired_version: Option<u64>,
+}
+
+pub(crate) type ManagedSettingsSender = mpsc::UnboundedSender<ManagedSettings>;
+pub(crate) type ManagedSettingsReceiver = mpsc::UnboundedReceiver<ManagedSettings>;
+
+/// validates the name of the desired section reserved for managed configuration
+pub(crate) fn validate_section(section: &str) -> Result<()> {
+    if section.is_empty()
+        || section
+            .chars()
+            .any(|c| matches!(c, '.' | '$' | ' ') || c.is_control())
+    {
+        anyhow::bail!("invalid managed configuration section name: {section}");
+    }
+
+    Ok(())
+}
+
+/// removes the managed configuration `section` from `update` and returns it
+pub(crate) fn extract(section: &str, update: &mut TwinUpdate) -> Option<ManagedSettings> {
+    let desired = match update.state {
+        TwinUpdateState::Complete => update.value.get_mut("desired")?,
+        TwinUpdateState::Partial => &mut update.value,
+    };
+    let settings = desired.as_object_mut()?.remove(section)?;
+
+    Some(ManagedSettings {
+        settings,
+        desired_version: desired.get("$version").and_then(Value::as_u64),
+    })
+}
+
+/// applies managed settings received from `rx` and reports the result in `section`
+pub(crate) async fn run(
+    section: String,
+    mut rx: ManagedSettingsReceiver,
+    twin: Arc<SharedTwin>,
+    diagnostics: Arc<Mutex<Diagnostics>>,
+    confirmation_timeout: Duration,
+) {
+    while let Some(ManagedSettings {
+        settings,
+        desired_version,
+    }) = rx.recv().await
+    {
+        debug!("managed configuration: {settings}");
+
+        let mut applied = Map::new();
+        let mut rejected = Map::new();
+
+        match settings {
+            Value::Object(settings) => {
+                for (key, value) in settings {
+                    // removed settings keep their current value
+                    if value.is_null() {
+                        continue;
+                    }
+
+                    match apply(&twin, &key, &value) {
+                        Ok(()) => {
+                            info!("managed configuration: applied {key}: {value}");
+                            applied.insert(key, value);
+                        }
+                        Err(e) => {
+                            warn!("managed configuration: rejected {key}: {e}");
+
+                            if let Ok(mut diagnostics) = diagnostics.lock() {
+                                diagnostics.report(ErrorEvent::SetOptionFailure(format!(
+                                    "managed configuration {key}: {e}"
+                                )));
+                            }
+
+                            rejected.insert(key, json!(e.to_string()));
+                        }
+                    }
+                }
+            }
+            Value::Null => continue,
+            _ => {
+                warn!("managed configuration: section {section} must be an object");
+                rejected.insert(section.clone(), json!("must be an object"));
+            }
+        }
+
+        let reported = json!({
+            section.as_str(): {
+                "applied": applied,
+                "rejected": rejected,
+                "desired_version": desired_version,
+            }
+        });
+
+        if let Err(e) = report(&twin, reported, confirmation_timeout).await {
+            error!("managed configuration: cannot report result: {e}");
+        }
+    }
+}
+
+fn apply(twin: &SharedTwin, key: &str, value: &Value) -> Result<()> {
+    match key {
+        "log_level" => {
+            let level = value
+                .as_str()
+                .ok_or_else(|| anyhow::anyhow!("must be a string"))?;
+
+            log::set_max_level(
+                LevelFilter::from_str(level)
+                    .map_err(|_| anyhow::anyhow!("unknown log level {level}"))?,
+            );
+        }
+        "do_work_freq_ms" => {
+            let freq = value
+                .as_u64()
+                .filter(|freq| DO_WORK_FREQUENCY_RANGE_IN_MS.contains(freq))
+                .ok_or_else(|| {
+                    anyhow::anyhow!("must be in range {DO_WORK_FREQUENCY_RANGE_IN_MS:?}")
+                })?;
+
+            twin.with(|twin| {
+                twin.set_option(
+                    CString::new("do_work_freq_ms")?,
+                    &freq as *const uint_fast64_t as *const c_void,
+                )
+            })?;
+        }
+        "retry_policy" => {
+            let (policy, timeout_secs) = config::retry_policy_from_json(value)?;
+
+            twin.with(|twin| twin.set_retry_policy(policy as u32, timeout_secs as usize))?;
+        }
+        _ => anyhow::bail!("unsupported setting"),
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn report(
+    twin: &SharedTwin,
+    reported: Value,
+    confirmation_timeout: Duration,
+) -> Result<()> {
+    let reported_state = CString::new(reported.to_string())?;
+    let size = reported_state.as_bytes().len();
+    let (tx, rx) = oneshot::channel::<ConfirmationResult>();
+
+    twin.with(|twin| {
+        twin.send_reported_state(
+            reported_state,
+            size,
+            Some(IotHubClient::c_reported_twin_callback),
+            // managed configuration reports aren't traced by the client
+            Box::into_raw(Box::new((tx, TraceId::default()))) as *mut c_void,
+        )
+    })?;
+
+    match tokio::time::timeout(confirmation_timeout, rx).await {
+        Ok(Ok(result)) if result.is_ok() => Ok(()),
+        Ok(Ok(result)) => anyhow::bail!("report not confirmed: {result}"),
+        Ok(Err(_)) => anyhow::bail!("report not confirmed"),
+        Err(_) => anyhow::bail!("report confirmation timed out"),
+    }
+}
@@ -1,10 +1,24 @@
-use anyhow::Result;
+#[cfg(feature = "gzip")]
+use crate::client::compression::{
+    Compression, CONTENT_ENCODING_GZIP, DEFAULT_COMPRESSION_THRESHOLD,
+};
+use crate::client::{
+    clock, event_hubs,
+    idempotency::IDEMPOTENCY_KEY_PROPERTY,
+    model_id::ModelId,
+    pnp,
+    schema::{SCHEMA_NAME_PROPERTY, SCHEMA_VERSION_PROPERTY},
+};
+use anyhow::{Context, Result};
 use azure_iot_sdk_sys::*;
+use bytes::Bytes;
 use log::{error, info};
+use serde::{de::DeserializeOwned, Serialize};
 use std::{
     collections::HashMap,
     ffi::{CStr, CString, NulError},
     slice,
+    time::{Duration, SystemTime},
 };
 
 /// incoming message result sent back to cloud
@@ -58,14 +72,14 @@ pub enum Direction {
 ///         .build()
 ///         .unwrap();
 ///
-///     client.send_d2c_message(msg);
+///     client.send_d2c_message(msg).await.unwrap();
 /// }
 /// ```
 #[derive(Default, Debug, Eq, PartialEq)]
 pub struct IotMessage {
     handle: Option<IOTHUB_MESSAGE_HANDLE>,
-    /// message body
-    pub body: Vec<u8>,
+    /// message body, cloned without copying the payload
+    pub body: Bytes,
     /// output queue name. default: "output"
     pub output_queue: CString,
     /// message direction
@@ -74,6 +88,8 @@ pub struct IotMessage {
     pub properties: HashMap<CString, CString>,
     /// map of [mqtt system message properties](https://docs.microsoft.com/de-de/azure/iot-hub/iot-c-sdk-ref/iothub-message-h/iothubmessage-getcontenttypesystemproperty)
     pub system_properties: HashMap<CString, CString>,
+    /// confirmation timeout of an outgoing message overriding `AZURE_SDK_CONFIRMATION_TIMEOUT_IN_SECS`
+    pub confirmation_timeout: Option<Duration>,
 }
 
 unsafe impl Send for IotMessage {}
@@ -139,6 +155,18 @@ impl IotMessage {
                 CString::new("$.ce")?,
                 IoTHubMessage_GetContentEncodingSystemProperty(handle),
             );
+            add_system_property(
+                CString::new("$.uid")?,
+                IoTHubMessage_GetMessageUserIdSystemProperty(handle),
+            );
+            add_system_property(
+                CString::new("$.ctime")?,
+                IoTHubMessage_GetMessageCreationTimeUtcSystemProperty(handle),
+            );
+
+            if property_keys.is_empty() {
+                properties = IotMessage::incoming_properties(handle);
+            }
 
             for k in property_keys {
                 let v = IoTHubMessage_GetProperty(handle, k.as_ptr());
@@ -152,15 +180,247 @@ impl IotMessage {
 
             Ok(IotMessage {
                 handle: Some(handle),
-                body,
+                body: body.into(),
                 direction: Direction::Incoming,
                 output_queue: CString::new("output")?,
                 system_properties,
                 properties,
+                confirmation_timeout: None,
             })
         }
     }
 
+    /// idempotency key of the message, if any
+    pub fn idempotency_key(&self) -> Option<String> {
+        self.properties
+            .get(CString::new(IDEMPOTENCY_KEY_PROPERTY).ok()?.as_c_str())
+            .map(|key| event_hubs::decode_property(&key.to_string_lossy()))
+    }
+
+    /// schema name and version declared by the message, if any
+    pub fn schema(&self) -> Option<(String, String)> {
+        let property = |key| {
+            self.properties
+                .get(CString::new(key).ok()?.as_c_str())
+                .map(|value| event_hubs::decode_property(&value.to_string_lossy()))
+        };
+
+        Some((
+            property(SCHEMA_NAME_PROPERTY)?,
+            property(SCHEMA_VERSION_PROPERTY)?,
+        ))
+    }
+
+    /// Deserialize the body into `T` according to content type and content encoding of the message. Bodies of
+    /// content type `application/cbor` and `application/msgpack` are supported if the "cbor" or "msgpack"
+    /// feature is enabled, all other bodies are read as JSON, e.g. `application/json` or no content type.
+    /// JSON may be encoded as utf-8 (default) or utf-16 with or without byte order mark, set by content
+    /// encoding or by the charset of the content type. gzip compressed bodies are decompressed first if the
+    /// "gzip" feature is enabled.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Command {
+    ///     action: String,
+    /// }
+    ///
+    /// let msg = IotMessage::builder()
+    ///     .set_body(br#"{"action": "reboot"}"#.to_vec())
+    ///     .set_content_type("application/json")
+    ///     .set_content_encoding("utf-8")
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let command: Command = msg.body_as().unwrap();
+    /// assert_eq!(command.action, "reboot");
+    /// ```
+    pub fn body_as<T: DeserializeOwned>(&self) -> Result<T> {
+        let system_property = |key| {
+            self.system_properties
+                .get(CString::new(key).ok()?.as_c_str())
+                .map(|value| value.to_string_lossy().trim().to_ascii_lowercase())
+        };
+        let content_type = system_property("$.ct").unwrap_or_default();
+        let mut content_encoding = system_property("$.ce").unwrap_or_default();
+        let mut parameters = content_type.split(';').map(str::trim);
+        let media_type = parameters.next().unwrap_or_default();
+
+        if let Some(charset) = parameters.find_map(|p| p.strip_prefix("charset=")) {
+            if content_encoding.is_empty() {
+                content_encoding = charset.trim_matches('"').to_string();
+            }
+        }
+
+        #[cfg(feature = "gzip")]
+        let decompressed;
+        #[allow(unused_mut)]
+        let mut body = &self.body[..];
+
+        #[cfg(feature = "gzip")]
+        if content_encoding == CONTENT_ENCODING_GZIP {
+            decompressed = Compression::Gzip.decompress(body)?;
+            body = &decompressed;
+            content_encoding.clear();
+        }
+
+        match media_type {
+            #[cfg(feature = "cbor")]
+            "application/cbor" => ciborium::from_reader(body)
+                .map_err(|e| anyhow::anyhow!("body_as: cannot deserialize cbor body: {e}")),
+            #[cfg(feature = "msgpack")]
+            "application/msgpack" => {
+                rmp_serde::from_slice(body).context("body_as: cannot deserialize msgpack body")
+            }
+            _ => {
+                let json = match content_encoding.as_str() {
+                    "" | "utf-8" | "utf8" => {
+                        String::from_utf8(body.to_vec()).context("body_as: body is not utf-8")?
+                    }
+                    "utf-16" | "utf-16le" | "utf-16be" => decode_utf16(body, &content_encoding)?,
+                    _ => anyhow::bail!("body_as: unsupported content encoding {content_encoding}"),
+                };
+
+                serde_json::from_str(json.trim_start_matches('\u{feff}'))
+                    .context("body_as: cannot deserialize json body")
+            }
+        }
+    }
+
+    /// message id and correlation id of an incoming message
+    pub(crate) fn incoming_ids(handle: IOTHUB_MESSAGE_HANDLE) -> (Option<String>, Option<String>) {
+        unsafe {
+            let to_string = |value: *const ::std::os::raw::c_char| {
+                (!value.is_null()).then(|| CStr::from_ptr(value).to_string_lossy().to_string())
+            };
+
+            (
+                to_string(IoTHubMessage_GetMessageId(handle)),
+                to_string(IoTHubMessage_GetCorrelationId(handle)),
+            )
+        }
+    }
+
+    /// all application properties of an incoming message
+    fn incoming_properties(handle: IOTHUB_MESSAGE_HANDLE) -> HashMap<CString, CString> {
+        let mut properties = HashMap::new();
+
+        unsafe {
+            // the property map is owned by the message
+            let map = IoTHubMessage_Properties(handle);
+            let mut keys: *const *const ::std::os::raw::c_char = std::ptr::null();
+            let mut values: *const *const ::std::os::raw::c_char = std::ptr::null();
+            let mut count: usize = 0;
+
+            if map.is_null()
+                || MAP_RESULT_TAG_MAP_OK
+                    != Map_GetInternals(map, &mut keys, &mut values, &mut count)
+            {
+                error!("Map_GetInternals: error while enumerating properties");
+                return properties;
+            }
+
+            if count == 0 || keys.is_null() || values.is_null() {
+                return properties;
+            }
+
+            for (key, value) in slice::from_raw_parts(keys, count)
+                .iter()
+                .zip(slice::from_raw_parts(values, count))
+            {
+                if !key.is_null() && !value.is_null() {
+                    properties.insert(
+                        CStr::from_ptr(*key).to_owned(),
+                        CStr::from_ptr(*value).to_owned(),
+                    );
+                }
+            }
+        }
+
+        properties
+    }
+
+    /// value of property `key` of an incoming message
+    pub(crate) fn incoming_property(handle: IOTHUB_MESSAGE_HANDLE, key: &CStr) -> Option<String> {
+        unsafe {
+            let value = IoTHubMessage_GetProperty(handle, key.as_ptr());
+
+            (!value.is_null()).then(|| CStr::from_ptr(value).to_string_lossy().to_string())
+        }
+    }
+
+    /// content type of an incoming message
+    pub(crate) fn incoming_content_type(handle: IOTHUB_MESSAGE_HANDLE) -> Option<String> {
+        unsafe {
+            let value = IoTHubMessage_GetContentTypeSystemProperty(handle);
+
+            (!value.is_null()).then(|| CStr::from_ptr(value).to_string_lossy().to_string())
+        }
+    }
+
+    /// name of the module input an incoming message was received on
+    pub(crate) fn incoming_input_name(handle: IOTHUB_MESSAGE_HANDLE) -> Option<String> {
+        unsafe {
+            let value = IoTHubMessage_GetInputName(handle);
+
+            (!value.is_null()).then(|| CStr::from_ptr(value).to_string_lossy().to_string())
+        }
+    }
+
+    /// outgoing message restored from its parts, e.g. after it was persisted
+    pub(crate) fn outgoing(
+        body: impl Into<Bytes>,
+        output_queue: CString,
+        properties: HashMap<CString, CString>,
+        system_properties: HashMap<CString, CString>,
+    ) -> IotMessage {
+        IotMessage {
+            handle: None,
+            body: body.into(),
+            output_queue,
+            direction: Direction::Outgoing,
+            properties,
+            system_properties,
+            confirmation_timeout: None,
+        }
+    }
+
+    /// outgoing copy of this message with `overrides` applied
+    /// copy of the message without its handle, the payload isn't copied
+    pub(crate) fn detached(&self) -> IotMessage {
+        IotMessage {
+            handle: None,
+            body: self.body.clone(),
+            output_queue: self.output_queue.clone(),
+            direction: self.direction,
+            properties: self.properties.clone(),
+            system_properties: self.system_properties.clone(),
+            confirmation_timeout: self.confirmation_timeout,
+        }
+    }
+
+    pub(crate) fn with_overrides(&self, overrides: &MessageOverrides) -> Result<IotMessage> {
+        let mut properties = self.properties.clone();
+
+        for (key, value) in &overrides.properties {
+            properties.insert(CString::new(key.as_str())?, CString::new(value.as_str())?);
+        }
+
+        Ok(IotMessage {
+            handle: None,
+            body: self.body.clone(),
+            output_queue: match &overrides.output_queue {
+                Some(queue) => CString::new(queue.as_str())?,
+                None => self.output_queue.clone(),
+            },
+            direction: Direction::Outgoing,
+            properties,
+            system_properties: self.system_properties.clone(),
+            confirmation_timeout: self.confirmation_timeout,
+        })
+    }
+
     pub(crate) fn create_outgoing_handle(&mut self) -> Result<IOTHUB_MESSAGE_HANDLE> {
         assert_eq!(self.direction, Direction::Outgoing);
 
@@ -174,6 +434,7 @@ impl IotMessage {
             }
 
             for (key, value) in &self.system_properties {
+                let key_ptr = key.as_ptr();
                 let key = key.to_str()?;
                 let res = match key {
                     "$.mid" => IoTHubMessage_SetMessageId(handle, value.as_ptr()),
@@ -182,6 +443,17 @@ impl IotMessage {
                     "$.ce" => {
                         IoTHubMessage_SetContentEncodingSystemProperty(handle, value.as_ptr())
                     }
+                    "$.sub" => IoTHubMessage_SetComponentName(handle, value.as_ptr()),
+                    "$.uid" => IoTHubMessage_SetMessageUserIdSystemProperty(handle, value.as_ptr()),
+                    "$.ctime" => IoTHubMessage_SetMessageCreationTimeUtcSystemProperty(
+                        handle,
+                        value.as_ptr(),
+                    ),
+                    // azure-sdk-c has no setters for absolute expiry time, message schema and
+                    // interface id, but passes properties with wire ids as is
+                    "$.exp" | "$.schema" | "$.ifid" => {
+                        IoTHubMessage_SetProperty(handle, key_ptr, value.as_ptr())
+                    }
                     _ => {
                         error!("unknown system property found for key: {key}");
                         IOTHUB_MESSAGE_RESULT_TAG_IOTHUB_MESSAGE_OK
@@ -249,19 +521,27 @@ impl IotMessage {
 ///         .build()
 ///         .unwrap();
 ///
-///     client.send_d2c_message(msg);
+///     client.send_d2c_message(msg).await.unwrap();
 /// }
 /// ```
 #[derive(Debug, Default)]
 pub struct IotMessageBuilder {
-    message: Option<Vec<u8>>,
+    message: Option<Bytes>,
     output_queue: String,
     properties: HashMap<String, String>,
     system_properties: HashMap<String, String>,
+    auto_creation_time_utc: bool,
+    confirmation_timeout: Option<Duration>,
+    #[cfg(feature = "gzip")]
+    compression: Option<Compression>,
+    #[cfg(feature = "gzip")]
+    compression_threshold: Option<usize>,
 }
 
 impl IotMessageBuilder {
-    /// Set the message body
+    /// Set the message body. Buffers convertible into [`Bytes`] are taken over without copying, e.g. `Vec<u8>`,
+    /// `&'static [u8]` or buffers shared with other owners like `Arc<[u8]>` by [`Bytes::from_owner`], so that
+    /// large payloads like camera frames aren't copied per message.
     /// ```rust, no_run
     /// use azure_iot_sdk::client::*;
     ///
@@ -281,14 +561,40 @@ impl IotMessageBuilder {
     ///         .build()
     ///         .unwrap();
     ///
-    ///     client.send_d2c_message(msg);
+    ///     client.send_d2c_message(msg).await.unwrap();
     /// }
     /// ```
-    pub fn set_body(mut self, body: Vec<u8>) -> Self {
-        self.message = Some(body);
+    pub fn set_body(mut self, body: impl Into<Bytes>) -> Self {
+        self.message = Some(body.into());
         self
     }
 
+    /// Set `body` serialized as JSON as message body and content type `application/json` and content encoding
+    /// `utf-8`, so that the body can be queried by iothub message routing. Fails if `body` cannot be
+    /// serialized.
+    /// ```rust
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[derive(serde::Serialize)]
+    /// struct Temperature {
+    ///     celsius: f64,
+    /// }
+    ///
+    /// let msg = IotMessage::builder()
+    ///     .set_json_body(&Temperature { celsius: 21.5 })
+    ///     .unwrap()
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(&msg.body[..], br#"{"celsius":21.5}"#);
+    /// ```
+    pub fn set_json_body<T: Serialize + ?Sized>(self, body: &T) -> Result<Self> {
+        Ok(self
+            .set_body(serde_json::to_vec(body)?)
+            .set_content_type(event_hubs::CONTENT_TYPE_JSON)
+            .set_content_encoding(event_hubs::CONTENT_ENCODING_UTF8))
+    }
+
     /// Set the identifier for this message
     /// ```rust, no_run
     /// use azure_iot_sdk::client::*;
@@ -307,13 +613,42 @@ impl IotMessageBuilder {
     ///         .build()
     ///         .unwrap();
     ///
-    ///     client.send_d2c_message(msg);
+    ///     client.send_d2c_message(msg).await.unwrap();
     /// }
     /// ```
     pub fn set_id(self, mid: impl Into<String>) -> Self {
         self.set_system_property("$.mid", mid)
     }
 
+    /// Set the Azure IoT Plug & Play component the message belongs to. Components must be registered by
+    /// [`crate::client::IotHubClientBuilder::pnp_components`], see also
+    /// [`crate::client::IotHubClient::send_d2c_component_message`].
+    pub fn set_component_name(self, component: impl Into<String>) -> Self {
+        self.set_system_property(pnp::COMPONENT_NAME_PROPERTY, component)
+    }
+
+    /// Set the Azure IoT Plug & Play component of telemetry according to the IoT Plug and Play conventions,
+    /// i.e. the component name as by [`IotMessageBuilder::set_component_name`] and JSON content type and
+    /// utf-8 content encoding. See also [`crate::client::IotHubClient::send_pnp_telemetry`].
+    /// ```rust
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// let msg = IotMessage::builder()
+    ///     .set_body(br#"{"temperature": 21.5}"#.to_vec())
+    ///     .set_component("thermostat1")
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let properties = event_hubs::system_properties(&msg).unwrap();
+    /// assert_eq!(properties["content-type"], event_hubs::CONTENT_TYPE_JSON);
+    /// assert_eq!(properties["content-encoding"], event_hubs::CONTENT_ENCODING_UTF8);
+    /// ```
+    pub fn set_component(self, component: impl Into<String>) -> Self {
+        self.set_component_name(component)
+            .set_content_type(event_hubs::CONTENT_TYPE_JSON)
+            .set_content_encoding(event_hubs::CONTENT_ENCODING_UTF8)
+    }
+
     /// Set the correlation identifier for this message
     /// ```rust, no_run
     /// use azure_iot_sdk::client::*;
@@ -332,13 +667,38 @@ impl IotMessageBuilder {
     ///         .build()
     ///         .unwrap();
     ///
-    ///     client.send_d2c_message(msg);
+    ///     client.send_d2c_message(msg).await.unwrap();
     /// }
     /// ```
     pub fn set_correlation_id(self, cid: impl Into<String>) -> Self {
         self.set_system_property("$.cid", cid)
     }
 
+    /// Set the user identifier for this message, e.g. in order to route messages by the `$userId` field
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let msg = IotMessage::builder()
+    ///         .set_user_id("my user id")
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     client.send_d2c_message(msg).await.unwrap();
+    /// }
+    /// ```
+    pub fn set_user_id(self, uid: impl Into<String>) -> Self {
+        self.set_system_property("$.uid", uid)
+    }
+
     /// Set the content-type for this message, such as `text/plain`.
     /// To allow routing query on the message body, this value should be set to `application/json`
     /// ```rust, no_run
@@ -358,7 +718,7 @@ impl IotMessageBuilder {
     ///         .build()
     ///         .unwrap();
     ///
-    ///     client.send_d2c_message(msg);
+    ///     client.send_d2c_message(msg).await.unwrap();
     /// }
     /// ```
     pub fn set_content_type(self, content_type: impl Into<String>) -> Self {
@@ -385,13 +745,166 @@ impl IotMessageBuilder {
     ///         .build()
     ///         .unwrap();
     ///
-    ///     client.send_d2c_message(msg);
+    ///     client.send_d2c_message(msg).await.unwrap();
     /// }
     /// ```
     pub fn set_content_encoding(self, content_encoding: impl Into<String>) -> Self {
         self.set_system_property("$.ce", content_encoding)
     }
 
+    /// Set the creation time of the message in UTC, i.e. the time of measurement in contrast to the time the
+    /// message was enqueued by iothub
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    /// use std::time::SystemTime;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let measured = SystemTime::now();
+    ///
+    ///     // ...
+    ///
+    ///     let msg = IotMessage::builder()
+    ///         .set_body(br#"{"temperature": 21.5}"#.to_vec())
+    ///         .set_creation_time_utc(measured)
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     client.send_d2c_message(msg).await.unwrap();
+    /// }
+    /// ```
+    pub fn set_creation_time_utc(self, creation_time: SystemTime) -> Self {
+        self.set_system_property("$.ctime", clock::rfc3339(creation_time))
+    }
+
+    /// Set whether the creation time is set to the current time by [`IotMessageBuilder::build`], if it isn't
+    /// set explicitly by [`IotMessageBuilder::set_creation_time_utc`]. Default is false.
+    pub fn auto_creation_time_utc(mut self, enable: bool) -> Self {
+        self.auto_creation_time_utc = enable;
+        self
+    }
+
+    /// Set the absolute expiry time of the message in UTC. The message is dropped by iothub instead of being
+    /// delivered if it is not received before, e.g. time-sensitive telemetry that was queued during a long
+    /// outage.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let msg = IotMessage::builder()
+    ///         .set_body(br#"{"position": [52.52, 13.40]}"#.to_vec())
+    ///         .set_expiry_time_utc(SystemTime::now() + Duration::from_secs(300))
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     client.send_d2c_message(msg).await.unwrap();
+    /// }
+    /// ```
+    pub fn set_expiry_time_utc(self, expiry: SystemTime) -> Self {
+        self.set_system_property("$.exp", clock::rfc3339(expiry))
+    }
+
+    /// Set the time to wait for the confirmation of the message by iothub, overriding
+    /// `AZURE_SDK_CONFIRMATION_TIMEOUT_IN_SECS` for this message, e.g. a short deadline for alarms and a long
+    /// one for bulk uploads. Replays of the offline store wait for the global timeout.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let msg = IotMessage::builder()
+    ///         .set_body(br#"{"alarm": "overheat"}"#.to_vec())
+    ///         .confirmation_timeout(Duration::from_secs(5))
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     client.send_d2c_message_confirmed(msg).await.unwrap();
+    /// }
+    /// ```
+    pub fn confirmation_timeout(mut self, timeout: Duration) -> Self {
+        self.confirmation_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the schema of the message body, e.g. used by ingestion pipelines of Azure Time Series Insights or
+    /// Azure Data Explorer in order to select the mapping of the body. Sent as system property `$.schema`.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let msg = IotMessage::builder()
+    ///         .set_body(br#"{"temperature": 21.5}"#.to_vec())
+    ///         .set_message_schema("temperature-v1")
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     client.send_d2c_message(msg).await.unwrap();
+    /// }
+    /// ```
+    pub fn set_message_schema(self, schema: impl Into<String>) -> Self {
+        self.set_system_property("$.schema", schema)
+    }
+
+    /// Set the Azure IoT Plug & Play interface the message conforms to. Sent as system property `$.ifid`.
+    /// See [`IotMessageBuilder::set_component_name`] for the component of the message.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let interface_id: ModelId = "dtmi:com:example:Thermostat;1".parse().unwrap();
+    ///     let msg = IotMessage::builder()
+    ///         .set_body(br#"{"temperature": 21.5}"#.to_vec())
+    ///         .set_interface_id(&interface_id)
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     client.send_d2c_message(msg).await.unwrap();
+    /// }
+    /// ```
+    pub fn set_interface_id(self, interface_id: &ModelId) -> Self {
+        self.set_system_property("$.ifid", interface_id.as_str())
+    }
+
     /// Set the output queue to be used with this message
     /// ```rust, no_run
     /// use azure_iot_sdk::client::*;
@@ -410,7 +923,7 @@ impl IotMessageBuilder {
     ///         .build()
     ///         .unwrap();
     ///
-    ///     client.send_d2c_message(msg);
+    ///     client.send_d2c_message(msg).await.unwrap();
     /// }
     /// ```
     pub fn set_output_queue(mut self, queue: impl Into<String>) -> Self {
@@ -437,19 +950,93 @@ impl IotMessageBuilder {
     ///         .build()
     ///         .unwrap();
     ///
-    ///     client.send_d2c_message(msg);
+    ///     client.send_d2c_message(msg).await.unwrap();
     /// }
     /// ```
     pub fn set_property(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
-        let key = IotMessageBuilder::urlencode(key);
-        let value = IotMessageBuilder::urlencode(value);
+        let key = urlencode(key);
+        let value = urlencode(value);
 
         self.properties.insert(key, value);
         self
     }
 
+    /// Set the idempotency key of the message, e.g. generated by [`crate::client::IdempotencyKeys`].
+    /// The key is sent as property [`crate::client::IDEMPOTENCY_KEY_PROPERTY`], so that backend consumers
+    /// can deduplicate messages sent more than once.
+    pub fn set_idempotency_key(self, key: impl Into<String>) -> Self {
+        self.set_property(IDEMPOTENCY_KEY_PROPERTY, key)
+    }
+
+    /// Declare the schema of the message body, e.g. registered in a [`crate::client::SchemaRegistry`]. Name
+    /// and version are sent as properties [`crate::client::SCHEMA_NAME_PROPERTY`] and
+    /// [`crate::client::SCHEMA_VERSION_PROPERTY`].
+    pub fn set_schema(self, name: impl Into<String>, version: impl Into<String>) -> Self {
+        self.set_property(SCHEMA_NAME_PROPERTY, name)
+            .set_property(SCHEMA_VERSION_PROPERTY, version)
+    }
+
+    #[cfg(feature = "gzip")]
+    /// Compress the body by [`build`](IotMessageBuilder::build) if it is at least as large as the compression
+    /// threshold, which defaults to 1 KiB. The content encoding is set according to `compression`, see
+    /// [`Compression`].<br>
+    /// ***Note***: this function is only available with "gzip" feature enabled.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    /// use serde_json::json;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let samples = vec![json!({"temperature": 21.5}); 1000];
+    ///     let msg = IotMessage::builder()
+    ///         .set_body(serde_json::to_vec(&samples).unwrap())
+    ///         .set_content_type("application/json")
+    ///         .compress(Compression::Gzip)
+    ///         .compression_threshold(4096)
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     client.send_d2c_message(msg).await.unwrap();
+    /// }
+    /// ```
+    pub fn compress(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    #[cfg(feature = "gzip")]
+    /// Set the minimum body size in bytes compressed by [`IotMessageBuilder::compress`]<br>
+    /// ***Note***: this function is only available with "gzip" feature enabled.
+    pub fn compression_threshold(mut self, threshold: usize) -> Self {
+        self.compression_threshold = Some(threshold);
+        self
+    }
+
     /// Build into a message instance
-    pub fn build(self) -> Result<IotMessage> {
+    pub fn build(mut self) -> Result<IotMessage> {
+        if self.auto_creation_time_utc && !self.system_properties.contains_key("$.ctime") {
+            self = self.set_creation_time_utc(SystemTime::now());
+        }
+
+        #[cfg(feature = "gzip")]
+        if let (Some(compression), Some(body)) = (self.compression, &self.message) {
+            let threshold = self
+                .compression_threshold
+                .unwrap_or(DEFAULT_COMPRESSION_THRESHOLD);
+
+            if body.len() >= threshold {
+                self.message = Some(compression.compress(body)?.into());
+                self = self.set_content_encoding(compression.content_encoding());
+            }
+        }
+
         Ok(IotMessage {
             handle: None,
             body: self.message.expect("no message buffer"),
@@ -473,6 +1060,7 @@ impl IotMessageBuilder {
                     Ok((key, value))
                 })
                 .collect::<Result<HashMap<CString, CString>, NulError>>()?,
+            confirmation_timeout: self.confirmation_timeout,
         })
     }
 
@@ -488,15 +1076,70 @@ impl IotMessageBuilder {
         value: impl Into<String>,
     ) -> Self {
         // we don't have to encode property_names as they are only used internally
-        let value = IotMessageBuilder::urlencode(value);
+        let value = urlencode(value);
 
         self.system_properties.insert(property_name.into(), value);
         self
     }
+}
 
-    fn urlencode(value: impl Into<String>) -> String {
-        url::form_urlencoded::Serializer::new(String::new())
-            .append_key_only(&value.into())
-            .finish()
+/// Overrides applied to a prepared [`IotMessage`] at send time by
+/// [`crate::client::IotHubClient::send_d2c_message_with`], e.g. in order to fan out one message to
+/// multiple routes without rebuilding it.
+#[derive(Clone, Debug, Default)]
+pub struct MessageOverrides {
+    output_queue: Option<String>,
+    properties: HashMap<String, String>,
+}
+
+impl MessageOverrides {
+    /// Get an empty set of overrides
+    pub fn new() -> Self {
+        MessageOverrides::default()
     }
+
+    /// Override the output queue of the message
+    pub fn set_output_queue(mut self, queue: impl Into<String>) -> Self {
+        self.output_queue = Some(queue.into());
+        self
+    }
+
+    /// Add a message property or override an existing one
+    pub fn set_property(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.properties.insert(urlencode(key), urlencode(value));
+        self
+    }
+}
+
+pub(crate) fn urlencode(value: impl Into<String>) -> String {
+    // spaces are encoded as "%20" instead of "+", since consumers of the Event Hubs-compatible
+    // endpoint don't necessarily decode "+" (a literal "+" is encoded as "%2B" anyway)
+    url::form_urlencoded::Serializer::new(String::new())
+        .append_key_only(&value.into())
+        .finish()
+        .replace('+', "%20")
+}
+
+/// decodes a utf-16 `body`. byte order is given by `encoding` or by the byte order mark, little endian
+/// by default.
+fn decode_utf16(body: &[u8], encoding: &str) -> Result<String> {
+    anyhow::ensure!(body.len() % 2 == 0, "body_as: body is not utf-16");
+
+    let big_endian = match (encoding, body) {
+        ("utf-16be", _) => true,
+        ("utf-16le", _) => false,
+        (_, [0xfe, 0xff, ..]) => true,
+        _ => false,
+    };
+    let units = body.chunks_exact(2).map(|unit| {
+        if big_endian {
+            u16::from_be_bytes([unit[0], unit[1]])
+        } else {
+            u16::from_le_bytes([unit[0], unit[1]])
+        }
+    });
+
+    char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .context("body_as: body is not utf-16")
 }
@@ -0,0 +1,138 @@
+use crate::client::{ConfirmationOutcome, DispositionResult};
+use std::time::Duration;
+
+// upper bounds of the latency buckets in milliseconds, the last bucket counts all slower confirmations
+static LATENCY_BOUNDS_IN_MS: [u64; 11] =
+    [10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000, 30000];
+
+/// Histogram of confirmation latencies with fixed buckets from 10ms to 30s, see [`DeliveryMetrics`]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LatencyHistogram {
+    counts: [u64; LATENCY_BOUNDS_IN_MS.len() + 1],
+    sum: Duration,
+    max: Duration,
+}
+
+impl LatencyHistogram {
+    pub(crate) fn add(&mut self, latency: Duration) {
+        let bucket = LATENCY_BOUNDS_IN_MS
+            .iter()
+            .position(|bound| latency <= Duration::from_millis(*bound))
+            .unwrap_or(LATENCY_BOUNDS_IN_MS.len());
+
+        self.counts[bucket] += 1;
+        self.sum += latency;
+        self.max = self.max.max(latency);
+    }
+
+    /// (upper bound, count) of all buckets in ascending order, counts aren't cumulative. the upper bound of the
+    /// last bucket is `None`.
+    pub fn buckets(&self) -> Vec<(Option<Duration>, u64)> {
+        LATENCY_BOUNDS_IN_MS
+            .iter()
+            .map(|bound| Some(Duration::from_millis(*bound)))
+            .chain([None])
+            .zip(self.counts)
+            .collect()
+    }
+
+    /// number of recorded latencies
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// sum of all recorded latencies
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    /// mean latency, `None` if nothing was recorded
+    pub fn mean(&self) -> Option<Duration> {
+        let count = self.count();
+
+        (count > 0).then(|| self.sum.div_f64(count as f64))
+    }
+
+    /// maximum latency, zero if nothing was recorded
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+}
+
+/// Snapshot of the delivery health of D2C messages since the client was built, see
+/// [`crate::client::IotHubClient::metrics`].
+///
+/// Chunks of oversized messages count as separate messages. Messages buffered while suspended count as sent once
+/// they are handed over to azure-sdk-c. Replays of the offline store aren't counted, since the replayed messages
+/// already counted as failed or timed out or weren't sent at all.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DeliveryMetrics {
+    /// D2C messages handed over to azure-sdk-c
+    pub sent: u64,
+    /// D2C messages confirmed by iothub
+    pub confirmed: u64,
+    /// D2C messages whose confirmation failed
+    pub failed: u64,
+    /// D2C messages whose confirmation wasn't received in time
+    pub timed_out: u64,
+    /// D2C messages whose confirmation was abandoned by [`crate::client::SendHandle::cancel`]
+    pub cancelled: u64,
+    /// latencies from send to successful confirmation
+    pub confirmation_latency: LatencyHistogram,
+}
+
+impl DeliveryMetrics {
+    pub(crate) fn add_outcome(&mut self, outcome: ConfirmationOutcome, latency: Duration) {
+        match outcome {
+            ConfirmationOutcome::Succeeded => {
+                self.confirmed += 1;
+                self.confirmation_latency.add(latency);
+            }
+            ConfirmationOutcome::Failed(_) => self.failed += 1,
+            ConfirmationOutcome::TimedOut => self.timed_out += 1,
+        }
+    }
+
+    /// D2C messages sent but not yet confirmed, failed or timed out
+    pub fn pending(&self) -> u64 {
+        self.sent
+            .saturating_sub(self.confirmed + self.failed + self.timed_out + self.cancelled)
+    }
+}
+
+/// Snapshot of the processing outcomes of incoming C2D messages since the client was built, see
+/// [`crate::client::IotHubClient::incoming_message_metrics`].
+///
+/// Messages rejected before they were handed over to an observer, e.g. since no observer matches or they cannot
+/// be parsed, count as rejected, too.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct IncomingMessageMetrics {
+    /// C2D messages completed
+    pub accepted: u64,
+    /// C2D messages rejected, including handler errors
+    pub rejected: u64,
+    /// C2D messages abandoned, including results not sent in time
+    pub abandoned: u64,
+    /// C2D messages acknowledged asynchronously
+    pub async_acked: u64,
+    /// durations from receipt to the disposition returned to iothub
+    pub processing_duration: LatencyHistogram,
+}
+
+impl IncomingMessageMetrics {
+    pub(crate) fn add_disposition(&mut self, disposition: DispositionResult, duration: Duration) {
+        match disposition {
+            DispositionResult::Accepted => self.accepted += 1,
+            DispositionResult::Rejected => self.rejected += 1,
+            DispositionResult::Abandoned => self.abandoned += 1,
+            DispositionResult::AsyncAck => self.async_acked += 1,
+        }
+
+        self.processing_duration.add(duration);
+    }
+
+    /// number of processed C2D messages
+    pub fn processed(&self) -> u64 {
+        self.accepted + self.rejected + self.abandoned + self.async_acked
+    }
+}
@@ -15,54 +15,222 @@ compile_error!("Either feature 'device_client' 'module_client' xor 'edge_client'
 #[cfg(all(feature = "module_client", feature = "edge_client"))]
 compile_error!("Either feature 'device_client' 'module_client' xor 'edge_client' feature must be enabled for this crate.");
 
-pub use self::message::{Direction, DispositionResult, IotMessage, IotMessageBuilder};
-pub use self::twin::ClientType;
+#[cfg(feature = "device_client")]
+pub use self::blob_upload::{BlobUpload, UploadProgress};
+pub use self::chunking::{
+    ChunkAssembler, CHUNK_COUNT_PROPERTY, CHUNK_INDEX_PROPERTY, TRANSFER_ID_PROPERTY,
+};
+#[cfg(feature = "gzip")]
+pub use self::compression::{Compression, CONTENT_ENCODING_GZIP};
+pub use self::config::{HttpProxyConfig, IotHubClientConfig};
+pub use self::connection_string::ConnectionString;
+#[cfg(feature = "encryption")]
+pub use self::encryption::KeyProvider;
+pub use self::idempotency::{IdempotencyKeys, IDEMPOTENCY_KEY_PROPERTY};
+pub use self::jobs::{Job, JobObserver, JobState};
+pub use self::latency::LatencyProfile;
+pub use self::message::{
+    Direction, DispositionResult, IotMessage, IotMessageBuilder, MessageOverrides,
+};
+pub use self::metrics::{DeliveryMetrics, IncomingMessageMetrics, LatencyHistogram};
+pub use self::model_id::ModelId;
+pub use self::namespace::Namespace;
+pub use self::offline_store::OfflineStore;
+pub use self::overflow::OverflowPolicy;
+pub use self::raw_handle::{RawClientHandle, RawHandle};
+pub use self::rejected_store::{RejectedMessage, RejectedMessageStore};
+pub use self::reported_array::ReportedArray;
+pub use self::restart::RestartPolicy;
+use self::retransmit::Retransmission;
+pub use self::retransmit::RetransmitPolicy;
+pub use self::routing::MessageFilter;
+pub use self::scheduler::TelemetryScheduler;
+pub use self::schema::{SchemaRegistry, SCHEMA_NAME_PROPERTY, SCHEMA_VERSION_PROPERTY};
+pub use self::send_handle::SendHandle;
+#[cfg(feature = "cbor")]
+pub use self::serializer::{CborSerializer, FORMAT_CBOR};
+pub use self::serializer::{JsonSerializer, Serializer, SerializerRegistry, FORMAT_JSON};
+#[cfg(feature = "msgpack")]
+pub use self::serializer::{MessagePackSerializer, FORMAT_MSGPACK};
+pub use self::sharding::ShardingStrategy;
+pub use self::shutdown::{ShutdownCoordinator, ShutdownObserver, ShutdownStage};
+pub use self::sink::D2cSink;
+pub use self::throttle::AdaptiveRateLimit;
+use self::throttle::RateLimiter;
+use self::trace_id::{Trace, TraceIdGenerator};
+pub use self::trace_id::{TraceId, TraceIdStrategy};
 #[cfg(feature = "device_client")]
 use self::twin::DeviceTwin;
+use self::twin::SharedTwin;
+pub use self::twin::{ClientType, Transport};
 #[cfg(any(feature = "module_client", feature = "edge_client"))]
 use crate::client::twin::ModuleTwin;
 use crate::client::twin::Twin;
+pub use crate::twin_state::{Section, TwinDocument, TwinUpdate, TwinUpdateState};
 use anyhow::Result;
 use azure_iot_sdk_sys::*;
+pub use bytes::Bytes;
+use clock::MonotonicClock;
+use confirmation_waiter::{ConfirmationWaiter, Handover};
 use core::slice;
-#[cfg(feature = "module_client")]
+use dedup::DedupWindow;
+use diagnostics::Diagnostics;
+pub use diagnostics::{AuditRecord, ErrorEvent, InboundCommand};
+#[cfg(feature = "edge_client")]
+pub use edge_workload::ServerCertificate;
+#[cfg(any(feature = "module_client", feature = "device_client"))]
 use eis_utils::*;
 use futures::task;
+use jobs::{JobContext, JobRegistry, JobReportReceiver};
 use log::{debug, error, info, trace, warn};
+use managed_config::{ManagedSettingsReceiver, ManagedSettingsSender};
+use offline_store::MessageStore;
+use overflow::BufferedMessage;
+use platform::PlatformRef;
+use rejected_store::RejectedStore;
+use routing::IncomingMessageRoute;
+#[cfg(any(feature = "module_client", feature = "device_client"))]
+use secondary_hub::{SecondaryHub, SecondaryHubSetting};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::cell::RefCell;
-#[cfg(feature = "module_client")]
-use std::time::SystemTime;
+use sharding::OutputSharding;
 use std::{
     boxed::Box,
+    cell::{Cell, RefCell},
+    collections::{hash_map, HashMap, VecDeque},
     env,
     ffi::{c_void, CStr, CString},
     mem, str,
     sync::{
-        atomic::{AtomicU32, Ordering},
-        Once,
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
     },
     task::{Context, Poll},
+    time::{Instant, SystemTime},
 };
 use tokio::{
-    sync::{mpsc, oneshot},
-    task::{JoinError, JoinSet},
+    runtime::Handle,
+    sync::{
+        mpsc::{self, error::TrySendError},
+        oneshot::{self, error::TryRecvError},
+        Notify, OwnedSemaphorePermit, Semaphore,
+    },
+    task::{AbortHandle, JoinSet},
     time::{timeout, Duration},
 };
+use uuid::Uuid;
 
+#[cfg(feature = "device_client")]
+/// streaming blob upload to the azure storage linked to iothub
+mod blob_upload;
+/// splitting of large payloads into multiple D2C messages and their reassembly
+mod chunking;
+/// wall clock used to timestamp outgoing messages
+mod clock;
+pub mod cloud_events;
+#[cfg(feature = "gzip")]
+/// compression of D2C and decompression of C2D message bodies
+mod compression;
+/// typed client configuration and its validation
+mod config;
+/// fallback waiting for confirmations if the tokio runtime is gone
+mod confirmation_waiter;
+/// parser and builder of iothub connection strings
+mod connection_string;
+/// deduplication of D2C messages by message id
+mod dedup;
+/// runtime information collected for support bundles
+mod diagnostics;
+#[cfg(feature = "edge_client")]
+/// client of the iotedge workload API
+mod edge_workload;
+#[cfg(feature = "encryption")]
+/// end-to-end payload encryption of D2C and C2D messages
+mod encryption;
+pub mod event_hubs;
+/// idempotency keys of D2C messages
+mod idempotency;
+/// long-running jobs started by direct methods
+mod jobs;
+/// bounded-latency hand over of incoming work from azure-sdk-c callbacks
+mod latency;
+/// runtime reconfiguration by a reserved desired section
+mod managed_config;
 /// iothub cloud to device (C2D) and device to cloud (D2C) messages
 mod message;
+/// delivery metrics of D2C messages
+mod metrics;
+/// validation of Azure IoT Plug & Play model ids
+mod model_id;
+/// namespacing of properties of components sharing one identity
+mod namespace;
+/// disk-persistent store-and-forward of D2C messages
+mod offline_store;
+/// policies for incoming messages overflowing their observer channel
+mod overflow;
+/// reference-counted initialization of the azure-sdk-c platform
+mod platform;
+/// IoT Plug and Play conventions of components
+mod pnp;
+/// escape hatch to the underlying azure-sdk-c handle
+mod raw_handle;
+/// disk-persistent store of rejected C2D messages for post-mortem analysis
+mod rejected_store;
+/// per-element patching of arrays in reported properties
+mod reported_array;
+/// policy of the watchdog restarting the client
+mod restart;
+/// retransmission of D2C messages whose confirmation failed
+mod retransmit;
+/// routing of incoming messages to observers
+mod routing;
+/// coalescing of telemetry datapoints into batched D2C messages
+mod scheduler;
+/// versioned telemetry schemas validated before send
+mod schema;
+#[cfg(any(feature = "module_client", feature = "device_client"))]
+/// additional hubs selected telemetry is published to
+mod secondary_hub;
+/// cancellable handles of sent D2C messages
+mod send_handle;
+/// pluggable serialization of message bodies
+mod serializer;
+/// distribution of messages across sharded output queues
+mod sharding;
+/// multi-stage shutdown of the client
+mod shutdown;
+/// futures sink of D2C messages
+mod sink;
+#[cfg(feature = "systemd")]
+/// readiness notification and watchdog of systemd
+mod systemd;
+#[cfg(feature = "test_hooks")]
+/// hooks to simulate hub behavior in tests
+mod test_hooks;
+/// adaptive rate limiting of D2C messages if iothub throttles
+mod throttle;
+/// generation of trace ids used to correlate sends and confirmations
+mod trace_id;
+/// fallback to websocket transports if the primary port is blocked
+mod transport_fallback;
 /// client implementation, either device, module or edge
 mod twin;
 
-static AZURE_SDK_LOGGING: &str = "AZURE_SDK_LOGGING";
-static AZURE_SDK_DO_WORK_FREQUENCY_IN_MS: &str = "AZURE_SDK_DO_WORK_FREQUENCY_IN_MS";
-static DO_WORK_FREQUENCY_RANGE_IN_MS: std::ops::RangeInclusive<u64> = 0..=100;
-static DO_WORK_FREQUENCY_DEFAULT_IN_MS: u64 = 100;
-static AZURE_SDK_CONFIRMATION_TIMEOUT_IN_SECS: &str = "AZURE_SDK_CONFIRMATION_TIMEOUT_IN_SECS";
-static CONFIRMATION_TIMEOUT_DEFAULT_IN_SECS: u64 = 30;
-
-#[cfg(feature = "module_client")]
+static DIRECT_METHOD_RESPONSE_MAX_SIZE: usize = 128 * 1024;
+static D2C_CHUNK_SIZE: usize = 192 * 1024;
+static SUSPEND_BUFFER_CAPACITY: usize = 1024;
+static QUALITY_CHECK_INTERVAL_IN_SECS: u64 = 10;
+#[cfg(any(feature = "module_client", feature = "device_client"))]
+static IDENTITY_CREDENTIAL_LIFETIME_IN_DAYS: u64 = 30;
+#[cfg(any(feature = "module_client", feature = "device_client"))]
+static IDENTITY_CREDENTIAL_RENEWAL_MARGIN_IN_DAYS: u64 = 1;
+#[cfg(any(feature = "module_client", feature = "device_client"))]
+static IDENTITY_CREDENTIAL_RENEWAL_RETRY_IN_SECS: u64 = 60;
+
+/// default property used by [`IotHubClientBuilder::timestamp_messages`]
+pub static CREATION_TIME_UTC_PROPERTY: &str = "iothub-creation-time-utc";
+
+#[cfg(any(feature = "module_client", feature = "device_client"))]
 macro_rules! days_to_secs {
     ($num_days:expr) => {
         $num_days * 24 * 60 * 60
@@ -70,7 +238,7 @@ macro_rules! days_to_secs {
 }
 
 /// [Restart policy](https://github.com/Azure/azure-iot-sdk-c/blob/main/doc/connection_and_messaging_reliability.md#connection-retry-policies) used to connect to iot-hib
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
 pub enum RetryPolicy {
     /// check [here](https://github.com/Azure/azure-iot-sdk-c/blob/main/doc/connection_and_messaging_reliability.md#connection-retry-policies) for meaning
     None = 0,
@@ -88,28 +256,51 @@ pub enum RetryPolicy {
     Random = 6,
 }
 
-/// Indicates [type](https://docs.microsoft.com/en-us/azure/iot-hub/iot-hub-devguide-module-twins#back-end-operations) of desired properties update
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
-pub enum TwinUpdateState {
-    /// complete update of desired properties
-    Complete = 0,
-    /// partial update of desired properties
-    Partial = 1,
-}
+/// Sender used to signal a new [`TwinUpdate`]
+pub type TwinObserver = mpsc::Sender<TwinUpdate>;
 
-/// Used to update [desired properties](https://docs.microsoft.com/en-us/azure/iot-hub/iot-hub-devguide-module-twins#back-end-operations) to the client
-#[derive(Debug)]
-pub struct TwinUpdate {
-    /// type of update [`TwinUpdateState`]
-    pub state: TwinUpdateState,
-    /// value
-    pub value: serde_json::Value,
+/// Sender used to signal non-fatal [`ErrorEvent`]s
+pub type ErrorObserver = mpsc::Sender<ErrorEvent>;
+
+/// Lifecycle events of the client connection
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LifecycleEvent {
+    /// transport selected on connect, see [`IotHubClientBuilder::websocket_fallback`]
+    TransportSelected {
+        /// transport used for the connection
+        transport: Transport,
+        /// true if the port of the configured transport was not reachable and the websocket transport is used instead
+        fallback: bool,
+    },
+    /// the underlying handle was recreated by the watchdog, see [`IotHubClientBuilder::connection_watchdog`]
+    Recovered {
+        /// reason of the unauthenticated state that lasted too long
+        reason: UnauthenticatedReason,
+    },
+    /// the underlying handle was recreated since the connection quality dropped below the threshold of the
+    /// [`RestartPolicy`], see [`IotHubClientBuilder::restart_policy`]
+    QualityRestart {
+        /// number of succeeded confirmations of the sampled outcomes
+        succeeded: usize,
+        /// number of sampled confirmation outcomes
+        samples: usize,
+    },
+    /// throttling by iothub was detected and D2C messages are slowed down, see
+    /// [`IotHubClientBuilder::adaptive_rate_limit`]
+    Throttled {
+        /// minimum interval between D2C messages from now on
+        interval: Duration,
+    },
 }
 
-/// Sender used to signal a new [`TwinUpdate`]
-pub type TwinObserver = mpsc::Sender<TwinUpdate>;
+/// Sender used to signal [`LifecycleEvent`]s
+pub type LifecycleObserver = mpsc::Sender<LifecycleEvent>;
 
-/// Reason for unauthenticated connection result
+/// Reason for unauthenticated connection result.<br>
+/// ***Note***: there are no variants for throttling or for closing by the client, since
+/// `IOTHUB_CLIENT_CONNECTION_STATUS_REASON` of azure-sdk-c has no such reason codes. Throttling is signaled as
+/// [`LifecycleEvent::Throttled`] instead and closing by the client is not signaled as connection status at all.
+/// Reasons not known by this crate are signaled as [`UnauthenticatedReason::Unknown`] with their raw value.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum UnauthenticatedReason {
     /// SAS token expired
@@ -124,8 +315,46 @@ pub enum UnauthenticatedReason {
     NoNetwork,
     /// other communication error
     CommunicationError,
-    /// unknown
-    Unknown,
+    /// iothub didn't answer keep alive pings
+    NoPingResponse,
+    /// daily message quota of iothub exceeded
+    QuotaExceeded,
+    /// reason not known by this crate with raw `IOTHUB_CLIENT_CONNECTION_STATUS_REASON` of azure-sdk-c,
+    /// e.g. introduced by a newer azure-sdk-c
+    Unknown(u32),
+}
+
+impl UnauthenticatedReason {
+    /// raw `IOTHUB_CLIENT_CONNECTION_STATUS_REASON` of azure-sdk-c
+    pub fn raw(&self) -> u32 {
+        match self {
+            UnauthenticatedReason::ExpiredSasToken => {
+                IOTHUB_CLIENT_CONNECTION_STATUS_REASON_TAG_IOTHUB_CLIENT_CONNECTION_EXPIRED_SAS_TOKEN
+            }
+            UnauthenticatedReason::DeviceDisabled => {
+                IOTHUB_CLIENT_CONNECTION_STATUS_REASON_TAG_IOTHUB_CLIENT_CONNECTION_DEVICE_DISABLED
+            }
+            UnauthenticatedReason::BadCredential => {
+                IOTHUB_CLIENT_CONNECTION_STATUS_REASON_TAG_IOTHUB_CLIENT_CONNECTION_BAD_CREDENTIAL
+            }
+            UnauthenticatedReason::RetryExpired => {
+                IOTHUB_CLIENT_CONNECTION_STATUS_REASON_TAG_IOTHUB_CLIENT_CONNECTION_RETRY_EXPIRED
+            }
+            UnauthenticatedReason::NoNetwork => {
+                IOTHUB_CLIENT_CONNECTION_STATUS_REASON_TAG_IOTHUB_CLIENT_CONNECTION_NO_NETWORK
+            }
+            UnauthenticatedReason::CommunicationError => {
+                IOTHUB_CLIENT_CONNECTION_STATUS_REASON_TAG_IOTHUB_CLIENT_CONNECTION_COMMUNICATION_ERROR
+            }
+            UnauthenticatedReason::NoPingResponse => {
+                IOTHUB_CLIENT_CONNECTION_STATUS_REASON_TAG_IOTHUB_CLIENT_CONNECTION_NO_PING_RESPONSE
+            }
+            UnauthenticatedReason::QuotaExceeded => {
+                IOTHUB_CLIENT_CONNECTION_STATUS_REASON_TAG_IOTHUB_CLIENT_CONNECTION_QUOTA_EXCEEDED
+            }
+            UnauthenticatedReason::Unknown(raw) => *raw,
+        }
+    }
 }
 
 /// Authentication status as a result of establishing a connection
@@ -140,6 +369,18 @@ pub enum AuthenticationStatus {
 /// Sender used to signal a new [`AuthenticationStatus`]
 pub type AuthenticationObserver = mpsc::Sender<AuthenticationStatus>;
 
+/// Failed connection attempt that is retried by azure-sdk-c according to the [`RetryPolicy`]. azure-sdk-c
+/// neither exposes the number of its attempts nor the delay chosen by the policy, thus only the reason is
+/// signaled.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RetryEvent {
+    /// reason of the failed attempt
+    pub reason: UnauthenticatedReason,
+}
+
+/// Sender used to signal a [`RetryEvent`]
+pub type RetryObserver = mpsc::Sender<RetryEvent>;
+
 /// DirectMethod
 #[derive(Debug)]
 pub struct DirectMethod {
@@ -168,11 +409,16 @@ pub type DispositionResultResponder = oneshot::Sender<Result<DispositionResult>>
 /// Sender used to signal a direct method to the iothub client consumer
 pub type IotMessageSender = mpsc::Sender<IncomingIotMessage>;
 
-/// Provides a channel and a property array to receive incoming cloud to device messages
+/// Provides a channel and a property array to receive incoming cloud to device messages. If the property
+/// array is empty, [`IotMessage::properties`] of incoming messages contains all application properties,
+/// otherwise only the listed ones.
 #[derive(Clone, Debug)]
 pub struct IncomingMessageObserver {
     responder: IotMessageSender,
     properties: Vec<String>,
+    dispositions: Vec<(Vec<MessageFilter>, DispositionResult)>,
+    // set by the client if the overflow policy buffers messages
+    buffer: Option<mpsc::Sender<BufferedMessage>>,
 }
 
 impl IncomingMessageObserver {
@@ -181,16 +427,461 @@ impl IncomingMessageObserver {
         IncomingMessageObserver {
             responder,
             properties,
+            dispositions: vec![],
+            buffer: None,
         }
     }
+
+    /// Call this function to return `disposition` to iothub for all messages matching all `filters` right away,
+    /// without handing them over to the channel, e.g. to complete messages the consumer isn't interested in.
+    /// Filters are evaluated in the order they were added, the first match wins.
+    /// ```rust
+    /// use azure_iot_sdk::client::*;
+    /// use tokio::sync::mpsc;
+    ///
+    /// let (tx_incoming_message, rx_incoming_message) = mpsc::channel(100);
+    /// let observer = IncomingMessageObserver::new(tx_incoming_message, vec![])
+    ///     .dispose(
+    ///         vec![MessageFilter::Property {
+    ///             key: "type".to_string(),
+    ///             value: "heartbeat".to_string(),
+    ///         }],
+    ///         DispositionResult::Accepted,
+    ///     )
+    ///     .dispose(
+    ///         vec![MessageFilter::ContentType("application/xml".to_string())],
+    ///         DispositionResult::Rejected,
+    ///     );
+    /// ```
+    pub fn dispose(mut self, filters: Vec<MessageFilter>, disposition: DispositionResult) -> Self {
+        self.dispositions.push((filters, disposition));
+        self
+    }
+
+    /// disposition of the first filters matched by the incoming message, if any
+    fn disposition(&self, handle: IOTHUB_MESSAGE_HANDLE) -> Option<DispositionResult> {
+        self.dispositions
+            .iter()
+            .find(|(filters, _)| MessageFilter::match_all(filters, handle))
+            .map(|(_, disposition)| *disposition)
+    }
+
+    /// true if messages are waiting in the overflow buffer
+    fn is_buffering(&self) -> bool {
+        self.buffer
+            .as_ref()
+            .is_some_and(|buffer| buffer.capacity() < buffer.max_capacity())
+    }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
+enum ConnectionSource {
+    #[cfg(any(feature = "module_client", feature = "device_client"))]
+    ConnectionString(String),
+    #[cfg(any(feature = "module_client", feature = "device_client"))]
+    IdentityService,
+    #[cfg(feature = "edge_client")]
+    EdgeEnvironment,
+}
+
+#[derive(Clone)]
+struct ConnectionStatusContext {
+    observer: Option<AuthenticationObserver>,
+    retry: Option<RetryObserver>,
+    diagnostics: Arc<Mutex<Diagnostics>>,
+    sas_token_expired: Option<Arc<Notify>>,
+    // notified on every connection status if the watchdog is enabled
+    status_changed: Option<Arc<Notify>>,
+    latency_profile: Option<LatencyProfile>,
+    #[cfg(feature = "systemd")]
+    systemd_readiness: Option<systemd::Readiness>,
+}
+
+/// Confirmations of D2C messages and reported properties that are still pending
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct PendingConfirmations {
+    /// number of pending confirmations
+    pub count: usize,
+    /// time since the oldest pending message or report was sent
+    pub oldest_age: Option<Duration>,
+}
+
+/// Effective configuration of the current connection as accepted by the underlying handle on
+/// [`IotHubClient::connect`]. Options that weren't applied, e.g. since the handle rejected them, aren't set.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct ClientCapabilities {
+    /// [`Transport`] in use
+    pub transport: Transport,
+    /// true if the transport tunnels over websockets via port 443
+    pub websockets: bool,
+    /// true if a http proxy is in use
+    pub proxy: bool,
+    /// true if custom trusted certificates are in use
+    pub trusted_certs: bool,
+    /// pnp model id announced to iothub or `None` if no model id was announced
+    pub pnp_model_id: Option<String>,
+    /// true if a custom retry policy is in use
+    pub retry_policy: bool,
+    /// true if a custom sas token lifetime is in use
+    pub sas_token_setting: bool,
+    /// percentage of D2C messages carrying diagnostic correlation headers or `None` if not set
+    pub diagnostic_sampling_percentage: Option<u32>,
+    /// true if the connection is established via a gateway
+    pub gateway: bool,
+    /// do_work frequency of the underlying handle in ms
+    pub do_work_freq_ms: u64,
+    /// true if http batching and minimum polling time are in use. Only applied with [`Transport::Http`].
+    pub http_setting: bool,
+}
+
+/// Outcome of the confirmation of a D2C message or reported properties
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ConfirmationOutcome {
+    /// iothub confirmed successfully
+    Succeeded,
+    /// iothub confirmed with failure, or the message was dropped before it was confirmed
+    Failed(ConfirmationResult),
+    /// confirmation wasn't received in time
+    TimedOut,
+}
+
+/// Result of a confirmation as received from azure-sdk-c
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ConfirmationResult {
+    /// D2C message confirmed by iothub
+    Ok,
+    /// D2C message dropped since the underlying handle was destroyed
+    Destroyed,
+    /// D2C message not confirmed due to an error, e.g. a dropped connection
+    Error,
+    /// D2C message not sent within the message timeout of azure-sdk-c
+    MessageTimeout,
+    /// reported properties confirmed with http status code
+    ReportedStatus(u32),
+}
+
+impl ConfirmationResult {
+    /// true if the D2C message or reported properties were confirmed successfully
+    pub fn is_ok(&self) -> bool {
+        match self {
+            ConfirmationResult::Ok => true,
+            ConfirmationResult::ReportedStatus(status) => (200..300).contains(status),
+            _ => false,
+        }
+    }
+
+    /// true if sending again later may succeed, false if iothub rejected the content, e.g. reported properties
+    /// that are malformed or too large
+    /// ```rust
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// assert!(ConfirmationResult::MessageTimeout.is_retryable());
+    /// assert!(ConfirmationResult::ReportedStatus(429).is_retryable());
+    /// assert!(!ConfirmationResult::ReportedStatus(400).is_retryable());
+    /// ```
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ConfirmationResult::Ok => false,
+            ConfirmationResult::Destroyed
+            | ConfirmationResult::Error
+            | ConfirmationResult::MessageTimeout => true,
+            ConfirmationResult::ReportedStatus(status) => {
+                matches!(status, 408 | 429) || *status >= 500
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ConfirmationResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfirmationResult::Ok => write!(f, "confirmed"),
+            ConfirmationResult::Destroyed => write!(f, "dropped since client was destroyed"),
+            ConfirmationResult::Error => write!(f, "not confirmed due to an error"),
+            ConfirmationResult::MessageTimeout => write!(f, "message timed out"),
+            ConfirmationResult::ReportedStatus(status) => write!(f, "status {status}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfirmationResult {}
+
+/// copy of a D2C message retained until its confirmation succeeded
+enum Retained {
+    /// stored offline if the confirmation fails
+    Offline(Arc<MessageStore>, IotMessage),
+    /// retransmitted if the confirmation fails
+    Retransmit(Retransmission),
+}
+
+/// D2C message or reported properties buffered while the client is suspended
+enum SuspendedSend {
+    D2cMessage(IotMessage),
+    Reported(serde_json::Value),
+}
+
+/// Closure called with trace id and [`ConfirmationOutcome`] of every confirmation
+#[derive(Clone)]
+struct ConfirmationCallback(Arc<dyn Fn(TraceId, ConfirmationOutcome) + Send + Sync>);
+
+impl std::fmt::Debug for ConfirmationCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ConfirmationCallback")
+    }
+}
+
+/// Reason why incoming work couldn't be delivered to the iothub client consumer
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DeadLetterReason {
+    /// the observer channel is closed
+    ChannelClosed,
+    /// the responder was dropped without sending a result
+    NoResult,
+    /// the incoming data couldn't be parsed
+    ParseFailure(String),
+    /// no observer matches the incoming message
+    NoRoute,
+    /// the observer channel is full, see [`IotHubClientBuilder::bounded_latency`] and
+    /// [`IotHubClientBuilder::incoming_overflow_policy`]
+    ChannelFull,
+    /// the result wasn't sent in time, see [`IotHubClientBuilder::bounded_latency`]
+    TimedOut,
+}
+
+/// Incoming work that couldn't be delivered to the iothub client consumer or D2C messages that couldn't be
+/// delivered to iothub
+#[derive(Debug)]
+pub enum DeadLetter {
+    /// cloud to device (C2D) message
+    IncomingMessage {
+        /// [`IotMessage`] if it could be parsed. messages handed over to the consumer are only kept if no
+        /// result was sent, i.e. [`DeadLetterReason::TimedOut`] and [`DeadLetterReason::NoResult`].
+        message: Option<IotMessage>,
+        /// [`DeadLetterReason`]
+        reason: DeadLetterReason,
+    },
+    /// direct method
+    DirectMethod {
+        /// method name if it could be parsed
+        name: Option<String>,
+        /// raw method payload
+        payload: String,
+        /// [`DeadLetterReason`]
+        reason: DeadLetterReason,
+    },
+    /// D2C message whose retransmissions are exhausted or stopped after a confirmation timeout, see
+    /// [`IotHubClientBuilder::retransmit_failed_messages`] and [`IotHubClientBuilder::dedup_window`]
+    D2cMessage {
+        /// [`IotMessage`] as sent by the application
+        message: IotMessage,
+        /// trace id returned by [`IotHubClient::send_d2c_message`]
+        trace_id: TraceId,
+        /// [`ConfirmationOutcome`] of the last retransmission
+        outcome: ConfirmationOutcome,
+    },
+}
+
+/// Sender used to signal a new [`DeadLetter`]
+pub type DeadLetterObserver = mpsc::Sender<DeadLetter>;
+
+/// Processing feedback of an incoming C2D message, see [`IotHubClientBuilder::observe_incoming_message_feedback`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IncomingMessageFeedback {
+    /// message id
+    pub message_id: Option<String>,
+    /// correlation id
+    pub correlation_id: Option<String>,
+    /// [`DispositionResult`] returned to iothub
+    pub disposition: DispositionResult,
+    /// time the message was received
+    pub received: SystemTime,
+    /// time from receipt until the disposition was returned to iothub
+    pub duration: Duration,
+}
+
+/// Sender used to signal [`IncomingMessageFeedback`]
+pub type IncomingMessageFeedbackObserver = mpsc::Sender<IncomingMessageFeedback>;
+
+#[derive(Clone)]
+struct IncomingMessageContext {
+    observer: Option<IncomingMessageObserver>,
+    routes: Vec<IncomingMessageRoute>,
+    tx_dead_letter: Option<DeadLetterObserver>,
+    tx_feedback: Option<IncomingMessageFeedbackObserver>,
+    rejected_store: Option<Arc<RejectedStore>>,
+    overflow_policy: OverflowPolicy,
+    audit: Option<Arc<Mutex<Diagnostics>>>,
+    diagnostics: Arc<Mutex<Diagnostics>>,
+    latency_profile: Option<LatencyProfile>,
+    #[cfg(feature = "encryption")]
+    encryption: Option<encryption::Encryption>,
+}
+
+#[derive(Clone)]
+struct DirectMethodContext {
+    observer: Option<DirectMethodObserver>,
+    jobs: Option<JobContext>,
+    tx_dead_letter: Option<DeadLetterObserver>,
+    audit: Option<Arc<Mutex<Diagnostics>>>,
+    diagnostics: Arc<Mutex<Diagnostics>>,
+    latency_profile: Option<LatencyProfile>,
+}
+
+#[derive(Clone)]
+struct TwinDesiredContext {
+    observer: Option<TwinObserver>,
+    managed_configuration: Option<(String, ManagedSettingsSender)>,
+    diagnostics: Arc<Mutex<Diagnostics>>,
+    latency_profile: Option<LatencyProfile>,
+}
+
+#[derive(Clone, Debug, Serialize)]
 struct RetrySetting {
     policy: RetryPolicy,
     timeout_secs: u32,
 }
 
+/// Behavior if a pnp model id is set but not supported by client type or transport.<br>
+/// ***Note***: the policy only applies if azure-sdk-c refuses to set the model id on the underlying handle.
+/// A model id rejected by iothub while connecting isn't detected, since azure-sdk-c signals no specific
+/// reason for it. Such failures are signaled as unauthenticated connection status like any other connect
+/// failure.
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+pub enum UnsupportedModelIdPolicy {
+    /// log a warning, record it in the support bundle and proceed without model id
+    Warn,
+    /// fail to build the client
+    #[default]
+    Error,
+}
+
+#[derive(Clone)]
+struct ProxySetting {
+    host_address: String,
+    port: u16,
+    credentials: Option<(String, String)>,
+}
+
+impl std::fmt::Debug for ProxySetting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProxySetting")
+            .field("host_address", &self.host_address)
+            .field("port", &self.port)
+            .field(
+                "username",
+                &self.credentials.as_ref().map(|(username, _)| username),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "device_client")]
+#[derive(Clone, Debug)]
+struct GatewaySetting {
+    host_name: String,
+    root_ca: String,
+}
+
+#[derive(Clone, Debug)]
+struct HttpSetting {
+    batching: bool,
+    min_polling_time: Duration,
+}
+
+#[derive(Clone, Debug)]
+struct SasTokenSetting {
+    lifetime: Duration,
+    renewal_margin: Duration,
+}
+
+/// options applied to each underlying handle
+#[derive(Clone, Debug)]
+struct TwinOptions {
+    model_id: Option<String>,
+    unsupported_model_id_policy: UnsupportedModelIdPolicy,
+    retry_setting: Option<RetrySetting>,
+    sas_token_setting: Option<SasTokenSetting>,
+    diagnostic_sampling_percentage: Option<u32>,
+    product_info: Option<String>,
+    trusted_certs: Option<String>,
+    proxy_setting: Option<ProxySetting>,
+    transport: Transport,
+    http_setting: Option<HttpSetting>,
+    do_work_freq_ms: u64,
+    logging: bool,
+    #[cfg(feature = "device_client")]
+    gateway_host_name: Option<String>,
+}
+
+impl TwinOptions {
+    #[cfg(any(feature = "module_client", feature = "device_client"))]
+    /// validates `connection_string` and appends the gateway host name if the device connects through a gateway
+    fn via_gateway(&self, connection_string: &str) -> Result<String> {
+        #[allow(unused_mut)]
+        let mut connection_string = IotHubClient::validate_connection_string(connection_string)?;
+
+        #[cfg(feature = "device_client")]
+        if let Some(gateway_host_name) = &self.gateway_host_name {
+            connection_string = connection_string.with_gateway_host_name(gateway_host_name);
+        }
+
+        Ok(connection_string.to_string())
+    }
+}
+
+/// raw pointers to the contexts passed to azure-sdk-c callbacks. they point to boxes owned by
+/// [`IotHubClient`] or to [`OwnedContexts`] of a renewed handle and thus stay valid as long as the handle is
+/// alive.
+struct CallbackContexts {
+    connection_status: *mut c_void,
+    incoming_message: Option<*mut c_void>,
+    twin_desired: Option<*mut c_void>,
+    direct_method: Option<*mut c_void>,
+    inputs: Vec<CString>,
+    // incoming messages aren't registered while paused by pause_incoming()
+    incoming_paused: Arc<AtomicBool>,
+}
+
+// contexts are only dereferenced by azure-sdk-c callbacks
+unsafe impl Send for CallbackContexts {}
+unsafe impl Sync for CallbackContexts {}
+
+/// copy of the client's contexts used by background tasks renewing the handle. every renewed handle owns
+/// a clone, so that the old and the new handle never share a context while the old one is destroyed.
+#[derive(Clone)]
+struct OwnedContexts {
+    connection_status: ConnectionStatusContext,
+    incoming_message: Option<IncomingMessageContext>,
+    twin_desired: Option<TwinDesiredContext>,
+    direct_method: Option<DirectMethodContext>,
+    inputs: Vec<CString>,
+    incoming_paused: Arc<AtomicBool>,
+}
+
+impl OwnedContexts {
+    /// pointers to the contexts, which stay valid as long as `self` is neither moved nor dropped, e.g. if boxed
+    fn callback_contexts(&mut self) -> CallbackContexts {
+        CallbackContexts {
+            connection_status: &mut self.connection_status as *mut ConnectionStatusContext
+                as *mut c_void,
+            incoming_message: self
+                .incoming_message
+                .as_mut()
+                .map(|context| context as *mut IncomingMessageContext as *mut c_void),
+            twin_desired: self
+                .twin_desired
+                .as_mut()
+                .map(|context| context as *mut TwinDesiredContext as *mut c_void),
+            direct_method: self
+                .direct_method
+                .as_mut()
+                .map(|context| context as *mut DirectMethodContext as *mut c_void),
+            inputs: self.inputs.clone(),
+            incoming_paused: self.incoming_paused.clone(),
+        }
+    }
+}
+
 /// Builder used to create an instance of [`IotHubClient`]
 /// ```no_run
 /// use azure_iot_sdk::client::*;
@@ -244,8 +935,60 @@ pub struct IotHubClientBuilder {
     tx_twin_desired: Option<Box<TwinObserver>>,
     tx_direct_method: Option<Box<DirectMethodObserver>>,
     tx_incoming_message: Option<Box<IncomingMessageObserver>>,
-    model_id: Option<&'static str>,
+    incoming_message_routes: Vec<IncomingMessageRoute>,
+    tx_dead_letter: Option<DeadLetterObserver>,
+    tx_incoming_message_feedback: Option<IncomingMessageFeedbackObserver>,
+    incoming_overflow_policy: OverflowPolicy,
+    tx_error: Option<ErrorObserver>,
+    tx_lifecycle: Option<LifecycleObserver>,
+    tx_retry: Option<RetryObserver>,
+    restart_policy: Option<RestartPolicy>,
+    long_running_methods: Option<(Vec<String>, JobObserver)>,
+    managed_configuration: Option<String>,
+    model_id: Option<String>,
+    unsupported_model_id_policy: UnsupportedModelIdPolicy,
+    pnp_components: Vec<String>,
     retry_setting: Option<RetrySetting>,
+    sas_token_setting: Option<SasTokenSetting>,
+    diagnostic_sampling_percentage: Option<u32>,
+    product_info: Option<String>,
+    on_confirmation: Option<ConfirmationCallback>,
+    trace_id_strategy: TraceIdStrategy,
+    trusted_certs: Option<String>,
+    proxy_setting: Option<ProxySetting>,
+    transport: Transport,
+    websocket_fallback: bool,
+    latency_profile: Option<LatencyProfile>,
+    max_in_flight: Option<usize>,
+    dedup_window: Option<Duration>,
+    adaptive_rate_limit: Option<AdaptiveRateLimit>,
+    http_setting: Option<HttpSetting>,
+    output_shardings: HashMap<String, (Vec<String>, ShardingStrategy)>,
+    outputs: Option<Vec<String>>,
+    audit_inbound_commands: bool,
+    timestamp_property: Option<String>,
+    auto_message_ids: bool,
+    chunk_oversized_messages: bool,
+    default_properties: HashMap<String, String>,
+    idempotency_keys: Option<Arc<IdempotencyKeys>>,
+    offline_store: Option<OfflineStore>,
+    rejected_message_store: Option<RejectedMessageStore>,
+    retransmit_policy: Option<RetransmitPolicy>,
+    serializers: SerializerRegistry,
+    telemetry_schemas: Option<SchemaRegistry>,
+    do_work_freq_ms: Option<u64>,
+    confirmation_timeout_secs: Option<u64>,
+    logging: Option<bool>,
+    #[cfg(any(feature = "module_client", feature = "device_client"))]
+    secondary_hubs: Vec<SecondaryHubSetting>,
+    #[cfg(feature = "device_client")]
+    gateway_setting: Option<GatewaySetting>,
+    #[cfg(feature = "edge_client")]
+    edge_trust_bundle: bool,
+    #[cfg(feature = "encryption")]
+    encryption: Option<encryption::Encryption>,
+    #[cfg(feature = "systemd")]
+    notify_systemd: bool,
 }
 
 impl IotHubClientBuilder {
@@ -347,6 +1090,45 @@ impl IotHubClientBuilder {
         IotHubClient::from_connection_string(connection_string, self)
     }
 
+    #[cfg(feature = "device_client")]
+    /// Call this function in order to build an instance of a device client based [`IotHubClient`].<br>
+    /// ***Note1***: this function gets its connection string from identity service. The credentials are renewed
+    /// automatically shortly before they expire or if iothub reports an expired sas token.<br>
+    /// ***Note2***: this function is only available with "device_client" feature enabled.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    /// use tokio::{select, sync::mpsc};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (tx_connection_status, mut rx_connection_status) = mpsc::channel(100);
+    ///     let (tx_direct_method, mut rx_direct_method) = mpsc::channel(100);
+    ///
+    ///     let mut client = IotHubClient::builder()
+    ///         .observe_connection_state(tx_connection_status)
+    ///         .observe_direct_methods(tx_direct_method)
+    ///         .build_device_client_from_identity()
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     loop {
+    ///         select! (
+    ///             status = rx_connection_status.recv() => {
+    ///                 // handle connection status;
+    ///                 // ...
+    ///             },
+    ///             status = rx_direct_method.recv() => {
+    ///                 // handle direct method calls;
+    ///                 // ...
+    ///             },
+    ///         )
+    ///     }
+    /// }
+    /// ```
+    pub async fn build_device_client_from_identity(&self) -> Result<IotHubClient> {
+        IotHubClient::from_identity_service(self).await
+    }
+
     #[cfg(feature = "module_client")]
     /// Call this function in order to build an instance of a module client based [`IotHubClient`] by connection string.<br>
     /// ***Note***: this function is only available with "module_client" feature enabled.
@@ -398,7 +1180,8 @@ impl IotHubClientBuilder {
 
     #[cfg(feature = "module_client")]
     /// Call this function in order to build an instance of a module client based [`IotHubClient`].<br>
-    /// ***Note1***: this function gets its connection string from identity service.<br>
+    /// ***Note1***: this function gets its connection string from identity service. The credentials are renewed
+    /// automatically shortly before they expire or if iothub reports an expired sas token.<br>
     /// ***Note2***: this function is only available with "module_client" feature enabled.
     /// ```no_run
     /// use azure_iot_sdk::client::*;
@@ -447,6 +1230,113 @@ impl IotHubClientBuilder {
         IotHubClient::from_identity_service(self).await
     }
 
+    #[cfg(feature = "edge_client")]
+    /// Call this function in order to build an instance of an edge client based [`IotHubClient`] without any I/O.
+    /// Options are validated, but the connection is established not until [`IotHubClient::connect`] is called.<br>
+    /// ***Note***: this function is only available with "edge_client" feature enabled.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = IotHubClient::builder().build_edge_client_lazy().unwrap();
+    ///
+    ///     client.connect().await.unwrap();
+    /// }
+    /// ```
+    pub fn build_edge_client_lazy(&self) -> Result<IotHubClient> {
+        IotHubClient::new(ConnectionSource::EdgeEnvironment, self)
+    }
+
+    #[cfg(feature = "device_client")]
+    /// Call this function in order to build an instance of a device client based [`IotHubClient`] without any I/O.
+    /// Options are validated, but the connection is established not until [`IotHubClient::connect`] is called.<br>
+    /// ***Note***: this function is only available with "device_client" feature enabled.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = IotHubClient::builder()
+    ///         .build_device_client_lazy("my-connection-string")
+    ///         .unwrap();
+    ///
+    ///     client.connect().await.unwrap();
+    /// }
+    /// ```
+    pub fn build_device_client_lazy(&self, connection_string: &str) -> Result<IotHubClient> {
+        IotHubClient::new(
+            ConnectionSource::ConnectionString(connection_string.to_string()),
+            self,
+        )
+    }
+
+    #[cfg(feature = "module_client")]
+    /// Call this function in order to build an instance of a module client based [`IotHubClient`] by connection string
+    /// without any I/O. Options are validated, but the connection is established not until [`IotHubClient::connect`]
+    /// is called.<br>
+    /// ***Note***: this function is only available with "module_client" feature enabled.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = IotHubClient::builder()
+    ///         .build_module_client_lazy("my-connection-string")
+    ///         .unwrap();
+    ///
+    ///     client.connect().await.unwrap();
+    /// }
+    /// ```
+    pub fn build_module_client_lazy(&self, connection_string: &str) -> Result<IotHubClient> {
+        IotHubClient::new(
+            ConnectionSource::ConnectionString(connection_string.to_string()),
+            self,
+        )
+    }
+
+    #[cfg(feature = "module_client")]
+    /// Call this function in order to build an instance of a module client based [`IotHubClient`] without any I/O.
+    /// The connection string is requested from identity service not until [`IotHubClient::connect`] is called.
+    /// Thus this function can also be used in non async and early boot contexts.<br>
+    /// ***Note***: this function is only available with "module_client" feature enabled.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = IotHubClient::builder()
+    ///         .build_module_client_from_identity_lazy()
+    ///         .unwrap();
+    ///
+    ///     client.connect().await.unwrap();
+    /// }
+    /// ```
+    pub fn build_module_client_from_identity_lazy(&self) -> Result<IotHubClient> {
+        IotHubClient::new(ConnectionSource::IdentityService, self)
+    }
+
+    #[cfg(feature = "device_client")]
+    /// Call this function in order to build an instance of a device client based [`IotHubClient`] without any I/O.
+    /// The connection string is requested from identity service not until [`IotHubClient::connect`] is called.
+    /// Thus this function can also be used in non async and early boot contexts.<br>
+    /// ***Note***: this function is only available with "device_client" feature enabled.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut client = IotHubClient::builder()
+    ///         .build_device_client_from_identity_lazy()
+    ///         .unwrap();
+    ///
+    ///     client.connect().await.unwrap();
+    /// }
+    /// ```
+    pub fn build_device_client_from_identity_lazy(&self) -> Result<IotHubClient> {
+        IotHubClient::new(ConnectionSource::IdentityService, self)
+    }
+
     /// Add connection state observer
     /// ```no_run
     /// use azure_iot_sdk::client::*;
@@ -555,24 +1445,79 @@ impl IotHubClientBuilder {
         self
     }
 
-    /// Add incoming message observer
+    /// Call this function to handle the direct methods `methods` as long-running jobs. A call of one of these
+    /// methods is answered immediately with status 202 and a job id, while the call is signaled as [`Job`]
+    /// to `tx_job` instead of the direct method observer:
+    /// ```json
+    /// { "job_id": "<id>" }
+    /// ```
+    /// The client tracks the state of all jobs, see [`IotHubClient::job_state`], and reports it in the
+    /// "jobs" section of the reported properties:
+    /// ```json
+    /// { "<id>": { "name": "<method>", "state": "running", "progress": 50, "detail": null, "updated": 1700000000 } }
+    /// ```
+    /// Finished jobs are reported with state "succeeded" and "result" or state "failed" and "error".
+    /// Only the latest 10 finished jobs are kept, older ones are removed from the reported properties.
     /// ```no_run
     /// use azure_iot_sdk::client::*;
-    /// use std::{thread, time};
-    /// use tokio::{select, sync::mpsc};
+    /// use serde_json::json;
+    /// use tokio::sync::mpsc;
     ///
     /// #[tokio::main]
     /// async fn main() {
-    ///     let (tx_incoming_message, mut rx_incoming_message) = mpsc::channel(100);
+    ///     let (tx_job, mut rx_job) = mpsc::channel(100);
     ///
     ///     #[cfg(feature = "edge_client")]
     ///     let mut client = IotHubClient::builder()
-    ///         .observe_incoming_messages(IncomingMessageObserver::new(tx_incoming_message, vec![]))
+    ///         .long_running_methods(&["update_firmware"], tx_job)
     ///         .build_edge_client()
     ///         .unwrap();
     ///     #[cfg(feature = "device_client")]
     ///     let mut client = IotHubClient::builder()
-    ///         .observe_incoming_messages(IncomingMessageObserver::new(tx_incoming_message, vec![]))
+    ///         .long_running_methods(&["update_firmware"], tx_job)
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .long_running_methods(&["update_firmware"], tx_job)
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    ///
+    ///     while let Some(job) = rx_job.recv().await {
+    ///         tokio::spawn(async move {
+    ///             job.progress(50, Some(json!({"step": "download"}))).unwrap();
+    ///             // ...
+    ///             job.succeed(json!({"version": "1.2.3"})).unwrap();
+    ///         });
+    ///     }
+    /// }
+    /// ```
+    pub fn long_running_methods(mut self, methods: &[&str], tx_job: JobObserver) -> Self {
+        self.long_running_methods = Some((
+            methods.iter().map(|method| method.to_string()).collect(),
+            tx_job,
+        ));
+        self
+    }
+
+    /// Add incoming message observer
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    /// use std::{thread, time};
+    /// use tokio::{select, sync::mpsc};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (tx_incoming_message, mut rx_incoming_message) = mpsc::channel(100);
+    ///
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .observe_incoming_messages(IncomingMessageObserver::new(tx_incoming_message, vec![]))
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .observe_incoming_messages(IncomingMessageObserver::new(tx_incoming_message, vec![]))
     ///         .build_device_client("my-connection-string")
     ///         .unwrap();
     ///     #[cfg(feature = "module_client")]
@@ -599,314 +1544,4114 @@ impl IotHubClientBuilder {
         self
     }
 
-    /// Set an Azure IoT Plug & Play model id.
+    /// Call this function to route incoming cloud to device (C2D) messages matching all `filters` to `observer`.
+    /// Routes are evaluated in the order they were added, the first matching route wins. Messages that don't
+    /// match any route are passed to the observer registered by [`IotHubClientBuilder::observe_incoming_messages`].
+    /// If there is none, such messages are rejected and signaled as [`DeadLetter`] with [`DeadLetterReason::NoRoute`].<br>
+    /// ***Note***: module and edge clients additionally receive messages on all inputs used by [`MessageFilter::Input`].
     /// ```no_run
     /// use azure_iot_sdk::client::*;
-    /// use std::{thread, time};
-    /// use tokio::{select, sync::mpsc};
+    /// use tokio::sync::mpsc;
     ///
     /// #[tokio::main]
     /// async fn main() {
+    ///     let (tx_config, mut rx_config) = mpsc::channel(100);
+    ///     let (tx_command, mut rx_command) = mpsc::channel(100);
+    ///     let config_filter = vec![MessageFilter::Property {
+    ///         key: "type".to_string(),
+    ///         value: "config".to_string(),
+    ///     }];
+    ///     let command_filter = vec![MessageFilter::Property {
+    ///         key: "type".to_string(),
+    ///         value: "command".to_string(),
+    ///     }];
+    ///
+    ///     let builder = IotHubClient::builder()
+    ///         .route_incoming_messages(config_filter, IncomingMessageObserver::new(tx_config, vec![]))
+    ///         .route_incoming_messages(command_filter, IncomingMessageObserver::new(tx_command, vec![]));
+    ///
     ///     #[cfg(feature = "edge_client")]
-    ///     let mut client = IotHubClient::builder()
-    ///         .pnp_model_id("my.pnp.id")
-    ///         .build_edge_client()
-    ///         .unwrap();
+    ///     let mut client = builder.build_edge_client().unwrap();
     ///     #[cfg(feature = "device_client")]
-    ///     let mut client = IotHubClient::builder()
-    ///         .pnp_model_id("my.pnp.id")
-    ///         .build_device_client("my-connection-string")
-    ///         .unwrap();
+    ///     let mut client = builder.build_device_client("my-connection-string").unwrap();
     ///     #[cfg(feature = "module_client")]
-    ///     let mut client = IotHubClient::builder()
-    ///         .pnp_model_id("my.pnp.id")
-    ///         .build_module_client("my-connection-string")
-    ///         .unwrap();
+    ///     let mut client = builder.build_module_client("my-connection-string").unwrap();
     /// }
     /// ```
-    pub fn pnp_model_id(mut self, model_id: &'static str) -> Self {
-        self.model_id = Some(model_id);
+    pub fn route_incoming_messages(
+        mut self,
+        filters: Vec<MessageFilter>,
+        observer: IncomingMessageObserver,
+    ) -> Self {
+        self.incoming_message_routes
+            .push(IncomingMessageRoute { filters, observer });
         self
     }
 
-    /// Call this function to set the restart policy used for connecting to iot-hub.
+    /// Add dead letter observer. Incoming C2D messages and direct methods that couldn't be delivered to
+    /// the consumer, e.g. since the observer channel is closed or the payload couldn't be parsed,
+    /// are signaled as [`DeadLetter`] together with the failure reason. D2C messages are signaled if their
+    /// retransmissions are exhausted, see [`IotHubClientBuilder::retransmit_failed_messages`].<br>
+    /// Dead letters are never waited for: if the channel is full they are dropped and counted as
+    /// `dead_letters_dropped` in the totals of [`IotHubClient::support_bundle`].
     /// ```no_run
     /// use azure_iot_sdk::client::*;
-    /// use std::{thread, time};
     /// use tokio::{select, sync::mpsc};
     ///
     /// #[tokio::main]
     /// async fn main() {
+    ///     let (tx_dead_letter, mut rx_dead_letter) = mpsc::channel(100);
+    ///
     ///     #[cfg(feature = "edge_client")]
     ///     let mut client = IotHubClient::builder()
-    ///         .retry_policy(RetryPolicy::None, 0)
+    ///         .observe_dead_letters(tx_dead_letter)
     ///         .build_edge_client()
     ///         .unwrap();
     ///     #[cfg(feature = "device_client")]
     ///     let mut client = IotHubClient::builder()
-    ///         .retry_policy(RetryPolicy::None, 0)
+    ///         .observe_dead_letters(tx_dead_letter)
     ///         .build_device_client("my-connection-string")
     ///         .unwrap();
     ///     #[cfg(feature = "module_client")]
     ///     let mut client = IotHubClient::builder()
-    ///         .retry_policy(RetryPolicy::None, 0)
+    ///         .observe_dead_letters(tx_dead_letter)
     ///         .build_module_client("my-connection-string")
     ///         .unwrap();
+    ///
+    ///     loop {
+    ///         select! (
+    ///             dead_letter = rx_dead_letter.recv() => {
+    ///                 // handle undeliverable C2D messages, direct methods and D2C messages;
+    ///                 // ...
+    ///             },
+    ///         )
+    ///     }
     /// }
     /// ```
-    pub fn retry_policy(mut self, policy: RetryPolicy, timeout_secs: u32) -> Self {
-        self.retry_setting = Some(RetrySetting {
-            policy,
-            timeout_secs,
-        });
+    pub fn observe_dead_letters(mut self, tx_dead_letter: DeadLetterObserver) -> Self {
+        self.tx_dead_letter = Some(tx_dead_letter);
         self
     }
-}
-
-/// iothub client to be instantiated in order to initiate iothub communication
-/// ```no_run
-/// use azure_iot_sdk::client::*;
-/// use std::{thread, time};
-/// use tokio::{select, sync::mpsc};
-///
-/// #[tokio::main]
-/// async fn main() {
-///     let (tx_connection_status, mut rx_connection_status) = mpsc::channel(100);
-///     let (tx_twin_desired, mut rx_twin_desired) = mpsc::channel(100);
-///     let (tx_direct_method, mut rx_direct_method) = mpsc::channel(100);
-///     let (tx_incoming_message, mut rx_incoming_message) = mpsc::channel(100);
-///     let builder = IotHubClient::builder()
-///         .observe_connection_state(tx_connection_status)
-///         .observe_desired_properties(tx_twin_desired)
-///         .observe_direct_methods(tx_direct_method)
-///         .observe_incoming_messages(IncomingMessageObserver::new(tx_incoming_message, vec![]));
-///
-///     #[cfg(feature = "edge_client")]
-///     let mut client = builder.build_edge_client().unwrap();
-///     #[cfg(feature = "device_client")]
-///     let mut client = builder.build_device_client("my-connection-string").unwrap();
-///     #[cfg(feature = "module_client")]
-///     let mut client = builder.build_module_client("my-connection-string").unwrap();
-///
-///     loop {
-///         select! (
-///             status = rx_connection_status.recv() => {
-///                 // handle connection status;
-///                 // ...
-///             },
-///             status = rx_twin_desired.recv() => {
-///                 // handle twin desired properties;
-///                 // ...
-///             },
-///             status = rx_direct_method.recv() => {
-///                 // handle direct method calls;
-///                 // ...
-///             },
-///             status = rx_incoming_message.recv() => {
-///                 // handle cloud to device messages;
-///                 // ...
-///             },
-///         )
-///     }
-/// }
-/// ```
-pub struct IotHubClient {
-    twin: Box<dyn Twin>,
-    tx_connection_status: Option<Box<AuthenticationObserver>>,
-    tx_twin_desired: Option<Box<TwinObserver>>,
-    tx_direct_method: Option<Box<DirectMethodObserver>>,
-    tx_incoming_message: Option<Box<IncomingMessageObserver>>,
-    model_id: Option<&'static str>,
-    retry_setting: Option<RetrySetting>,
-    confirmation_set: RefCell<JoinSet<()>>,
-    trace_id: AtomicU32,
-}
-
-impl IotHubClient {
-    /// Call this function in order to get the underlying azure-sdk-c version string.
-    /// ```rust, no_run
-    /// use azure_iot_sdk::client::*;
-    ///
-    /// IotHubClient::sdk_version_string();
-    /// ```
-    pub fn sdk_version_string() -> String {
-        twin::sdk_version_string()
-    }
-
-    /// Call this function to get the configured [`ClientType`].
-    /// ```rust, no_run
-    /// use azure_iot_sdk::client::*;
-    ///
-    /// IotHubClient::client_type();
-    /// ```
-    pub fn client_type() -> ClientType {
-        if cfg!(feature = "device_client") {
-            ClientType::Device
-        } else if cfg!(feature = "module_client") {
-            ClientType::Module
-        } else if cfg!(feature = "edge_client") {
-            ClientType::Edge
-        } else {
-            panic!("no client type feature set")
-        }
-    }
 
-    /// Call this function to get a builder to build an instance of [`IotHubClient`].
-    /// ```rust, no_run
+    /// Call this function in order to get the final [`DispositionResult`] and processing duration of every
+    /// incoming C2D message signaled as [`IncomingMessageFeedback`], e.g. to correlate delivery counts of
+    /// iothub with the outcomes on the device. Feedback is dropped if the channel is full. Aggregated counters
+    /// are available by [`IotHubClient::incoming_message_metrics`] regardless of this observer.
+    /// ```no_run
     /// use azure_iot_sdk::client::*;
+    /// use tokio::sync::mpsc;
     ///
     /// #[tokio::main]
     /// async fn main() {
+    ///     let (tx_incoming_message, mut rx_incoming_message) = mpsc::channel(100);
+    ///     let (tx_feedback, mut rx_feedback) = mpsc::channel(100);
+    ///     let builder = IotHubClient::builder()
+    ///         .observe_incoming_messages(IncomingMessageObserver::new(tx_incoming_message, vec![]))
+    ///         .observe_incoming_message_feedback(tx_feedback);
+    ///
     ///     #[cfg(feature = "edge_client")]
-    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     let mut client = builder.build_edge_client().unwrap();
     ///     #[cfg(feature = "device_client")]
-    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     let mut client = builder.build_device_client("my-connection-string").unwrap();
     ///     #[cfg(feature = "module_client")]
-    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///     let mut client = builder.build_module_client("my-connection-string").unwrap();
+    ///
+    ///     while let Some(feedback) = rx_feedback.recv().await {
+    ///         println!(
+    ///             "{:?}: {:?} after {:?}",
+    ///             feedback.message_id, feedback.disposition, feedback.duration
+    ///         );
+    ///     }
     /// }
     /// ```
-    pub fn builder() -> IotHubClientBuilder {
-        IotHubClientBuilder::default()
+    pub fn observe_incoming_message_feedback(
+        mut self,
+        tx_feedback: IncomingMessageFeedbackObserver,
+    ) -> Self {
+        self.tx_incoming_message_feedback = Some(tx_feedback);
+        self
     }
 
-    /// Call this function to send a message (D2C) to iothub.
-    /// ```rust, no_run
+    /// Call this function in order to get all internal non-fatal errors signaled as [`ErrorEvent`], e.g. parse
+    /// failures, failed confirmations or options that cannot be applied on reconnect. Thus degradation can be
+    /// detected without scraping logs. Events are dropped if the channel is full.
+    /// ```no_run
     /// use azure_iot_sdk::client::*;
+    /// use tokio::sync::mpsc;
     ///
     /// #[tokio::main]
     /// async fn main() {
+    ///     let (tx_error, mut rx_error) = mpsc::channel(100);
+    ///
     ///     #[cfg(feature = "edge_client")]
-    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     let mut client = IotHubClient::builder().observe_errors(tx_error).build_edge_client().unwrap();
     ///     #[cfg(feature = "device_client")]
-    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     let mut client = IotHubClient::builder().observe_errors(tx_error).build_device_client("my-connection-string").unwrap();
     ///     #[cfg(feature = "module_client")]
-    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
-    ///
-    ///     let msg = IotMessage::builder()
-    ///         .set_body(
-    ///             serde_json::to_vec(r#"{"my telemetry message": "hi from device"}"#).unwrap(),
-    ///         )
-    ///         .set_id("my msg id")
-    ///         .set_correlation_id("my correleation id")
-    ///         .set_property(
-    ///             "my property key",
-    ///             "my property value",
-    ///         )
-    ///         .set_output_queue("my output queue")
-    ///         .build()
-    ///         .unwrap();
+    ///     let mut client = IotHubClient::builder().observe_errors(tx_error).build_module_client("my-connection-string").unwrap();
     ///
-    ///     client.send_d2c_message(msg);
+    ///     while let Some(error) = rx_error.recv().await {
+    ///         println!("{error}");
+    ///     }
     /// }
     /// ```
-    pub fn send_d2c_message(&self, mut message: IotMessage) -> Result<()> {
-        let handle = message.create_outgoing_handle()?;
-        let queue = message.output_queue.clone();
-        let (tx, rx) = oneshot::channel::<bool>();
-        let trace_id = self.trace_id.fetch_add(1, Ordering::Relaxed);
-
-        debug!("send_d2c_message({trace_id}): {queue:?}");
-
-        self.twin.send_event_to_output_async(
-            handle,
-            queue,
-            Some(IotHubClient::c_d2c_confirmation_callback),
-            Box::into_raw(Box::new((tx, trace_id))) as *mut c_void,
-        )?;
-
-        self.spawn_confirmation((rx, trace_id));
-
-        Ok(())
+    pub fn observe_errors(mut self, tx_error: ErrorObserver) -> Self {
+        self.tx_error = Some(tx_error);
+        self
     }
 
-    /// Call this function to report twin properties to iothub.
-    /// ```rust, no_run
+    /// Call this function in order to get [`LifecycleEvent`]s of the client connection signaled, e.g. the
+    /// transport selected by [`IotHubClientBuilder::websocket_fallback`]. Events are dropped if the channel is full.
+    /// ```no_run
     /// use azure_iot_sdk::client::*;
-    /// use serde_json::json;
+    /// use tokio::sync::mpsc;
     ///
     /// #[tokio::main]
     /// async fn main() {
+    ///     let (tx_lifecycle, mut rx_lifecycle) = mpsc::channel(100);
+    ///
     ///     #[cfg(feature = "edge_client")]
-    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     let mut client = IotHubClient::builder().observe_lifecycle(tx_lifecycle).build_edge_client().unwrap();
     ///     #[cfg(feature = "device_client")]
-    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     let mut client = IotHubClient::builder().observe_lifecycle(tx_lifecycle).build_device_client("my-connection-string").unwrap();
     ///     #[cfg(feature = "module_client")]
-    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
-    ///
-    ///     let reported = json!({
-    ///         "my_status": {
-    ///             "status": "ok",
-    ///             "timestamp": "2022-03-10",
-    ///         }
-    ///     });
+    ///     let mut client = IotHubClient::builder().observe_lifecycle(tx_lifecycle).build_module_client("my-connection-string").unwrap();
     ///
-    ///     client.twin_report(reported);
+    ///     while let Some(event) = rx_lifecycle.recv().await {
+    ///         println!("{event:?}");
+    ///     }
     /// }
     /// ```
-    pub fn twin_report(&self, reported: serde_json::Value) -> Result<()> {
-        let trace_id = self.trace_id.fetch_add(1, Ordering::Relaxed);
-        debug!("send reported({trace_id}): {reported:?}");
-
-        let reported_state = CString::new(reported.to_string())?;
-        let size = reported_state.as_bytes().len();
-        let (tx, rx) = oneshot::channel::<bool>();
-
-        self.twin.send_reported_state(
-            reported_state,
-            size,
-            Some(IotHubClient::c_reported_twin_callback),
-            Box::into_raw(Box::new((tx, trace_id))) as *mut c_void,
-        )?;
-
-        self.spawn_confirmation((rx, trace_id));
-
-        Ok(())
+    pub fn observe_lifecycle(mut self, tx_lifecycle: LifecycleObserver) -> Self {
+        self.tx_lifecycle = Some(tx_lifecycle);
+        self
     }
 
-    /// Call this function to trigger a twin update that is asynchronously signaled as twin_desired stream.
-    /// ```rust, no_run
+    /// Call this function in order to get every failed connection attempt, that is retried by azure-sdk-c,
+    /// signaled as [`RetryEvent`]. Thus devices stuck in retry loops can be detected and alerted on. The end of
+    /// retries is signaled as [`UnauthenticatedReason::RetryExpired`] by the authentication observer. Events are
+    /// dropped if the channel is full.
+    /// ```no_run
     /// use azure_iot_sdk::client::*;
-    /// use serde_json::json;
+    /// use tokio::sync::mpsc;
     ///
     /// #[tokio::main]
     /// async fn main() {
+    ///     let (tx_retry, mut rx_retry) = mpsc::channel(100);
+    ///
     ///     #[cfg(feature = "edge_client")]
-    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     let mut client = IotHubClient::builder().observe_retry_events(tx_retry).build_edge_client().unwrap();
     ///     #[cfg(feature = "device_client")]
-    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     let mut client = IotHubClient::builder().observe_retry_events(tx_retry).build_device_client("my-connection-string").unwrap();
     ///     #[cfg(feature = "module_client")]
-    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///     let mut client = IotHubClient::builder().observe_retry_events(tx_retry).build_module_client("my-connection-string").unwrap();
     ///
-    ///     client.twin_async();
+    ///     let mut attempts = 0;
+    ///
+    ///     while let Some(RetryEvent { reason }) = rx_retry.recv().await {
+    ///         attempts += 1;
+    ///
+    ///         if attempts > 10 {
+    ///             println!("stuck in retry loop: {reason:?}");
+    ///         }
+    ///     }
     /// }
     /// ```
-    pub fn twin_async(&mut self) -> Result<()> {
-        debug!("twin_complete: get entire twin");
-
-        let Some(tx) = self.tx_twin_desired.as_deref_mut() else {
-            anyhow::bail!("twin observer not present")
-        };
-
-        self.twin.twin_async(
-            Some(IotHubClient::c_twin_callback),
-            tx as *mut TwinObserver as *mut c_void,
-        )
+    pub fn observe_retry_events(mut self, tx_retry: RetryObserver) -> Self {
+        self.tx_retry = Some(tx_retry);
+        self
     }
 
-    /// Call this function to properly shutdown IotHub. All reported properties and D2C messages will be
-    /// continued to completion.
-    /// ```rust, no_run
+    /// Call this function to reserve the desired properties section `section` for managed configuration of the
+    /// client. Supported settings in this section are applied at runtime, without rebuilding the client.
+    /// Thus fleet operators can tune the SDK remotely:
+    /// ```json
+    /// {
+    ///     "log_level": "info",
+    ///     "do_work_freq_ms": 10,
+    ///     "retry_policy": { "policy": "ExponentialBackoffWithJitter", "timeout_secs": 0 }
+    /// }
+    /// ```
+    /// `log_level` sets the maximum level of the `log` crate and thus affects the whole application.
+    /// The result is reported in the same section of the reported properties:
+    /// ```json
+    /// { "applied": { "log_level": "info" }, "rejected": { "sampling_rate": "unsupported setting" }, "desired_version": 7 }
+    /// ```
+    /// The section is removed from [`TwinUpdate`]s before they are signaled to the twin observer. Settings
+    /// applied by this section take precedence over the client options and are applied again on reconnect,
+    /// since the complete twin is received then.<br>
+    /// ***Note***: twin property names must not contain '.', '$' and ' ', so that e.g. "sdk_config" can be used.
+    /// ```no_run
     /// use azure_iot_sdk::client::*;
-    /// use serde_json::json;
     ///
     /// #[tokio::main]
     /// async fn main() {
     ///     #[cfg(feature = "edge_client")]
-    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     let mut client = IotHubClient::builder()
+    ///         .managed_configuration("sdk_config")
+    ///         .build_edge_client()
+    ///         .unwrap();
     ///     #[cfg(feature = "device_client")]
-    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
-    ///     #[cfg(feature = "module_client")]
-    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///     let mut client = IotHubClient::builder()
+    ///         .managed_configuration("sdk_config")
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .managed_configuration("sdk_config")
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn managed_configuration(mut self, section: &str) -> Self {
+        self.managed_configuration = Some(section.to_string());
+        self
+    }
+
+    #[cfg(feature = "encryption")]
+    /// Call this function to encrypt the body of all outgoing D2C messages and to decrypt the body of all incoming
+    /// C2D messages end-to-end by AES-256-GCM, with keys provided by `provider`. Thus payloads aren't readable by
+    /// iothub, e.g. if data at rest on the hub side isn't trusted. Incoming messages that are not encrypted or
+    /// cannot be decrypted are rejected and signaled as [`DeadLetterReason::ParseFailure`]. See [`KeyProvider`]
+    /// for the message format.<br>
+    /// ***Note1***: encrypted bodies can't be queried by iothub message routing.<br>
+    /// ***Note2***: this function is only available with "encryption" feature enabled.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    /// use std::sync::Arc;
+    ///
+    /// struct StaticKey([u8; 32]);
+    ///
+    /// #[cfg(feature = "encryption")]
+    /// impl KeyProvider for StaticKey {
+    ///     fn current_key(&self) -> anyhow::Result<(String, [u8; 32])> {
+    ///         Ok(("key-1".to_string(), self.0))
+    ///     }
+    ///
+    ///     fn key(&self, key_id: &str) -> anyhow::Result<[u8; 32]> {
+    ///         anyhow::ensure!(key_id == "key-1", "unknown key {key_id}");
+    ///         Ok(self.0)
+    ///     }
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(all(feature = "encryption", feature = "edge_client"))]
+    ///     let mut client = IotHubClient::builder()
+    ///         .encrypt_payloads(Arc::new(StaticKey([0; 32])))
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(all(feature = "encryption", feature = "device_client"))]
+    ///     let mut client = IotHubClient::builder()
+    ///         .encrypt_payloads(Arc::new(StaticKey([0; 32])))
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(all(feature = "encryption", feature = "module_client"))]
+    ///     let mut client = IotHubClient::builder()
+    ///         .encrypt_payloads(Arc::new(StaticKey([0; 32])))
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn encrypt_payloads(mut self, provider: Arc<dyn KeyProvider>) -> Self {
+        self.encryption = Some(encryption::Encryption(provider));
+        self
+    }
+
+    /// Call this function to record every incoming direct method and C2D message together with its result
+    /// and handler latency as [`AuditRecord`]. Records can be obtained by [`IotHubClient::audit_trail`] and are
+    /// part of [`IotHubClient::support_bundle`].
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .audit_inbound_commands(true)
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .audit_inbound_commands(true)
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .audit_inbound_commands(true)
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn audit_inbound_commands(mut self, enable: bool) -> Self {
+        self.audit_inbound_commands = enable;
+        self
+    }
+
+    /// Call this function to stamp every outgoing D2C message with the current UTC time as RFC 3339 string,
+    /// e.g. "2024-01-31T12:30:00.123Z". The time is taken from a clock that is anchored at client creation
+    /// and advanced by the monotonic clock, so stamps stay consistent on small adjustments of the system time.
+    /// The clock is re-anchored if the system time deviates by more than 2 seconds, e.g. once the wall clock
+    /// is synced after boot.
+    /// `property` defaults to [`CREATION_TIME_UTC_PROPERTY`]. Property key and value are URL-encoded like
+    /// properties added by [`IotMessageBuilder::set_property`]. An already existing property isn't overwritten.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .timestamp_messages(None)
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .timestamp_messages(None)
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .timestamp_messages(Some("my-timestamp"))
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn timestamp_messages(mut self, property: Option<&str>) -> Self {
+        self.timestamp_property = Some(property.unwrap_or(CREATION_TIME_UTC_PROPERTY).to_string());
+        self
+    }
+
+    /// Call this function to assign a random UUIDv4 message id to every outgoing D2C message without one,
+    /// so that duplicate detection and end-to-end tracing by message id work consistently. Message ids set
+    /// by [`IotMessageBuilder::set_id`] aren't overwritten.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .auto_message_ids(true)
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .auto_message_ids(true)
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .auto_message_ids(true)
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn auto_message_ids(mut self, enable: bool) -> Self {
+        self.auto_message_ids = enable;
+        self
+    }
+
+    /// Call this function to split D2C messages with bodies above 192 KiB into chunks, which are sent as
+    /// separate messages within the 256 KB message size limit of iothub. Otherwise such messages are rejected
+    /// by iothub. All chunks keep the properties of the message and carry the transfer id, i.e. the message id
+    /// or a random UUID, as well as chunk index and count as properties, see [`ChunkAssembler`] for their
+    /// reassembly by consumers. Message id and idempotency key of each chunk are suffixed by its index.
+    /// The confirmation of a chunked message succeeds if all chunks are confirmed successfully.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .chunk_oversized_messages(true)
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .chunk_oversized_messages(true)
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .chunk_oversized_messages(true)
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    ///
+    ///     let msg = IotMessage::builder()
+    ///         .set_body(vec![0u8; 1024 * 1024])
+    ///         .set_id("log-bundle-42")
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     client.send_d2c_message_confirmed(msg).await.unwrap();
+    /// }
+    /// ```
+    pub fn chunk_oversized_messages(mut self, enable: bool) -> Self {
+        self.chunk_oversized_messages = enable;
+        self
+    }
+
+    /// Call this function to declare an application property, e.g. site id or firmware version, that is added to
+    /// every outgoing D2C message. Properties set on the message itself by [`IotMessageBuilder::set_property`]
+    /// aren't overwritten. Can be called multiple times in order to declare multiple properties.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .default_message_property("site-id", "my-site")
+    ///         .default_message_property("firmware-version", "1.2.3")
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .default_message_property("site-id", "my-site")
+    ///         .default_message_property("firmware-version", "1.2.3")
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .default_message_property("site-id", "my-site")
+    ///         .default_message_property("firmware-version", "1.2.3")
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn default_message_property(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.default_properties
+            .insert(message::urlencode(key), message::urlencode(value));
+        self
+    }
+
+    /// Call this function to assign an idempotency key generated by `keys` to every outgoing D2C message that has
+    /// none set by [`IotMessageBuilder::set_idempotency_key`]. The key is sent as property
+    /// [`IDEMPOTENCY_KEY_PROPERTY`] and assigned once on [`IotHubClient::send_d2c_message`], so that it is
+    /// preserved if the message is sent again, e.g. by retries of azure-sdk-c or after it was buffered by
+    /// [`IotHubClient::suspend`]. Thus backend consumers can deduplicate messages by their key.<br>
+    /// ***Note***: copies sent by [`IotHubClient::send_d2c_message_with`] share the key of the prepared message
+    /// if it has one.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let keys = IdempotencyKeys::persistent("my-device", "/var/lib/my-service/idempotency").unwrap();
+    ///
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().idempotency_keys(keys).build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().idempotency_keys(keys).build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().idempotency_keys(keys).build_module_client("my-connection-string").unwrap();
+    /// }
+    /// ```
+    pub fn idempotency_keys(mut self, keys: IdempotencyKeys) -> Self {
+        self.idempotency_keys = Some(Arc::new(keys));
+        self
+    }
+
+    /// Call this function to persist outgoing D2C messages in `store` while the client is not authenticated
+    /// or if their confirmation failed or timed out. Stored messages are replayed in order once the client is
+    /// authenticated again, while new messages are stored behind them as long as the store isn't empty. If a
+    /// limit of the store is exceeded, the oldest messages are dropped and confirmed as
+    /// [`ConfirmationOutcome::Failed`]. The confirmation of a stored message is passed to
+    /// [`IotHubClientBuilder::on_confirmation`] when its replay succeeded.<br>
+    /// ***Note***: delivery is at-least-once, e.g. a message whose confirmation timed out might arrive twice.
+    /// Consider [`IotHubClientBuilder::idempotency_keys`] to deduplicate messages. Output shardings and secondary
+    /// hubs aren't applied to replayed messages.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let store = OfflineStore::new("/var/lib/my-service/offline")
+    ///         .max_messages(1000)
+    ///         .max_bytes(16 * 1024 * 1024);
+    ///
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().offline_store(store).build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().offline_store(store).build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().offline_store(store).build_module_client("my-connection-string").unwrap();
+    /// }
+    /// ```
+    pub fn offline_store(mut self, store: OfflineStore) -> Self {
+        self.offline_store = Some(store);
+        self
+    }
+
+    /// Call this function to persist incoming C2D messages in `store` that were rejected or abandoned due to a
+    /// failure, e.g. a handler error, a result that wasn't sent in time or a body that couldn't be parsed. Each
+    /// message is stored with its body, all application properties and the failure, so rejected commands can be
+    /// analyzed later by [`IotHubClient::rejected_messages`]. If a limit of the store is exceeded, the oldest
+    /// messages are dropped. Messages disposed by [`IncomingMessageObserver::dispose`] aren't stored.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let store = RejectedMessageStore::new("/var/lib/my-service/rejected").max_messages(20);
+    ///
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().rejected_message_store(store).build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().rejected_message_store(store).build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().rejected_message_store(store).build_module_client("my-connection-string").unwrap();
+    /// }
+    /// ```
+    pub fn rejected_message_store(mut self, store: RejectedMessageStore) -> Self {
+        self.rejected_message_store = Some(store);
+        self
+    }
+
+    /// Call this function to retransmit D2C messages whose confirmation failed or timed out according to
+    /// `policy`, instead of losing them. A copy of each message is kept until it is confirmed. Retransmissions
+    /// keep the trace id of the message and their outcome is signaled as the confirmation of the message. If the
+    /// retries are exhausted, the message is signaled as [`DeadLetter::D2cMessage`] to the observer registered
+    /// by [`IotHubClientBuilder::observe_dead_letters`]. The client cannot be built if
+    /// [`IotHubClientBuilder::offline_store`] is set too, since the store replays failed messages itself.<br>
+    /// ***Note***: messages are held in memory while they are retransmitted, i.e. they are lost on restart.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    /// use std::time::Duration;
+    /// use tokio::sync::mpsc;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (tx_dead_letter, mut rx_dead_letter) = mpsc::channel(100);
+    ///     let builder = IotHubClient::builder()
+    ///         .retransmit_failed_messages(RetransmitPolicy::new(3))
+    ///         .observe_dead_letters(tx_dead_letter);
+    ///
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = builder.build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = builder.build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = builder.build_module_client("my-connection-string").unwrap();
+    ///
+    ///     while let Some(dead_letter) = rx_dead_letter.recv().await {
+    ///         if let DeadLetter::D2cMessage { message, .. } = dead_letter {
+    ///             // e.g. persist the message for later upload
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn retransmit_failed_messages(mut self, policy: RetransmitPolicy) -> Self {
+        self.retransmit_policy = Some(policy);
+        self
+    }
+
+    /// Call this function to set the [`SerializerRegistry`] used by [`IotHubClient::serialized_message`].
+    /// Thus the wire format of message bodies, e.g. JSON, CBOR or MessagePack, is selected per client by its
+    /// default format and can be changed without touching call sites. Default is [`SerializerRegistry::new`].
+    /// The client cannot be built if no serializer is registered for the default format.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let serializers = SerializerRegistry::new().default_format(FORMAT_JSON);
+    ///
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .serializers(serializers)
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .serializers(serializers)
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .serializers(serializers)
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn serializers(mut self, serializers: SerializerRegistry) -> Self {
+        self.serializers = serializers;
+        self
+    }
+
+    /// Call this function to check outgoing D2C messages against the telemetry contract in `schemas`. Messages
+    /// declaring a schema by [`IotMessageBuilder::set_schema`] are rejected by [`IotHubClient::send_d2c_message`]
+    /// and its variants if the schema isn't registered or, if enabled by
+    /// [`SchemaRegistry::validate_before_send`], if their JSON body violates it. See [`SchemaRegistry::validate`].
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    /// use serde_json::json;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let schemas = SchemaRegistry::new()
+    ///         .register("temperature", "1", json!({"type": "object", "required": ["celsius"]}))
+    ///         .unwrap()
+    ///         .require_schema(true);
+    ///
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .telemetry_schemas(schemas)
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .telemetry_schemas(schemas)
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .telemetry_schemas(schemas)
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    ///
+    ///     let msg = client
+    ///         .serialized_message(None, &json!({"celsius": 21.5}))
+    ///         .unwrap()
+    ///         .set_schema("temperature", "1")
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     client.send_d2c_message(msg).await.unwrap();
+    /// }
+    /// ```
+    pub fn telemetry_schemas(mut self, schemas: SchemaRegistry) -> Self {
+        self.telemetry_schemas = Some(schemas);
+        self
+    }
+
+    /// Set an Azure IoT Plug & Play model id. The id is validated as [`ModelId`] when the client is built.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    /// use std::{thread, time};
+    /// use tokio::{select, sync::mpsc};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .pnp_model_id("dtmi:com:example:Thermostat;1")
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .pnp_model_id("dtmi:com:example:Thermostat;1")
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .pnp_model_id("dtmi:com:example:Thermostat;1")
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn pnp_model_id(mut self, model_id: impl Into<String>) -> Self {
+        self.model_id = Some(model_id.into());
+        self
+    }
+
+    /// Call this function to set the behavior if the pnp model id set by [`IotHubClientBuilder::pnp_model_id`]
+    /// is not supported by client type or transport. Default is [`UnsupportedModelIdPolicy::Error`].
+    /// Rejections by iothub while connecting aren't covered, see [`UnsupportedModelIdPolicy`].
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .pnp_model_id("dtmi:com:example:Thermostat;1")
+    ///         .unsupported_model_id_policy(UnsupportedModelIdPolicy::Warn)
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .pnp_model_id("dtmi:com:example:Thermostat;1")
+    ///         .unsupported_model_id_policy(UnsupportedModelIdPolicy::Warn)
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .pnp_model_id("dtmi:com:example:Thermostat;1")
+    ///         .unsupported_model_id_policy(UnsupportedModelIdPolicy::Warn)
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn unsupported_model_id_policy(mut self, policy: UnsupportedModelIdPolicy) -> Self {
+        self.unsupported_model_id_policy = policy;
+        self
+    }
+
+    /// Call this function to register the components of the Azure IoT Plug & Play model set by
+    /// [`IotHubClientBuilder::pnp_model_id`]. Telemetry and reported properties of registered components are
+    /// shaped according to the IoT Plug and Play conventions by [`IotHubClient::send_d2c_component_message`] and
+    /// [`IotHubClient::twin_report_component`]. Building the client fails if a component name is invalid or no
+    /// model id is set.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .pnp_model_id("dtmi:com:example:TemperatureController;1")
+    ///         .pnp_components(&["thermostat1", "deviceInfo"])
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .pnp_model_id("dtmi:com:example:TemperatureController;1")
+    ///         .pnp_components(&["thermostat1", "deviceInfo"])
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .pnp_model_id("dtmi:com:example:TemperatureController;1")
+    ///         .pnp_components(&["thermostat1", "deviceInfo"])
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn pnp_components(mut self, components: &[&str]) -> Self {
+        self.pnp_components = components.iter().map(|c| c.to_string()).collect();
+        self
+    }
+
+    /// Call this function to set the [`Transport`] used to connect to iothub. Default is [`Transport::Mqtt`].
+    /// Use [`Transport::MqttWebSocket`] or [`Transport::AmqpWebSocket`] if only port 443 is allowed by firewalls.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .transport(Transport::MqttWebSocket)
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .transport(Transport::MqttWebSocket)
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .transport(Transport::MqttWebSocket)
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Call this function to fall back to the websocket variant of the configured [`Transport`] via port 443,
+    /// e.g. [`Transport::MqttWebSocket`] instead of [`Transport::Mqtt`], if the port of the configured transport
+    /// is blocked by a firewall. The port is probed by a tcp connect on every connect of the client, which blocks
+    /// for at most 5 seconds per address of the host. The selected transport is signaled as
+    /// [`LifecycleEvent::TransportSelected`] (see [`IotHubClientBuilder::observe_lifecycle`]).<br>
+    /// ***Note***: this setting is ignored by transports that already connect via port 443.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .websocket_fallback(true)
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .websocket_fallback(true)
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .websocket_fallback(true)
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn websocket_fallback(mut self, enable: bool) -> Self {
+        self.websocket_fallback = enable;
+        self
+    }
+
+    /// Call this function to configure [`Transport::Http`]. If `batching` is enabled, pending D2C messages
+    /// are sent in batches. `min_polling_time` defines the minimum interval C2D messages are polled by.<br>
+    /// ***Note***: this setting is ignored by other transports.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .transport(Transport::Http)
+    ///         .http_settings(true, Duration::from_secs(1500))
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn http_settings(mut self, batching: bool, min_polling_time: Duration) -> Self {
+        self.http_setting = Some(HttpSetting {
+            batching,
+            min_polling_time,
+        });
+        self
+    }
+
+    /// Call this function to distribute D2C messages sent to output queue `output` across the output queues
+    /// `outputs` by [`ShardingStrategy`], e.g. in order to parallelize processing by edgeHub routes.<br>
+    /// ***Note***: output queues are only considered by module and edge clients.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .shard_output("output", vec!["output1", "output2"], ShardingStrategy::RoundRobin)
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .shard_output(
+    ///             "output",
+    ///             vec!["output1", "output2"],
+    ///             ShardingStrategy::PropertyHash("sensor".to_string()),
+    ///         )
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn shard_output(
+        mut self,
+        output: &str,
+        outputs: Vec<&str>,
+        strategy: ShardingStrategy,
+    ) -> Self {
+        if outputs.is_empty() {
+            warn!("ignore sharding of output {output} since no outputs are given");
+            return self;
+        }
+
+        self.output_shardings.insert(
+            output.to_string(),
+            (outputs.iter().map(|o| o.to_string()).collect(), strategy),
+        );
+        self
+    }
+
+    #[cfg(feature = "edge_client")]
+    /// Call this function to declare the output queues the module sends D2C messages to, i.e. the outputs
+    /// routed by edgeHub. Sending to an output that isn't declared fails, so that a typo or a mismatch with
+    /// the routes is caught at send time instead of being silently dropped by edgeHub. Outputs sharded by
+    /// [`IotHubClientBuilder::shard_output`] must be declared, the sharded output itself doesn't.
+    /// [`IotHubClient::output_metrics`] provides delivery metrics per declared output.<br>
+    /// ***Note***: this function is only available with "edge_client" feature enabled.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     {
+    ///         let client = IotHubClient::builder()
+    ///             .outputs(&["telemetry", "alerts"])
+    ///             .build_edge_client()
+    ///             .unwrap();
+    ///
+    ///         let message = IotMessage::builder()
+    ///             .set_body(vec![])
+    ///             .set_output_queue("telemetyr")
+    ///             .build()
+    ///             .unwrap();
+    ///
+    ///         assert!(client.send_d2c_message(message).await.is_err());
+    ///     }
+    /// }
+    /// ```
+    pub fn outputs(mut self, outputs: &[&str]) -> Self {
+        self.outputs = Some(outputs.iter().map(|o| o.to_string()).collect());
+        self
+    }
+
+    #[cfg(any(feature = "module_client", feature = "device_client"))]
+    /// Call this function to additionally publish D2C messages sent to one of `outputs` to a secondary hub,
+    /// e.g. a regional hub and an analytics hub. The secondary hub is identified by `name` and connected by
+    /// `connection_string` with the same transport and options as the client. Its connection state is tracked
+    /// independently and can be requested by [`IotHubClient::secondary_hub_status`].<br>
+    /// ***Note1***: confirmations of secondary hubs are not passed to [`IotHubClientBuilder::on_confirmation`],
+    /// failures are logged and part of [`IotHubClient::support_bundle`].<br>
+    /// ***Note2***: this function is only available with "device_client" or "module_client" feature enabled.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .secondary_hub("analytics", "my-analytics-connection-string", vec!["telemetry"])
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .secondary_hub("analytics", "my-analytics-connection-string", vec!["telemetry"])
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn secondary_hub(
+        mut self,
+        name: &str,
+        connection_string: &str,
+        outputs: Vec<&str>,
+    ) -> Self {
+        if outputs.is_empty() {
+            warn!("ignore secondary hub {name} since no outputs are given");
+            return self;
+        }
+
+        self.secondary_hubs.retain(|hub| hub.name != name);
+        self.secondary_hubs.push(SecondaryHubSetting {
+            name: name.to_string(),
+            connection_string: connection_string.to_string(),
+            outputs: outputs.iter().map(|o| o.to_string()).collect(),
+        });
+        self
+    }
+
+    /// Call this function to set the restart policy used for connecting to iot-hub.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    /// use std::{thread, time};
+    /// use tokio::{select, sync::mpsc};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .retry_policy(RetryPolicy::None, 0)
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .retry_policy(RetryPolicy::None, 0)
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .retry_policy(RetryPolicy::None, 0)
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn retry_policy(mut self, policy: RetryPolicy, timeout_secs: u32) -> Self {
+        self.retry_setting = Some(RetrySetting {
+            policy,
+            timeout_secs,
+        });
+        self
+    }
+
+    /// Call this function to bound the worst-case latency the client adds to the azure-sdk-c worker thread
+    /// according to [`LatencyProfile`], e.g. for real-time gateways. By default callbacks block until incoming
+    /// work other than C2D messages is handed over to observers, see [`IotHubClientBuilder::incoming_overflow_policy`],
+    /// and until C2D messages and direct methods are answered, which delays
+    /// all subsequent callbacks and sends. With a profile applied incoming work is rejected immediately if an
+    /// observer channel is full and results not sent within [`LatencyProfile::result_timeout`] are dead lettered.<br>
+    /// ***Note***: observer channels should be sized for bursts, since overflowing work is lost. Keep handlers
+    /// of C2D messages and direct methods short and finish long-running work by
+    /// [`IotHubClientBuilder::long_running_methods`] instead.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let profile = LatencyProfile {
+    ///         result_timeout: Duration::from_millis(50),
+    ///         ..Default::default()
+    ///     };
+    ///
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().bounded_latency(profile).build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().bounded_latency(profile).build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().bounded_latency(profile).build_module_client("my-connection-string").unwrap();
+    /// }
+    /// ```
+    pub fn bounded_latency(mut self, profile: LatencyProfile) -> Self {
+        self.latency_profile = Some(profile);
+        self
+    }
+
+    /// Call this function to set the [`OverflowPolicy`] applied to incoming C2D messages if the channel of their
+    /// [`IncomingMessageObserver`] is full. By default the azure-sdk-c worker thread is blocked until the channel
+    /// has room, see [`OverflowPolicy::Block`]. Messages that are abandoned or rejected due to a full channel are
+    /// signaled as [`DeadLetter`] with [`DeadLetterReason::ChannelFull`]. The client cannot be built with a buffer
+    /// of size 0.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    /// use tokio::sync::mpsc;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (tx_incoming_message, mut rx_incoming_message) = mpsc::channel(10);
+    ///     let builder = IotHubClient::builder()
+    ///         .observe_incoming_messages(IncomingMessageObserver::new(tx_incoming_message, vec![]))
+    ///         .incoming_overflow_policy(OverflowPolicy::Abandon);
+    ///
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = builder.build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = builder.build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = builder.build_module_client("my-connection-string").unwrap();
+    /// }
+    /// ```
+    pub fn incoming_overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.incoming_overflow_policy = policy;
+        self
+    }
+
+    /// Call this function to limit the number of D2C messages sent but not yet confirmed by iothub to
+    /// `max_in_flight`. If the limit is reached, [`IotHubClient::send_d2c_message`] and its variants apply
+    /// backpressure by waiting until a pending confirmation succeeds, fails or times out. Thus neither the
+    /// confirmation tasks nor the send queue of azure-sdk-c grow without bound under sustained load. Default is
+    /// no limit. The client cannot be built if `max_in_flight` is 0.<br>
+    /// ***Note***: messages buffered by [`IotHubClient::suspend`] or stored by
+    /// [`IotHubClientBuilder::offline_store`] aren't in flight until they are actually sent.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().max_in_flight(100).build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().max_in_flight(100).build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().max_in_flight(100).build_module_client("my-connection-string").unwrap();
+    /// }
+    /// ```
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = Some(max_in_flight);
+        self
+    }
+
+    /// Call this function to pace D2C messages at a rate adapted to iothub throttling instead of hammering the
+    /// hub and burning the daily quota. [`IotHubClient::send_d2c_message`] and its variants wait for the next
+    /// send slot of the current rate, which starts at [`AdaptiveRateLimit::max_rate`]. If
+    /// [`AdaptiveRateLimit::failures`] confirmations in a row fail or time out, throttling is assumed: the rate
+    /// is halved and [`LifecycleEvent::Throttled`] is signaled. Succeeded confirmations raise the rate again.
+    /// The current rate is returned by [`IotHubClient::d2c_rate`]. The client cannot be built if a rate isn't
+    /// positive and finite.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let limit = AdaptiveRateLimit::new(10.0).slow_down_to(0.1);
+    ///
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().adaptive_rate_limit(limit).build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().adaptive_rate_limit(limit).build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().adaptive_rate_limit(limit).build_module_client("my-connection-string").unwrap();
+    /// }
+    /// ```
+    pub fn adaptive_rate_limit(mut self, limit: AdaptiveRateLimit) -> Self {
+        self.adaptive_rate_limit = Some(limit);
+        self
+    }
+
+    /// Call this function to drop D2C messages whose message id was already sent within `window`, so that
+    /// retry logic at higher layers cannot send the same message twice after an ambiguous confirmation timeout.
+    /// A dropped duplicate returns the trace id of the message sent first and is considered confirmed by
+    /// [`IotHubClient::send_d2c_message_confirmed`]. Messages without message id aren't deduplicated. If a send
+    /// fails before the message is handed over, its message id can be sent again right away. Retransmissions
+    /// by [`IotHubClientBuilder::retransmit_failed_messages`] stop after a confirmation timeout, since the
+    /// message might have been delivered.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().dedup_window(Duration::from_secs(600)).build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().dedup_window(Duration::from_secs(600)).build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().dedup_window(Duration::from_secs(600)).build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let msg = IotMessage::builder()
+    ///         .set_body(b"my telemetry".to_vec())
+    ///         .set_id("reading-42")
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     client.send_d2c_message(msg).await.unwrap();
+    /// }
+    /// ```
+    pub fn dedup_window(mut self, window: Duration) -> Self {
+        self.dedup_window = Some(window);
+        self
+    }
+
+    /// Call this function to supervise the connection by a watchdog. If the client stays unauthenticated for
+    /// `dead_after`, e.g. after [`UnauthenticatedReason::RetryExpired`], the underlying azure-sdk-c handle is
+    /// destroyed and recreated with all callbacks and options applied. A successful recreation is signaled as
+    /// [`LifecycleEvent::Recovered`], a failed one as [`ErrorEvent::RecoveryFailure`] and retried after
+    /// `dead_after`.<br>
+    /// ***Note***: the watchdog recreates the handle with the options of the last connect, e.g. a retry policy
+    /// changed by [`IotHubClient::set_retry_policy`] is applied again on the next connect. This is a shorthand
+    /// for [`IotHubClientBuilder::restart_policy`] with [`RestartPolicy::on_stall`].
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .connection_watchdog(Duration::from_secs(600))
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .connection_watchdog(Duration::from_secs(600))
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .connection_watchdog(Duration::from_secs(600))
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn connection_watchdog(mut self, dead_after: Duration) -> Self {
+        self.restart_policy = Some(RestartPolicy::on_stall(dead_after));
+        self
+    }
+
+    /// Call this function to let a watchdog restart the client according to `policy`, so that a device heals
+    /// itself without supervisor code. The client is restarted by recreating the underlying azure-sdk-c handle
+    /// like [`IotHubClientBuilder::connection_watchdog`] does if it stays unauthenticated for
+    /// [`RestartPolicy::stall_after`]. If [`RestartPolicy::on_quality_below`] is set, it is also restarted while
+    /// authenticated if [`IotHubClient::connection_quality`] drops below the threshold. This is signaled as
+    /// [`LifecycleEvent::QualityRestart`] and the quality is sampled again afterwards.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let policy = RestartPolicy::on_stall(Duration::from_secs(600)).on_quality_below(0.5);
+    ///
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().restart_policy(policy).build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().restart_policy(policy).build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().restart_policy(policy).build_module_client("my-connection-string").unwrap();
+    /// }
+    /// ```
+    pub fn restart_policy(mut self, policy: RestartPolicy) -> Self {
+        self.restart_policy = Some(policy);
+        self
+    }
+
+    #[cfg(feature = "systemd")]
+    /// Call this function to tie the supervision of a systemd service to the hub connectivity of the client.
+    /// `READY=1` is notified once the client is authenticated for the first time, thus the service should be
+    /// of `Type=notify`. If `WatchdogSec=` is configured for the service, the watchdog is petted as long as the
+    /// client is authenticated and no confirmation is pending longer than the confirmation timeout.<br>
+    /// ***Note***: this function is only available with "systemd" feature enabled.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(all(feature = "systemd", feature = "edge_client"))]
+    ///     let mut client = IotHubClient::builder()
+    ///         .notify_systemd(true)
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(all(feature = "systemd", feature = "device_client"))]
+    ///     let mut client = IotHubClient::builder()
+    ///         .notify_systemd(true)
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(all(feature = "systemd", feature = "module_client"))]
+    ///     let mut client = IotHubClient::builder()
+    ///         .notify_systemd(true)
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn notify_systemd(mut self, enable: bool) -> Self {
+        self.notify_systemd = enable;
+        self
+    }
+
+    /// Call this function to set the lifetime of SAS tokens and the margin before expiry at which tokens get renewed.
+    /// By default azure-sdk-c uses a lifetime of 3600s and renews tokens after 1800s.<br>
+    /// ***Note***: `renewal_margin` must be smaller than `lifetime`, otherwise building the client fails.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .sas_token_lifetime(Duration::from_secs(900), Duration::from_secs(300))
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .sas_token_lifetime(Duration::from_secs(900), Duration::from_secs(300))
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .sas_token_lifetime(Duration::from_secs(900), Duration::from_secs(300))
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn sas_token_lifetime(mut self, lifetime: Duration, renewal_margin: Duration) -> Self {
+        self.sas_token_setting = Some(SasTokenSetting {
+            lifetime,
+            renewal_margin,
+        });
+        self
+    }
+
+    /// Call this function to let `percentage` of D2C messages carry diagnostic correlation headers, so that
+    /// iothub traces them end-to-end, e.g. for distributed tracing with Azure Monitor. By default no messages
+    /// are sampled.<br>
+    /// ***Note***: `percentage` must not exceed 100, otherwise building the client fails.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .diagnostic_sampling(10)
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .diagnostic_sampling(10)
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .diagnostic_sampling(10)
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn diagnostic_sampling(mut self, percentage: u32) -> Self {
+        self.diagnostic_sampling_percentage = Some(percentage);
+        self
+    }
+
+    /// Call this function to append `product_info` to the user agent the client announces to iothub, e.g.
+    /// "my-agent/1.2.3", so that connections can be distinguished by application and version in the
+    /// connection telemetry of iothub.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .product_info("my-agent/1.2.3")
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .product_info("my-agent/1.2.3")
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .product_info("my-agent/1.2.3")
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn product_info(mut self, product_info: &str) -> Self {
+        self.product_info = Some(product_info.to_string());
+        self
+    }
+
+    /// Call this function to register a closure that is called with trace id and [`ConfirmationOutcome`]
+    /// of every D2C message and reported properties confirmation. The trace id is returned by
+    /// [`IotHubClient::send_d2c_message`] and [`IotHubClient::twin_report`].
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let on_confirmation = |trace_id, outcome| println!("confirmation({trace_id}): {outcome:?}");
+    ///
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .on_confirmation(on_confirmation)
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .on_confirmation(on_confirmation)
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .on_confirmation(on_confirmation)
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn on_confirmation(
+        mut self,
+        callback: impl Fn(TraceId, ConfirmationOutcome) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_confirmation = Some(ConfirmationCallback(Arc::new(callback)));
+        self
+    }
+
+    /// Call this function to set the [`TraceIdStrategy`] used to generate trace ids returned by
+    /// [`IotHubClient::send_d2c_message`] and [`IotHubClient::twin_report`], e.g. in order to line up device
+    /// logs with an organization-wide correlation scheme. Default is [`TraceIdStrategy::Sequential`].
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .trace_id_strategy(TraceIdStrategy::TenantPrefixed(42))
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .trace_id_strategy(TraceIdStrategy::TenantPrefixed(42))
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .trace_id_strategy(TraceIdStrategy::TenantPrefixed(42))
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn trace_id_strategy(mut self, strategy: TraceIdStrategy) -> Self {
+        self.trace_id_strategy = strategy;
+        self
+    }
+
+    /// Call this function to set trusted CA certificates in PEM format used to establish TLS connections to
+    /// iothub or edgeHub, e.g. on devices with a minimal CA store or when using private root CAs.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let pem = std::fs::read_to_string("/etc/ssl/certs/my-root-ca.pem").unwrap();
+    ///
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .trusted_certs(&pem)
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .trusted_certs(&pem)
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .trusted_certs(&pem)
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn trusted_certs(mut self, pem: &str) -> Self {
+        self.trusted_certs = Some(pem.to_string());
+        self
+    }
+
+    #[cfg(feature = "edge_client")]
+    /// Call this function to automatically apply the trust bundle of the IoT Edge workload API
+    /// (see [`IotHubClient::edge_trust_bundle`]) as trusted certificates. The bundle is fetched on every
+    /// [`IotHubClient::connect`] and appended to certificates set by [`IotHubClientBuilder::trusted_certs`],
+    /// so that a rotated edge CA is picked up on reconnect.<br>
+    /// ***Note***: since fetching the bundle is asynchronous, the client must be built by
+    /// [`IotHubClientBuilder::build_edge_client_lazy`]. [`IotHubClientBuilder::build_edge_client`] fails if set.<br>
+    /// ***Note***: this function is only available with "edge_client" feature enabled.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     {
+    ///         let mut client = IotHubClient::builder()
+    ///             .edge_trust_bundle(true)
+    ///             .build_edge_client_lazy()
+    ///             .unwrap();
+    ///
+    ///         client.connect().await.unwrap();
+    ///     }
+    /// }
+    /// ```
+    pub fn edge_trust_bundle(mut self, enable: bool) -> Self {
+        self.edge_trust_bundle = enable;
+        self
+    }
+
+    #[cfg(feature = "device_client")]
+    /// Call this function to connect a downstream (leaf) device through an IoT Edge gateway.
+    /// `GatewayHostName=<host_name>` is appended to the connection string and `root_ca_pem`, the edge
+    /// root CA certificate in PEM format, is added to the trusted certificates (see [`IotHubClientBuilder::trusted_certs`]).<br>
+    /// ***Note***: this function is only available with "device_client" feature enabled.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root_ca = std::fs::read_to_string("/etc/ssl/certs/azure-iot-test-only.root.ca.cert.pem").unwrap();
+    ///
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .gateway("my-edge-gateway.local", &root_ca)
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn gateway(mut self, host_name: &str, root_ca_pem: &str) -> Self {
+        self.gateway_setting = Some(GatewaySetting {
+            host_name: host_name.to_string(),
+            root_ca: root_ca_pem.to_string(),
+        });
+        self
+    }
+
+    /// Call this function to connect via HTTP(S) proxy. Optional `credentials` are passed as (username, password).<br>
+    /// ***Note***: azure-sdk-c only supports proxies for websocket based transports, e.g. [`Transport::MqttWebSocket`]
+    /// or [`Transport::AmqpWebSocket`].
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .transport(Transport::MqttWebSocket)
+    ///         .http_proxy("my-proxy", 8080, Some(("my-user", "my-password")))
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .transport(Transport::MqttWebSocket)
+    ///         .http_proxy("my-proxy", 8080, Some(("my-user", "my-password")))
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .transport(Transport::MqttWebSocket)
+    ///         .http_proxy("my-proxy", 8080, None)
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn http_proxy(
+        mut self,
+        host_address: &str,
+        port: u16,
+        credentials: Option<(&str, &str)>,
+    ) -> Self {
+        self.proxy_setting = Some(ProxySetting {
+            host_address: host_address.to_string(),
+            port,
+            credentials: credentials
+                .map(|(username, password)| (username.to_string(), password.to_string())),
+        });
+        self
+    }
+
+    /// Call this function to apply all settings of an [`IotHubClientConfig`] at once. Only settings the config
+    /// provides, i.e. that aren't `None`, replace the ones previously set by the corresponding builder functions.
+    /// The config is validated when the client is built.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let config = IotHubClientConfig {
+    ///         transport: Some(Transport::Amqp),
+    ///         do_work_freq_ms: Some(10),
+    ///         ..IotHubClientConfig::default()
+    ///     };
+    ///
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .config(config)
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .config(config)
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .config(config)
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn config(mut self, config: IotHubClientConfig) -> Self {
+        if let Some(transport) = config.transport {
+            self.transport = transport;
+        }
+        if let Some((policy, timeout_secs)) = config.retry_policy {
+            self.retry_setting = Some(RetrySetting {
+                policy,
+                timeout_secs,
+            });
+        }
+        if let Some((lifetime, renewal_margin)) = config.sas_token_lifetime {
+            self.sas_token_setting = Some(SasTokenSetting {
+                lifetime,
+                renewal_margin,
+            });
+        }
+        if let Some((batching, min_polling_time)) = config.http_settings {
+            self.http_setting = Some(HttpSetting {
+                batching,
+                min_polling_time,
+            });
+        }
+        if let Some(http_proxy) = config.http_proxy {
+            self.proxy_setting = Some(ProxySetting {
+                host_address: http_proxy.host_address,
+                port: http_proxy.port,
+                credentials: http_proxy.credentials,
+            });
+        }
+        if let Some(trusted_certs) = config.trusted_certs {
+            self.trusted_certs = Some(trusted_certs);
+        }
+        if let Some(model_id) = config.model_id {
+            self.model_id = Some(model_id);
+        }
+        if let Some(policy) = config.unsupported_model_id_policy {
+            self.unsupported_model_id_policy = policy;
+        }
+        self.do_work_freq_ms = config.do_work_freq_ms.or(self.do_work_freq_ms);
+        self.confirmation_timeout_secs = config
+            .confirmation_timeout_secs
+            .or(self.confirmation_timeout_secs);
+        self.logging = config.logging.or(self.logging);
+        self
+    }
+
+    /// Call this function to get a builder configured by the JSON config file at `path`.
+    /// See [`IotHubClientConfig::from_file`] for the file format.
+    /// ```no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let builder = IotHubClientBuilder::from_config_file("/etc/my-app/iothub.json").unwrap();
+    ///
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = builder.build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = builder.build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = builder.build_module_client("my-connection-string").unwrap();
+    /// }
+    /// ```
+    pub fn from_config_file(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        Ok(IotHubClientBuilder::default().config(IotHubClientConfig::from_file(path)?))
+    }
+
+    /// effective config of this builder, do_work frequency, confirmation timeout and logging fall back to
+    /// environment variables if not set, since these are the only settings configurable by environment
+    fn effective_config(&self) -> IotHubClientConfig {
+        let env = IotHubClientConfig::from_env();
+
+        IotHubClientConfig {
+            transport: Some(self.transport),
+            retry_policy: self
+                .retry_setting
+                .as_ref()
+                .map(|r| (r.policy, r.timeout_secs)),
+            sas_token_lifetime: self
+                .sas_token_setting
+                .as_ref()
+                .map(|s| (s.lifetime, s.renewal_margin)),
+            http_settings: self
+                .http_setting
+                .as_ref()
+                .map(|h| (h.batching, h.min_polling_time)),
+            http_proxy: self.proxy_setting.as_ref().map(|p| HttpProxyConfig {
+                host_address: p.host_address.clone(),
+                port: p.port,
+                credentials: p.credentials.clone(),
+            }),
+            trusted_certs: self.trusted_certs.clone(),
+            model_id: self.model_id.clone(),
+            unsupported_model_id_policy: Some(self.unsupported_model_id_policy),
+            do_work_freq_ms: self.do_work_freq_ms.or(env.do_work_freq_ms),
+            confirmation_timeout_secs: self
+                .confirmation_timeout_secs
+                .or(env.confirmation_timeout_secs),
+            logging: self.logging.or(env.logging),
+        }
+    }
+}
+
+/// iothub client to be instantiated in order to initiate iothub communication
+/// ```no_run
+/// use azure_iot_sdk::client::*;
+/// use std::{thread, time};
+/// use tokio::{select, sync::mpsc};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let (tx_connection_status, mut rx_connection_status) = mpsc::channel(100);
+///     let (tx_twin_desired, mut rx_twin_desired) = mpsc::channel(100);
+///     let (tx_direct_method, mut rx_direct_method) = mpsc::channel(100);
+///     let (tx_incoming_message, mut rx_incoming_message) = mpsc::channel(100);
+///     let builder = IotHubClient::builder()
+///         .observe_connection_state(tx_connection_status)
+///         .observe_desired_properties(tx_twin_desired)
+///         .observe_direct_methods(tx_direct_method)
+///         .observe_incoming_messages(IncomingMessageObserver::new(tx_incoming_message, vec![]));
+///
+///     #[cfg(feature = "edge_client")]
+///     let mut client = builder.build_edge_client().unwrap();
+///     #[cfg(feature = "device_client")]
+///     let mut client = builder.build_device_client("my-connection-string").unwrap();
+///     #[cfg(feature = "module_client")]
+///     let mut client = builder.build_module_client("my-connection-string").unwrap();
+///
+///     loop {
+///         select! (
+///             status = rx_connection_status.recv() => {
+///                 // handle connection status;
+///                 // ...
+///             },
+///             status = rx_twin_desired.recv() => {
+///                 // handle twin desired properties;
+///                 // ...
+///             },
+///             status = rx_direct_method.recv() => {
+///                 // handle direct method calls;
+///                 // ...
+///             },
+///             status = rx_incoming_message.recv() => {
+///                 // handle cloud to device messages;
+///                 // ...
+///             },
+///         )
+///     }
+/// }
+/// ```
+pub struct IotHubClient {
+    twin: Arc<SharedTwin>,
+    source: ConnectionSource,
+    connection_status_context: Box<ConnectionStatusContext>,
+    twin_desired_context: Option<Box<TwinDesiredContext>>,
+    direct_method_context: Option<Box<DirectMethodContext>>,
+    incoming_message_context: Option<Box<IncomingMessageContext>>,
+    options: TwinOptions,
+    on_confirmation: Option<ConfirmationCallback>,
+    latency_profile: Option<LatencyProfile>,
+    confirmation_timeout_secs: u64,
+    // configured transport if falling back to websockets is enabled
+    websocket_fallback: Option<Transport>,
+    tx_lifecycle: Option<LifecycleObserver>,
+    // policy by which the watchdog recreates the handle
+    restart_policy: Option<RestartPolicy>,
+    watchdog: Option<tokio::task::JoinHandle<()>>,
+    // receiver of managed configuration settings until processed by the spawned task
+    managed_configuration: Option<(String, ManagedSettingsReceiver)>,
+    managed_configuration_task: Option<tokio::task::JoinHandle<()>>,
+    // receiver of job states until reported by the spawned task
+    job_reports: Option<JobReportReceiver>,
+    job_reports_task: Option<tokio::task::JoinHandle<()>>,
+    // overflow buffers of incoming messages until forwarded by the spawned tasks
+    incoming_buffers: Vec<(mpsc::Receiver<BufferedMessage>, IotMessageSender)>,
+    incoming_buffer_tasks: Vec<tokio::task::JoinHandle<()>>,
+    offline_store: Option<Arc<MessageStore>>,
+    rejected_store: Option<Arc<RejectedStore>>,
+    retransmit_policy: Option<RetransmitPolicy>,
+    tx_dead_letter: Option<DeadLetterObserver>,
+    offline_replay_task: Option<tokio::task::JoinHandle<()>>,
+    job_registry: Arc<Mutex<JobRegistry>>,
+    output_shardings: HashMap<CString, OutputSharding>,
+    // declared output queues, if any
+    outputs: Option<Vec<CString>>,
+    confirmation_set: RefCell<JoinSet<()>>,
+    // abort handles of D2C confirmation tasks by trace, used to cancel messages
+    confirmation_aborts: RefCell<HashMap<Trace, AbortHandle>>,
+    confirmation_waiter: Arc<ConfirmationWaiter>,
+    // permits of D2C messages in flight, if limited
+    in_flight: Option<Arc<Semaphore>>,
+    rate_limiter: Option<RateLimiter>,
+    dedup_window: Option<DedupWindow>,
+    suspended: RefCell<Option<VecDeque<(Trace, SuspendedSend)>>>,
+    incoming_paused: Arc<AtomicBool>,
+    sends_stopped: Cell<bool>,
+    trace_id: TraceIdGenerator,
+    diagnostics: Arc<Mutex<Diagnostics>>,
+    capabilities: ClientCapabilities,
+    timestamp_property: Option<CString>,
+    auto_message_ids: bool,
+    chunk_oversized_messages: bool,
+    default_properties: HashMap<CString, CString>,
+    pnp_components: Vec<String>,
+    idempotency_keys: Option<Arc<IdempotencyKeys>>,
+    serializers: SerializerRegistry,
+    telemetry_schemas: Option<SchemaRegistry>,
+    clock: MonotonicClock,
+    #[cfg(any(feature = "module_client", feature = "device_client"))]
+    secondary_hubs: Vec<SecondaryHub>,
+    #[cfg(any(feature = "module_client", feature = "device_client"))]
+    credential_renewal: Option<tokio::task::JoinHandle<()>>,
+    // configured trusted certs the edge trust bundle is appended to on connect
+    #[cfg(feature = "edge_client")]
+    edge_trust_bundle: Option<Option<String>>,
+    #[cfg(feature = "encryption")]
+    encryption: Option<encryption::Encryption>,
+    #[cfg(feature = "systemd")]
+    notify_systemd: bool,
+    #[cfg(feature = "systemd")]
+    systemd_watchdog: Option<tokio::task::JoinHandle<()>>,
+    // dropped after all handles are destroyed in drop()
+    platform: Option<PlatformRef>,
+}
+
+impl IotHubClient {
+    /// Call this function in order to get the underlying azure-sdk-c version string.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// IotHubClient::sdk_version_string();
+    /// ```
+    pub fn sdk_version_string() -> String {
+        twin::sdk_version_string()
+    }
+
+    /// Call this function to get the configured [`ClientType`].
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// IotHubClient::client_type();
+    /// ```
+    pub fn client_type() -> ClientType {
+        if cfg!(feature = "device_client") {
+            ClientType::Device
+        } else if cfg!(feature = "module_client") {
+            ClientType::Module
+        } else if cfg!(feature = "edge_client") {
+            ClientType::Edge
+        } else {
+            panic!("no client type feature set")
+        }
+    }
+
+    /// Call this function to get a builder to build an instance of [`IotHubClient`].
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    /// }
+    /// ```
+    pub fn builder() -> IotHubClientBuilder {
+        IotHubClientBuilder::default()
+    }
+
+    /// Call this function to get a message builder with `body` serialized in `format` or, if `None`, in the
+    /// default format of the [`SerializerRegistry`] set by [`IotHubClientBuilder::serializers`].
+    /// Content type and content encoding are set according to the serializer.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    /// use serde_json::json;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let msg = client
+    ///         .serialized_message(None, &json!({"temperature": 21.5}))
+    ///         .unwrap()
+    ///         .set_output_queue("telemetry")
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     client.send_d2c_message(msg).await.unwrap();
+    /// }
+    /// ```
+    pub fn serialized_message(
+        &self,
+        format: Option<&str>,
+        body: &serde_json::Value,
+    ) -> Result<IotMessageBuilder> {
+        self.serializers.message(format, body)
+    }
+
+    /// Call this function to get a [`futures::Sink`] of D2C messages sending into this client, e.g. in order to
+    /// forward a stream of telemetry built with stream combinators. The sink waits while the limit set by
+    /// [`IotHubClientBuilder::max_in_flight`] is reached, see [`D2cSink`].
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    /// use futures::{stream, StreamExt};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().max_in_flight(10).build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().max_in_flight(10).build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().max_in_flight(10).build_module_client("my-connection-string").unwrap();
+    ///
+    ///     stream::iter(0..100)
+    ///         .map(|i| IotMessage::builder().set_body(format!("sample {i}").into_bytes()).build())
+    ///         .forward(client.d2c_sink())
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn d2c_sink(&self) -> D2cSink<'_> {
+        D2cSink::new(self)
+    }
+
+    /// Call this function to get a [`TelemetryScheduler`] coalescing datapoints into one D2C message per
+    /// `window` or per `max_datapoints`, whatever comes first, e.g. in order to reduce the number of messages
+    /// billed by iothub. `max_datapoints` is at least 1.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    /// use serde_json::json;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+    ///
+    ///     tokio::spawn(async move {
+    ///         for i in 0..1000 {
+    ///             tx.send(json!({"sample": i})).await.unwrap();
+    ///         }
+    ///     });
+    ///
+    ///     client
+    ///         .telemetry_scheduler(Duration::from_secs(30), 50)
+    ///         .run(&mut rx)
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn telemetry_scheduler(
+        &self,
+        window: Duration,
+        max_datapoints: usize,
+    ) -> TelemetryScheduler<'_> {
+        TelemetryScheduler::new(self, window, max_datapoints)
+    }
+
+    /// Call this function to send `telemetry` serialized as JSON with content type `application/json` and
+    /// content encoding `utf-8`, see [`IotMessageBuilder::set_json_body`]. Returns the trace id like
+    /// [`IotHubClient::send_d2c_message`].
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[derive(serde::Serialize)]
+    /// struct Temperature {
+    ///     celsius: f64,
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     client.send_telemetry(&Temperature { celsius: 21.5 }).await.unwrap();
+    /// }
+    /// ```
+    pub async fn send_telemetry<T: serde::Serialize + ?Sized>(
+        &self,
+        telemetry: &T,
+    ) -> Result<TraceId> {
+        let message = IotMessage::builder().set_json_body(telemetry)?.build()?;
+
+        self.send_d2c_message(message).await
+    }
+
+    /// Call this function to send a message (D2C) to iothub. Returns the trace id of the message
+    /// that is passed to the closure registered by [`IotHubClientBuilder::on_confirmation`]. The returned
+    /// future waits while the limit set by [`IotHubClientBuilder::max_in_flight`] is reached.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let msg = IotMessage::builder()
+    ///         .set_body(
+    ///             serde_json::to_vec(r#"{"my telemetry message": "hi from device"}"#).unwrap(),
+    ///         )
+    ///         .set_id("my msg id")
+    ///         .set_correlation_id("my correleation id")
+    ///         .set_property(
+    ///             "my property key",
+    ///             "my property value",
+    ///         )
+    ///         .set_output_queue("my output queue")
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     client.send_d2c_message(msg).await.unwrap();
+    /// }
+    /// ```
+    pub async fn send_d2c_message(&self, message: IotMessage) -> Result<TraceId> {
+        self.send_d2c_message_notify(message, None).await
+    }
+
+    /// Call this function to send a message to iothub like [`IotHubClient::send_d2c_message`], but get a
+    /// [`SendHandle`] in order to cancel the message if it becomes stale before it is confirmed, e.g. a status
+    /// update superseded by a newer one.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let status = |state: &str| {
+    ///         IotMessage::builder()
+    ///             .set_body(format!(r#"{{"state": "{state}"}}"#).into_bytes())
+    ///             .build()
+    ///             .unwrap()
+    ///     };
+    ///     let pending = client.send_d2c_message_cancellable(status("updating")).await.unwrap();
+    ///
+    ///     // ...
+    ///
+    ///     pending.cancel().unwrap();
+    ///     client.send_d2c_message(status("updated")).await.unwrap();
+    /// }
+    /// ```
+    pub async fn send_d2c_message_cancellable(
+        &self,
+        message: IotMessage,
+    ) -> Result<SendHandle<'_>> {
+        let traces = self.send_d2c_message_traced(message, None).await?;
+
+        Ok(SendHandle::new(self, traces))
+    }
+
+    /// Call this function to send a message to iothub and wait for its confirmation. Other than
+    /// [`IotHubClient::send_d2c_message`] the returned future resolves with the confirmation result, so
+    /// that business logic can be coupled to the actual delivery. Fails if iothub confirms with failure,
+    /// the confirmation isn't received in time or pending confirmations are aborted, e.g. by
+    /// [`IotHubClient::shutdown`]. Messages sent while suspended are confirmed after [`IotHubClient::resume`].
+    /// If iothub confirms with failure, the error can be downcast to the [`ConfirmationResult`], e.g. in order
+    /// to distinguish messages that will never succeed from those to be sent again later.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let msg = IotMessage::builder()
+    ///         .set_body(b"my telemetry".to_vec())
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     match client.send_d2c_message_confirmed(msg).await {
+    ///         Ok(()) => {
+    ///             // e.g. delete the message from local storage
+    ///         }
+    ///         Err(e) if e.downcast_ref::<ConfirmationResult>().is_some_and(|r| !r.is_retryable()) => {
+    ///             // e.g. move the message to a dead letter storage
+    ///         }
+    ///         Err(_) => {
+    ///             // e.g. keep the message in local storage and send it again later
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub async fn send_d2c_message_confirmed(&self, message: IotMessage) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        let trace_id = self.send_d2c_message_notify(message, Some(tx)).await?;
+
+        match rx.await {
+            Ok(ConfirmationOutcome::Succeeded) => Ok(()),
+            Ok(ConfirmationOutcome::Failed(result)) => Err(anyhow::Error::new(result)
+                .context(format!("send_d2c_message({trace_id}): confirmation failed"))),
+            Ok(ConfirmationOutcome::TimedOut) => {
+                anyhow::bail!("send_d2c_message({trace_id}): confirmation timed out")
+            }
+            Err(_) => anyhow::bail!("send_d2c_message({trace_id}): confirmation aborted"),
+        }
+    }
+
+    /// Call this function to send multiple messages as a batch with a single aggregate confirmation. All
+    /// messages are handed over to azure-sdk-c at once, so that transports supporting batching, i.e. AMQP and
+    /// HTTP with batching enabled by [`IotHubClientBuilder::http_settings`], combine them, while MQTT sends
+    /// them sequentially. The returned future resolves when all messages are confirmed and fails if any
+    /// message cannot be sent or isn't confirmed successfully. Messages sent before a failure are still sent.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let batch = (0..10)
+    ///         .map(|i| {
+    ///             IotMessage::builder()
+    ///                 .set_body(format!("sample {i}").into_bytes())
+    ///                 .build()
+    ///                 .unwrap()
+    ///         })
+    ///         .collect();
+    ///
+    ///     client.send_d2c_batch(batch).await.unwrap();
+    /// }
+    /// ```
+    pub async fn send_d2c_batch(&self, messages: Vec<IotMessage>) -> Result<()> {
+        let size = messages.len();
+        let mut confirmations = Vec::with_capacity(size);
+        let mut send_error = None;
+
+        for message in messages {
+            let (tx, rx) = oneshot::channel();
+
+            match self.send_d2c_message_notify(message, Some(tx)).await {
+                Ok(trace_id) => confirmations.push((trace_id, rx)),
+                Err(e) => {
+                    send_error = Some(e);
+                    break;
+                }
+            }
+        }
+
+        let sent = confirmations.len();
+
+        debug!("send_d2c_batch: {sent} of {size} messages sent");
+
+        let mut failed = vec![];
+
+        for (trace_id, rx) in confirmations {
+            if rx.await != Ok(ConfirmationOutcome::Succeeded) {
+                failed.push(trace_id);
+            }
+        }
+
+        if let Some(e) = send_error {
+            anyhow::bail!("send_d2c_batch: only {sent} of {size} messages sent: {e}");
+        }
+
+        anyhow::ensure!(
+            failed.is_empty(),
+            "send_d2c_batch: {} of {size} messages not confirmed: {failed:?}",
+            failed.len()
+        );
+
+        Ok(())
+    }
+
+    /// sends `message` and signals its [`ConfirmationOutcome`] to `waiter`, if any. duplicates within the
+    /// dedup window are dropped.
+    async fn send_d2c_message_notify(
+        &self,
+        message: IotMessage,
+        waiter: Option<oneshot::Sender<ConfirmationOutcome>>,
+    ) -> Result<TraceId> {
+        let traces = self.send_d2c_message_traced(message, waiter).await?;
+
+        Ok(traces[0].id)
+    }
+
+    /// sends `message` like [`IotHubClient::send_d2c_message_notify`], but returns the traces of all chunks
+    /// if the message is split into chunks. the first one is the trace of the message.
+    async fn send_d2c_message_traced(
+        &self,
+        message: IotMessage,
+        waiter: Option<oneshot::Sender<ConfirmationOutcome>>,
+    ) -> Result<Vec<Trace>> {
+        let message_id = match &self.dedup_window {
+            Some(_) => message
+                .system_properties
+                .get(CString::new("$.mid")?.as_c_str())
+                .cloned(),
+            None => None,
+        };
+        let (Some(dedup), Some(message_id)) = (&self.dedup_window, message_id) else {
+            return self.send_d2c_message_unique(message, waiter).await;
+        };
+
+        if let Some(trace_id) = dedup.reserve(&message_id)? {
+            debug!("send_d2c_message: message id {message_id:?} already sent as message({trace_id}), drop duplicate");
+
+            if let Some(waiter) = waiter {
+                let _ = waiter.send(ConfirmationOutcome::Succeeded);
+            }
+
+            return Ok(vec![Trace::new(trace_id)]);
+        }
+
+        match self.send_d2c_message_unique(message, waiter).await {
+            Ok(traces) => {
+                dedup.sent(&message_id, traces[0].id);
+                Ok(traces)
+            }
+            Err(e) => {
+                dedup.release(&message_id);
+                Err(e)
+            }
+        }
+    }
+
+    /// sends `message` and signals its [`ConfirmationOutcome`] to `waiter`, if any. returns the traces of the
+    /// message or of all its chunks.
+    async fn send_d2c_message_unique(
+        &self,
+        mut message: IotMessage,
+        waiter: Option<oneshot::Sender<ConfirmationOutcome>>,
+    ) -> Result<Vec<Trace>> {
+        if self.sends_stopped.get() {
+            anyhow::bail!("send_d2c_message: client is shutting down");
+        }
+
+        // the permit is held until the confirmation is finished
+        let permit = match &self.in_flight {
+            Some(in_flight) => Some(in_flight.clone().acquire_owned().await?),
+            None => None,
+        };
+
+        self.pace_d2c().await;
+
+        if let Some(component) = message
+            .system_properties
+            .get(CString::new(pnp::COMPONENT_NAME_PROPERTY)?.as_c_str())
+        {
+            self.check_component(&event_hubs::decode_property(&component.to_string_lossy()))?;
+        }
+
+        self.check_output(&message.output_queue)?;
+
+        if let Some(schemas) = &self.telemetry_schemas {
+            schemas.validate(&message)?;
+        }
+
+        if let Some(property) = &self.timestamp_property {
+            if !message.properties.contains_key(property) {
+                message.properties.insert(
+                    property.clone(),
+                    CString::new(message::urlencode(self.clock.now_rfc3339()))?,
+                );
+            }
+        }
+
+        if self.auto_message_ids {
+            if let hash_map::Entry::Vacant(entry) =
+                message.system_properties.entry(CString::new("$.mid")?)
+            {
+                entry.insert(CString::new(Uuid::new_v4().to_string())?);
+            }
+        }
+
+        for (key, value) in &self.default_properties {
+            if !message.properties.contains_key(key) {
+                message.properties.insert(key.clone(), value.clone());
+            }
+        }
+
+        if let Some(keys) = &self.idempotency_keys {
+            if let hash_map::Entry::Vacant(entry) = message
+                .properties
+                .entry(CString::new(IDEMPOTENCY_KEY_PROPERTY)?)
+            {
+                entry.insert(CString::new(message::urlencode(keys.next_key()?))?);
+            }
+        }
+
+        #[cfg(feature = "encryption")]
+        if let Some(encryption) = &self.encryption {
+            encryption::encrypt(encryption.0.as_ref(), &mut message)?;
+        }
+
+        if self.chunk_oversized_messages && message.body.len() > D2C_CHUNK_SIZE {
+            return self.send_d2c_chunks(message, waiter, permit).await;
+        }
+
+        Ok(vec![self.dispatch_d2c(message, waiter, permit).await?])
+    }
+
+    /// sends the chunks of an oversized `message` and signals their aggregate [`ConfirmationOutcome`] to
+    /// `waiter`, if any. returns the traces of all chunks in order.
+    async fn send_d2c_chunks(
+        &self,
+        message: IotMessage,
+        waiter: Option<oneshot::Sender<ConfirmationOutcome>>,
+        mut permit: Option<OwnedSemaphorePermit>,
+    ) -> Result<Vec<Trace>> {
+        let transfer_id = match message
+            .system_properties
+            .get(CString::new("$.mid")?.as_c_str())
+        {
+            Some(id) => event_hubs::decode_property(&id.to_string_lossy()),
+            None => Uuid::new_v4().to_string(),
+        };
+        let chunks = chunking::chunk_message(&transfer_id, &message, D2C_CHUNK_SIZE)?;
+        let mut traces = Vec::with_capacity(chunks.len());
+        let mut confirmations = Vec::with_capacity(chunks.len());
+
+        debug!(
+            "send_d2c_message: send {} bytes as {} chunks of transfer {transfer_id}",
+            message.body.len(),
+            chunks.len()
+        );
+
+        for chunk in chunks {
+            // the first chunk was already paced with the message
+            let permit = match (permit.take(), &self.in_flight) {
+                (Some(permit), _) => Some(permit),
+                (None, Some(in_flight)) => {
+                    self.pace_d2c().await;
+                    Some(in_flight.clone().acquire_owned().await?)
+                }
+                (None, None) => {
+                    self.pace_d2c().await;
+                    None
+                }
+            };
+            let (tx, rx) = oneshot::channel();
+            let trace = self
+                .dispatch_d2c(chunk, waiter.as_ref().map(|_| tx), permit)
+                .await?;
+
+            traces.push(trace);
+            confirmations.push(rx);
+        }
+
+        if let Some(waiter) = waiter {
+            tokio::spawn(async move {
+                let mut outcome = ConfirmationOutcome::Succeeded;
+
+                for rx in confirmations {
+                    match rx.await {
+                        Ok(ConfirmationOutcome::Succeeded) => {}
+                        Ok(failed) => {
+                            outcome = failed;
+                            break;
+                        }
+                        Err(_) => {
+                            outcome = ConfirmationOutcome::Failed(ConfirmationResult::Error);
+                            break;
+                        }
+                    }
+                }
+
+                if waiter.send(outcome).is_err() {
+                    debug!("send_d2c_message: confirmation of transfer {transfer_id} not awaited");
+                }
+            });
+        }
+
+        Ok(traces)
+    }
+
+    /// waits for the next send slot of the adaptive rate limit, if any, after adapting the rate to the
+    /// confirmations received meanwhile
+    async fn pace_d2c(&self) {
+        let Some(limiter) = &self.rate_limiter else {
+            return;
+        };
+        let totals = match self.diagnostics.lock() {
+            Ok(diagnostics) => diagnostics.outcome_totals(),
+            Err(poisoned) => poisoned.into_inner().outcome_totals(),
+        };
+
+        if let Some(rate) = limiter.adapt(totals) {
+            warn!(
+                "send_d2c_message: throttling detected, slow down to {rate:.3} messages per second"
+            );
+
+            if let Some(tx) = &self.tx_lifecycle {
+                if let Err(e) = tx.try_send(LifecycleEvent::Throttled {
+                    interval: Duration::from_secs_f64(1.0 / rate),
+                }) {
+                    warn!("cannot signal lifecycle event: {e}");
+                }
+            }
+        }
+
+        let wait = limiter.reserve(Instant::now());
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// hands `message` over to azure-sdk-c or buffers it while suspended or offline
+    async fn dispatch_d2c(
+        &self,
+        message: IotMessage,
+        waiter: Option<oneshot::Sender<ConfirmationOutcome>>,
+        permit: Option<OwnedSemaphorePermit>,
+    ) -> Result<Trace> {
+        let trace = self.trace_id.next();
+
+        if let Some(buffer) = self.suspended.borrow_mut().as_mut() {
+            if buffer.len() == SUSPEND_BUFFER_CAPACITY {
+                anyhow::bail!("send_d2c_message({trace}): suspend buffer is full");
+            }
+
+            debug!("send_d2c_message({trace}): buffered while suspended");
+            self.add_confirmation_waiter(trace, waiter);
+            buffer.push_back((trace, SuspendedSend::D2cMessage(message)));
+            return Ok(trace);
+        }
+
+        // messages are stored behind already stored ones to keep the order
+        if let Some(store) = &self.offline_store {
+            if !self.is_connected() || !store.is_empty() {
+                debug!("send_d2c_message({trace}): stored offline");
+                self.add_confirmation_waiter(trace, waiter);
+
+                if let Err(e) = store
+                    .push_blocking(trace, message, &self.diagnostics, &self.on_confirmation)
+                    .await
+                {
+                    if let Ok(mut diagnostics) = self.diagnostics.lock() {
+                        diagnostics.take_confirmation_waiter(trace);
+                    }
+
+                    return Err(e);
+                }
+
+                return Ok(trace);
+            }
+        }
+
+        // the waiter is registered before sending, since the confirmation may be received immediately
+        let registered = waiter.is_some();
+
+        self.add_confirmation_waiter(trace, waiter);
+
+        let result = self.send_d2c(message, trace, permit);
+
+        if result.is_err() && registered {
+            if let Ok(mut diagnostics) = self.diagnostics.lock() {
+                diagnostics.take_confirmation_waiter(trace);
+            }
+        }
+
+        result.map(|()| trace)
+    }
+
+    fn add_confirmation_waiter(
+        &self,
+        trace: Trace,
+        waiter: Option<oneshot::Sender<ConfirmationOutcome>>,
+    ) {
+        let Some(waiter) = waiter else {
+            return;
+        };
+
+        if let Ok(mut diagnostics) = self.diagnostics.lock() {
+            diagnostics.add_confirmation_waiter(trace, waiter);
+        }
+    }
+
+    /// Call this function to send a copy of a prepared message with `overrides` applied, e.g. additional
+    /// properties or a different output queue. The prepared message stays untouched, so that it can be
+    /// fanned out to multiple routes without rebuilding it. Returns the trace id like
+    /// [`IotHubClient::send_d2c_message`].
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let msg = IotMessage::builder()
+    ///         .set_body(b"my telemetry".to_vec())
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     for route in ["telemetry", "archive"] {
+    ///         let overrides = MessageOverrides::new()
+    ///             .set_output_queue(route)
+    ///             .set_property("route", route);
+    ///
+    ///         client.send_d2c_message_with(&msg, &overrides).await.unwrap();
+    ///     }
+    /// }
+    /// ```
+    pub async fn send_d2c_message_with(
+        &self,
+        message: &IotMessage,
+        overrides: &MessageOverrides,
+    ) -> Result<TraceId> {
+        self.send_d2c_message(message.with_overrides(overrides)?)
+            .await
+    }
+
+    /// Call this function to send telemetry of an Azure IoT Plug & Play component registered by
+    /// [`IotHubClientBuilder::pnp_components`]. The component name is set as message property according to
+    /// the IoT Plug and Play conventions. Returns the trace id like [`IotHubClient::send_d2c_message`].
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .pnp_model_id("dtmi:com:example:TemperatureController;1")
+    ///         .pnp_components(&["thermostat1"])
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .pnp_model_id("dtmi:com:example:TemperatureController;1")
+    ///         .pnp_components(&["thermostat1"])
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .pnp_model_id("dtmi:com:example:TemperatureController;1")
+    ///         .pnp_components(&["thermostat1"])
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    ///
+    ///     let msg = IotMessage::builder()
+    ///         .set_body(br#"{"temperature": 21.5}"#.to_vec())
+    ///         .set_content_type("application/json")
+    ///         .set_content_encoding("utf-8")
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     client.send_d2c_component_message("thermostat1", msg).await.unwrap();
+    /// }
+    /// ```
+    pub async fn send_d2c_component_message(
+        &self,
+        component: &str,
+        mut message: IotMessage,
+    ) -> Result<TraceId> {
+        self.check_component(component)?;
+
+        message.system_properties.insert(
+            CString::new(pnp::COMPONENT_NAME_PROPERTY)?,
+            CString::new(message::urlencode(component))?,
+        );
+
+        self.send_d2c_message(message).await
+    }
+
+    /// Call this function to send `telemetry` of an Azure IoT Plug & Play component registered by
+    /// [`IotHubClientBuilder::pnp_components`] as JSON body shaped according to the IoT Plug and Play
+    /// conventions, see [`IotMessageBuilder::set_component`]. Returns the trace id like
+    /// [`IotHubClient::send_d2c_message`].
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    /// use serde_json::json;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .pnp_model_id("dtmi:com:example:TemperatureController;1")
+    ///         .pnp_components(&["thermostat1"])
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .pnp_model_id("dtmi:com:example:TemperatureController;1")
+    ///         .pnp_components(&["thermostat1"])
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .pnp_model_id("dtmi:com:example:TemperatureController;1")
+    ///         .pnp_components(&["thermostat1"])
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    ///
+    ///     client
+    ///         .send_pnp_telemetry("thermostat1", json!({"temperature": 21.5}))
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub async fn send_pnp_telemetry(
+        &self,
+        component: &str,
+        telemetry: serde_json::Value,
+    ) -> Result<TraceId> {
+        self.check_component(component)?;
+
+        let message = IotMessage::builder()
+            .set_body(serde_json::to_vec(&telemetry)?)
+            .set_component(component)
+            .build()?;
+
+        self.send_d2c_message(message).await
+    }
+
+    fn check_component(&self, component: &str) -> Result<()> {
+        anyhow::ensure!(
+            self.pnp_components.iter().any(|c| c == component),
+            "pnp component {component} not registered"
+        );
+
+        Ok(())
+    }
+
+    fn check_output(&self, output: &CStr) -> Result<()> {
+        if let Some(outputs) = &self.outputs {
+            anyhow::ensure!(
+                outputs.iter().any(|o| o.as_c_str() == output)
+                    || self.output_shardings.contains_key(output),
+                "output queue {output:?} not declared"
+            );
+        }
+
+        Ok(())
+    }
+
+    fn send_d2c(
+        &self,
+        mut message: IotMessage,
+        trace: Trace,
+        permit: Option<OwnedSemaphorePermit>,
+    ) -> Result<()> {
+        self.check_pending_confirmations()?;
+
+        // a copy is retained to be stored offline or retransmitted if the confirmation fails
+        let retained = match (&self.offline_store, self.retransmit_policy) {
+            (Some(store), _) => Some(Retained::Offline(
+                store.clone(),
+                message.with_overrides(&MessageOverrides::new())?,
+            )),
+            (None, Some(policy)) => Some(Retained::Retransmit(Retransmission {
+                policy,
+                twin: self.twin.clone(),
+                message: message.with_overrides(&MessageOverrides::new())?,
+                retry_timed_out: self.dedup_window.is_none(),
+                tx_dead_letter: self.tx_dead_letter.clone(),
+                diagnostics: self.diagnostics.clone(),
+            })),
+            (None, None) => None,
+        };
+        let handle = message.create_outgoing_handle()?;
+        let queue = match self.output_shardings.get(&message.output_queue) {
+            Some(sharding) => sharding.select(&message),
+            None => message.output_queue.clone(),
+        };
+        let (tx, rx) = oneshot::channel::<ConfirmationResult>();
+
+        debug!("send_d2c_message({trace}): {queue:?}");
+
+        self.twin.with(|twin| {
+            twin.send_event_to_output_async(
+                handle,
+                queue.clone(),
+                Some(IotHubClient::c_d2c_confirmation_callback),
+                Box::into_raw(Box::new((tx, trace.id))) as *mut c_void,
+            )
+        })?;
+
+        if self.outputs.is_some() {
+            if let Ok(mut diagnostics) = self.diagnostics.lock() {
+                diagnostics.add_pending_output(trace, queue.to_string_lossy().into_owned());
+            }
+        }
+
+        self.spawn_confirmation(
+            (rx, trace),
+            true,
+            message.confirmation_timeout,
+            retained,
+            permit,
+        );
+
+        // the azure-sdk-c clones the message on send, so the same handle can be passed to secondary hubs
+        #[cfg(any(feature = "module_client", feature = "device_client"))]
+        for hub in &self.secondary_hubs {
+            if hub.publishes(&message.output_queue) {
+                if let Err(e) = hub.send(handle, queue.clone(), trace.id) {
+                    error!("send_d2c_message({trace}): {e}");
+
+                    if let Ok(mut diagnostics) = self.diagnostics.lock() {
+                        diagnostics.report(ErrorEvent::SendFailure(format!(
+                            "send_d2c_message({trace}): {e}"
+                        )));
+                    }
+                }
+            }
+        }
+
+        if let Ok(mut diagnostics) = self.diagnostics.lock() {
+            diagnostics.d2c_metrics.sent += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Call this function to report twin properties to iothub. Returns the trace id of the report
+    /// that is passed to the closure registered by [`IotHubClientBuilder::on_confirmation`].
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    /// use serde_json::json;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let reported = json!({
+    ///         "my_status": {
+    ///             "status": "ok",
+    ///             "timestamp": "2022-03-10",
+    ///         }
+    ///     });
+    ///
+    ///     client.twin_report(reported);
+    /// }
+    /// ```
+    pub fn twin_report(&self, reported: serde_json::Value) -> Result<TraceId> {
+        if self.sends_stopped.get() {
+            anyhow::bail!("twin_report: client is shutting down");
+        }
+
+        let trace = self.trace_id.next();
+
+        if let Some(buffer) = self.suspended.borrow_mut().as_mut() {
+            if buffer.len() == SUSPEND_BUFFER_CAPACITY {
+                anyhow::bail!("send reported({trace}): suspend buffer is full");
+            }
+
+            debug!("send reported({trace}): buffered while suspended");
+            buffer.push_back((trace, SuspendedSend::Reported(reported)));
+            return Ok(trace.id);
+        }
+
+        self.send_reported(reported, trace)
+    }
+
+    /// Call this function to report properties of an Azure IoT Plug & Play component registered by
+    /// [`IotHubClientBuilder::pnp_components`]. `properties` are nested in the component and marked by
+    /// `"__t": "c"` according to the IoT Plug and Play conventions. Returns the trace id like
+    /// [`IotHubClient::twin_report`].
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    /// use serde_json::json;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .pnp_model_id("dtmi:com:example:TemperatureController;1")
+    ///         .pnp_components(&["deviceInfo"])
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .pnp_model_id("dtmi:com:example:TemperatureController;1")
+    ///         .pnp_components(&["deviceInfo"])
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .pnp_model_id("dtmi:com:example:TemperatureController;1")
+    ///         .pnp_components(&["deviceInfo"])
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    ///
+    ///     // reports {"deviceInfo": {"__t": "c", "manufacturer": "my-manufacturer"}}
+    ///     client
+    ///         .twin_report_component("deviceInfo", json!({"manufacturer": "my-manufacturer"}))
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn twin_report_component(
+        &self,
+        component: &str,
+        properties: serde_json::Value,
+    ) -> Result<TraceId> {
+        self.check_component(component)?;
+
+        self.twin_report(pnp::component_reported(component, properties)?)
+    }
+
+    fn send_reported(&self, reported: serde_json::Value, trace: Trace) -> Result<TraceId> {
+        self.check_pending_confirmations()?;
+
+        debug!("send reported({trace}): {reported:?}");
+
+        let reported_state = CString::new(reported.to_string())?;
+        let size = reported_state.as_bytes().len();
+        let (tx, rx) = oneshot::channel::<ConfirmationResult>();
+
+        self.twin.with(|twin| {
+            twin.send_reported_state(
+                reported_state,
+                size,
+                Some(IotHubClient::c_reported_twin_callback),
+                Box::into_raw(Box::new((tx, trace.id))) as *mut c_void,
+            )
+        })?;
+
+        self.spawn_confirmation((rx, trace), false, None, None, None);
+
+        if let Ok(mut diagnostics) = self.diagnostics.lock() {
+            diagnostics.reported_properties_sent += 1;
+        }
+
+        Ok(trace.id)
+    }
+
+    /// Call this function to prepare the result of a direct method that might exceed the direct method
+    /// response size limit of 128KB. If `response` fits the limit, it is returned unchanged. Otherwise it is
+    /// sent as chunked D2C messages and a reference payload is returned instead, that can be used as result:
+    /// ```json
+    /// { "transfer_id": "<id>", "chunks": <number of chunks>, "size": <size of response in bytes> }
+    /// ```
+    /// All chunks carry the transfer id as correlation id and as property "transfer-id", as well as the
+    /// properties "chunk-index" and "chunk-count". Thus the backend can reassemble the response.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    /// use serde_json::json;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let result = client
+    ///         .large_direct_method_response("get_logs", json!({"logs": "..."}))
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     // send result by DirectMethodResponder
+    ///     // ...
+    /// }
+    /// ```
+    pub async fn large_direct_method_response(
+        &self,
+        method_name: &str,
+        response: serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        let body = Bytes::from(response.to_string());
+
+        if body.len() <= DIRECT_METHOD_RESPONSE_MAX_SIZE {
+            return Ok(response);
+        }
+
+        let transfer_id = format!(
+            "{method_name}-{}-{}",
+            diagnostics::now_secs(),
+            self.trace_id.last()
+        );
+
+        debug!(
+            "large_direct_method_response: send {} bytes as chunked transfer {transfer_id}",
+            body.len()
+        );
+
+        let chunks =
+            chunking::chunk_messages(&transfer_id, &body, D2C_CHUNK_SIZE, "application/json")?;
+        let chunk_count = chunks.len();
+
+        for chunk in chunks {
+            self.send_d2c_message(chunk).await?;
+        }
+
+        Ok(json!({
+            "transfer_id": transfer_id,
+            "chunks": chunk_count,
+            "size": body.len(),
+        }))
+    }
+
+    /// Call this function to get the state of the long-running [`Job`] with id `id`.
+    /// Returns `None` if the job is unknown or was evicted after more recent jobs finished.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     if let Some(JobState::Running { progress, .. }) = client.job_state("update_firmware-1700000000-1") {
+    ///         println!("progress: {progress}%");
+    ///     }
+    /// }
+    /// ```
+    pub fn job_state(&self, id: &str) -> Option<JobState> {
+        self.job_registry.lock().ok()?.state(id)
+    }
+
+    /// Call this function to trigger a twin update that is asynchronously signaled as twin_desired stream.
+    /// The received complete twin can be accessed typed by [`TwinUpdate::document`].
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    /// use serde_json::json;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     client.twin_async();
+    /// }
+    /// ```
+    pub fn twin_async(&mut self) -> Result<()> {
+        debug!("twin_complete: get entire twin");
+
+        let Some(context) = self.twin_desired_context.as_deref_mut() else {
+            anyhow::bail!("twin observer not present")
+        };
+
+        self.twin.with(|twin| {
+            twin.twin_async(
+                Some(IotHubClient::c_twin_callback),
+                context as *mut TwinDesiredContext as *mut c_void,
+            )
+        })
+    }
+
+    /// Call this function to get the recorded [`AuditRecord`]s of incoming direct methods and C2D messages.
+    /// Records are only collected if enabled by [`IotHubClientBuilder::audit_inbound_commands`].
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().audit_inbound_commands(true).build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().audit_inbound_commands(true).build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().audit_inbound_commands(true).build_module_client("my-connection-string").unwrap();
+    ///
+    ///     for record in client.audit_trail() {
+    ///         println!("{record:?}");
+    ///     }
+    /// }
+    /// ```
+    pub fn audit_trail(&self) -> Vec<AuditRecord> {
+        match self.diagnostics.lock() {
+            Ok(diagnostics) => diagnostics.audit_records(),
+            Err(poisoned) => poisoned.into_inner().audit_records(),
+        }
+    }
+
+    /// Call this function to get a support bundle as JSON document. The bundle aggregates sdk versions,
+    /// current options, connection history, statistics and last errors. Secrets are redacted, so the bundle
+    /// can be shipped to the backend, e.g. as result of a direct method.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let bundle = client.support_bundle();
+    /// }
+    /// ```
+    pub fn support_bundle(&self) -> serde_json::Value {
+        let pending_confirmations = self.confirmation_set.borrow().len();
+        let diagnostics = match self.diagnostics.lock() {
+            Ok(diagnostics) => diagnostics,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        #[allow(unused_mut)]
+        let mut bundle = json!({
+            "versions": {
+                "azure-iot-sdk": env!("CARGO_PKG_VERSION"),
+                "azure-sdk-c": IotHubClient::sdk_version_string(),
+            },
+            "client_type": IotHubClient::client_type(),
+            "options": {
+                "do_work_freq_ms": self.options.do_work_freq_ms,
+                "confirmation_timeout_secs": self.confirmation_timeout_secs,
+                "logging": self.options.logging,
+                "model_id": self.options.model_id,
+                "retry_setting": self.options.retry_setting,
+                "sas_token_setting": self.options.sas_token_setting.as_ref().map(|s| json!({
+                    "lifetime_secs": s.lifetime.as_secs(),
+                    "renewal_margin_secs": s.renewal_margin.as_secs(),
+                })),
+                "diagnostic_sampling_percentage": self.options.diagnostic_sampling_percentage,
+                "product_info": self.options.product_info,
+                "trusted_certs": self.options.trusted_certs.is_some(),
+                // credentials are redacted
+                "proxy_setting": self.options.proxy_setting.as_ref().map(|p| json!({
+                    "host_address": p.host_address,
+                    "port": p.port,
+                    "username": p.credentials.as_ref().map(|(username, _)| username),
+                })),
+                "transport": self.options.transport,
+                "http_setting": self.options.http_setting.as_ref().map(|h| json!({
+                    "batching": h.batching,
+                    "min_polling_time_secs": h.min_polling_time.as_secs(),
+                })),
+            },
+            "capabilities": self.capabilities,
+            "connection_history": diagnostics.connection_history_json(),
+            "stats": {
+                "pending_confirmations": pending_confirmations,
+                "totals": diagnostics.stats_json(),
+            },
+            "last_errors": diagnostics.last_errors_json(),
+            "audit_trail": diagnostics.audit_trail_json(),
+        });
+
+        #[cfg(any(feature = "module_client", feature = "device_client"))]
+        {
+            bundle["secondary_hubs"] = self
+                .secondary_hubs
+                .iter()
+                .map(SecondaryHub::support_bundle)
+                .collect();
+        }
+
+        bundle
+    }
+
+    /// Call this function to get the number of D2C messages and reported properties not yet confirmed by iothub
+    /// and the age of the oldest one, e.g. in order to decide whether it is safe to reboot now.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let pending = client.pending_confirmations();
+    ///
+    ///     if pending.count == 0 {
+    ///         // safe to reboot
+    ///     } else if pending.oldest_age > Some(Duration::from_secs(10)) {
+    ///         // wait for confirmations
+    ///         client.shutdown().await;
+    ///     }
+    /// }
+    /// ```
+    pub fn pending_confirmations(&self) -> PendingConfirmations {
+        match self.diagnostics.lock() {
+            Ok(diagnostics) => diagnostics.pending_confirmations(),
+            Err(poisoned) => poisoned.into_inner().pending_confirmations(),
+        }
+    }
+
+    /// Call this function to get the number of D2C messages and reported properties not yet confirmed by iothub.
+    /// Shorthand for `pending_confirmations().count`, see [`IotHubClient::pending_confirmations`].
+    pub fn pending_confirmation_count(&self) -> usize {
+        self.pending_confirmations().count
+    }
+
+    /// Call this function to wait at most `timeout` for the confirmations of all D2C messages and reported
+    /// properties sent so far, including their retransmissions, without shutting the client down, e.g. in order
+    /// to checkpoint that all data is delivered before the device is suspended. Returns an error if
+    /// confirmations are still pending after `timeout`. Messages buffered while sends are suspended or stored
+    /// offline aren't waited for, since they aren't sent yet.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     client.send_d2c_message(IotMessage::builder().set_body(vec![]).build().unwrap()).await.unwrap();
+    ///
+    ///     if client.flush(Duration::from_secs(30)).await.is_ok() {
+    ///         // all data delivered, safe to suspend
+    ///     }
+    /// }
+    /// ```
+    pub async fn flush(&self, timeout: Duration) -> Result<()> {
+        let pending = self.flush_confirmations(timeout).await;
+
+        anyhow::ensure!(
+            pending == 0,
+            "flush: {pending} confirmations still pending after {timeout:?}"
+        );
+
+        Ok(())
+    }
+
+    /// Call this function to get the connection quality as share of succeeded confirmations of the last 32
+    /// D2C messages and reported properties, from 0.0 (all failed or timed out) to 1.0 (all succeeded).
+    /// Returns `None` if fewer than 8 confirmations were received since the client was built or restarted by
+    /// the watchdog.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     if client.connection_quality().is_some_and(|quality| quality < 0.8) {
+    ///         // reduce telemetry rate
+    ///     }
+    /// }
+    /// ```
+    pub fn connection_quality(&self) -> Option<f64> {
+        match self.diagnostics.lock() {
+            Ok(diagnostics) => diagnostics.connection_quality(),
+            Err(poisoned) => poisoned.into_inner().connection_quality(),
+        }
+    }
+
+    /// Call this function to get a snapshot of [`DeliveryMetrics`] of D2C messages, e.g. in order to report the
+    /// delivery health of a fleet in the telemetry of an agent. The counters and the histogram of confirmation
+    /// latencies are kept since the client was built.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let metrics = client.metrics();
+    ///
+    ///     println!(
+    ///         "sent: {}, confirmed: {}, failed: {}, timed out: {}, mean latency: {:?}",
+    ///         metrics.sent,
+    ///         metrics.confirmed,
+    ///         metrics.failed,
+    ///         metrics.timed_out,
+    ///         metrics.confirmation_latency.mean()
+    ///     );
+    /// }
+    /// ```
+    pub fn metrics(&self) -> DeliveryMetrics {
+        match self.diagnostics.lock() {
+            Ok(diagnostics) => diagnostics.d2c_metrics.clone(),
+            Err(poisoned) => poisoned.into_inner().d2c_metrics.clone(),
+        }
+    }
+
+    /// Call this function to get a snapshot of [`IncomingMessageMetrics`], i.e. the dispositions and processing
+    /// durations of all C2D messages received since the client was built.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let metrics = client.incoming_message_metrics();
+    ///
+    ///     println!(
+    ///         "processed: {}, rejected: {}, mean duration: {:?}",
+    ///         metrics.processed(),
+    ///         metrics.rejected,
+    ///         metrics.processing_duration.mean()
+    ///     );
+    /// }
+    /// ```
+    pub fn incoming_message_metrics(&self) -> IncomingMessageMetrics {
+        match self.diagnostics.lock() {
+            Ok(diagnostics) => diagnostics.c2d_metrics.clone(),
+            Err(poisoned) => poisoned.into_inner().c2d_metrics.clone(),
+        }
+    }
+
+    #[cfg(feature = "edge_client")]
+    /// Call this function to get the delivery metrics of D2C messages per output declared by
+    /// [`IotHubClientBuilder::outputs`]. Messages sent to a sharded output count for the output they were
+    /// sharded to. Returns an empty map if no outputs are declared.<br>
+    /// ***Note***: this function is only available with "edge_client" feature enabled.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     {
+    ///         let client = IotHubClient::builder()
+    ///             .outputs(&["telemetry", "alerts"])
+    ///             .build_edge_client()
+    ///             .unwrap();
+    ///
+    ///         for (output, metrics) in client.output_metrics() {
+    ///             println!("{output}: sent {}, failed {}", metrics.sent, metrics.failed);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn output_metrics(&self) -> HashMap<String, DeliveryMetrics> {
+        let Some(outputs) = &self.outputs else {
+            return HashMap::new();
+        };
+        let diagnostics = match self.diagnostics.lock() {
+            Ok(diagnostics) => diagnostics,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        outputs
+            .iter()
+            .map(|output| {
+                let output = output.to_string_lossy().into_owned();
+                let metrics = diagnostics
+                    .output_metrics
+                    .get(&output)
+                    .cloned()
+                    .unwrap_or_default();
+
+                (output, metrics)
+            })
+            .collect()
+    }
+
+    /// Call this function to get the current rate of D2C messages per second set by
+    /// [`IotHubClientBuilder::adaptive_rate_limit`]. Returns `None` if the rate isn't limited.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let limit = AdaptiveRateLimit::new(10.0);
+    ///
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().adaptive_rate_limit(limit).build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().adaptive_rate_limit(limit).build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().adaptive_rate_limit(limit).build_module_client("my-connection-string").unwrap();
+    ///
+    ///     if client.d2c_rate().is_some_and(|rate| rate < 1.0) {
+    ///         // hub throttles, skip optional telemetry
+    ///     }
+    /// }
+    /// ```
+    pub fn d2c_rate(&self) -> Option<f64> {
+        self.rate_limiter.as_ref().map(RateLimiter::rate)
+    }
+
+    /// Call this function to get the [`ClientCapabilities`] effectively applied to the current connection,
+    /// e.g. in order to verify the configuration of a device remotely. Returns the defaults before
+    /// [`IotHubClient::connect`] succeeded.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let capabilities = client.capabilities();
+    ///
+    ///     println!("websockets in use: {}", capabilities.websockets);
+    /// }
+    /// ```
+    pub fn capabilities(&self) -> ClientCapabilities {
+        self.capabilities.clone()
+    }
+
+    #[cfg(any(feature = "module_client", feature = "device_client"))]
+    /// Call this function to swap the connection to iothub, e.g. in order to migrate credentials or hubs,
+    /// without dropping telemetry. A second connection is established by `connection_string`. As soon as
+    /// it is authenticated within `timeout`, all senders and observers are switched over and the old connection
+    /// is destroyed. If the new connection can't be authenticated, the old connection is kept.<br>
+    /// ***Note1***: confirmations still pending for the old connection are awaited for at most `timeout` before
+    /// it is destroyed. Confirmations still pending afterwards are signaled as failed, i.e. their messages are
+    /// retransmitted or stored offline if configured.<br>
+    /// ***Note2***: this function is only available with "device_client" or "module_client" feature enabled.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     #[cfg(any(feature = "device_client", feature = "module_client"))]
+    ///     client
+    ///         .swap_connection("my-new-connection-string", Duration::from_secs(60))
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub async fn swap_connection(
+        &mut self,
+        connection_string: &str,
+        timeout: Duration,
+    ) -> Result<()> {
+        info!("swap connection");
+
+        if !self.twin.is_connected() {
+            anyhow::bail!("client not connected, call connect() first");
+        }
+
+        let mut twin = IotHubClient::create_twin_from_connection_string(
+            &self.options.via_gateway(connection_string)?,
+            self.options.transport,
+        )?;
+        let (tx, mut rx) = mpsc::channel(10);
+        let mut swap_context = Box::new(ConnectionStatusContext {
+            observer: Some(tx),
+            retry: None,
+            diagnostics: Arc::new(Mutex::new(Diagnostics::default())),
+            sas_token_expired: None,
+            status_changed: None,
+            latency_profile: None,
+            #[cfg(feature = "systemd")]
+            systemd_readiness: None,
+        });
+
+        let authenticated = async {
+            twin.set_connection_status_callback(
+                Some(IotHubClient::c_connection_status_callback),
+                swap_context.as_mut() as *mut ConnectionStatusContext as *mut c_void,
+            )?;
+
+            // apply all options to the new connection
+            let capabilities =
+                IotHubClient::apply_options(twin.as_ref(), &self.options, &self.diagnostics)?;
+
+            match tokio::time::timeout(timeout, async {
+                while let Some(status) = rx.recv().await {
+                    if let AuthenticationStatus::Authenticated = status {
+                        return Ok(());
+                    }
+                    debug!("swap connection: {status:?}");
+                }
+                anyhow::bail!("connection status channel unexpectedly closed")
+            })
+            .await
+            {
+                Ok(result) => result.map(|_| capabilities),
+                Err(_) => anyhow::bail!("new connection not authenticated in time"),
+            }
+        };
+
+        match authenticated.await {
+            Ok(capabilities) => self.capabilities = capabilities,
+            Err(e) => {
+                error!("swap connection failed: {e}");
+                twin.destroy();
+                return Err(e);
+            }
+        }
+
+        // credentials of the new connection are not maintained by identity service
+        if let Some(renewal) = self.credential_renewal.take() {
+            renewal.abort();
+        }
+
+        // nothing is sent meanwhile, since the client is borrowed exclusively. thus only messages in flight on
+        // the old connection are awaited.
+        let pending = self.flush_confirmations(timeout).await;
+
+        if pending > 0 {
+            warn!("swap connection: {pending} confirmations still pending on old connection");
+        }
+
+        // switch senders and observers over to the new connection
+        let result = self
+            .callback_contexts()
+            .and_then(|contexts| IotHubClient::apply_callbacks(twin.as_ref(), &contexts));
+        if let Some(retired) = self.twin.replace(Some(twin)) {
+            // destroying joins the azure-sdk-c worker thread
+            if let Err(e) = tokio::task::spawn_blocking(move || retired.destroy()).await {
+                error!("swap connection: cannot destroy old connection: {e}");
+            }
+        }
+        self.source = ConnectionSource::ConnectionString(connection_string.to_string());
+
+        info!("swap connection: done");
+
+        result.and_then(|_| self.spawn_watchdog())
+    }
+
+    /// Call this function to get the last connection status received for the current connection, e.g. to make quick
+    /// decisions in request handlers without mirroring the connection state observer. Returns `None` if the client
+    /// isn't connected or no status was received yet.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     if let Some(AuthenticationStatus::Unauthenticated(reason)) = client.connection_state() {
+    ///         println!("unauthenticated: {reason:?}");
+    ///     }
+    /// }
+    /// ```
+    pub fn connection_state(&self) -> Option<AuthenticationStatus> {
+        match self.diagnostics.lock() {
+            Ok(diagnostics) => diagnostics.last_connection_status(),
+            Err(poisoned) => poisoned.into_inner().last_connection_status(),
+        }
+    }
+
+    /// Call this function to check if the client is currently authenticated by iothub.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     if client.is_connected() {
+    ///         // ...
+    ///     }
+    /// }
+    /// ```
+    pub fn is_connected(&self) -> bool {
+        self.connection_state() == Some(AuthenticationStatus::Authenticated)
+    }
+
+    /// Call this function to get the number of D2C messages waiting for replay in the store set by
+    /// [`IotHubClientBuilder::offline_store`]. Returns 0 if no store is set.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let store = OfflineStore::new("/var/lib/my-service/offline");
+    ///
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().offline_store(store).build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().offline_store(store).build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().offline_store(store).build_module_client("my-connection-string").unwrap();
+    ///
+    ///     println!("{} messages stored offline", client.offline_messages());
+    /// }
+    /// ```
+    pub fn offline_messages(&self) -> usize {
+        self.offline_store.as_ref().map_or(0, |store| store.len())
+    }
+
+    /// Call this function to get all C2D messages persisted in the store set by
+    /// [`IotHubClientBuilder::rejected_message_store`] in the order they were received. Returns an empty list
+    /// if no store is set.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let store = RejectedMessageStore::new("/var/lib/my-service/rejected");
+    ///
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().rejected_message_store(store).build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().rejected_message_store(store).build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().rejected_message_store(store).build_module_client("my-connection-string").unwrap();
+    ///
+    ///     for rejected in client.rejected_messages().unwrap() {
+    ///         println!("{:?}: {}", rejected.received, rejected.error);
+    ///     }
+    ///
+    ///     client.clear_rejected_messages().unwrap();
+    /// }
+    /// ```
+    pub fn rejected_messages(&self) -> Result<Vec<RejectedMessage>> {
+        self.rejected_store
+            .as_ref()
+            .map_or(Ok(vec![]), |store| store.messages())
+    }
+
+    /// Call this function to remove all C2D messages from the store set by
+    /// [`IotHubClientBuilder::rejected_message_store`], e.g. after they were analyzed.
+    pub fn clear_rejected_messages(&self) -> Result<()> {
+        self.rejected_store
+            .as_ref()
+            .map_or(Ok(()), |store| store.clear())
+    }
+
+    /// Call this function to get the underlying azure-sdk-c handle of the connected client, e.g. in order to
+    /// use features of azure-sdk-c not wrapped by this crate. Fails if the client is not connected.<br>
+    /// The returned [`RawHandle`] locks the handle, so that it is neither destroyed nor recreated while it is
+    /// used. Callbacks installed by [`RawHandle::install_callback`] are tracked and reported by
+    /// [`IotHubClient::foreign_callbacks`].
+    ///
+    /// # Safety
+    ///
+    /// The handle is owned by the client. Callers must ensure that:
+    /// - the handle is not destroyed and not used after the [`RawHandle`] is dropped, e.g. by storing it
+    /// - callbacks registered by the client are not replaced, i.e. the connection status, twin, direct method
+    ///   and (input) message callbacks, nor options applied by the client are changed
+    /// - contexts passed with foreign callbacks outlive the handle, since callbacks might still be called
+    ///   until the handle is destroyed
+    /// - foreign callbacks are installed again after the handle was recreated, e.g. after
+    ///   [`LifecycleEvent::Recovered`], [`LifecycleEvent::QualityRestart`], credential renewal or a swap of
+    ///   credentials, since they are lost with the handle. [`IotHubClient::foreign_callbacks`] is empty then.
+    /// - the [`RawHandle`] is dropped soon, since the client cannot use the handle meanwhile
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     // wait for authentication ...
+    ///
+    ///     unsafe {
+    ///         let mut handle = client.raw_handle().unwrap();
+    ///
+    ///         handle
+    ///             .install_callback("file-upload-progress", |handle| {
+    ///                 // register callback with azure-sdk-c by handle
+    ///                 Ok(())
+    ///             })
+    ///             .unwrap();
+    ///     }
+    ///
+    ///     assert_eq!(client.foreign_callbacks(), vec!["file-upload-progress"]);
+    /// }
+    /// ```
+    pub unsafe fn raw_handle(&mut self) -> Result<RawHandle<'_>> {
+        RawHandle::new(self.twin.lock())
+    }
+
+    /// Call this function to get the names of the foreign callbacks installed on the current handle by
+    /// [`RawHandle::install_callback`]. The list is cleared when the handle is recreated.
+    pub fn foreign_callbacks(&self) -> Vec<String> {
+        self.twin.foreign_callbacks()
+    }
+
+    #[cfg(any(feature = "module_client", feature = "device_client"))]
+    /// Call this function to get the last connection status of the secondary hub registered by
+    /// [`IotHubClientBuilder::secondary_hub`] as `name`. Returns `None` if there is no such hub
+    /// or no status was received yet.<br>
+    /// ***Note***: this function is only available with "device_client" or "module_client" feature enabled.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .secondary_hub("analytics", "my-analytics-connection-string", vec!["telemetry"])
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .secondary_hub("analytics", "my-analytics-connection-string", vec!["telemetry"])
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    ///
+    ///     #[cfg(any(feature = "device_client", feature = "module_client"))]
+    ///     let status = client.secondary_hub_status("analytics");
+    /// }
+    /// ```
+    pub fn secondary_hub_status(&self, name: &str) -> Option<AuthenticationStatus> {
+        self.secondary_hubs
+            .iter()
+            .find(|hub| hub.name == name)
+            .and_then(SecondaryHub::connection_status)
+    }
+
+    /// Call this function to connect a client built by one of the `build_*_lazy` functions of [`IotHubClientBuilder`]
+    /// or to reconnect a client after [`IotHubClient::disconnect`]. All I/O, e.g. requesting the connection string
+    /// from identity service and creating the underlying azure-sdk-c handle, happens here. All registered observers
+    /// and options are applied to the new handle. Thus a client can be built early, e.g. at process start, while
+    /// network activity is gated on readiness conditions until this function is called. Calling this function on a
+    /// connected client has no effect.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client_lazy().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client_lazy("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client_from_identity_lazy().unwrap();
+    ///
+    ///     client.connect().await.unwrap();
+    /// }
+    /// ```
+    pub async fn connect(&mut self) -> Result<()> {
+        if self.twin.is_connected() {
+            debug!("connect: already connected");
+            return Ok(());
+        }
+
+        info!("connect");
+
+        #[cfg(any(feature = "module_client", feature = "device_client"))]
+        if let ConnectionSource::IdentityService = self.source {
+            let connection_string = self
+                .options
+                .via_gateway(&IotHubClient::connection_string_from_identity_service().await?)?;
+
+            self.iothub_init()?;
+
+            self.select_transport(IotHubClient::transport_host(&connection_string).as_deref());
+
+            let twin = IotHubClient::create_twin_from_connection_string(
+                &connection_string,
+                self.options.transport,
+            )?;
+
+            self.attach(twin)?;
+
+            return self.spawn_credential_renewal();
+        }
+
+        #[cfg(feature = "edge_client")]
+        if let Some(trusted_certs) = &self.edge_trust_bundle {
+            let trust_bundle = IotHubClient::edge_trust_bundle().await?;
+
+            CString::new(trust_bundle.as_str())?;
+
+            self.options.trusted_certs = Some(match trusted_certs {
+                Some(certs) => format!("{certs}\n{trust_bundle}"),
+                None => trust_bundle,
+            });
+        }
+
+        self.attach_twin()
+    }
+
+    /// Call this function to deliberately drop the connection to iothub, e.g. during metered-network windows.
+    /// The underlying azure-sdk-c handle is destroyed, but all registered observers and options are preserved,
+    /// so that the connection can be resumed later by [`IotHubClient::connect`] without rebuilding the client.
+    /// Calling this function on a disconnected client has no effect.<br>
+    /// ***Note***: D2C messages and reported properties not yet confirmed are signaled as failed. Call
+    /// [`IotHubClient::shutdown`] before in order to wait for pending confirmations.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     client.disconnect();
+    ///
+    ///     // metered-network window
+    ///     // ...
+    ///
+    ///     client.connect().await.unwrap();
+    /// }
+    /// ```
+    pub fn disconnect(&mut self) {
+        if !self.twin.is_connected() {
+            debug!("disconnect: not connected");
+            return;
+        }
+
+        info!("disconnect");
+
+        #[cfg(any(feature = "module_client", feature = "device_client"))]
+        if let Some(renewal) = self.credential_renewal.take() {
+            renewal.abort();
+        }
+
+        if let Some(watchdog) = self.watchdog.take() {
+            watchdog.abort();
+        }
+
+        self.detach();
+        self.capabilities = ClientCapabilities::default();
+    }
+
+    /// Call this function to change the [`RetryPolicy`] at runtime, e.g. to switch to [`RetryPolicy::None`] during
+    /// a controlled firmware update and to restore the previous policy afterwards. The policy is applied to the
+    /// current connection immediately and kept for later connects. Overrides the policy set by
+    /// [`IotHubClientBuilder::retry_policy`].<br>
+    /// ***Note***: a running credential renewal of a client built by the identity service keeps the policy of the
+    /// last connect until the client reconnects.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     let previous = client.retry_policy();
+    ///
+    ///     client.set_retry_policy(RetryPolicy::None, 0).unwrap();
+    ///
+    ///     // controlled firmware update
+    ///     // ...
+    ///
+    ///     let (policy, timeout_secs) =
+    ///         previous.unwrap_or((RetryPolicy::ExponentialBackoffWithJitter, 0));
+    ///     client.set_retry_policy(policy, timeout_secs).unwrap();
+    /// }
+    /// ```
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy, timeout_secs: u32) -> Result<()> {
+        info!("set retry policy: {policy:?}, timeout_secs: {timeout_secs}");
+
+        if self.twin.is_connected() {
+            self.twin
+                .with(|twin| twin.set_retry_policy(policy as u32, timeout_secs as usize))?;
+
+            #[cfg(any(feature = "module_client", feature = "device_client"))]
+            for hub in &self.secondary_hubs {
+                if let Some(twin) = &hub.twin {
+                    twin.set_retry_policy(policy as u32, timeout_secs as usize)?;
+                }
+            }
+
+            self.capabilities.retry_policy = true;
+        }
+
+        self.options.retry_setting = Some(RetrySetting {
+            policy,
+            timeout_secs,
+        });
+
+        Ok(())
+    }
+
+    /// Call this function to get the [`RetryPolicy`] and its timeout in seconds, if set by
+    /// [`IotHubClientBuilder::retry_policy`] or [`IotHubClient::set_retry_policy`]. Otherwise the
+    /// azure-sdk-c default applies.
+    pub fn retry_policy(&self) -> Option<(RetryPolicy, u32)> {
+        self.options
+            .retry_setting
+            .as_ref()
+            .map(|retry_setting| (retry_setting.policy, retry_setting.timeout_secs))
+    }
+
+    /// Call this function if the application knows that the network is down, e.g. from NetworkManager signals.
+    /// The connection is dropped like by [`IotHubClient::disconnect`], so that neither azure-sdk-c work nor
+    /// retries happen while suspended. D2C messages and reported properties sent meanwhile are buffered
+    /// (up to 1024 entries) and sent in order by [`IotHubClient::resume`]. Their trace ids are returned as usual,
+    /// confirmations are signaled after resume. Calling this function on a suspended client has no effect.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    /// use serde_json::json;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     // network went down
+    ///     client.suspend();
+    ///
+    ///     // buffered until resume
+    ///     client.twin_report(json!({"my_status": "offline"})).unwrap();
+    ///
+    ///     // network is up again
+    ///     client.resume().await.unwrap();
+    /// }
+    /// ```
+    pub fn suspend(&mut self) {
+        if self.suspended.borrow().is_some() {
+            debug!("suspend: already suspended");
+            return;
+        }
+
+        info!("suspend");
+
+        self.disconnect();
+        self.suspended.replace(Some(match self.latency_profile {
+            Some(_) => VecDeque::with_capacity(SUSPEND_BUFFER_CAPACITY),
+            None => VecDeque::new(),
+        }));
+    }
+
+    /// Call this function to resume a client suspended by [`IotHubClient::suspend`]. The connection is
+    /// re-established by [`IotHubClient::connect`] and all buffered D2C messages and reported properties are sent
+    /// in order. If the connection can't be established the client stays suspended. Buffered entries that can't be
+    /// sent are signaled as [`ConfirmationOutcome::Failed`]. Calling this function on a client that isn't
+    /// suspended has no effect.
+    pub async fn resume(&mut self) -> Result<()> {
+        if self.suspended.borrow().is_none() {
+            debug!("resume: not suspended");
+            return Ok(());
+        }
+
+        info!("resume");
+
+        self.connect().await?;
+
+        let buffer = self.suspended.take().unwrap_or_default();
+
+        debug!("resume: send {} buffered entries", buffer.len());
+
+        for (trace, send) in buffer {
+            let result = match send {
+                SuspendedSend::D2cMessage(message) => self.send_d2c(message, trace, None),
+                SuspendedSend::Reported(reported) => {
+                    self.send_reported(reported, trace).map(|_| ())
+                }
+            };
+
+            if let Err(e) = result {
+                error!("resume({trace}): cannot send buffered entry: {e}");
+                Self::finish_confirmation(
+                    &self.diagnostics,
+                    &self.on_confirmation,
+                    trace,
+                    ConfirmationOutcome::Failed(ConfirmationResult::Error),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Call this function to stop receiving C2D messages and module input messages, e.g. while the
+    /// application is overloaded. The incoming message callbacks are unregistered, so that messages aren't
+    /// accepted into observer channels but queue up in iothub until [`IotHubClient::resume_incoming`] is called.
+    /// The pause persists across reconnects. Calling this function on a paused client has no effect.<br>
+    /// ***Note***: messages queue up in iothub only until their time to live expires.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    /// use tokio::sync::mpsc;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let (tx_incoming_message, mut rx_incoming_message) = mpsc::channel(100);
+    ///
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .observe_incoming_messages(IncomingMessageObserver::new(tx_incoming_message, vec![]))
+    ///         .build_edge_client()
+    ///         .unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .observe_incoming_messages(IncomingMessageObserver::new(tx_incoming_message, vec![]))
+    ///         .build_device_client("my-connection-string")
+    ///         .unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder()
+    ///         .observe_incoming_messages(IncomingMessageObserver::new(tx_incoming_message, vec![]))
+    ///         .build_module_client("my-connection-string")
+    ///         .unwrap();
+    ///
+    ///     // overloaded
+    ///     client.pause_incoming().unwrap();
+    ///
+    ///     // work off the backlog
+    ///     // ...
+    ///
+    ///     client.resume_incoming().unwrap();
+    /// }
+    /// ```
+    pub fn pause_incoming(&mut self) -> Result<()> {
+        if self.incoming_paused.swap(true, Ordering::Relaxed) {
+            debug!("pause_incoming: already paused");
+            return Ok(());
+        }
+
+        info!("pause incoming messages");
+
+        self.register_incoming(false).map_err(|e| {
+            self.incoming_paused.store(false, Ordering::Relaxed);
+            e
+        })
+    }
+
+    /// Call this function to receive C2D messages and module input messages again after
+    /// [`IotHubClient::pause_incoming`]. Messages queued up in iothub meanwhile are delivered.
+    /// Calling this function on a client that isn't paused has no effect.
+    pub fn resume_incoming(&mut self) -> Result<()> {
+        if !self.incoming_paused.swap(false, Ordering::Relaxed) {
+            debug!("resume_incoming: not paused");
+            return Ok(());
+        }
+
+        info!("resume incoming messages");
+
+        self.register_incoming(true).map_err(|e| {
+            self.incoming_paused.store(true, Ordering::Relaxed);
+            e
+        })
+    }
+
+    /// registers or unregisters the incoming message callback of the current handle, if any
+    fn register_incoming(&mut self, register: bool) -> Result<()> {
+        if !self.twin.is_connected() {
+            return Ok(());
+        }
+
+        let inputs = self.inputs()?;
+        let Some(context) = self
+            .incoming_message_context
+            .as_deref_mut()
+            .map(|context| context as *mut IncomingMessageContext as *mut c_void)
+        else {
+            return Ok(());
+        };
+
+        self.twin.with(|twin| {
+            for input in inputs {
+                if register {
+                    twin.set_input_message_callback(
+                        input,
+                        Some(IotHubClient::c_c2d_message_callback),
+                        context,
+                    )?;
+                } else {
+                    twin.set_input_message_callback(input, None, std::ptr::null_mut())?;
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    #[cfg(feature = "edge_client")]
+    /// Call this function to request a server certificate for `common_name` from the IoT Edge workload API,
+    /// e.g. for a local TLS endpoint exposed by the module. The certificate is signed by the edge CA and
+    /// expires at `expiration` at the latest.<br>
+    /// ***Note1***: this function is only available with "edge_client" feature enabled.<br>
+    /// ***Note2***: the workload API doesn't sign CSRs on behalf of modules, thus key pairs are always
+    /// generated by the edge runtime. Use [`IotHubClient::edge_sign`] to sign data with the module identity.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let cert = IotHubClient::edge_server_certificate(
+    ///         "my-module",
+    ///         SystemTime::now() + Duration::from_secs(90 * 24 * 60 * 60),
+    ///     )
+    ///     .await
+    ///     .unwrap();
+    /// }
+    /// ```
+    pub async fn edge_server_certificate(
+        common_name: &str,
+        expiration: SystemTime,
+    ) -> Result<ServerCertificate> {
+        edge_workload::server_certificate(common_name, expiration).await
+    }
+
+    #[cfg(feature = "edge_client")]
+    /// Call this function to sign `data` on behalf of the module by the IoT Edge workload API, e.g. to
+    /// authenticate the module against local services. `data` is signed by HMAC-SHA256 with the identity key
+    /// `key_id` of the module, which is "primary" or "secondary". Returns the digest.<br>
+    /// ***Note***: this function is only available with "edge_client" feature enabled.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let digest = IotHubClient::edge_sign("primary", b"my-data").await.unwrap();
+    /// }
+    /// ```
+    pub async fn edge_sign(key_id: &str, data: &[u8]) -> Result<Vec<u8>> {
+        edge_workload::sign(key_id, data).await
+    }
+
+    #[cfg(feature = "edge_client")]
+    /// Call this function to get the trust bundle of the IoT Edge workload API in PEM format, e.g. in order to
+    /// validate certificates issued by [`IotHubClient::edge_server_certificate`] on clients of the module.<br>
+    /// ***Note***: this function is only available with "edge_client" feature enabled.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let trust_bundle = IotHubClient::edge_trust_bundle().await.unwrap();
+    /// }
+    /// ```
+    pub async fn edge_trust_bundle() -> Result<String> {
+        edge_workload::trust_bundle().await
+    }
+
+    /// Call this function to properly shutdown IotHub. All reported properties and D2C messages will be
+    /// continued to completion. Use [`ShutdownCoordinator`] if stages, e.g. a final "going offline" report,
+    /// and progress of the shutdown are required.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    /// use serde_json::json;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
     ///
     ///     let reported = json!({
     ///         "my_status": {
@@ -920,231 +5665,1233 @@ impl IotHubClient {
     ///     client.shutdown();
     /// }
     /// ```
-    #[allow(clippy::await_holding_refcell_ref)]
     pub async fn shutdown(&self) {
         info!("shutdown");
 
-        let join_all = async {
-            debug!(
-                "there are {} pending confirmations.",
-                self.confirmation_set.borrow().len()
-            );
-            while self
-                .confirmation_set
-                .borrow_mut()
-                .join_next()
-                .await
-                .is_some()
-            {}
-        };
+        self.flush_confirmations(Duration::from_secs(self.confirmation_timeout_secs))
+            .await;
+
+        self.abort_confirmations().await;
+    }
+
+    #[allow(clippy::await_holding_refcell_ref)]
+    async fn abort_confirmations(&self) {
+        /*
+           We abort and join all "wait for pending confirmations" tasks
+           (https://docs.rs/tokio/latest/src/tokio/task/join_set.rs.html#362).
+           This means:
+               - we have a clean shutdown
+               - we shutdown as fast as possible
+               - we DON'T WAIT for pending confirmations
+
+            Further the following line of code creates a false positive clippy warning which we
+            allow on function scope.
+        */
+
+        // aborted confirmations are not pending anymore, thus they aren't handed over to the waiter thread
+        if let Ok(mut diagnostics) = self.diagnostics.lock() {
+            diagnostics.clear_pending_confirmations();
+        }
+
+        self.confirmation_set.borrow_mut().shutdown().await;
+        self.confirmation_aborts.borrow_mut().clear();
+    }
+
+    /// waits at most `deadline` for pending confirmations and returns the number of still pending ones
+    async fn flush_confirmations(&self, deadline: Duration) -> usize {
+        let join_all = async {
+            debug!(
+                "there are {} pending confirmations.",
+                self.confirmation_set.borrow().len()
+            );
+            // the set is only borrowed while polled, so that messages can still be sent meanwhile
+            while std::future::poll_fn(|cx| self.confirmation_set.borrow_mut().poll_join_next(cx))
+                .await
+                .is_some()
+            {}
+        };
+
+        if tokio::time::timeout(deadline, join_all).await.is_err() {
+            warn!(
+                "there are {} pending confirmations after flush.",
+                self.confirmation_set.borrow().len()
+            );
+        }
+
+        self.confirmation_set.borrow().len()
+    }
+
+    #[cfg(feature = "edge_client")]
+    pub(crate) fn from_edge_environment(params: &IotHubClientBuilder) -> Result<IotHubClient> {
+        anyhow::ensure!(
+            !params.edge_trust_bundle,
+            "edge trust bundle must be applied by connect(): use build_edge_client_lazy()"
+        );
+
+        let mut client = IotHubClient::new(ConnectionSource::EdgeEnvironment, params)?;
+
+        client.attach_twin()?;
+
+        Ok(client)
+    }
+
+    #[cfg(any(feature = "module_client", feature = "device_client"))]
+    pub(crate) async fn from_identity_service(params: &IotHubClientBuilder) -> Result<Self> {
+        let mut client = IotHubClient::new(ConnectionSource::IdentityService, params)?;
+
+        client.connect().await?;
+
+        Ok(client)
+    }
+
+    #[cfg(any(feature = "module_client", feature = "device_client"))]
+    pub(crate) fn from_connection_string(
+        connection_string: &str,
+        params: &IotHubClientBuilder,
+    ) -> Result<Self> {
+        let mut client = IotHubClient::new(
+            ConnectionSource::ConnectionString(connection_string.to_string()),
+            params,
+        )?;
+
+        client.attach_twin()?;
+
+        Ok(client)
+    }
+
+    #[cfg(any(feature = "module_client", feature = "device_client"))]
+    async fn connection_string_from_identity_service() -> Result<String> {
+        let connection_info = request_connection_string_from_eis_with_expiry(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .saturating_add(Duration::from_secs(days_to_secs!(
+                    IDENTITY_CREDENTIAL_LIFETIME_IN_DAYS
+                ))),
+        )
+        .await
+        .map_err(|err| {
+            error!(
+                "iot identity service failed to create {:?} client identity: {err}.",
+                IotHubClient::client_type()
+            );
+
+            err
+        })?;
+
+        debug!(
+            "used con_str: {}",
+            connection_info.connection_string.as_str()
+        );
+
+        Ok(connection_info.connection_string)
+    }
+
+    #[cfg(any(feature = "module_client", feature = "device_client"))]
+    /// spawns a task that requests fresh credentials from identity service shortly before they expire or as soon
+    /// as iothub reports an expired sas token. the underlying handle is replaced transparently, i.e. all
+    /// observers and options stay in place.
+    fn spawn_credential_renewal(&mut self) -> Result<()> {
+        let twin = self.twin.clone();
+        let options = self.options.clone();
+        let contexts = self.owned_contexts()?;
+        let diagnostics = self.diagnostics.clone();
+        let sas_token_expired = self
+            .connection_status_context
+            .sas_token_expired
+            .clone()
+            .unwrap_or_default();
+        let renewal_interval = Duration::from_secs(days_to_secs!(
+            IDENTITY_CREDENTIAL_LIFETIME_IN_DAYS - IDENTITY_CREDENTIAL_RENEWAL_MARGIN_IN_DAYS
+        ));
+
+        if let Some(renewal) = self.credential_renewal.take() {
+            renewal.abort();
+        }
+
+        self.credential_renewal = Some(tokio::spawn(async move {
+            let mut next_renewal = renewal_interval;
+
+            loop {
+                match timeout(next_renewal, sas_token_expired.notified()).await {
+                    Ok(()) => info!("credential renewal: sas token expired"),
+                    Err(_) => info!("credential renewal: credentials about to expire"),
+                }
+
+                next_renewal =
+                    match IotHubClient::renew_credentials(&twin, &options, &contexts, &diagnostics)
+                        .await
+                    {
+                        Ok(()) => {
+                            info!("credential renewal: done");
+                            renewal_interval
+                        }
+                        Err(e) => {
+                            error!("credential renewal failed: {e}");
+
+                            if let Ok(mut diagnostics) = diagnostics.lock() {
+                                diagnostics
+                                    .report(ErrorEvent::CredentialRenewalFailure(e.to_string()));
+                            }
+
+                            Duration::from_secs(IDENTITY_CREDENTIAL_RENEWAL_RETRY_IN_SECS)
+                        }
+                    };
+            }
+        }));
+
+        Ok(())
+    }
+
+    #[cfg(any(feature = "module_client", feature = "device_client"))]
+    async fn renew_credentials(
+        twin: &Arc<SharedTwin>,
+        options: &TwinOptions,
+        contexts: &OwnedContexts,
+        diagnostics: &Arc<Mutex<Diagnostics>>,
+    ) -> Result<()> {
+        IotHubClient::recreate_twin(
+            twin,
+            &ConnectionSource::IdentityService,
+            options,
+            contexts,
+            diagnostics,
+        )
+        .await
+    }
+
+    #[cfg(any(feature = "module_client", feature = "device_client"))]
+    fn create_twin_from_connection_string(
+        connection_string: &str,
+        transport: Transport,
+    ) -> Result<Box<dyn Twin>> {
+        #[cfg(feature = "module_client")]
+        let mut twin = Box::<ModuleTwin>::default();
+
+        #[cfg(feature = "device_client")]
+        let mut twin = Box::<DeviceTwin>::default();
+
+        twin.create_from_connection_string(CString::new(connection_string)?, transport)?;
+
+        Ok(twin)
+    }
+
+    #[cfg(any(feature = "module_client", feature = "device_client"))]
+    fn validate_connection_string(connection_string: &str) -> Result<ConnectionString> {
+        let connection_string: ConnectionString = connection_string.parse()?;
+
+        if IotHubClient::client_type() == ClientType::Module
+            && connection_string.module_id().is_none()
+        {
+            anyhow::bail!("connection string: module client requires ModuleId");
+        }
+
+        Ok(connection_string)
+    }
+
+    /// validates all options and creates an instance without any hub I/O or underlying handle
+    fn new(source: ConnectionSource, params: &IotHubClientBuilder) -> Result<Self> {
+        let config = params.effective_config();
+
+        config.validate()?;
+        params.serializers.validate()?;
+
+        if !params.pnp_components.is_empty() && params.model_id.is_none() {
+            anyhow::bail!("pnp components require a pnp model id");
+        }
+
+        for component in &params.pnp_components {
+            pnp::validate_component(component)?;
+        }
+
+        if params
+            .diagnostic_sampling_percentage
+            .is_some_and(|percentage| percentage > 100)
+        {
+            anyhow::bail!("diagnostic sampling percentage must not exceed 100");
+        }
+
+        if params.max_in_flight == Some(0) {
+            anyhow::bail!("max in flight must be greater than 0");
+        }
+
+        if params.offline_store.is_some() && params.retransmit_policy.is_some() {
+            anyhow::bail!("offline store and retransmission of failed messages are exclusive");
+        }
+
+        if params
+            .adaptive_rate_limit
+            .is_some_and(|limit| !limit.is_valid())
+        {
+            anyhow::bail!("adaptive rate limit must be positive and finite");
+        }
+
+        if let Some(outputs) = &params.outputs {
+            if outputs.is_empty() {
+                anyhow::bail!("declared outputs must not be empty");
+            }
 
-        if tokio::time::timeout(
-            Duration::from_secs(Self::get_confirmation_timeout()),
-            join_all,
-        )
-        .await
-        .is_err()
+            for (output, (shards, _)) in &params.output_shardings {
+                if let Some(shard) = shards.iter().find(|shard| !outputs.contains(shard)) {
+                    anyhow::bail!("output {shard} sharded from {output} is not declared");
+                }
+            }
+        }
+
+        #[cfg(any(feature = "module_client", feature = "device_client"))]
+        #[allow(irrefutable_let_patterns)]
+        if let ConnectionSource::ConnectionString(connection_string) = &source {
+            IotHubClient::validate_connection_string(connection_string)?;
+        }
+
+        let observers = params.tx_incoming_message.as_deref().into_iter().chain(
+            params
+                .incoming_message_routes
+                .iter()
+                .map(|route| &route.observer),
+        );
+
+        for filter in params
+            .incoming_message_routes
+            .iter()
+            .flat_map(|route| &route.filters)
+            .chain(observers.flat_map(|observer| {
+                observer
+                    .dispositions
+                    .iter()
+                    .flat_map(|(filters, _)| filters)
+            }))
         {
-            warn!(
-                "there are {} pending confirmations on shutdown.",
-                self.confirmation_set.borrow().len()
-            );
+            match filter {
+                MessageFilter::Property { key, .. } => CString::new(key.as_str())?,
+                MessageFilter::Input(input) => CString::new(input.as_str())?,
+                MessageFilter::ContentType(content_type) => CString::new(content_type.as_str())?,
+            };
         }
 
-        /*
-           We abort and join all "wait for pending confirmations" tasks
-           (https://docs.rs/tokio/latest/src/tokio/task/join_set.rs.html#362).
-           This means:
-               - we have a clean shutdown
-               - we shutdown as fast as possible
-               - we DON'T WAIT for pending confirmations
+        #[allow(unused_mut)]
+        let mut trusted_certs = params.trusted_certs.clone();
 
-            Further the following line of code creates a false positive clippy warning which we
-            allow on function scope.
-        */
+        #[cfg(feature = "device_client")]
+        if let Some(gateway_setting) = &params.gateway_setting {
+            trusted_certs = Some(match trusted_certs {
+                Some(certs) => format!("{certs}\n{}", gateway_setting.root_ca),
+                None => gateway_setting.root_ca.clone(),
+            });
+        }
 
-        self.confirmation_set.borrow_mut().shutdown().await;
-    }
+        if let Some(trusted_certs) = &trusted_certs {
+            CString::new(trusted_certs.as_str())?;
+        }
 
-    #[cfg(feature = "edge_client")]
-    pub(crate) fn from_edge_environment(params: &IotHubClientBuilder) -> Result<IotHubClient> {
-        IotHubClient::iothub_init()?;
+        if let Some(product_info) = &params.product_info {
+            CString::new(product_info.as_str())?;
+        }
 
-        let mut twin = Box::<ModuleTwin>::default();
+        let mut diagnostics = match &params.latency_profile {
+            Some(profile) => Diagnostics::preallocated(profile.max_pending_confirmations),
+            None => Diagnostics::default(),
+        };
+        diagnostics.error_observer = params.tx_error.clone();
 
-        twin.create_from_edge_environment()?;
+        let diagnostics = Arc::new(Mutex::new(diagnostics));
+        let audit = params.audit_inbound_commands.then(|| diagnostics.clone());
+        let rejected_store = params
+            .rejected_message_store
+            .clone()
+            .map(|store| RejectedStore::open(store).map(Arc::new))
+            .transpose()?;
 
-        let mut client = IotHubClient {
-            twin,
-            tx_connection_status: params.tx_connection_status.clone(),
-            tx_twin_desired: params.tx_twin_desired.clone(),
-            tx_direct_method: params.tx_direct_method.clone(),
-            tx_incoming_message: params.tx_incoming_message.clone(),
-            model_id: params.model_id,
-            retry_setting: params.retry_setting.clone(),
+        #[cfg(feature = "edge_client")]
+        let edge_trust_bundle = params.edge_trust_bundle.then(|| trusted_certs.clone());
+
+        let (tx_managed_configuration, rx_managed_configuration) =
+            match &params.managed_configuration {
+                Some(section) => {
+                    managed_config::validate_section(section)?;
+
+                    let (tx, rx) = mpsc::unbounded_channel();
+
+                    (Some((section.clone(), tx)), Some((section.clone(), rx)))
+                }
+                None => (None, None),
+            };
+
+        let job_registry = Arc::new(Mutex::new(JobRegistry::default()));
+        let (jobs, rx_job_reports) = match &params.long_running_methods {
+            Some((methods, observer)) => {
+                let (context, rx) =
+                    JobContext::new(methods, observer.clone(), job_registry.clone());
+
+                (Some(context), Some(rx))
+            }
+            None => (None, None),
+        };
+
+        let mut incoming_observer = params.tx_incoming_message.as_deref().cloned();
+        let mut incoming_routes = params.incoming_message_routes.clone();
+        let mut incoming_buffers = vec![];
+
+        if let OverflowPolicy::AcceptAndBuffer(size) = params.incoming_overflow_policy {
+            anyhow::ensure!(size > 0, "overflow buffer size must be greater than 0");
+
+            for observer in incoming_observer
+                .iter_mut()
+                .chain(incoming_routes.iter_mut().map(|route| &mut route.observer))
+            {
+                let (tx, rx) = mpsc::channel(size);
+
+                observer.buffer = Some(tx);
+                incoming_buffers.push((rx, observer.responder.clone()));
+            }
+        }
+
+        Ok(IotHubClient {
+            twin: Arc::new(SharedTwin::default()),
+            source,
+            connection_status_context: Box::new(ConnectionStatusContext {
+                observer: params.tx_connection_status.as_deref().cloned(),
+                retry: params.tx_retry.clone(),
+                diagnostics: diagnostics.clone(),
+                sas_token_expired: Some(Arc::new(Notify::new())),
+                status_changed: params.restart_policy.map(|_| Arc::new(Notify::new())),
+                latency_profile: params.latency_profile,
+                #[cfg(feature = "systemd")]
+                systemd_readiness: params.notify_systemd.then(systemd::Readiness::default),
+            }),
+            twin_desired_context: (params.tx_twin_desired.is_some()
+                || tx_managed_configuration.is_some())
+            .then(|| {
+                Box::new(TwinDesiredContext {
+                    observer: params.tx_twin_desired.as_deref().cloned(),
+                    managed_configuration: tx_managed_configuration,
+                    diagnostics: diagnostics.clone(),
+                    latency_profile: params.latency_profile,
+                })
+            }),
+            direct_method_context: (params.tx_direct_method.is_some() || jobs.is_some()).then(
+                || {
+                    Box::new(DirectMethodContext {
+                        observer: params.tx_direct_method.as_deref().cloned(),
+                        jobs,
+                        tx_dead_letter: params.tx_dead_letter.clone(),
+                        audit: audit.clone(),
+                        diagnostics: diagnostics.clone(),
+                        latency_profile: params.latency_profile,
+                    })
+                },
+            ),
+            incoming_message_context: (params.tx_incoming_message.is_some()
+                || !params.incoming_message_routes.is_empty())
+            .then(|| {
+                Box::new(IncomingMessageContext {
+                    observer: incoming_observer,
+                    routes: incoming_routes,
+                    tx_dead_letter: params.tx_dead_letter.clone(),
+                    tx_feedback: params.tx_incoming_message_feedback.clone(),
+                    rejected_store: rejected_store.clone(),
+                    overflow_policy: params.incoming_overflow_policy,
+                    audit: audit.clone(),
+                    diagnostics: diagnostics.clone(),
+                    latency_profile: params.latency_profile,
+                    #[cfg(feature = "encryption")]
+                    encryption: params.encryption.clone(),
+                })
+            }),
+            options: TwinOptions {
+                model_id: params.model_id.clone(),
+                unsupported_model_id_policy: params.unsupported_model_id_policy,
+                retry_setting: params.retry_setting.clone(),
+                sas_token_setting: params.sas_token_setting.clone(),
+                diagnostic_sampling_percentage: params.diagnostic_sampling_percentage,
+                product_info: params.product_info.clone(),
+                trusted_certs,
+                proxy_setting: params.proxy_setting.clone(),
+                transport: params.transport,
+                http_setting: params.http_setting.clone(),
+                do_work_freq_ms: config.do_work_freq_ms(),
+                logging: config.logging.unwrap_or_default(),
+                #[cfg(feature = "device_client")]
+                gateway_host_name: params
+                    .gateway_setting
+                    .as_ref()
+                    .map(|gateway_setting| gateway_setting.host_name.clone()),
+            },
+            on_confirmation: params.on_confirmation.clone(),
+            latency_profile: params.latency_profile,
+            confirmation_timeout_secs: config.confirmation_timeout_secs(),
+            restart_policy: params.restart_policy,
+            watchdog: None,
+            websocket_fallback: params
+                .websocket_fallback
+                .then_some(params.transport)
+                .filter(|transport| transport.websocket_fallback().is_some()),
+            tx_lifecycle: params.tx_lifecycle.clone(),
+            managed_configuration: rx_managed_configuration,
+            managed_configuration_task: None,
+            job_reports: rx_job_reports,
+            job_reports_task: None,
+            incoming_buffers,
+            incoming_buffer_tasks: vec![],
+            offline_store: params
+                .offline_store
+                .clone()
+                .map(|store| MessageStore::open(store).map(Arc::new))
+                .transpose()?,
+            rejected_store,
+            offline_replay_task: None,
+            retransmit_policy: params.retransmit_policy,
+            tx_dead_letter: params.tx_dead_letter.clone(),
+            job_registry,
+            output_shardings: params
+                .output_shardings
+                .iter()
+                .map(|(output, (outputs, strategy))| {
+                    let outputs = outputs
+                        .iter()
+                        .map(|o| CString::new(o.as_str()))
+                        .collect::<Result<Vec<CString>, _>>()?;
+                    Ok((
+                        CString::new(output.as_str())?,
+                        OutputSharding::new(outputs, strategy.clone()),
+                    ))
+                })
+                .collect::<Result<HashMap<CString, OutputSharding>>>()?,
+            outputs: params
+                .outputs
+                .as_ref()
+                .map(|outputs| {
+                    outputs
+                        .iter()
+                        .map(|o| CString::new(o.as_str()))
+                        .collect::<Result<Vec<CString>, _>>()
+                })
+                .transpose()?,
             confirmation_set: JoinSet::new().into(),
-            trace_id: AtomicU32::new(0),
+            confirmation_aborts: HashMap::new().into(),
+            confirmation_waiter: Arc::default(),
+            in_flight: params
+                .max_in_flight
+                .map(|max_in_flight| Arc::new(Semaphore::new(max_in_flight))),
+            rate_limiter: params.adaptive_rate_limit.map(RateLimiter::new),
+            dedup_window: params.dedup_window.map(DedupWindow::new),
+            suspended: None.into(),
+            incoming_paused: Arc::new(AtomicBool::new(false)),
+            sends_stopped: false.into(),
+            trace_id: TraceIdGenerator::new(params.trace_id_strategy.clone()),
+            diagnostics,
+            capabilities: ClientCapabilities::default(),
+            timestamp_property: params
+                .timestamp_property
+                .as_deref()
+                .map(|property| CString::new(message::urlencode(property)))
+                .transpose()?,
+            auto_message_ids: params.auto_message_ids,
+            chunk_oversized_messages: params.chunk_oversized_messages,
+            default_properties: params
+                .default_properties
+                .iter()
+                .map(|(key, value)| {
+                    Ok((CString::new(key.as_str())?, CString::new(value.as_str())?))
+                })
+                .collect::<Result<HashMap<CString, CString>>>()?,
+            pnp_components: params.pnp_components.clone(),
+            idempotency_keys: params.idempotency_keys.clone(),
+            serializers: params.serializers.clone(),
+            telemetry_schemas: params.telemetry_schemas.clone(),
+            clock: MonotonicClock::new(),
+            #[cfg(any(feature = "module_client", feature = "device_client"))]
+            secondary_hubs: params
+                .secondary_hubs
+                .iter()
+                .map(SecondaryHub::new)
+                .collect::<Result<Vec<SecondaryHub>>>()?,
+            #[cfg(any(feature = "module_client", feature = "device_client"))]
+            credential_renewal: None,
+            #[cfg(feature = "edge_client")]
+            edge_trust_bundle,
+            #[cfg(feature = "encryption")]
+            encryption: params.encryption.clone(),
+            #[cfg(feature = "systemd")]
+            notify_systemd: params.notify_systemd,
+            #[cfg(feature = "systemd")]
+            systemd_watchdog: None,
+            platform: None,
+        })
+    }
+
+    /// creates the underlying handle from the connection source, registers callbacks and applies options
+    fn attach_twin(&mut self) -> Result<()> {
+        self.iothub_init()?;
+
+        let host = match &self.source {
+            #[cfg(any(feature = "module_client", feature = "device_client"))]
+            ConnectionSource::ConnectionString(connection_string) => {
+                IotHubClient::transport_host(&self.options.via_gateway(connection_string)?)
+            }
+            #[cfg(any(feature = "module_client", feature = "device_client"))]
+            ConnectionSource::IdentityService => None,
+            #[cfg(feature = "edge_client")]
+            ConnectionSource::EdgeEnvironment => env::var("IOTEDGE_GATEWAYHOSTNAME").ok(),
         };
 
-        client.set_callbacks()?;
+        self.select_transport(host.as_deref());
 
-        client.set_options()?;
+        let twin = match &self.source {
+            #[cfg(any(feature = "module_client", feature = "device_client"))]
+            ConnectionSource::ConnectionString(connection_string) => {
+                IotHubClient::create_twin_from_connection_string(
+                    &self.options.via_gateway(connection_string)?,
+                    self.options.transport,
+                )?
+            }
+            #[cfg(any(feature = "module_client", feature = "device_client"))]
+            ConnectionSource::IdentityService => {
+                anyhow::bail!(
+                    "connection string from identity service must be requested by connect()"
+                )
+            }
+            #[cfg(feature = "edge_client")]
+            ConnectionSource::EdgeEnvironment => {
+                let mut twin = Box::<ModuleTwin>::default();
+                twin.create_from_edge_environment(self.options.transport)?;
+                twin
+            }
+        };
 
-        Ok(client)
+        self.attach(twin)
     }
 
-    #[cfg(feature = "module_client")]
-    pub(crate) async fn from_identity_service(params: &IotHubClientBuilder) -> Result<Self> {
-        let connection_info = request_connection_string_from_eis_with_expiry(
-            SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)?
-                .saturating_add(Duration::from_secs(days_to_secs!(30))),
+    #[cfg(any(feature = "module_client", feature = "device_client"))]
+    /// host the client connects to, i.e. the gateway if any
+    fn transport_host(connection_string: &str) -> Option<String> {
+        let connection_string = connection_string.parse::<ConnectionString>().ok()?;
+
+        Some(
+            connection_string
+                .gateway_host_name()
+                .unwrap_or(connection_string.host_name())
+                .to_string(),
         )
-        .await
-        .map_err(|err| {
-            error!("iot identity service failed to create module client identity: {err}.");
+    }
 
-            err
-        })?;
+    /// selects the transport if falling back to websockets is enabled and signals it as lifecycle event
+    fn select_transport(&mut self, host: Option<&str>) {
+        let Some(primary) = self.websocket_fallback else {
+            return;
+        };
 
-        debug!(
-            "used con_str: {}",
-            connection_info.connection_string.as_str()
-        );
+        let (transport, fallback) = transport_fallback::select(primary, host);
 
-        IotHubClient::from_connection_string(connection_info.connection_string.as_str(), params)
+        info!("select transport: {transport:?} (fallback: {fallback})");
+
+        self.options.transport = transport;
+
+        if let Some(tx) = &self.tx_lifecycle {
+            if let Err(e) = tx.try_send(LifecycleEvent::TransportSelected {
+                transport,
+                fallback,
+            }) {
+                warn!("cannot signal lifecycle event: {e}");
+            }
+        }
     }
 
-    #[cfg(any(feature = "module_client", feature = "device_client"))]
-    pub(crate) fn from_connection_string(
-        connection_string: &str,
-        params: &IotHubClientBuilder,
-    ) -> Result<Self> {
-        IotHubClient::iothub_init()?;
+    fn attach(&mut self, mut twin: Box<dyn Twin>) -> Result<()> {
+        // build_* functions might be called outside of a runtime, thus fail instead of panicking on spawn
+        if self.spawns_tasks() && tokio::runtime::Handle::try_current().is_err() {
+            twin.destroy();
+            anyhow::bail!(
+                "options set require a tokio runtime: build within a runtime or use a build_*_lazy function and connect()"
+            );
+        }
 
-        #[cfg(feature = "module_client")]
-        let mut twin = Box::<ModuleTwin>::default();
+        let contexts = self.callback_contexts()?;
 
-        #[cfg(feature = "device_client")]
-        let mut twin = Box::<DeviceTwin>::default();
+        match IotHubClient::apply_callbacks(twin.as_ref(), &contexts).and_then(|_| {
+            IotHubClient::apply_options(twin.as_ref(), &self.options, &self.diagnostics)
+        }) {
+            Ok(capabilities) => {
+                info!("client capabilities: {capabilities:?}");
+                self.capabilities = capabilities;
+            }
+            Err(e) => {
+                twin.destroy();
+                return Err(e);
+            }
+        }
+
+        if let Some(retired) = self.twin.replace(Some(twin)) {
+            retired.destroy();
+        }
 
-        twin.create_from_connection_string(CString::new(connection_string)?)?;
+        #[cfg(any(feature = "module_client", feature = "device_client"))]
+        if let Err(e) = self.attach_secondary_hubs() {
+            self.detach();
+            return Err(e);
+        }
 
-        let mut client = IotHubClient {
-            twin,
-            tx_connection_status: params.tx_connection_status.clone(),
-            tx_twin_desired: params.tx_twin_desired.clone(),
-            tx_direct_method: params.tx_direct_method.clone(),
-            tx_incoming_message: params.tx_incoming_message.clone(),
-            model_id: params.model_id,
-            retry_setting: params.retry_setting.clone(),
-            confirmation_set: JoinSet::new().into(),
-            trace_id: AtomicU32::new(0),
+        if let Some((section, rx)) = self.managed_configuration.take() {
+            self.managed_configuration_task = Some(tokio::spawn(managed_config::run(
+                section,
+                rx,
+                self.twin.clone(),
+                self.diagnostics.clone(),
+                Duration::from_secs(self.confirmation_timeout_secs),
+            )));
+        }
+
+        if let Some(rx) = self.job_reports.take() {
+            self.job_reports_task = Some(tokio::spawn(jobs::run(
+                rx,
+                self.twin.clone(),
+                self.diagnostics.clone(),
+                Duration::from_secs(self.confirmation_timeout_secs),
+            )));
+        }
+
+        for (rx, tx) in mem::take(&mut self.incoming_buffers) {
+            self.incoming_buffer_tasks
+                .push(tokio::spawn(overflow::run(rx, tx)));
+        }
+
+        // the replay keeps running across reconnects and waits until the client is authenticated
+        if let (Some(store), None) = (&self.offline_store, &self.offline_replay_task) {
+            self.offline_replay_task = Some(tokio::spawn(offline_store::run(
+                store.clone(),
+                self.twin.clone(),
+                self.diagnostics.clone(),
+                self.on_confirmation.clone(),
+                Duration::from_secs(self.confirmation_timeout_secs),
+            )));
+        }
+
+        // the watchdog keeps running across reconnects, since a disconnected client isn't healthy
+        #[cfg(feature = "systemd")]
+        if self.notify_systemd && self.systemd_watchdog.is_none() {
+            self.systemd_watchdog = Some(tokio::spawn(systemd::run_watchdog(
+                self.diagnostics.clone(),
+                Duration::from_secs(self.confirmation_timeout_secs),
+            )));
+        }
+
+        self.spawn_watchdog()
+    }
+
+    /// returns true if attaching a handle spawns background tasks, which requires a tokio runtime
+    fn spawns_tasks(&self) -> bool {
+        #[cfg(feature = "systemd")]
+        if self.notify_systemd && self.systemd_watchdog.is_none() {
+            return true;
+        }
+
+        self.managed_configuration.is_some()
+            || self.job_reports.is_some()
+            || !self.incoming_buffers.is_empty()
+            || (self.offline_store.is_some() && self.offline_replay_task.is_none())
+            || (self.restart_policy.is_some()
+                && self.connection_status_context.status_changed.is_some())
+    }
+
+    /// spawns the watchdog that recreates the handle according to the restart policy, i.e. if the client stays
+    /// unauthenticated too long or the connection quality drops too low
+    fn spawn_watchdog(&mut self) -> Result<()> {
+        let (Some(policy), Some(status_changed)) = (
+            self.restart_policy,
+            self.connection_status_context.status_changed.clone(),
+        ) else {
+            return Ok(());
         };
+        let dead_after = policy.stall_after();
 
-        client.set_callbacks()?;
+        let twin = self.twin.clone();
+        let source = self.source.clone();
+        let options = self.options.clone();
+        let contexts = self.owned_contexts()?;
+        let diagnostics = self.diagnostics.clone();
+        let tx_lifecycle = self.tx_lifecycle.clone();
 
-        client.set_options()?;
+        if let Some(watchdog) = self.watchdog.take() {
+            watchdog.abort();
+        }
 
-        Ok(client)
+        self.watchdog = Some(tokio::spawn(async move {
+            let last_status = || match diagnostics.lock() {
+                Ok(diagnostics) => diagnostics.last_connection_status(),
+                Err(poisoned) => poisoned.into_inner().last_connection_status(),
+            };
+
+            let restart = |event: LifecycleEvent| {
+                IotHubClient::restart_twin(
+                    &twin,
+                    &source,
+                    &options,
+                    &contexts,
+                    &diagnostics,
+                    &tx_lifecycle,
+                    event,
+                )
+            };
+
+            loop {
+                let Some(AuthenticationStatus::Unauthenticated(reason)) = last_status() else {
+                    let Some(threshold) = policy.quality_below() else {
+                        status_changed.notified().await;
+                        continue;
+                    };
+
+                    // confirmations aren't signaled to the watchdog, thus the quality is checked periodically
+                    if tokio::time::timeout(
+                        Duration::from_secs(QUALITY_CHECK_INTERVAL_IN_SECS),
+                        status_changed.notified(),
+                    )
+                    .await
+                    .is_ok()
+                    {
+                        continue;
+                    }
+
+                    let Ok((quality, (succeeded, samples))) = diagnostics
+                        .lock()
+                        .map(|d| (d.connection_quality(), d.confirmation_samples()))
+                    else {
+                        continue;
+                    };
+
+                    if policy.quality_violated(quality) {
+                        warn!("watchdog: {succeeded} of {samples} confirmations succeeded, quality below {threshold}, recreate handle");
+
+                        restart(LifecycleEvent::QualityRestart { succeeded, samples }).await;
+                    }
+
+                    continue;
+                };
+
+                let deadline = tokio::time::Instant::now() + dead_after;
+                let mut recovered = false;
+
+                // wait for authentication until the deadline
+                while tokio::time::timeout_at(deadline, status_changed.notified())
+                    .await
+                    .is_ok()
+                {
+                    if last_status() == Some(AuthenticationStatus::Authenticated) {
+                        recovered = true;
+                        break;
+                    }
+                }
+
+                if recovered {
+                    continue;
+                }
+
+                warn!("watchdog: unauthenticated ({reason:?}) for {dead_after:?}, recreate handle");
+
+                restart(LifecycleEvent::Recovered { reason }).await;
+            }
+        }));
+
+        Ok(())
     }
 
-    fn iothub_init() -> Result<()> {
-        static IOTHUB_INIT_ONCE: Once = Once::new();
-        static mut IOTHUB_INIT_RESULT: i32 = -1;
+    /// recreates the handle on behalf of the watchdog and signals `event` if succeeded. the connection quality
+    /// is sampled again afterwards.
+    async fn restart_twin(
+        twin: &Arc<SharedTwin>,
+        source: &ConnectionSource,
+        options: &TwinOptions,
+        contexts: &OwnedContexts,
+        diagnostics: &Arc<Mutex<Diagnostics>>,
+        tx_lifecycle: &Option<LifecycleObserver>,
+        event: LifecycleEvent,
+    ) {
+        let result =
+            IotHubClient::recreate_twin(twin, source, options, contexts, diagnostics).await;
+
+        if let Ok(mut diagnostics) = diagnostics.lock() {
+            diagnostics.reset_connection_quality();
+        }
+
+        match result {
+            Ok(()) => {
+                info!("watchdog: handle recreated");
 
-        unsafe {
-            IOTHUB_INIT_ONCE.call_once(|| {
-                IOTHUB_INIT_RESULT = IoTHub_Init();
-            });
+                if let Some(tx) = tx_lifecycle {
+                    if let Err(e) = tx.try_send(event) {
+                        warn!("cannot signal lifecycle event: {e}");
+                    }
+                }
+            }
+            Err(e) => {
+                error!("watchdog: cannot recreate handle: {e}");
 
-            match IOTHUB_INIT_RESULT {
-                0 => Ok(()),
-                _ => anyhow::bail!("error while IoTHub_Init()"),
+                if let Ok(mut diagnostics) = diagnostics.lock() {
+                    diagnostics.report(ErrorEvent::RecoveryFailure(e.to_string()));
+                }
             }
         }
     }
 
-    fn set_callbacks(&mut self) -> Result<()> {
-        if let Some(tx) = self.tx_connection_status.as_deref_mut() {
-            self.twin.set_connection_status_callback(
-                Some(IotHubClient::c_connection_status_callback),
-                tx as *mut AuthenticationObserver as *mut c_void,
-            )?;
+    /// creates a new handle from `source` with all callbacks and options applied and replaces the current one
+    async fn recreate_twin(
+        twin: &Arc<SharedTwin>,
+        source: &ConnectionSource,
+        options: &TwinOptions,
+        contexts: &OwnedContexts,
+        diagnostics: &Arc<Mutex<Diagnostics>>,
+    ) -> Result<()> {
+        #[cfg(any(feature = "module_client", feature = "device_client"))]
+        let connection_string = match source {
+            ConnectionSource::ConnectionString(connection_string) => {
+                options.via_gateway(connection_string)?
+            }
+            ConnectionSource::IdentityService => options
+                .via_gateway(&IotHubClient::connection_string_from_identity_service().await?)?,
+        };
+
+        #[cfg(feature = "edge_client")]
+        let ConnectionSource::EdgeEnvironment = source;
+
+        let twin = twin.clone();
+        let options = options.clone();
+        let contexts = contexts.clone();
+        let diagnostics = diagnostics.clone();
+
+        // creating and destroying handles blocks. destroying joins the azure-sdk-c worker thread, which might wait
+        // in a callback for a consumer using the client.
+        tokio::task::spawn_blocking(move || {
+            let retired = twin.renew(|| {
+                #[cfg(any(feature = "module_client", feature = "device_client"))]
+                let mut new_twin = IotHubClient::create_twin_from_connection_string(
+                    &connection_string,
+                    options.transport,
+                )?;
+
+                #[cfg(feature = "edge_client")]
+                let mut new_twin = {
+                    let mut twin = Box::<ModuleTwin>::default();
+                    twin.create_from_edge_environment(options.transport)?;
+                    twin
+                };
+
+                // the new handle gets contexts of its own, since the old one might still call back
+                let mut contexts = Box::new(contexts);
+
+                if let Err(e) =
+                    IotHubClient::apply_callbacks(new_twin.as_ref(), &contexts.callback_contexts())
+                        .and_then(|_| {
+                            IotHubClient::apply_options(new_twin.as_ref(), &options, &diagnostics)
+                        })
+                {
+                    new_twin.destroy();
+                    return Err(e);
+                }
+
+                Ok((new_twin, contexts as Box<dyn Send>))
+            })?;
+
+            if let Some(retired) = retired {
+                retired.destroy();
+            }
+
+            Ok(())
+        })
+        .await?
+    }
+
+    #[cfg(any(feature = "module_client", feature = "device_client"))]
+    fn attach_secondary_hubs(&mut self) -> Result<()> {
+        for hub in self.secondary_hubs.iter_mut() {
+            let mut twin = hub.create_twin(self.options.transport)?;
+
+            // apply all options to the secondary connection
+            if let Err(e) =
+                IotHubClient::apply_options(twin.as_ref(), &self.options, &self.diagnostics)
+            {
+                twin.destroy();
+                return Err(e);
+            }
+
+            hub.twin = Some(twin);
         }
 
-        if let Some(tx) = self.tx_incoming_message.as_deref_mut() {
-            self.twin.set_input_message_callback(
-                Some(IotHubClient::c_c2d_message_callback),
-                tx as *mut IncomingMessageObserver as *mut c_void,
-            )?;
+        Ok(())
+    }
+
+    /// destroys all underlying handles
+    fn detach(&mut self) {
+        #[cfg(any(feature = "module_client", feature = "device_client"))]
+        for hub in self.secondary_hubs.iter_mut() {
+            hub.destroy();
         }
 
-        if let Some(tx) = self.tx_twin_desired.as_deref_mut() {
-            self.twin.set_twin_callback(
-                Some(IotHubClient::c_twin_callback),
-                tx as *mut TwinObserver as *mut c_void,
-            )?;
+        if let Some(retired) = self.twin.replace(None) {
+            retired.destroy();
         }
 
-        if let Some(tx) = self.tx_direct_method.as_deref_mut() {
-            self.twin.set_method_callback(
-                Some(IotHubClient::c_direct_method_callback),
-                tx as *mut DirectMethodObserver as *mut c_void,
-            )?;
+        if let Ok(mut diagnostics) = self.diagnostics.lock() {
+            diagnostics.reset_connection_status();
+        }
+    }
+
+    /// initializes the azure-sdk-c platform once per client, it's deinitialized when the last client is dropped
+    fn iothub_init(&mut self) -> Result<()> {
+        if self.platform.is_none() {
+            self.platform = Some(PlatformRef::acquire()?);
         }
 
         Ok(())
     }
 
-    fn set_options(&mut self) -> Result<()> {
-        let mut do_work_freq = None;
+    /// inputs the incoming message callback is registered for
+    fn inputs(&self) -> Result<Vec<CString>> {
+        let mut inputs = vec![CString::new("input")?];
 
-        if let Ok(freq) = env::var(AZURE_SDK_DO_WORK_FREQUENCY_IN_MS) {
-            match freq.parse::<u64>() {
-                Ok(freq) if DO_WORK_FREQUENCY_RANGE_IN_MS.contains(&freq) => {
-                    info!("set do_work frequency {freq}ms");
-                    do_work_freq = Some(freq);
+        if let Some(context) = self.incoming_message_context.as_deref() {
+            if IotHubClient::client_type() != ClientType::Device {
+                for input in context.routes.iter().flat_map(IncomingMessageRoute::inputs) {
+                    let input = CString::new(input)?;
+
+                    if !inputs.contains(&input) {
+                        inputs.push(input);
+                    }
                 }
-                _ => error!("ignore do_work frequency {freq} since not in range of {DO_WORK_FREQUENCY_RANGE_IN_MS:?}ms"),
-            };
+            }
+        }
+
+        Ok(inputs)
+    }
+
+    fn callback_contexts(&mut self) -> Result<CallbackContexts> {
+        let inputs = self.inputs()?;
+
+        Ok(CallbackContexts {
+            connection_status: self.connection_status_context.as_mut()
+                as *mut ConnectionStatusContext as *mut c_void,
+            incoming_message: self
+                .incoming_message_context
+                .as_deref_mut()
+                .map(|context| context as *mut IncomingMessageContext as *mut c_void),
+            twin_desired: self
+                .twin_desired_context
+                .as_deref_mut()
+                .map(|context| context as *mut TwinDesiredContext as *mut c_void),
+            direct_method: self
+                .direct_method_context
+                .as_deref_mut()
+                .map(|context| context as *mut DirectMethodContext as *mut c_void),
+            inputs,
+            incoming_paused: self.incoming_paused.clone(),
+        })
+    }
+
+    fn owned_contexts(&self) -> Result<OwnedContexts> {
+        Ok(OwnedContexts {
+            connection_status: self.connection_status_context.as_ref().clone(),
+            incoming_message: self.incoming_message_context.as_deref().cloned(),
+            twin_desired: self.twin_desired_context.as_deref().cloned(),
+            direct_method: self.direct_method_context.as_deref().cloned(),
+            inputs: self.inputs()?,
+            incoming_paused: self.incoming_paused.clone(),
+        })
+    }
+
+    fn apply_callbacks(twin: &dyn Twin, contexts: &CallbackContexts) -> Result<()> {
+        // the connection status is always observed in order to keep track of the connection history
+        twin.set_connection_status_callback(
+            Some(IotHubClient::c_connection_status_callback),
+            contexts.connection_status,
+        )?;
+
+        if let Some(context) = contexts
+            .incoming_message
+            .filter(|_| !contexts.incoming_paused.load(Ordering::Relaxed))
+        {
+            for input in &contexts.inputs {
+                twin.set_input_message_callback(
+                    input.clone(),
+                    Some(IotHubClient::c_c2d_message_callback),
+                    context,
+                )?;
+            }
+        }
+
+        if let Some(context) = contexts.twin_desired {
+            twin.set_twin_callback(Some(IotHubClient::c_twin_callback), context)?;
         }
 
-        if do_work_freq.is_none() {
-            do_work_freq = Some(DO_WORK_FREQUENCY_DEFAULT_IN_MS);
-            info!("set default do_work frequency {DO_WORK_FREQUENCY_DEFAULT_IN_MS}ms")
+        if let Some(context) = contexts.direct_method {
+            twin.set_method_callback(Some(IotHubClient::c_direct_method_callback), context)?;
         }
 
-        self.twin.set_option(
+        Ok(())
+    }
+
+    /// applies all options to `twin` and returns the resulting capabilities
+    fn apply_options(
+        twin: &dyn Twin,
+        options: &TwinOptions,
+        diagnostics: &Arc<Mutex<Diagnostics>>,
+    ) -> Result<ClientCapabilities> {
+        // transport and gateway are accepted on creation of the handle, all other capabilities are only set
+        // once the handle accepted the corresponding option
+        let mut capabilities = ClientCapabilities {
+            transport: options.transport,
+            websockets: matches!(
+                options.transport,
+                Transport::MqttWebSocket | Transport::AmqpWebSocket
+            ),
+            #[cfg(feature = "device_client")]
+            gateway: options.gateway_host_name.is_some(),
+            ..Default::default()
+        };
+
+        twin.set_option(
             CString::new("do_work_freq_ms")?,
-            do_work_freq.as_mut().unwrap() as *const uint_fast64_t as *const c_void,
+            &options.do_work_freq_ms as *const uint_fast64_t as *const c_void,
         )?;
+        capabilities.do_work_freq_ms = options.do_work_freq_ms;
 
-        if env::var(AZURE_SDK_LOGGING).is_ok() {
-            self.twin.set_option(
+        if options.logging {
+            twin.set_option(
                 CString::new("logtrace")?,
                 &mut true as *const bool as *const c_void,
             )?
         }
 
-        if let Some(model_id) = self.model_id {
+        if let Some(model_id) = &options.model_id {
             info!("set pnp model id: {model_id}");
-            let model_id = CString::new(model_id)?;
-            self.twin.set_option(
+            let model_id = CString::new(model_id.as_str())?;
+
+            match twin.set_option(
                 CString::new("model_id")?,
                 model_id.as_ptr() as *const c_void,
+            ) {
+                Ok(_) => capabilities.pnp_model_id = Some(model_id.to_string_lossy().into_owned()),
+                Err(e) => match options.unsupported_model_id_policy {
+                    UnsupportedModelIdPolicy::Error => {
+                        return Err(
+                            e.context("pnp model id is not supported by client type or transport")
+                        );
+                    }
+                    UnsupportedModelIdPolicy::Warn => {
+                        warn!("pnp model id is not supported by client type or transport: {e}");
+
+                        if let Ok(mut diagnostics) = diagnostics.lock() {
+                            diagnostics.report(ErrorEvent::SetOptionFailure(format!(
+                                "pnp model id not announced: {e}"
+                            )));
+                        }
+                    }
+                },
+            }
+        }
+
+        if let Some(retry_setting) = &options.retry_setting {
+            info!("set retry policy: {retry_setting:?}");
+            twin.set_retry_policy(
+                retry_setting.policy as u32,
+                retry_setting.timeout_secs as usize,
+            )?;
+            capabilities.retry_policy = true;
+        }
+
+        if let Some(trusted_certs) = &options.trusted_certs {
+            info!("set trusted certs");
+            let trusted_certs = CString::new(trusted_certs.as_str())?;
+            twin.set_option(
+                CString::new("TrustedCerts")?,
+                trusted_certs.as_ptr() as *const c_void,
+            )?;
+            capabilities.trusted_certs = true;
+        }
+
+        if let Some(proxy_setting) = &options.proxy_setting {
+            info!("set http proxy: {proxy_setting:?}");
+            let host_address = CString::new(proxy_setting.host_address.as_str())?;
+            let (username, password) = match &proxy_setting.credentials {
+                Some((username, password)) => (
+                    Some(CString::new(username.as_str())?),
+                    Some(CString::new(password.as_str())?),
+                ),
+                None => (None, None),
+            };
+            let proxy_options = HTTP_PROXY_OPTIONS {
+                host_address: host_address.as_ptr(),
+                port: proxy_setting.port.into(),
+                username: username.as_ref().map_or(std::ptr::null(), |u| u.as_ptr()),
+                password: password.as_ref().map_or(std::ptr::null(), |p| p.as_ptr()),
+            };
+
+            twin.set_option(
+                CString::new("proxy_data")?,
+                &proxy_options as *const HTTP_PROXY_OPTIONS as *const c_void,
+            )?;
+            capabilities.proxy = true;
+        }
+
+        if let Some(http_setting) = &options.http_setting {
+            if options.transport == Transport::Http {
+                info!("set http setting: {http_setting:?}");
+                let min_polling_time: u32 = http_setting.min_polling_time.as_secs().try_into()?;
+
+                twin.set_option(
+                    CString::new("SetBatching")?,
+                    &http_setting.batching as *const bool as *const c_void,
+                )?;
+                twin.set_option(
+                    CString::new("MinimumPollingTime")?,
+                    &min_polling_time as *const u32 as *const c_void,
+                )?;
+                capabilities.http_setting = true;
+            } else {
+                warn!(
+                    "ignore http setting since transport is {:?}",
+                    options.transport
+                );
+            }
+        }
+
+        if let Some(sas_token_setting) = &options.sas_token_setting {
+            info!("set sas token setting: {sas_token_setting:?}");
+
+            let lifetime_secs: usize = sas_token_setting.lifetime.as_secs().try_into()?;
+            let refresh_secs: usize = (sas_token_setting.lifetime
+                - sas_token_setting.renewal_margin)
+                .as_secs()
+                .try_into()?;
+
+            twin.set_option(
+                CString::new("sas_token_lifetime")?,
+                &lifetime_secs as *const usize as *const c_void,
+            )?;
+            twin.set_option(
+                CString::new("sas_token_refresh_time")?,
+                &refresh_secs as *const usize as *const c_void,
+            )?;
+            capabilities.sas_token_setting = true;
+        }
+
+        if let Some(percentage) = options.diagnostic_sampling_percentage {
+            info!("set diagnostic sampling percentage: {percentage}");
+
+            twin.set_option(
+                CString::new("diag_sampling_percentage")?,
+                &percentage as *const u32 as *const c_void,
             )?;
+            capabilities.diagnostic_sampling_percentage = Some(percentage);
         }
 
-        if let Some(retry_setting) = &self.retry_setting {
-            info!("set retry policy: {retry_setting:?}");
-            self.twin.set_retry_policy(
-                retry_setting.policy as u32,
-                retry_setting.timeout_secs as usize,
+        if let Some(product_info) = &options.product_info {
+            info!("set product info: {product_info}");
+            let product_info = CString::new(product_info.as_str())?;
+
+            twin.set_option(
+                CString::new("product_info")?,
+                product_info.as_ptr() as *const c_void,
             )?;
         }
 
-        Ok(())
+        Ok(capabilities)
     }
 
     unsafe extern "C" fn c_connection_status_callback(
@@ -1152,7 +6899,7 @@ impl IotHubClient {
         status_reason: IOTHUB_CLIENT_CONNECTION_STATUS_REASON,
         context: *mut ::std::os::raw::c_void,
     ) {
-        let tx = &mut *(context as *mut AuthenticationObserver);
+        let context = &mut *(context as *mut ConnectionStatusContext);
 
         let status = match connection_status {
             IOTHUB_CLIENT_CONNECTION_STATUS_TAG_IOTHUB_CLIENT_CONNECTION_AUTHENTICATED => {
@@ -1182,10 +6929,18 @@ impl IotHubClient {
                             UnauthenticatedReason::CommunicationError,
                         )
                     }
+                    IOTHUB_CLIENT_CONNECTION_STATUS_REASON_TAG_IOTHUB_CLIENT_CONNECTION_NO_PING_RESPONSE => {
+                        AuthenticationStatus::Unauthenticated(UnauthenticatedReason::NoPingResponse)
+                    }
+                    IOTHUB_CLIENT_CONNECTION_STATUS_REASON_TAG_IOTHUB_CLIENT_CONNECTION_QUOTA_EXCEEDED => {
+                        AuthenticationStatus::Unauthenticated(UnauthenticatedReason::QuotaExceeded)
+                    }
                     _ => {
-                        error!("unknown unauthenticated reason");
+                        error!("unknown unauthenticated reason: {status_reason}");
 
-                        AuthenticationStatus::Unauthenticated(UnauthenticatedReason::Unknown)
+                        AuthenticationStatus::Unauthenticated(UnauthenticatedReason::Unknown(
+                            status_reason,
+                        ))
                     }
                 }
             }
@@ -1197,15 +6952,161 @@ impl IotHubClient {
 
         debug!("Received connection status: {status:?}");
 
-        tx.blocking_send(status)
-            .expect("c_connection_status_callback: cannot blocking_send");
+        if let (
+            AuthenticationStatus::Unauthenticated(UnauthenticatedReason::ExpiredSasToken),
+            Some(sas_token_expired),
+        ) = (status, &context.sas_token_expired)
+        {
+            sas_token_expired.notify_one();
+        }
+
+        if let Ok(mut diagnostics) = context.diagnostics.lock() {
+            diagnostics.add_connection_status(status);
+        }
+
+        if let Some(status_changed) = &context.status_changed {
+            status_changed.notify_one();
+        }
+
+        #[cfg(feature = "systemd")]
+        if let (AuthenticationStatus::Authenticated, Some(readiness)) =
+            (status, context.systemd_readiness.as_mut())
+        {
+            readiness.authenticated();
+        }
+
+        if let (Some(tx), AuthenticationStatus::Unauthenticated(reason)) = (&context.retry, status)
+        {
+            if reason != UnauthenticatedReason::RetryExpired {
+                if let Err(e) = tx.try_send(RetryEvent { reason }) {
+                    warn!("cannot signal retry event: {e}");
+                }
+            }
+        }
+
+        if let Some(tx) = &context.observer {
+            latency::deliver_observed(
+                tx,
+                status,
+                context.latency_profile.as_ref(),
+                &context.diagnostics,
+                |status| format!("connection status {status:?}"),
+            );
+        }
     }
 
     unsafe extern "C" fn c_c2d_message_callback(
         handle: *mut IOTHUB_MESSAGE_HANDLE_DATA_TAG,
         context: *mut ::std::os::raw::c_void,
     ) -> IOTHUBMESSAGE_DISPOSITION_RESULT {
-        let observer = &mut *(context as *mut IncomingMessageObserver);
+        let context = &mut *(context as *mut IncomingMessageContext);
+
+        // ids are only read if they are recorded
+        let ids = (context.audit.is_some() || context.tx_feedback.is_some())
+            .then(|| IotMessage::incoming_ids(handle));
+        let received = SystemTime::now();
+        let start = Instant::now();
+        let (result, failure) = IotHubClient::handle_c2d_message(handle, context);
+        let disposition = match result {
+            IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_ACCEPTED => {
+                DispositionResult::Accepted
+            }
+            IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_ABANDONED => {
+                DispositionResult::Abandoned
+            }
+            IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_ASYNC_ACK => {
+                DispositionResult::AsyncAck
+            }
+            _ => DispositionResult::Rejected,
+        };
+        let duration = start.elapsed();
+
+        if let Ok(mut diagnostics) = context.diagnostics.lock() {
+            diagnostics
+                .c2d_metrics
+                .add_disposition(disposition, duration);
+        }
+
+        if let (Some(store), Some(failure)) = (&context.rejected_store, failure) {
+            // the message is read once more, since it might have been handed over to the observer
+            let stored = IotMessage::from_incoming_handle(handle, vec![])
+                .and_then(|message| store.push(received, &failure, &message));
+
+            if let Err(e) = stored {
+                warn!("c_c2d_message_callback: cannot store rejected message: {e}");
+            }
+        }
+
+        let Some((message_id, correlation_id)) = ids else {
+            return result;
+        };
+
+        if let Some(tx) = &context.tx_feedback {
+            let feedback = IncomingMessageFeedback {
+                message_id: message_id.clone(),
+                correlation_id: correlation_id.clone(),
+                disposition,
+                received,
+                duration,
+            };
+
+            if let Err(e) = tx.try_send(feedback) {
+                debug!("c_c2d_message_callback: cannot signal feedback: {e}");
+            }
+        }
+
+        if let Some(audit) = &context.audit {
+            if let Ok(mut diagnostics) = audit.lock() {
+                diagnostics.add_audit_record(AuditRecord {
+                    received,
+                    command: InboundCommand::IncomingMessage {
+                        message_id,
+                        correlation_id,
+                        disposition,
+                    },
+                    latency: duration,
+                });
+            }
+        }
+
+        result
+    }
+
+    /// disposition of the message and the failure, if the message wasn't processed successfully
+    unsafe fn handle_c2d_message(
+        handle: *mut IOTHUB_MESSAGE_HANDLE_DATA_TAG,
+        context: &mut IncomingMessageContext,
+    ) -> (IOTHUBMESSAGE_DISPOSITION_RESULT, Option<String>) {
+        let observer = match context
+            .routes
+            .iter()
+            .find(|route| route.matches(handle))
+            .map(|route| &route.observer)
+            .or(context.observer.as_ref())
+        {
+            Some(observer) => observer,
+            None => {
+                error!("no observer matches c2d message");
+                IotHubClient::send_dead_letter(
+                    &context.tx_dead_letter,
+                    &context.diagnostics,
+                    DeadLetter::IncomingMessage {
+                        message: None,
+                        reason: DeadLetterReason::NoRoute,
+                    },
+                );
+                return (
+                    IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_REJECTED,
+                    Some("no observer matches".to_string()),
+                );
+            }
+        };
+
+        if let Some(disposition) = observer.disposition(handle) {
+            debug!("c2d message disposed by filter: {disposition:?}");
+            return (IotHubClient::c_disposition_result(disposition), None);
+        }
+
         let mut property_keys: Vec<CString> = vec![];
 
         for property in &observer.properties {
@@ -1215,52 +7116,180 @@ impl IotHubClient {
                     error!(
                         "invalid property in c2d message received. payload: {property}, error: {e}"
                     );
-                    return IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_REJECTED;
+                    IotHubClient::send_dead_letter(
+                        &context.tx_dead_letter,
+                        &context.diagnostics,
+                        DeadLetter::IncomingMessage {
+                            message: None,
+                            reason: DeadLetterReason::ParseFailure(e.to_string()),
+                        },
+                    );
+                    return (
+                        IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_REJECTED,
+                        Some(format!("invalid property {property}: {e}")),
+                    );
                 }
             }
         }
 
-        match IotMessage::from_incoming_handle(handle, property_keys) {
+        let message = IotMessage::from_incoming_handle(handle, property_keys);
+
+        #[cfg(feature = "encryption")]
+        let message = match &context.encryption {
+            Some(encryption) => message.and_then(|mut msg| {
+                encryption::decrypt_incoming(encryption.0.as_ref(), handle, &mut msg)?;
+                Ok(msg)
+            }),
+            None => message,
+        };
+
+        #[cfg(feature = "gzip")]
+        let message = message.and_then(|mut msg| {
+            compression::decompress_incoming(&mut msg)?;
+            Ok(msg)
+        });
+
+        match message {
             Ok(msg) => {
                 debug!("Received message from iothub: {msg:?}");
 
+                // kept until the result is sent, so that a message without result can be signaled as dead letter
+                let retained = context.tx_dead_letter.is_some().then(|| msg.detached());
                 let (tx_result, rx_result) = oneshot::channel::<Result<DispositionResult>>();
+                let message = IncomingIotMessage {
+                    inner: msg,
+                    responder: tx_result,
+                };
+                let delivered = match context.overflow_policy {
+                    OverflowPolicy::Block => latency::deliver(
+                        &observer.responder,
+                        message,
+                        context.latency_profile.as_ref(),
+                    ),
+                    // buffered messages are forwarded first to keep the order
+                    OverflowPolicy::AcceptAndBuffer(_) if observer.is_buffering() => {
+                        Err(TrySendError::Full(message))
+                    }
+                    _ => observer.responder.try_send(message),
+                };
 
-                observer
-                    .responder
-                    .blocking_send(IncomingIotMessage {
-                        inner: msg,
-                        responder: tx_result,
-                    })
-                    .expect("c_c2d_message_callback: cannot blocking_send");
+                if let Err(e) = delivered {
+                    let failure = format!("cannot hand over message: {e}");
+                    let (reason, message) = match e {
+                        TrySendError::Full(msg) => (DeadLetterReason::ChannelFull, msg),
+                        TrySendError::Closed(msg) => (DeadLetterReason::ChannelClosed, msg),
+                    };
+                    let message = match (&reason, &observer.buffer) {
+                        (DeadLetterReason::ChannelFull, Some(buffer)) => {
+                            match buffer.try_send((message, rx_result)) {
+                                Ok(()) => {
+                                    debug!("c2d message buffered and completed");
+                                    return (
+                                        IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_ACCEPTED,
+                                        None,
+                                    );
+                                }
+                                Err(e) => e.into_inner().0,
+                            }
+                        }
+                        _ => message,
+                    };
+                    let result = match (&reason, context.overflow_policy) {
+                        (DeadLetterReason::ChannelFull, OverflowPolicy::Abandon) => {
+                            IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_ABANDONED
+                        }
+                        _ => IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_REJECTED,
+                    };
 
-                match rx_result.blocking_recv() {
-                    Ok(Ok(DispositionResult::Accepted)) => {
-                        IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_ACCEPTED
-                    }
-                    Ok(Ok(DispositionResult::Rejected)) => {
-                        IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_REJECTED
-                    }
-                    Ok(Ok(DispositionResult::Abandoned)) => {
-                        IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_ABANDONED
-                    }
-                    Ok(Ok(DispositionResult::AsyncAck)) => {
-                        IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_ASYNC_ACK
-                    }
+                    error!("c_c2d_message_callback: {failure}");
+                    IotHubClient::send_dead_letter(
+                        &context.tx_dead_letter,
+                        &context.diagnostics,
+                        DeadLetter::IncomingMessage {
+                            message: Some(message.inner),
+                            reason,
+                        },
+                    );
+                    return (result, Some(failure));
+                }
+
+                match latency::await_result(rx_result, context.latency_profile.as_ref()) {
+                    Ok(Ok(disposition)) => (
+                        IotHubClient::c_disposition_result(disposition),
+                        (disposition == DispositionResult::Rejected)
+                            .then(|| "rejected by observer".to_string()),
+                    ),
                     Ok(Err(e)) => {
                         error!("cannot handle c2d message: {e}");
-                        IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_REJECTED
+                        (
+                            IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_REJECTED,
+                            Some(e.to_string()),
+                        )
+                    }
+                    Err(TryRecvError::Empty) => {
+                        error!("c2d msg result not sent in time, abandon message");
+                        IotHubClient::send_dead_letter(
+                            &context.tx_dead_letter,
+                            &context.diagnostics,
+                            DeadLetter::IncomingMessage {
+                                message: retained,
+                                reason: DeadLetterReason::TimedOut,
+                            },
+                        );
+                        (
+                            IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_ABANDONED,
+                            Some("result not sent in time".to_string()),
+                        )
                     }
                     Err(e) => {
                         error!("c2d msg result channel unexpectedly closed: {e}");
-                        IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_REJECTED
+                        IotHubClient::send_dead_letter(
+                            &context.tx_dead_letter,
+                            &context.diagnostics,
+                            DeadLetter::IncomingMessage {
+                                message: retained,
+                                reason: DeadLetterReason::NoResult,
+                            },
+                        );
+                        (
+                            IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_REJECTED,
+                            Some(format!("result channel closed: {e}")),
+                        )
                     }
                 }
             }
             Err(e) => {
                 error!("cannot create IotMessage from incomming handle: {e}");
+                IotHubClient::send_dead_letter(
+                    &context.tx_dead_letter,
+                    &context.diagnostics,
+                    DeadLetter::IncomingMessage {
+                        message: None,
+                        reason: DeadLetterReason::ParseFailure(e.to_string()),
+                    },
+                );
+                (
+                    IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_REJECTED,
+                    Some(e.to_string()),
+                )
+            }
+        }
+    }
+
+    fn c_disposition_result(disposition: DispositionResult) -> IOTHUBMESSAGE_DISPOSITION_RESULT {
+        match disposition {
+            DispositionResult::Accepted => {
+                IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_ACCEPTED
+            }
+            DispositionResult::Rejected => {
                 IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_REJECTED
             }
+            DispositionResult::Abandoned => {
+                IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_ABANDONED
+            }
+            DispositionResult::AsyncAck => {
+                IOTHUBMESSAGE_DISPOSITION_RESULT_TAG_IOTHUBMESSAGE_ASYNC_ACK
+            }
         }
     }
 
@@ -1270,7 +7299,7 @@ impl IotHubClient {
         size: usize,
         context: *mut ::std::os::raw::c_void,
     ) {
-        let tx = &mut *(context as *mut TwinObserver);
+        let context = &mut *(context as *mut TwinDesiredContext);
 
         match String::from_utf8(slice::from_raw_parts(payload, size).to_vec()) {
             Ok(desired_string) => {
@@ -1282,18 +7311,47 @@ impl IotHubClient {
                             "Twin callback. state: {desired_state:?} size: {size} payload: {desired_json}"
                         );
 
-                        tx.blocking_send(TwinUpdate {
+                        let mut update = TwinUpdate {
                             state: desired_state,
                             value: desired_json,
-                        })
-                        .expect("c_twin_callback: cannot blocking_send");
+                        };
+
+                        if let Some((section, tx)) = &context.managed_configuration {
+                            if let Some(settings) = managed_config::extract(section, &mut update) {
+                                if tx.send(settings).is_err() {
+                                    error!("c_twin_callback: managed configuration not processed");
+                                }
+                            }
+                        }
+
+                        if let Some(observer) = &context.observer {
+                            latency::deliver_observed(
+                                observer,
+                                update,
+                                context.latency_profile.as_ref(),
+                                &context.diagnostics,
+                                |update| format!("desired properties {:?}", update.state),
+                            );
+                        }
+                    }
+                    Err(e) => {
+                        error!(
+                            "desired twin cannot be parsed. payload: {desired_string} error: {e}"
+                        );
+                        IotHubClient::report_parse_failure(
+                            &context.diagnostics,
+                            format!("desired twin: {e}"),
+                        );
                     }
-                    Err(e) => error!(
-                        "desired twin cannot be parsed. payload: {desired_string} error: {e}"
-                    ),
                 };
             }
-            Err(e) => error!("desired twin cannot be parsed: {e}"),
+            Err(e) => {
+                error!("desired twin cannot be parsed: {e}");
+                IotHubClient::report_parse_failure(
+                    &context.diagnostics,
+                    format!("desired twin: {e}"),
+                );
+            }
         }
     }
 
@@ -1303,9 +7361,12 @@ impl IotHubClient {
     ) {
         trace!("SendReportedTwin result: {status_code}");
 
-        let (tx_confirm, trace_id) = *Box::from_raw(context as *mut (oneshot::Sender<bool>, u32));
+        let (tx_confirm, trace_id) =
+            *Box::from_raw(context as *mut (oneshot::Sender<ConfirmationResult>, TraceId));
+        let result =
+            ConfirmationResult::ReportedStatus(u32::try_from(status_code).unwrap_or_default());
 
-        if tx_confirm.send(status_code == 204).is_err() {
+        if tx_confirm.send(result).is_err() {
             error!("c_reported_twin_callback({trace_id}): cannot send result {status_code} for confirmation since receiver already timed out and dropped");
         }
     }
@@ -1318,64 +7379,172 @@ impl IotHubClient {
         response_size: *mut usize,
         context: *mut ::std::os::raw::c_void,
     ) -> ::std::os::raw::c_int {
+        let context = &mut *(context as *mut DirectMethodContext);
+
+        let Some(audit) = context.audit.clone() else {
+            return IotHubClient::handle_direct_method(
+                method_name,
+                payload,
+                size,
+                response,
+                response_size,
+                context,
+            );
+        };
+
+        let name = CStr::from_ptr(method_name).to_string_lossy().to_string();
+        let received = SystemTime::now();
+        let start = Instant::now();
+        let result_code = IotHubClient::handle_direct_method(
+            method_name,
+            payload,
+            size,
+            response,
+            response_size,
+            context,
+        );
+
+        if let Ok(mut diagnostics) = audit.lock() {
+            diagnostics.add_audit_record(AuditRecord {
+                received,
+                command: InboundCommand::DirectMethod { name, result_code },
+                latency: start.elapsed(),
+            });
+        }
+
+        result_code
+    }
+
+    unsafe fn handle_direct_method(
+        method_name: *const ::std::os::raw::c_char,
+        payload: *const ::std::os::raw::c_uchar,
+        size: usize,
+        response: *mut *mut ::std::os::raw::c_uchar,
+        response_size: *mut usize,
+        context: &mut DirectMethodContext,
+    ) -> ::std::os::raw::c_int {
+        let (result_code, result) =
+            IotHubClient::direct_method_result(method_name, payload, size, context);
+
+        // the response is allocated once it's final, since azure-sdk-c only frees the one handed back
+        let result = result.unwrap_or_else(|| CString::from_vec_unchecked(b"{ }".to_vec()));
+        *response_size = result.as_bytes().len();
+        *response = result.into_raw() as *mut u8;
+
+        result_code
+    }
+
+    /// result code and response of a direct method, `None` if the response is empty
+    unsafe fn direct_method_result(
+        method_name: *const ::std::os::raw::c_char,
+        payload: *const ::std::os::raw::c_uchar,
+        size: usize,
+        context: &mut DirectMethodContext,
+    ) -> (::std::os::raw::c_int, Option<CString>) {
         const METHOD_RESPONSE_SUCCESS: i32 = 200;
+        const METHOD_RESPONSE_ACCEPTED: i32 = 202;
         const METHOD_RESPONSE_ERROR: i32 = 401;
 
-        let tx_direct_method = &mut *(context as *mut DirectMethodObserver);
-
-        let empty_result: CString = CString::from_vec_unchecked(b"{ }".to_vec());
-        *response_size = empty_result.as_bytes().len();
-        *response = empty_result.into_raw() as *mut u8;
+        let raw_payload = slice::from_raw_parts(payload, size);
+        let dead_letter = |name: Option<&str>, reason| {
+            IotHubClient::send_dead_letter(
+                &context.tx_dead_letter,
+                &context.diagnostics,
+                DeadLetter::DirectMethod {
+                    name: name.map(str::to_string),
+                    payload: String::from_utf8_lossy(raw_payload).to_string(),
+                    reason,
+                },
+            )
+        };
 
         let method_name = match CStr::from_ptr(method_name).to_str() {
             Ok(name) => name,
             Err(e) => {
                 error!("cannot parse method name: {e}");
-                return METHOD_RESPONSE_ERROR;
+                dead_letter(None, DeadLetterReason::ParseFailure(e.to_string()));
+                return (METHOD_RESPONSE_ERROR, None);
             }
         };
 
-        let payload: serde_json::Value = match str::from_utf8(slice::from_raw_parts(payload, size))
-        {
+        let payload: serde_json::Value = match str::from_utf8(raw_payload) {
             Ok(p) => match serde_json::from_str(p) {
                 Ok(json) => json,
                 Err(e) => {
                     error!("cannot parse direct method payload: {e}");
-                    return METHOD_RESPONSE_ERROR;
+                    dead_letter(
+                        Some(method_name),
+                        DeadLetterReason::ParseFailure(e.to_string()),
+                    );
+                    return (METHOD_RESPONSE_ERROR, None);
                 }
             },
             Err(e) => {
                 error!("cannot parse direct method payload: {e}");
-                return METHOD_RESPONSE_ERROR;
+                dead_letter(
+                    Some(method_name),
+                    DeadLetterReason::ParseFailure(e.to_string()),
+                );
+                return (METHOD_RESPONSE_ERROR, None);
             }
         };
 
         debug!("Received direct method call: {method_name:?} with payload: {payload}");
 
+        if let Some(jobs) = context
+            .jobs
+            .as_mut()
+            .filter(|jobs| jobs.is_long_running(method_name))
+        {
+            return match jobs
+                .start(method_name, payload, context.latency_profile.as_ref())
+                .and_then(|id| Ok(CString::new(json!({ "job_id": id }).to_string())?))
+            {
+                Ok(r) => (METHOD_RESPONSE_ACCEPTED, Some(r)),
+                Err(e) => {
+                    error!("cannot start job: {e}");
+                    dead_letter(Some(method_name), DeadLetterReason::ChannelClosed);
+                    (METHOD_RESPONSE_ERROR, None)
+                }
+            };
+        }
+
+        let Some(observer) = &context.observer else {
+            error!("no observer for direct method {method_name}");
+            dead_letter(Some(method_name), DeadLetterReason::NoRoute);
+            return (METHOD_RESPONSE_ERROR, None);
+        };
+
         let (tx_result, rx_result) = oneshot::channel::<Result<Option<serde_json::Value>>>();
 
-        tx_direct_method
-            .blocking_send(DirectMethod {
+        if let Err(e) = latency::deliver(
+            observer,
+            DirectMethod {
                 name: method_name.to_string(),
                 payload,
                 responder: tx_result,
-            })
-            .expect("c_direct_method_callback: cannot blocking_send");
+            },
+            context.latency_profile.as_ref(),
+        ) {
+            error!("c_direct_method_callback: cannot hand over direct method: {e}");
+            let reason = match e {
+                TrySendError::Full(_) => DeadLetterReason::ChannelFull,
+                TrySendError::Closed(_) => DeadLetterReason::ChannelClosed,
+            };
+            dead_letter(Some(method_name), reason);
+            return (METHOD_RESPONSE_ERROR, None);
+        }
 
-        match rx_result.blocking_recv() {
+        match latency::await_result(rx_result, context.latency_profile.as_ref()) {
             Ok(Ok(None)) => {
                 debug!("direct method has no result");
-                return METHOD_RESPONSE_SUCCESS;
+                return (METHOD_RESPONSE_SUCCESS, None);
             }
             Ok(Ok(Some(result))) => {
                 debug!("direct method result: {result:?}");
 
                 match CString::new(result.to_string()) {
-                    Ok(r) => {
-                        *response_size = r.as_bytes().len();
-                        *response = r.into_raw() as *mut u8;
-                        return METHOD_RESPONSE_SUCCESS;
-                    }
+                    Ok(r) => return (METHOD_RESPONSE_SUCCESS, Some(r)),
                     Err(e) => {
                         error!("cannot parse direct method result: {e}");
                     }
@@ -1385,58 +7554,137 @@ impl IotHubClient {
                 error!("direct method error: {e:?}");
 
                 match CString::new(json!(e.to_string()).to_string()) {
-                    Ok(r) => {
-                        *response_size = r.as_bytes().len();
-                        *response = r.into_raw() as *mut u8;
-                    }
+                    Ok(r) => return (METHOD_RESPONSE_ERROR, Some(r)),
                     Err(e) => {
                         error!("cannot parse direct method result: {e}");
                     }
                 }
             }
+            Err(TryRecvError::Empty) => {
+                error!("direct method result not sent in time");
+                dead_letter(Some(method_name), DeadLetterReason::TimedOut);
+            }
             Err(e) => {
                 error!("direct method result channel unexpectedly closed: {e}");
+                dead_letter(Some(method_name), DeadLetterReason::NoResult);
             }
         }
 
-        METHOD_RESPONSE_ERROR
+        (METHOD_RESPONSE_ERROR, None)
     }
 
     unsafe extern "C" fn c_d2c_confirmation_callback(
         status: IOTHUB_CLIENT_CONFIRMATION_RESULT,
         context: *mut std::ffi::c_void,
     ) {
-        let (tx_confirm, trace_id) = *Box::from_raw(context as *mut (oneshot::Sender<bool>, u32));
-        let mut succeeded = false;
+        let (tx_confirm, trace_id) =
+            *Box::from_raw(context as *mut (oneshot::Sender<ConfirmationResult>, TraceId));
 
-        match status {
+        let result = match status {
             IOTHUB_CLIENT_CONFIRMATION_RESULT_TAG_IOTHUB_CLIENT_CONFIRMATION_OK => {
-                succeeded = true;
-                debug!("c_d2c_confirmation_callback({trace_id}): received confirmation from iothub.");
-            },
-            IOTHUB_CLIENT_CONFIRMATION_RESULT_TAG_IOTHUB_CLIENT_CONFIRMATION_BECAUSE_DESTROY => error!("c_d2c_confirmation_callback ({trace_id}): received confirmation from iothub with error IOTHUB_CLIENT_CONFIRMATION_BECAUSE_DESTROY."),
-            IOTHUB_CLIENT_CONFIRMATION_RESULT_TAG_IOTHUB_CLIENT_CONFIRMATION_ERROR =>  error!("c_d2c_confirmation_callback ({trace_id}): received confirmation from iothub with error IOTHUB_CLIENT_CONFIRMATION_ERROR."),
-            IOTHUB_CLIENT_CONFIRMATION_RESULT_TAG_IOTHUB_CLIENT_CONFIRMATION_MESSAGE_TIMEOUT => error!("c_d2c_confirmation_callback ({trace_id}): received confirmation from iothub with error IOTHUB_CLIENT_CONFIRMATION_MESSAGE_TIMEOUT."),
-            _ => error!("c_d2c_confirmation_callback({trace_id}): received confirmation from iothub with unknown IOTHUB_CLIENT_CONFIRMATION_RESULT"),
-        }
+                debug!(
+                    "c_d2c_confirmation_callback({trace_id}): received confirmation from iothub."
+                );
+                ConfirmationResult::Ok
+            }
+            IOTHUB_CLIENT_CONFIRMATION_RESULT_TAG_IOTHUB_CLIENT_CONFIRMATION_BECAUSE_DESTROY => {
+                error!("c_d2c_confirmation_callback ({trace_id}): received confirmation from iothub with error IOTHUB_CLIENT_CONFIRMATION_BECAUSE_DESTROY.");
+                ConfirmationResult::Destroyed
+            }
+            IOTHUB_CLIENT_CONFIRMATION_RESULT_TAG_IOTHUB_CLIENT_CONFIRMATION_ERROR => {
+                error!("c_d2c_confirmation_callback ({trace_id}): received confirmation from iothub with error IOTHUB_CLIENT_CONFIRMATION_ERROR.");
+                ConfirmationResult::Error
+            }
+            IOTHUB_CLIENT_CONFIRMATION_RESULT_TAG_IOTHUB_CLIENT_CONFIRMATION_MESSAGE_TIMEOUT => {
+                error!("c_d2c_confirmation_callback ({trace_id}): received confirmation from iothub with error IOTHUB_CLIENT_CONFIRMATION_MESSAGE_TIMEOUT.");
+                ConfirmationResult::MessageTimeout
+            }
+            _ => {
+                error!("c_d2c_confirmation_callback({trace_id}): received confirmation from iothub with unknown IOTHUB_CLIENT_CONFIRMATION_RESULT");
+                ConfirmationResult::Error
+            }
+        };
 
-        if tx_confirm.send(succeeded).is_err() {
+        if tx_confirm.send(result).is_err() {
             error!("c_d2c_confirmation_callback({trace_id}): cannot send confirmation result since receiver already timed out and dropped")
         };
     }
 
-    fn spawn_confirmation(&self, (rx, trace_id): (oneshot::Receiver<bool>, u32)) {
+    fn send_dead_letter(
+        tx: &Option<DeadLetterObserver>,
+        diagnostics: &Arc<Mutex<Diagnostics>>,
+        dead_letter: DeadLetter,
+    ) {
+        if let DeadLetter::IncomingMessage {
+            reason: DeadLetterReason::ParseFailure(e),
+            ..
+        }
+        | DeadLetter::DirectMethod {
+            reason: DeadLetterReason::ParseFailure(e),
+            ..
+        } = &dead_letter
+        {
+            IotHubClient::report_parse_failure(diagnostics, e.clone());
+        }
+
+        // dead letters are signaled from azure-sdk-c callbacks, thus a slow consumer must not block the sdk
+        if let Some(tx) = tx {
+            if let Err(e) = tx.try_send(dead_letter) {
+                error!("cannot send dead letter: {e}");
+
+                diagnostics
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .dead_letters_dropped += 1;
+            }
+        }
+    }
+
+    /// rejects sends immediately if the maximum of pending confirmations of the latency profile is reached
+    fn check_pending_confirmations(&self) -> Result<()> {
+        let Some(profile) = &self.latency_profile else {
+            return Ok(());
+        };
+
+        let pending = self.pending_confirmation_count();
+
+        anyhow::ensure!(
+            pending < profile.max_pending_confirmations,
+            "{pending} confirmations pending, send rejected"
+        );
+
+        Ok(())
+    }
+
+    fn report_parse_failure(diagnostics: &Arc<Mutex<Diagnostics>>, error: String) {
+        if let Ok(mut diagnostics) = diagnostics.lock() {
+            diagnostics.report(ErrorEvent::ParseFailure(error));
+        }
+    }
+
+    fn spawn_confirmation(
+        &self,
+        (rx, trace): (oneshot::Receiver<ConfirmationResult>, Trace),
+        d2c: bool,
+        confirmation_timeout: Option<Duration>,
+        retained: Option<Retained>,
+        permit: Option<OwnedSemaphorePermit>,
+    ) {
         let before = self.confirmation_set.borrow().len();
         let waker = task::noop_waker();
         let mut cx = Context::from_waker(&waker);
-        let mut poll = Poll::Ready(Some(Ok::<_, JoinError>(())));
+        let mut poll = Poll::Ready(Some(Ok(())));
 
-        // check if some confirmations run to completion meanwhile
+        // check if some confirmations run to completion or were cancelled meanwhile
         // we don't wait for completion here
-        while let Poll::Ready(Some(Ok(()))) = poll {
+        while let Poll::Ready(Some(_)) = poll {
             poll = self.confirmation_set.borrow_mut().poll_join_next(&mut cx);
         }
 
+        self.confirmation_aborts
+            .borrow_mut()
+            .retain(|_, abort| !abort.is_finished());
+
         trace!(
             "cleaned {} confirmations",
             before - self.confirmation_set.borrow().len()
@@ -1446,49 +7694,274 @@ impl IotHubClient {
         //   - succeeded: confirmation callback sent success
         //   - failed: confirmation callback sent failure
         //   - timed out: confirmation didn't send anything
-        self.confirmation_set.borrow_mut().spawn(async move {
-            match timeout(Duration::from_secs(Self::get_confirmation_timeout()), rx).await {
-                // if really needed we could pass around the json of property or D2C msg to get logged here as context
-                Ok(Ok(false)) => error!("confirmation({trace_id}): failed"),
-                Err(_) => warn!("confirmation({trace_id}): timed out"),
-                _ => debug!("confirmation({trace_id}): successfully received"),
+        let diagnostics = self.diagnostics.clone();
+        let on_confirmation = self.on_confirmation.clone();
+        let confirmation_timeout = confirmation_timeout
+            .unwrap_or_else(|| Duration::from_secs(self.confirmation_timeout_secs));
+
+        if let Ok(mut diagnostics) = diagnostics.lock() {
+            diagnostics.add_pending_confirmation(trace, d2c);
+        }
+
+        let wait = Box::pin(Self::wait_confirmation(
+            rx,
+            trace,
+            confirmation_timeout,
+            retained,
+            diagnostics,
+            on_confirmation,
+            permit,
+        ));
+
+        // spawning without runtime would panic, thus the waiter thread waits instead
+        if Handle::try_current().is_err() {
+            warn!("confirmation({trace}): no tokio runtime available, wait on waiter thread");
+
+            if let Err(e) = self.confirmation_waiter.spawn(wait) {
+                error!("confirmation({trace}): cannot wait for confirmation: {e}");
+
+                if let Ok(mut diagnostics) = self.diagnostics.lock() {
+                    diagnostics.remove_pending_confirmation(trace);
+                }
+            }
+
+            return;
+        }
+
+        let mut handover = Handover {
+            wait: Some(wait),
+            trace,
+            diagnostics: self.diagnostics.clone(),
+            waiter: self.confirmation_waiter.clone(),
+        };
+        let abort = self.confirmation_set.borrow_mut().spawn(async move {
+            if let Some(wait) = handover.wait.take() {
+                wait.await
             }
         });
+
+        if d2c {
+            self.confirmation_aborts.borrow_mut().insert(trace, abort);
+        }
     }
 
-    fn get_confirmation_timeout() -> u64 {
-        static INIT: Once = Once::new();
-        static mut CONFIRMATION_TIMEOUT_IN_SECS: u64 = CONFIRMATION_TIMEOUT_DEFAULT_IN_SECS;
+    /// removes the D2C message `trace` from the suspend buffer and the offline store and abandons the wait
+    /// for its confirmation. returns false if there was nothing to cancel.
+    pub(crate) fn cancel_d2c(&self, trace: Trace) -> Result<bool> {
+        let mut cancelled = false;
 
-        unsafe {
-            INIT.call_once(|| {
-                let mut confirmation_timeout_secs = None;
+        if let Some(buffer) = self.suspended.borrow_mut().as_mut() {
+            let len = buffer.len();
 
-                if let Ok(timeout_secs) = env::var(AZURE_SDK_CONFIRMATION_TIMEOUT_IN_SECS) {
-                    match timeout_secs.parse::<u64>() {
-                        Ok(timeout_secs) => {
-                            info!("set confirmation timeout to {timeout_secs}s");
-                            confirmation_timeout_secs = Some(timeout_secs);
-                        }
-                        _ => error!("ignore invalid confirmation timeout {timeout_secs}"),
-                    };
+            buffer.retain(|(queued, send)| {
+                *queued != trace || !matches!(send, SuspendedSend::D2cMessage(_))
+            });
+            cancelled |= buffer.len() < len;
+        }
+
+        if let Some(store) = &self.offline_store {
+            cancelled |= store.remove(trace)?;
+        }
+
+        // not pending anymore before aborted, so that the wait isn't handed over to the waiter thread
+        match self.diagnostics.lock() {
+            Ok(mut diagnostics) => cancelled |= diagnostics.cancel_pending_confirmation(trace),
+            Err(poisoned) => cancelled |= poisoned.into_inner().cancel_pending_confirmation(trace),
+        }
+
+        if let Some(abort) = self.confirmation_aborts.borrow_mut().remove(&trace) {
+            abort.abort();
+        }
+
+        if cancelled {
+            info!("send_d2c_message({trace}): cancelled");
+        }
+
+        Ok(cancelled)
+    }
+
+    /// stores the `retained` copy of a D2C message offline if its confirmation didn't succeed, so that it is
+    /// finished when its replay is confirmed. otherwise the confirmation is finished right away.
+    fn conclude_confirmation(
+        diagnostics: &Arc<Mutex<Diagnostics>>,
+        on_confirmation: &Option<ConfirmationCallback>,
+        retained: Option<Retained>,
+        trace: Trace,
+        outcome: ConfirmationOutcome,
+    ) {
+        if let (Some(Retained::Offline(store, message)), false) =
+            (retained, outcome == ConfirmationOutcome::Succeeded)
+        {
+            match store.push(trace, &message, diagnostics, on_confirmation) {
+                Ok(()) => {
+                    if let Ok(mut diagnostics) = diagnostics.lock() {
+                        diagnostics.conclude_pending_confirmation(trace, outcome);
+                    }
+
+                    return;
                 }
+                Err(e) => error!("confirmation({trace}): cannot store message offline: {e}"),
+            }
+        }
+
+        Self::finish_confirmation(diagnostics, on_confirmation, trace, outcome);
+    }
+
+    /// waits for the confirmation `trace` and concludes its outcome
+    async fn wait_confirmation(
+        rx: oneshot::Receiver<ConfirmationResult>,
+        trace: Trace,
+        confirmation_timeout: Duration,
+        retained: Option<Retained>,
+        diagnostics: Arc<Mutex<Diagnostics>>,
+        on_confirmation: Option<ConfirmationCallback>,
+        permit: Option<OwnedSemaphorePermit>,
+    ) {
+        let outcome = match timeout(confirmation_timeout, rx).await {
+            Ok(Ok(result)) => Self::confirmation_outcome(Some(result), trace.id),
+            // the sender was dropped without the callback being called, e.g. since the handle was destroyed
+            Ok(Err(_)) => Self::confirmation_outcome(Some(ConfirmationResult::Destroyed), trace.id),
+            Err(_) => Self::confirmation_outcome(None, trace.id),
+        };
+        let (outcome, retained) = match retained {
+            Some(Retained::Retransmit(retransmission))
+                if outcome != ConfirmationOutcome::Succeeded =>
+            {
+                (
+                    retransmission
+                        .run(trace.id, outcome, confirmation_timeout)
+                        .await,
+                    None,
+                )
+            }
+            retained => (outcome, retained),
+        };
 
-                if confirmation_timeout_secs.is_none() {
-                    confirmation_timeout_secs = Some(CONFIRMATION_TIMEOUT_DEFAULT_IN_SECS);
-                    info!(
-                        "set default confirmation timeout {CONFIRMATION_TIMEOUT_DEFAULT_IN_SECS}s"
+        match retained {
+            // storing the message offline does blocking file I/O
+            Some(Retained::Offline(..)) if outcome != ConfirmationOutcome::Succeeded => {
+                let concluded = tokio::task::spawn_blocking(move || {
+                    Self::conclude_confirmation(
+                        &diagnostics,
+                        &on_confirmation,
+                        retained,
+                        trace,
+                        outcome,
                     )
+                })
+                .await;
+
+                if let Err(e) = concluded {
+                    error!("confirmation({trace}): cannot store message offline: {e}");
                 }
-                CONFIRMATION_TIMEOUT_IN_SECS = confirmation_timeout_secs.unwrap()
-            });
-            CONFIRMATION_TIMEOUT_IN_SECS
+            }
+            retained => Self::conclude_confirmation(
+                &diagnostics,
+                &on_confirmation,
+                retained,
+                trace,
+                outcome,
+            ),
+        }
+        drop(permit);
+    }
+
+    /// maps the received confirmation to its outcome, `None` means nothing was received in time
+    fn confirmation_outcome(
+        received: Option<ConfirmationResult>,
+        trace_id: TraceId,
+    ) -> ConfirmationOutcome {
+        match received {
+            Some(result) if result.is_ok() => {
+                debug!("confirmation({trace_id}): successfully received");
+                ConfirmationOutcome::Succeeded
+            }
+            // if really needed we could pass around the json of property or D2C msg to get logged here as context
+            Some(result) => {
+                error!("confirmation({trace_id}): failed: {result}");
+                ConfirmationOutcome::Failed(result)
+            }
+            None => {
+                warn!("confirmation({trace_id}): timed out");
+                ConfirmationOutcome::TimedOut
+            }
+        }
+    }
+
+    fn finish_confirmation(
+        diagnostics: &Arc<Mutex<Diagnostics>>,
+        on_confirmation: &Option<ConfirmationCallback>,
+        trace: Trace,
+        outcome: ConfirmationOutcome,
+    ) {
+        if let Ok(mut diagnostics) = diagnostics.lock() {
+            diagnostics.conclude_pending_confirmation(trace, outcome);
+
+            match outcome {
+                ConfirmationOutcome::Succeeded => diagnostics.confirmations_succeeded += 1,
+                ConfirmationOutcome::Failed(_) => {
+                    diagnostics.confirmations_failed += 1;
+                    diagnostics.report(ErrorEvent::ConfirmationFailed { trace_id: trace.id });
+                }
+                ConfirmationOutcome::TimedOut => {
+                    diagnostics.confirmations_timed_out += 1;
+                    diagnostics.report(ErrorEvent::ConfirmationTimedOut { trace_id: trace.id });
+                }
+            }
+        }
+
+        if let Some(waiter) = diagnostics
+            .lock()
+            .ok()
+            .and_then(|mut diagnostics| diagnostics.take_confirmation_waiter(trace))
+        {
+            let _ = waiter.send(outcome);
+        }
+
+        if let Some(on_confirmation) = on_confirmation {
+            (on_confirmation.0)(trace.id, outcome);
         }
     }
 }
 
 impl Drop for IotHubClient {
     fn drop(&mut self) {
-        self.twin.destroy()
+        if let Some(task) = self.managed_configuration_task.take() {
+            task.abort();
+        }
+
+        if let Some(task) = self.job_reports_task.take() {
+            task.abort();
+        }
+
+        for task in self.incoming_buffer_tasks.drain(..) {
+            task.abort();
+        }
+
+        if let Some(task) = self.offline_replay_task.take() {
+            task.abort();
+        }
+
+        #[cfg(feature = "systemd")]
+        if let Some(task) = self.systemd_watchdog.take() {
+            task.abort();
+        }
+
+        #[cfg(any(feature = "module_client", feature = "device_client"))]
+        if let Some(renewal) = self.credential_renewal.take() {
+            renewal.abort();
+        }
+
+        if let Some(watchdog) = self.watchdog.take() {
+            watchdog.abort();
+        }
+
+        self.detach();
+
+        // a still running renewal must not create a new handle once the client is gone
+        self.twin.close();
+
+        // waits still running on the waiter thread end soon, since the handle is destroyed
+        self.confirmation_waiter.shutdown();
     }
 }
@@ -0,0 +1,129 @@
+use anyhow::Result;
+use std::{fmt, str::FromStr};
+
+const SCHEME: &str = "dtmi:";
+const MAX_LENGTH: usize = 2048;
+const MAX_VERSION: u32 = 999_999_999;
+
+/// Validated [digital twin model identifier](https://github.com/Azure/opendigitaltwins-dtdl/blob/master/DTDL/v3/DTDL.v3.md#digital-twin-model-identifier)
+/// (DTMI) of an Azure IoT Plug & Play model, e.g. `dtmi:com:example:Thermostat;1`.
+/// Model ids set by [`crate::client::IotHubClientBuilder::pnp_model_id`] are validated the same way when the
+/// client is built.
+/// ```rust
+/// use azure_iot_sdk::client::ModelId;
+///
+/// let model_id: ModelId = "dtmi:com:example:Thermostat;1".parse().unwrap();
+///
+/// assert_eq!(model_id.path(), "com:example:Thermostat");
+/// assert_eq!(model_id.segments().collect::<Vec<_>>(), ["com", "example", "Thermostat"]);
+/// assert_eq!(model_id.version(), 1);
+/// assert_eq!(model_id.to_string(), "dtmi:com:example:Thermostat;1");
+///
+/// assert!("my.pnp.id".parse::<ModelId>().is_err());
+/// assert!("dtmi:com:example:Thermostat".parse::<ModelId>().is_err());
+/// assert!("dtmi:com:1example:Thermostat;1".parse::<ModelId>().is_err());
+/// ```
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ModelId {
+    id: String,
+    version: u32,
+}
+
+impl ModelId {
+    /// model id as string
+    pub fn as_str(&self) -> &str {
+        &self.id
+    }
+
+    /// path of the model id without scheme and version, e.g. `com:example:Thermostat`
+    pub fn path(&self) -> &str {
+        let end = self.id.rfind(';').unwrap_or(self.id.len());
+        &self.id[SCHEME.len()..end]
+    }
+
+    /// segments of the path, e.g. `com`, `example` and `Thermostat`
+    pub fn segments(&self) -> impl Iterator<Item = &str> {
+        self.path().split(':')
+    }
+
+    /// version of the model
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn validate_segment(segment: &str) -> Result<()> {
+        let Some(first) = segment.chars().next() else {
+            anyhow::bail!("empty path segment");
+        };
+
+        if !first.is_ascii_alphabetic() {
+            anyhow::bail!("path segment {segment} must start with a letter");
+        }
+
+        if segment.ends_with('_') {
+            anyhow::bail!("path segment {segment} must not end with an underscore");
+        }
+
+        if let Some(c) = segment
+            .chars()
+            .find(|c| !c.is_ascii_alphanumeric() && *c != '_')
+        {
+            anyhow::bail!("path segment {segment} contains invalid character {c:?}");
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for ModelId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = || {
+            if s.len() > MAX_LENGTH {
+                anyhow::bail!("longer than {MAX_LENGTH} characters");
+            }
+
+            let Some(rest) = s.strip_prefix(SCHEME) else {
+                anyhow::bail!("scheme must be {SCHEME}");
+            };
+
+            let Some((path, version)) = rest.split_once(';') else {
+                anyhow::bail!("version is missing");
+            };
+
+            for segment in path.split(':') {
+                ModelId::validate_segment(segment)?;
+            }
+
+            if version.starts_with('0') || !version.chars().all(|c| c.is_ascii_digit()) {
+                anyhow::bail!("version {version} must be a positive integer without leading zeros");
+            }
+
+            match version.parse::<u32>() {
+                Ok(version) if (1..=MAX_VERSION).contains(&version) => Ok(version),
+                _ => anyhow::bail!("version {version} not in range of 1..={MAX_VERSION}"),
+            }
+        };
+
+        match parse() {
+            Ok(version) => Ok(ModelId {
+                id: s.to_string(),
+                version,
+            }),
+            Err(e) => anyhow::bail!("invalid pnp model id {s}: {e}"),
+        }
+    }
+}
+
+impl fmt::Display for ModelId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.id)
+    }
+}
+
+impl AsRef<str> for ModelId {
+    fn as_ref(&self) -> &str {
+        &self.id
+    }
+}
@@ -0,0 +1,129 @@
+use crate::client::IotMessageBuilder;
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+static PROPERTY_SEPARATOR: char = '_';
+// characters not allowed in twin property names
+static RESERVED_CHARS: [char; 3] = ['.', '$', ' '];
+
+/// Namespace of an independent software component, e.g. a tenant, sharing a single module identity with other
+/// components in one process. Reported properties are nested in a section named like the namespace and
+/// telemetry properties are prefixed by `<namespace>_`, so that components can't overwrite each other.
+/// ```rust
+/// use azure_iot_sdk::client::*;
+/// use serde_json::json;
+///
+/// let tenant_a = Namespace::new("tenant_a").unwrap();
+/// let tenant_b = Namespace::new("tenant_b").unwrap();
+///
+/// // reported properties of several components are merged into one report
+/// let reported = Namespace::merge([
+///     tenant_a.reported(json!({"status": "ok"})).unwrap(),
+///     tenant_b.reported(json!({"status": "degraded"})).unwrap(),
+/// ])
+/// .unwrap();
+///
+/// assert_eq!(
+///     reported,
+///     json!({"tenant_a": {"status": "ok"}, "tenant_b": {"status": "degraded"}})
+/// );
+/// assert_eq!(tenant_a.section(&json!({"tenant_a": {"interval": 5}})), Some(&json!({"interval": 5})));
+///
+/// let msg = tenant_a
+///     .set_property(IotMessage::builder(), "unit", "celsius")
+///     .set_body(b"21.5".to_vec())
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(tenant_a.property("unit"), "tenant_a_unit");
+/// ```
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Namespace(String);
+
+impl Namespace {
+    /// Get a namespace named `name`, which must be a valid twin property name
+    pub fn new(name: impl Into<String>) -> Result<Self> {
+        let name = name.into();
+
+        anyhow::ensure!(!name.is_empty(), "namespace must not be empty");
+        anyhow::ensure!(
+            !name.contains(RESERVED_CHARS) && !name.chars().any(char::is_control),
+            "namespace {name} must not contain control characters or any of {RESERVED_CHARS:?}"
+        );
+
+        Ok(Namespace(name))
+    }
+
+    /// name of the namespace
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+
+    /// Get `properties` nested in the section of the namespace, e.g. to be passed to
+    /// [`crate::client::IotHubClient::twin_report`]
+    pub fn reported(&self, properties: Value) -> Result<Value> {
+        anyhow::ensure!(
+            properties.is_object(),
+            "reported properties of namespace {} must be an object",
+            self.0
+        );
+
+        Ok(Value::Object(Map::from_iter([(
+            self.0.clone(),
+            properties,
+        )])))
+    }
+
+    /// Get the section of the namespace of desired or reported properties `twin`, if any
+    pub fn section<'a>(&self, twin: &'a Value) -> Option<&'a Value> {
+        twin.get(&self.0)
+    }
+
+    /// Get the message property name `key` prefixed by the namespace
+    pub fn property(&self, key: &str) -> String {
+        format!("{}{PROPERTY_SEPARATOR}{key}", self.0)
+    }
+
+    /// Add a message property with `key` prefixed by the namespace
+    pub fn set_property(
+        &self,
+        builder: IotMessageBuilder,
+        key: &str,
+        value: impl Into<String>,
+    ) -> IotMessageBuilder {
+        builder.set_property(self.property(key), value)
+    }
+
+    /// Merge `reports`, e.g. of several namespaces, into one report. Objects are merged recursively, while
+    /// different values of the same property are rejected as collision.
+    pub fn merge(reports: impl IntoIterator<Item = Value>) -> Result<Value> {
+        let mut merged = Value::Object(Map::new());
+
+        for report in reports {
+            Namespace::merge_into(&mut merged, report, "")?;
+        }
+
+        Ok(merged)
+    }
+
+    fn merge_into(target: &mut Value, source: Value, path: &str) -> Result<()> {
+        match (target, source) {
+            (Value::Object(target), Value::Object(source)) => {
+                for (key, value) in source {
+                    let path = format!("{path}/{key}");
+
+                    match target.get_mut(&key) {
+                        Some(existing) => Namespace::merge_into(existing, value, &path)?,
+                        None => {
+                            target.insert(key, value);
+                        }
+                    }
+                }
+
+                Ok(())
+            }
+            (target, source) if *target == source => Ok(()),
+            _ => anyhow::bail!("colliding values of reported property {path}"),
+        }
+    }
+}
@@ -0,0 +1,537 @@
+use crate::client::{
+    diagnostics::Diagnostics, trace_id::Trace, twin::SharedTwin, AuthenticationStatus,
+    ConfirmationCallback, ConfirmationOutcome, ConfirmationResult, IotHubClient, IotMessage,
+    TraceId,
+};
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use std::{
+    collections::{HashMap, VecDeque},
+    ffi::{c_void, CString},
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::{oneshot, Notify};
+
+static FILE_EXTENSION: &str = "msg";
+static TMP_EXTENSION: &str = "tmp";
+static FORMAT_VERSION: u8 = 2;
+static DEFAULT_MAX_MESSAGES: usize = 10_000;
+static DEFAULT_MAX_BYTES: u64 = 64 * 1024 * 1024;
+static REPLAY_RETRY_INTERVAL_IN_SECS: u64 = 10;
+
+/// Settings of the disk-persistent store-and-forward queue of D2C messages.
+/// See [`crate::client::IotHubClientBuilder::offline_store`].
+#[derive(Clone, Debug)]
+pub struct OfflineStore {
+    dir: PathBuf,
+    max_messages: usize,
+    max_bytes: u64,
+}
+
+impl OfflineStore {
+    /// Get settings of a store in directory `dir` limited to 10000 messages and 64MiB
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        OfflineStore {
+            dir: dir.into(),
+            max_messages: DEFAULT_MAX_MESSAGES,
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+
+    /// Set the maximum number of stored messages
+    pub fn max_messages(mut self, max_messages: usize) -> Self {
+        self.max_messages = max_messages;
+        self
+    }
+
+    /// Set the maximum size of all stored message files in bytes
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+}
+
+/// persisted messages in order, each stored in a file named by its sequence number
+#[derive(Debug)]
+struct MessageFiles {
+    setting: OfflineStore,
+    // sequence number, trace and file size of stored messages
+    entries: VecDeque<(u64, Trace, u64)>,
+    bytes: u64,
+    next_seq: u64,
+}
+
+impl MessageFiles {
+    fn open(setting: OfflineStore) -> Result<Self> {
+        fs::create_dir_all(&setting.dir)
+            .with_context(|| format!("cannot create offline store {:?}", setting.dir))?;
+
+        let mut entries = vec![];
+
+        for entry in fs::read_dir(&setting.dir)? {
+            let path = entry?.path();
+            let extension = path.extension().and_then(|e| e.to_str());
+
+            // leftover of a write interrupted by a crash, the message was never stored
+            if extension == Some(TMP_EXTENSION) {
+                warn!("offline store: remove incomplete file {path:?}");
+                fs::remove_file(&path)?;
+                continue;
+            }
+
+            if extension != Some(FILE_EXTENSION) {
+                continue;
+            }
+
+            let Some(seq) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse::<u64>().ok())
+            else {
+                warn!("offline store: ignore unexpected file {path:?}");
+                continue;
+            };
+
+            let content = fs::read(&path)?;
+
+            match decode(&content) {
+                Ok((trace_id, _)) => {
+                    entries.push((seq, Trace::new(trace_id), content.len() as u64))
+                }
+                Err(e) => {
+                    warn!("offline store: remove corrupt file {path:?}: {e}");
+                    fs::remove_file(&path)?;
+                }
+            }
+        }
+
+        entries.sort_unstable_by_key(|(seq, _, _)| *seq);
+
+        let files = MessageFiles {
+            next_seq: entries.last().map_or(0, |(seq, _, _)| seq + 1),
+            bytes: entries.iter().map(|(_, _, size)| size).sum(),
+            entries: entries.into(),
+            setting,
+        };
+
+        info!(
+            "offline store: {} messages with {} bytes",
+            files.entries.len(),
+            files.bytes
+        );
+
+        Ok(files)
+    }
+
+    fn path(&self, seq: u64) -> PathBuf {
+        self.setting.dir.join(format!("{seq:020}.{FILE_EXTENSION}"))
+    }
+
+    /// stores `message`, the oldest messages are dropped if the limits are exceeded
+    fn push(&mut self, trace: Trace, message: &IotMessage) -> Result<Vec<Trace>> {
+        let content = encode(trace.id, message);
+        let size = content.len() as u64;
+
+        anyhow::ensure!(
+            size <= self.setting.max_bytes && self.setting.max_messages > 0,
+            "offline store: message({trace}) exceeds the limits"
+        );
+
+        let mut dropped = vec![];
+
+        while self.entries.len() >= self.setting.max_messages
+            || self.bytes + size > self.setting.max_bytes
+        {
+            dropped.push(self.pop()?);
+        }
+
+        let path = self.path(self.next_seq);
+
+        write_durable(&path, &content)
+            .with_context(|| format!("offline store: cannot write {path:?}"))?;
+
+        self.entries.push_back((self.next_seq, trace, size));
+        self.bytes += size;
+        self.next_seq += 1;
+
+        Ok(dropped)
+    }
+
+    /// oldest message with its sequence number and trace
+    fn front(&self) -> Result<Option<(u64, Trace, IotMessage)>> {
+        let Some((seq, trace, _)) = self.entries.front() else {
+            return Ok(None);
+        };
+        let (_, message) = decode(&fs::read(self.path(*seq))?)?;
+
+        Ok(Some((*seq, *trace, message)))
+    }
+
+    /// removes the oldest message and returns its trace
+    fn pop(&mut self) -> Result<Trace> {
+        let Some((seq, trace, size)) = self.entries.pop_front() else {
+            anyhow::bail!("offline store: empty");
+        };
+
+        self.bytes -= size;
+        fs::remove_file(self.path(seq))?;
+
+        Ok(trace)
+    }
+
+    /// removes the message `trace` wherever it is stored. returns false if there is no such message.
+    fn remove(&mut self, trace: Trace) -> Result<bool> {
+        let Some(index) = self
+            .entries
+            .iter()
+            .position(|(_, stored, _)| *stored == trace)
+        else {
+            return Ok(false);
+        };
+        let Some((seq, _, size)) = self.entries.remove(index) else {
+            return Ok(false);
+        };
+
+        self.bytes -= size;
+        fs::remove_file(self.path(seq))?;
+
+        Ok(true)
+    }
+
+    /// removes the oldest message if it is still the one with sequence number `seq`, i.e. it wasn't dropped
+    /// meanwhile
+    fn remove_replayed(&mut self, seq: u64) -> Result<bool> {
+        if self.entries.front().map(|(stored, _, _)| *stored) != Some(seq) {
+            return Ok(false);
+        }
+
+        self.pop().map(|_| true)
+    }
+}
+
+/// store shared by the client and the replay task
+#[derive(Debug)]
+pub(crate) struct MessageStore {
+    files: Mutex<MessageFiles>,
+    pushed: Notify,
+}
+
+impl MessageStore {
+    pub(crate) fn open(setting: OfflineStore) -> Result<Self> {
+        Ok(MessageStore {
+            files: Mutex::new(MessageFiles::open(setting)?),
+            pushed: Notify::new(),
+        })
+    }
+
+    fn files(&self) -> std::sync::MutexGuard<'_, MessageFiles> {
+        self.files
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.files().entries.is_empty()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.files().entries.len()
+    }
+
+    /// removes the stored message `trace` without confirming it. returns false if there is no such message.
+    pub(crate) fn remove(&self, trace: Trace) -> Result<bool> {
+        self.files().remove(trace)
+    }
+
+    /// stores `message` for replay. messages dropped to keep the limits are finished as failed.
+    pub(crate) fn push(
+        &self,
+        trace: Trace,
+        message: &IotMessage,
+        diagnostics: &Arc<Mutex<Diagnostics>>,
+        on_confirmation: &Option<ConfirmationCallback>,
+    ) -> Result<()> {
+        let dropped = self.files().push(trace, message)?;
+
+        debug!("offline store: message({trace}) stored");
+
+        self.pushed.notify_one();
+
+        if !dropped.is_empty() {
+            warn!("offline store: full, dropped messages {dropped:?}");
+        }
+
+        for trace in dropped {
+            IotHubClient::finish_confirmation(
+                diagnostics,
+                on_confirmation,
+                trace,
+                ConfirmationOutcome::Failed(ConfirmationResult::Error),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// stores `message` like [`MessageStore::push`], but on a blocking thread, since file I/O would stall the
+    /// runtime
+    pub(crate) async fn push_blocking(
+        self: &Arc<Self>,
+        trace: Trace,
+        message: IotMessage,
+        diagnostics: &Arc<Mutex<Diagnostics>>,
+        on_confirmation: &Option<ConfirmationCallback>,
+    ) -> Result<()> {
+        let store = self.clone();
+        let diagnostics = diagnostics.clone();
+        let on_confirmation = on_confirmation.clone();
+
+        tokio::task::spawn_blocking(move || {
+            store.push(trace, &message, &diagnostics, &on_confirmation)
+        })
+        .await?
+    }
+}
+
+/// replays stored messages in order while the client is authenticated. a message is removed once it is
+/// confirmed, otherwise the replay is retried later.
+pub(crate) async fn run(
+    store: Arc<MessageStore>,
+    twin: Arc<SharedTwin>,
+    diagnostics: Arc<Mutex<Diagnostics>>,
+    on_confirmation: Option<ConfirmationCallback>,
+    confirmation_timeout: Duration,
+) {
+    loop {
+        let _ = tokio::time::timeout(
+            Duration::from_secs(REPLAY_RETRY_INTERVAL_IN_SECS),
+            store.pushed.notified(),
+        )
+        .await;
+
+        loop {
+            let authenticated = diagnostics.lock().is_ok_and(|d| {
+                d.last_connection_status() == Some(AuthenticationStatus::Authenticated)
+            });
+
+            if !authenticated {
+                break;
+            }
+
+            // the lock must not be held while the message is dropped or replayed
+            let next = blocking(&store, |files| files.front()).await;
+
+            let (seq, trace, message) = match next {
+                Ok(Some(next)) => next,
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("offline store: cannot read message, drop it: {e}");
+
+                    match blocking(&store, |files| files.pop()).await {
+                        Ok(trace) => IotHubClient::finish_confirmation(
+                            &diagnostics,
+                            &on_confirmation,
+                            trace,
+                            ConfirmationOutcome::Failed(ConfirmationResult::Error),
+                        ),
+                        Err(e) => {
+                            warn!("offline store: cannot drop message: {e}");
+                            break;
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            let outcome = match replay(&twin, message, trace.id, confirmation_timeout).await {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    warn!("offline store: cannot replay message({trace}): {e}");
+                    ConfirmationOutcome::Failed(ConfirmationResult::Error)
+                }
+            };
+
+            if outcome != ConfirmationOutcome::Succeeded {
+                debug!("offline store: message({trace}) not confirmed, retry later");
+                break;
+            }
+
+            let removed = blocking(&store, move |files| files.remove_replayed(seq)).await;
+
+            match removed {
+                // a message dropped while it was replayed is already finished as failed
+                Ok(false) => {}
+                Ok(true) => IotHubClient::finish_confirmation(
+                    &diagnostics,
+                    &on_confirmation,
+                    trace,
+                    outcome,
+                ),
+                // the message is replayed once more after a restart
+                Err(e) => {
+                    warn!("offline store: cannot remove replayed message({trace}): {e}");
+                    IotHubClient::finish_confirmation(
+                        &diagnostics,
+                        &on_confirmation,
+                        trace,
+                        outcome,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// runs `f` on the locked files of `store` on a blocking thread, since file I/O would stall the runtime
+async fn blocking<T: Send + 'static>(
+    store: &Arc<MessageStore>,
+    f: impl FnOnce(&mut MessageFiles) -> Result<T> + Send + 'static,
+) -> Result<T> {
+    let store = store.clone();
+
+    tokio::task::spawn_blocking(move || f(&mut store.files())).await?
+}
+
+/// writes `content` to a temporary file that is renamed to `path`, both synced to disk, so that `path` is
+/// complete even after a crash
+fn write_durable(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let tmp = path.with_extension(TMP_EXTENSION);
+    let mut file = fs::File::create(&tmp)?;
+
+    file.write_all(content)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+
+    // the rename is durable only once the directory is synced
+    if let Some(dir) = path.parent() {
+        fs::File::open(dir)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+async fn replay(
+    twin: &SharedTwin,
+    message: IotMessage,
+    trace_id: TraceId,
+    confirmation_timeout: Duration,
+) -> Result<ConfirmationOutcome> {
+    debug!("offline store: replay message({trace_id})");
+
+    let rx = send(twin, message, trace_id)?;
+
+    Ok(match tokio::time::timeout(confirmation_timeout, rx).await {
+        Ok(Ok(result)) if result.is_ok() => ConfirmationOutcome::Succeeded,
+        Ok(Ok(result)) => ConfirmationOutcome::Failed(result),
+        Ok(Err(_)) => ConfirmationOutcome::Failed(ConfirmationResult::Error),
+        Err(_) => ConfirmationOutcome::TimedOut,
+    })
+}
+
+/// sends `message` directly to iothub, i.e. it isn't stored offline again if its confirmation fails.
+/// the azure-sdk-c clones the message on send, so it can be dropped before the confirmation is received.
+pub(crate) fn send(
+    twin: &SharedTwin,
+    mut message: IotMessage,
+    trace_id: TraceId,
+) -> Result<oneshot::Receiver<ConfirmationResult>> {
+    let handle = message.create_outgoing_handle()?;
+    let queue = message.output_queue.clone();
+    let (tx, rx) = oneshot::channel::<ConfirmationResult>();
+
+    twin.with(|twin| {
+        twin.send_event_to_output_async(
+            handle,
+            queue,
+            Some(IotHubClient::c_d2c_confirmation_callback),
+            Box::into_raw(Box::new((tx, trace_id))) as *mut c_void,
+        )
+    })?;
+
+    Ok(rx)
+}
+
+pub(crate) fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+pub(crate) fn put_map(buf: &mut Vec<u8>, map: &HashMap<CString, CString>) {
+    buf.extend_from_slice(&(map.len() as u32).to_le_bytes());
+
+    for (key, value) in map {
+        put_bytes(buf, key.as_bytes());
+        put_bytes(buf, value.as_bytes());
+    }
+}
+
+/// version, trace id as u128, output queue, body, properties and system properties, all little endian with
+/// lengths as u32
+fn encode(trace_id: TraceId, message: &IotMessage) -> Vec<u8> {
+    let mut buf = vec![FORMAT_VERSION];
+
+    buf.extend_from_slice(&trace_id.to_le_bytes());
+    put_bytes(&mut buf, message.output_queue.as_bytes());
+    put_bytes(&mut buf, &message.body);
+    put_map(&mut buf, &message.properties);
+    put_map(&mut buf, &message.system_properties);
+
+    buf
+}
+
+pub(crate) struct Reader<'a>(pub(crate) &'a [u8]);
+
+impl Reader<'_> {
+    pub(crate) fn take(&mut self, len: usize) -> Result<&[u8]> {
+        anyhow::ensure!(self.0.len() >= len, "truncated message file");
+
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+
+        Ok(head)
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<Vec<u8>> {
+        let len = self.u32()? as usize;
+
+        Ok(self.take(len)?.to_vec())
+    }
+
+    pub(crate) fn map(&mut self) -> Result<HashMap<CString, CString>> {
+        let mut map = HashMap::new();
+
+        for _ in 0..self.u32()? {
+            map.insert(CString::new(self.bytes()?)?, CString::new(self.bytes()?)?);
+        }
+
+        Ok(map)
+    }
+}
+
+fn decode(content: &[u8]) -> Result<(TraceId, IotMessage)> {
+    let mut reader = Reader(content);
+    let version = reader.take(1)?[0];
+    let trace_id = match version {
+        // version 1 stored 32 bit trace ids
+        1 => TraceId::from(reader.u32()?),
+        v if v == FORMAT_VERSION => TraceId::from_le_bytes(reader.take(16)?.try_into()?),
+        _ => anyhow::bail!("unsupported message file version {version}"),
+    };
+    let output_queue = CString::new(reader.bytes()?)?;
+    let body = reader.bytes()?;
+    let properties = reader.map()?;
+    let system_properties = reader.map()?;
+
+    Ok((
+        trace_id,
+        IotMessage::outgoing(body, output_queue, properties, system_properties),
+    ))
+}
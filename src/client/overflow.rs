@@ -0,0 +1,54 @@
+use crate::client::{DispositionResult, IncomingIotMessage, IotMessageSender};
+use anyhow::Result;
+use log::{debug, warn};
+use tokio::sync::{mpsc, oneshot};
+
+/// Policy applied to incoming C2D messages if the channel of their [`crate::client::IncomingMessageObserver`]
+/// is full, see [`crate::client::IotHubClientBuilder::incoming_overflow_policy`]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum OverflowPolicy {
+    /// block the azure-sdk-c worker thread until the channel has room, which delays all subsequent callbacks
+    /// and sends. if [`crate::client::IotHubClientBuilder::bounded_latency`] is set, the message is rejected
+    /// instead.
+    #[default]
+    Block,
+    /// abandon the message without blocking, so that iothub delivers it again later
+    Abandon,
+    /// reject the message
+    Reject,
+    /// fire-and-forget: complete the message right away and buffer up to the given number of messages until the
+    /// channel has room. messages are rejected if the buffer is full, too.<br>
+    /// ***Note***: buffered messages are delivered at most once, i.e. they are lost if the process terminates
+    /// before the consumer received them, and the disposition sent by the consumer is ignored, since the message
+    /// was already completed. Use [`OverflowPolicy::Block`] or [`OverflowPolicy::Abandon`] if messages must not
+    /// get lost.
+    AcceptAndBuffer(usize),
+}
+
+/// buffered message together with the receiver of its disposition
+pub(crate) type BufferedMessage = (
+    IncomingIotMessage,
+    oneshot::Receiver<Result<DispositionResult>>,
+);
+
+/// forwards buffered messages to the observer channel `tx`, waiting for room. buffered messages were already
+/// completed, thus dispositions sent by the consumer are only logged.
+pub(crate) async fn run(mut rx: mpsc::Receiver<BufferedMessage>, tx: IotMessageSender) {
+    while let Some((message, rx_result)) = rx.recv().await {
+        if tx.send(message).await.is_err() {
+            warn!("overflow buffer: observer channel closed, drop buffered message");
+            continue;
+        }
+
+        tokio::spawn(async move {
+            match rx_result.await {
+                Ok(Ok(DispositionResult::Accepted)) => {}
+                Ok(Ok(disposition)) => {
+                    warn!("overflow buffer: ignore {disposition:?} of completed message")
+                }
+                Ok(Err(e)) => warn!("overflow buffer: ignore error of completed message: {e}"),
+                Err(_) => debug!("overflow buffer: no result of completed message"),
+            }
+        });
+    }
+}
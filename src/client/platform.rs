@@ -0,0 +1,75 @@
+use anyhow::Result;
+use azure_iot_sdk_sys::*;
+use log::debug;
+use std::sync::Mutex;
+
+/// number of clients holding the azure-sdk-c platform initialized
+static REFERENCES: Mutex<usize> = Mutex::new(0);
+/// number of calls of `IoTHub_Init()`
+#[cfg(feature = "test_hooks")]
+static INITIALIZATIONS: Mutex<usize> = Mutex::new(0);
+
+/// Reference to the global azure-sdk-c platform initialization.
+/// The first reference calls `IoTHub_Init()`, dropping the last one calls `IoTHub_Deinit()`.
+/// Thus a reference must only be dropped after all handles created by its owner are destroyed.
+#[derive(Debug)]
+pub(crate) struct PlatformRef(());
+
+impl PlatformRef {
+    pub(crate) fn acquire() -> Result<Self> {
+        let mut references = REFERENCES
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if *references == 0 {
+            debug!("IoTHub_Init()");
+
+            if unsafe { IoTHub_Init() } != 0 {
+                anyhow::bail!("error while IoTHub_Init()");
+            }
+
+            #[cfg(feature = "test_hooks")]
+            {
+                *INITIALIZATIONS
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()) += 1;
+            }
+        }
+
+        *references += 1;
+
+        Ok(PlatformRef(()))
+    }
+
+    /// number of currently held references
+    #[cfg(feature = "test_hooks")]
+    pub(crate) fn count() -> usize {
+        *REFERENCES
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// number of calls of `IoTHub_Init()` so far
+    #[cfg(feature = "test_hooks")]
+    pub(crate) fn initializations() -> usize {
+        *INITIALIZATIONS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Drop for PlatformRef {
+    fn drop(&mut self) {
+        let mut references = REFERENCES
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        *references -= 1;
+
+        if *references == 0 {
+            debug!("IoTHub_Deinit()");
+
+            unsafe { IoTHub_Deinit() };
+        }
+    }
+}
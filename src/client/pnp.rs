@@ -0,0 +1,31 @@
+use anyhow::Result;
+use serde_json::{json, Value};
+
+/// system property carrying the component name of telemetry
+pub(crate) static COMPONENT_NAME_PROPERTY: &str = "$.sub";
+static COMPONENT_MARKER: &str = "__t";
+static MAX_COMPONENT_NAME_LENGTH: usize = 64;
+
+/// validates `name` according to the naming rules of DTDL
+pub(crate) fn validate_component(name: &str) -> Result<()> {
+    let valid = name.len() <= MAX_COMPONENT_NAME_LENGTH
+        && name.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && !name.ends_with('_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    anyhow::ensure!(valid, "invalid pnp component name {name}");
+
+    Ok(())
+}
+
+/// reported properties of `component` shaped according to the IoT Plug and Play conventions, i.e.
+/// nested in the component and marked by `"__t": "c"`
+pub(crate) fn component_reported(component: &str, properties: Value) -> Result<Value> {
+    let Value::Object(mut properties) = properties else {
+        anyhow::bail!("reported properties of component {component} must be an object");
+    };
+
+    properties.insert(COMPONENT_MARKER.to_string(), json!("c"));
+
+    Ok(json!({ component: properties }))
+}
@@ -0,0 +1,56 @@
+use crate::client::twin::LockedTwin;
+use anyhow::Result;
+use azure_iot_sdk_sys::*;
+
+/// handle of azure-sdk-c underlying the client
+#[cfg(feature = "device_client")]
+pub type RawClientHandle = IOTHUB_DEVICE_CLIENT_HANDLE;
+/// handle of azure-sdk-c underlying the client
+#[cfg(any(feature = "module_client", feature = "edge_client"))]
+pub type RawClientHandle = IOTHUB_MODULE_CLIENT_HANDLE;
+
+/// Underlying azure-sdk-c handle of a connected client, see [`crate::client::IotHubClient::raw_handle`].
+///
+/// The handle is locked as long as the guard lives: it is neither destroyed nor recreated, e.g. by the
+/// watchdog or credential renewal, and all other uses of the handle by the client wait for the guard to be
+/// dropped. Thus the guard should be dropped as soon as possible and cannot be sent to other threads.
+pub struct RawHandle<'a> {
+    twin: LockedTwin<'a>,
+    handle: RawClientHandle,
+}
+
+impl<'a> RawHandle<'a> {
+    pub(crate) fn new(twin: LockedTwin<'a>) -> Result<Self> {
+        let Some(handle) = twin.raw_handle() else {
+            anyhow::bail!("client is not connected");
+        };
+
+        Ok(RawHandle {
+            twin,
+            handle: handle as RawClientHandle,
+        })
+    }
+
+    /// underlying handle, valid until the guard is dropped
+    pub fn get(&self) -> RawClientHandle {
+        self.handle
+    }
+
+    /// Install a foreign callback on the handle by `install` and track it as `name`, so that it is reported
+    /// by [`crate::client::IotHubClient::foreign_callbacks`] until the handle is recreated.
+    ///
+    /// # Safety
+    ///
+    /// `install` must comply with the safety contract of [`crate::client::IotHubClient::raw_handle`].
+    pub unsafe fn install_callback(
+        &mut self,
+        name: &str,
+        install: impl FnOnce(RawClientHandle) -> Result<()>,
+    ) -> Result<()> {
+        install(self.handle)?;
+
+        self.twin.add_foreign_callback(name);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,262 @@
+use crate::client::{
+    offline_store::{put_bytes, put_map, Reader},
+    Direction, IotMessage,
+};
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use std::{
+    collections::VecDeque,
+    ffi::CString,
+    fs,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+static FILE_EXTENSION: &str = "rejected";
+static FORMAT_VERSION: u8 = 1;
+static DEFAULT_MAX_MESSAGES: usize = 100;
+static DEFAULT_MAX_BYTES: u64 = 4 * 1024 * 1024;
+
+/// Settings of the disk-persistent store of rejected C2D messages.
+/// See [`crate::client::IotHubClientBuilder::rejected_message_store`].
+#[derive(Clone, Debug)]
+pub struct RejectedMessageStore {
+    dir: PathBuf,
+    max_messages: usize,
+    max_bytes: u64,
+}
+
+impl RejectedMessageStore {
+    /// Get settings of a store in directory `dir` limited to 100 messages and 4MiB
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        RejectedMessageStore {
+            dir: dir.into(),
+            max_messages: DEFAULT_MAX_MESSAGES,
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+
+    /// Set the maximum number of stored messages
+    pub fn max_messages(mut self, max_messages: usize) -> Self {
+        self.max_messages = max_messages;
+        self
+    }
+
+    /// Set the maximum size of all stored message files in bytes
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+}
+
+/// C2D message that was rejected or abandoned due to a failure, see
+/// [`crate::client::IotHubClient::rejected_messages`]
+#[derive(Debug)]
+pub struct RejectedMessage {
+    /// time the message was received
+    pub received: SystemTime,
+    /// reason why the message wasn't processed successfully
+    pub error: String,
+    /// [`IotMessage`] as received from iothub with all application properties. the body is still encrypted
+    /// or compressed, if it was.
+    pub message: IotMessage,
+}
+
+/// rejected messages in order, each stored in a file named by its sequence number
+#[derive(Debug)]
+struct RejectedFiles {
+    setting: RejectedMessageStore,
+    // sequence number and file size of stored messages
+    entries: VecDeque<(u64, u64)>,
+    bytes: u64,
+    next_seq: u64,
+}
+
+impl RejectedFiles {
+    fn open(setting: RejectedMessageStore) -> Result<Self> {
+        fs::create_dir_all(&setting.dir)
+            .with_context(|| format!("cannot create rejected message store {:?}", setting.dir))?;
+
+        let mut entries = vec![];
+
+        for entry in fs::read_dir(&setting.dir)? {
+            let path = entry?.path();
+
+            if path.extension().and_then(|e| e.to_str()) != Some(FILE_EXTENSION) {
+                continue;
+            }
+
+            let Some(seq) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse::<u64>().ok())
+            else {
+                warn!("rejected message store: ignore unexpected file {path:?}");
+                continue;
+            };
+
+            entries.push((seq, fs::metadata(&path)?.len()));
+        }
+
+        entries.sort_unstable_by_key(|(seq, _)| *seq);
+
+        let files = RejectedFiles {
+            next_seq: entries.last().map_or(0, |(seq, _)| seq + 1),
+            bytes: entries.iter().map(|(_, size)| size).sum(),
+            entries: entries.into(),
+            setting,
+        };
+
+        info!(
+            "rejected message store: {} messages with {} bytes",
+            files.entries.len(),
+            files.bytes
+        );
+
+        Ok(files)
+    }
+
+    fn path(&self, seq: u64) -> PathBuf {
+        self.setting.dir.join(format!("{seq:020}.{FILE_EXTENSION}"))
+    }
+
+    /// stores `content`, the oldest messages are dropped if the limits are exceeded
+    fn push(&mut self, content: &[u8]) -> Result<()> {
+        let size = content.len() as u64;
+
+        anyhow::ensure!(
+            size <= self.setting.max_bytes && self.setting.max_messages > 0,
+            "rejected message store: message exceeds the limits"
+        );
+
+        while self.entries.len() >= self.setting.max_messages
+            || self.bytes + size > self.setting.max_bytes
+        {
+            let Some((seq, size)) = self.entries.pop_front() else {
+                break;
+            };
+
+            debug!("rejected message store: full, drop message {seq}");
+
+            self.bytes -= size;
+            fs::remove_file(self.path(seq))?;
+        }
+
+        let path = self.path(self.next_seq);
+        let tmp = path.with_extension("tmp");
+
+        fs::write(&tmp, content)
+            .and_then(|_| fs::rename(&tmp, &path))
+            .with_context(|| format!("rejected message store: cannot write {path:?}"))?;
+
+        self.entries.push_back((self.next_seq, size));
+        self.bytes += size;
+        self.next_seq += 1;
+
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        while let Some((seq, size)) = self.entries.pop_front() {
+            self.bytes -= size;
+            fs::remove_file(self.path(seq))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// store shared by the client and the C2D message callback
+#[derive(Debug)]
+pub(crate) struct RejectedStore {
+    files: Mutex<RejectedFiles>,
+}
+
+impl RejectedStore {
+    pub(crate) fn open(setting: RejectedMessageStore) -> Result<Self> {
+        Ok(RejectedStore {
+            files: Mutex::new(RejectedFiles::open(setting)?),
+        })
+    }
+
+    fn files(&self) -> std::sync::MutexGuard<'_, RejectedFiles> {
+        self.files
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// stores `message` received at `received` together with its `error`
+    pub(crate) fn push(
+        &self,
+        received: SystemTime,
+        error: &str,
+        message: &IotMessage,
+    ) -> Result<()> {
+        self.files().push(&encode(received, error, message))
+    }
+
+    /// all stored messages in the order they were received. corrupt files are skipped.
+    pub(crate) fn messages(&self) -> Result<Vec<RejectedMessage>> {
+        let files = self.files();
+        let mut messages = vec![];
+
+        for (seq, _) in &files.entries {
+            let path = files.path(*seq);
+
+            match decode(&fs::read(&path)?) {
+                Ok(message) => messages.push(message),
+                Err(e) => warn!("rejected message store: skip corrupt file {path:?}: {e}"),
+            }
+        }
+
+        Ok(messages)
+    }
+
+    pub(crate) fn clear(&self) -> Result<()> {
+        self.files().clear()
+    }
+}
+
+/// version, receive time in ms since unix epoch as u64, error, body, properties and system properties, all
+/// lengths as u32 little endian
+fn encode(received: SystemTime, error: &str, message: &IotMessage) -> Vec<u8> {
+    let received = received
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+    let mut buf = vec![FORMAT_VERSION];
+
+    buf.extend_from_slice(&received.to_le_bytes());
+    put_bytes(&mut buf, error.as_bytes());
+    put_bytes(&mut buf, &message.body);
+    put_map(&mut buf, &message.properties);
+    put_map(&mut buf, &message.system_properties);
+
+    buf
+}
+
+fn decode(content: &[u8]) -> Result<RejectedMessage> {
+    let mut reader = Reader(content);
+    let version = reader.take(1)?[0];
+
+    anyhow::ensure!(
+        version == FORMAT_VERSION,
+        "unsupported message file version {version}"
+    );
+
+    let received = u64::from_le_bytes(reader.take(8)?.try_into()?);
+    let error = String::from_utf8_lossy(&reader.bytes()?).into_owned();
+    let body = reader.bytes()?;
+    let properties = reader.map()?;
+    let system_properties = reader.map()?;
+    let mut message = IotMessage::outgoing(body, CString::default(), properties, system_properties);
+
+    message.direction = Direction::Incoming;
+
+    Ok(RejectedMessage {
+        received: UNIX_EPOCH + Duration::from_millis(received),
+        error,
+        message,
+    })
+}
@@ -0,0 +1,575 @@
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+// maximum nesting of objects in twin properties
+static MAX_TWIN_DEPTH: usize = 10;
+// maximum length of keys in twin properties
+static MAX_KEY_LENGTH: usize = 1024;
+// characters not allowed in twin property names, percent-encoded in element keys
+static RESERVED_CHARS: [(char, &str); 4] = [('%', "%25"), ('.', "%2E"), ('$', "%24"), (' ', "%20")];
+
+/// Array of objects in reported properties that is patched per element instead of as a whole.
+///
+/// Patches of reported properties replace arrays entirely, so that large inventory-like arrays would have
+/// to be reported completely on every change and easily exceed the size limits of the twin. Thus the
+/// elements are reported as object `<property>: { <element key>: <element> }` keyed by a field of the
+/// elements. Patches only contain elements that changed, fields that changed within an element and `null`
+/// for removed elements or fields, which is how twin patches delete properties. Characters not allowed in
+/// twin property names, i.e. `.`, `$` and space, are percent-encoded in element keys.
+///
+/// The last reported state is kept, so that a patch is the difference to it. On start it can be restored
+/// from the reported properties by [`ReportedArray::from_reported`].
+/// ```rust
+/// use azure_iot_sdk::client::*;
+/// use serde_json::json;
+///
+/// let mut packages = ReportedArray::new("packages", "name").unwrap();
+///
+/// let patch = packages
+///     .replace(vec![
+///         json!({"name": "openssl", "version": "3.0.2"}),
+///         json!({"name": "libc6", "version": "2.35"}),
+///     ])
+///     .unwrap();
+///
+/// assert_eq!(
+///     patch,
+///     json!({"packages": {
+///         "openssl": {"name": "openssl", "version": "3.0.2"},
+///         "libc6": {"name": "libc6", "version": "2.35"},
+///     }})
+/// );
+///
+/// // only the difference is reported
+/// let patch = packages
+///     .replace(vec![
+///         json!({"name": "openssl", "version": "3.0.13"}),
+///         json!({"name": "python3.10", "version": "3.10.12"}),
+///     ])
+///     .unwrap();
+///
+/// assert_eq!(
+///     patch,
+///     json!({"packages": {
+///         "openssl": {"version": "3.0.13"},
+///         "libc6": null,
+///         "python3%2E10": {"name": "python3.10", "version": "3.10.12"},
+///     }})
+/// );
+///
+/// assert_eq!(
+///     packages.append(vec![json!({"name": "curl", "version": "7.81.0"})]).unwrap(),
+///     json!({"packages": {"curl": {"name": "curl", "version": "7.81.0"}}})
+/// );
+/// assert_eq!(packages.remove("curl").unwrap(), json!({"packages": {"curl": null}}));
+/// assert_eq!(packages.len(), 2);
+///
+/// // plain arrays and nulls are rejected
+/// assert!(packages.append(vec![json!({"name": "git", "tags": ["vcs"]})]).is_err());
+/// assert!(packages.append(vec![json!({"name": "git", "version": null})]).is_err());
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReportedArray {
+    property: String,
+    key: String,
+    // last reported elements by their encoded key
+    elements: BTreeMap<String, Value>,
+}
+
+impl ReportedArray {
+    /// Get an empty array reported as `property` whose elements are identified by their field `key`
+    pub fn new(property: impl Into<String>, key: impl Into<String>) -> Result<Self> {
+        let property = property.into();
+
+        validate_name(&property)?;
+
+        Ok(ReportedArray {
+            property,
+            key: key.into(),
+            elements: BTreeMap::new(),
+        })
+    }
+
+    /// Get an array reported as `property` restored from the last `reported` properties, e.g. the reported
+    /// section of the complete twin requested by [`crate::client::IotHubClient::twin_async`]. The array is empty
+    /// if `reported` doesn't contain `property`.
+    /// ```rust
+    /// use azure_iot_sdk::client::*;
+    /// use serde_json::json;
+    ///
+    /// let reported = json!({"packages": {"openssl": {"name": "openssl", "version": "3.0.2"}}, "$version": 4});
+    /// let mut packages = ReportedArray::from_reported("packages", "name", &reported).unwrap();
+    ///
+    /// assert_eq!(packages.elements(), vec![json!({"name": "openssl", "version": "3.0.2"})]);
+    /// assert_eq!(
+    ///     packages.replace(vec![]).unwrap(),
+    ///     json!({"packages": {"openssl": null}})
+    /// );
+    /// ```
+    pub fn from_reported(
+        property: impl Into<String>,
+        key: impl Into<String>,
+        reported: &Value,
+    ) -> Result<Self> {
+        let mut array = ReportedArray::new(property, key)?;
+
+        let elements = match reported.get(&array.property) {
+            None | Some(Value::Null) => return Ok(array),
+            Some(Value::Object(elements)) => elements,
+            Some(_) => anyhow::bail!(
+                "reported property {} is not an object of elements",
+                array.property
+            ),
+        };
+
+        for (key, element) in elements {
+            // elements removed by a patch might be reported as null
+            if element.is_null() {
+                continue;
+            }
+
+            let (encoded, element) = array.validate_element(element.clone())?;
+
+            anyhow::ensure!(
+                encoded == *key,
+                "element {key} of reported property {} doesn't match its key",
+                array.property
+            );
+
+            array.elements.insert(encoded, element);
+        }
+
+        Ok(array)
+    }
+
+    /// name of the reported property
+    pub fn property(&self) -> &str {
+        &self.property
+    }
+
+    /// number of elements
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    /// true if there are no elements
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// elements ordered by their encoded key
+    pub fn elements(&self) -> Vec<Value> {
+        self.elements.values().cloned().collect()
+    }
+
+    /// Get the patch replacing all elements by `elements`
+    pub fn replace(&mut self, elements: impl IntoIterator<Item = Value>) -> Result<Value> {
+        let mut replaced = BTreeMap::new();
+
+        for element in elements {
+            let (key, element) = self.validate_element(element)?;
+
+            anyhow::ensure!(
+                replaced.insert(key.clone(), element).is_none(),
+                "duplicate element {key} in reported property {}",
+                self.property
+            );
+        }
+
+        let mut patch = Map::new();
+
+        for key in self.elements.keys() {
+            if !replaced.contains_key(key) {
+                patch.insert(key.clone(), Value::Null);
+            }
+        }
+
+        for (key, element) in &replaced {
+            if let Some(diff) = merge_patch(self.elements.get(key), element) {
+                patch.insert(key.clone(), diff);
+            }
+        }
+
+        self.elements = replaced;
+
+        Ok(self.patch(patch))
+    }
+
+    /// Get the patch appending `elements`. Fails if an element with the same key exists.
+    pub fn append(&mut self, elements: impl IntoIterator<Item = Value>) -> Result<Value> {
+        let mut appended = BTreeMap::new();
+
+        for element in elements {
+            let (key, element) = self.validate_element(element)?;
+
+            anyhow::ensure!(
+                !self.elements.contains_key(&key) && !appended.contains_key(&key),
+                "element {key} already exists in reported property {}",
+                self.property
+            );
+
+            appended.insert(key, element);
+        }
+
+        let patch = appended.clone().into_iter().collect();
+
+        self.elements.append(&mut appended);
+
+        Ok(self.patch(patch))
+    }
+
+    /// Get the patch inserting `element` or updating the element with the same key
+    pub fn upsert(&mut self, element: Value) -> Result<Value> {
+        let (key, element) = self.validate_element(element)?;
+        let mut patch = Map::new();
+
+        if let Some(diff) = merge_patch(self.elements.get(&key), &element) {
+            patch.insert(key.clone(), diff);
+        }
+
+        self.elements.insert(key, element);
+
+        Ok(self.patch(patch))
+    }
+
+    /// Get the patch removing the element with `key`. Fails if there is no such element.
+    pub fn remove(&mut self, key: &str) -> Result<Value> {
+        let encoded = encode_key(key);
+
+        anyhow::ensure!(
+            self.elements.remove(&encoded).is_some(),
+            "no element {key} in reported property {}",
+            self.property
+        );
+
+        Ok(self.patch(Map::from_iter([(encoded, Value::Null)])))
+    }
+
+    /// Validate `reported` properties against the conventions of the twin, i.e. objects must not be nested
+    /// deeper than 10 levels and there must be no arrays, since patches replace arrays entirely. Arrays should be
+    /// reported by [`ReportedArray`] instead.
+    /// ```rust
+    /// use azure_iot_sdk::client::*;
+    /// use serde_json::json;
+    ///
+    /// assert!(ReportedArray::validate_reported(&json!({"status": {"state": "ok"}})).is_ok());
+    /// assert!(ReportedArray::validate_reported(&json!({"status": {"errors": ["e1", "e2"]}})).is_err());
+    /// ```
+    pub fn validate_reported(reported: &Value) -> Result<()> {
+        validate_value(reported, "", 0)
+    }
+
+    /// validates `element` and returns its encoded key
+    fn validate_element(&self, element: Value) -> Result<(String, Value)> {
+        let key = match element.get(&self.key) {
+            Some(Value::String(key)) => key.clone(),
+            Some(Value::Number(key)) => key.to_string(),
+            _ => anyhow::bail!(
+                "element of reported property {} has no string or number field {}",
+                self.property,
+                self.key
+            ),
+        };
+
+        let encoded = encode_key(&key);
+
+        validate_name(&encoded)?;
+        anyhow::ensure!(
+            element.is_object(),
+            "element {key} of reported property {} is not an object",
+            self.property
+        );
+        // the elements are nested in the property
+        validate_value(&element, &format!("/{}/{encoded}", self.property), 2).with_context(
+            || {
+                format!(
+                    "invalid element {key} of reported property {}",
+                    self.property
+                )
+            },
+        )?;
+
+        Ok((encoded, element))
+    }
+
+    fn patch(&self, elements: Map<String, Value>) -> Value {
+        Value::Object(Map::from_iter([(
+            self.property.clone(),
+            Value::Object(elements),
+        )]))
+    }
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    anyhow::ensure!(!name.is_empty(), "twin property name must not be empty");
+    anyhow::ensure!(
+        name.len() <= MAX_KEY_LENGTH,
+        "twin property name {name} exceeds {MAX_KEY_LENGTH} bytes"
+    );
+    anyhow::ensure!(
+        !name.contains(['.', '$', ' ']) && !name.chars().any(char::is_control),
+        "twin property name {name} must not contain control characters, '.', '$' or ' '"
+    );
+
+    Ok(())
+}
+
+fn validate_value(value: &Value, path: &str, depth: usize) -> Result<()> {
+    match value {
+        Value::Array(_) => {
+            anyhow::bail!("array at {path} is replaced entirely by every patch, use ReportedArray")
+        }
+        Value::Null => anyhow::bail!("null at {path} removes the property in a patch"),
+        Value::Object(object) => {
+            anyhow::ensure!(
+                depth < MAX_TWIN_DEPTH,
+                "object at {path} exceeds the maximum depth of {MAX_TWIN_DEPTH}"
+            );
+
+            for (key, value) in object {
+                validate_value(value, &format!("{path}/{key}"), depth + 1)?;
+            }
+
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn encode_key(key: &str) -> String {
+    key.chars()
+        .fold(String::with_capacity(key.len()), |mut encoded, c| {
+            match RESERVED_CHARS.iter().find(|(reserved, _)| *reserved == c) {
+                Some((_, escaped)) => encoded.push_str(escaped),
+                None => encoded.push(c),
+            }
+            encoded
+        })
+}
+
+/// merge patch (RFC 7386) turning `old` into `new`, `None` if they are equal
+fn merge_patch(old: Option<&Value>, new: &Value) -> Option<Value> {
+    match (old, new) {
+        (Some(old), new) if old == new => None,
+        (Some(Value::Object(old)), Value::Object(new)) => {
+            let mut patch = Map::new();
+
+            for key in old.keys() {
+                if !new.contains_key(key) {
+                    patch.insert(key.clone(), Value::Null);
+                }
+            }
+
+            for (key, value) in new {
+                if let Some(diff) = merge_patch(old.get(key), value) {
+                    patch.insert(key.clone(), diff);
+                }
+            }
+
+            Some(Value::Object(patch))
+        }
+        _ => Some(new.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // value nested in `levels` objects
+    fn nested(levels: usize) -> Value {
+        (0..levels).fold(json!(1), |value, _| json!({ "level": value }))
+    }
+
+    fn packages() -> ReportedArray {
+        let mut packages = ReportedArray::new("packages", "name").unwrap();
+
+        packages
+            .replace(vec![
+                json!({"name": "openssl", "version": "3.0.2"}),
+                json!({"name": "libc6", "version": "2.35", "config": {"x": 1, "y": 2}}),
+            ])
+            .unwrap();
+
+        packages
+    }
+
+    #[test]
+    fn invalid_property_names() {
+        assert!(ReportedArray::new("", "name").is_err());
+        assert!(ReportedArray::new("my.packages", "name").is_err());
+        assert!(ReportedArray::new("$packages", "name").is_err());
+        assert!(ReportedArray::new("my packages", "name").is_err());
+        assert!(ReportedArray::new("my\npackages", "name").is_err());
+        assert!(ReportedArray::new("p".repeat(MAX_KEY_LENGTH + 1), "name").is_err());
+        assert!(ReportedArray::new("p".repeat(MAX_KEY_LENGTH), "name").is_ok());
+    }
+
+    #[test]
+    fn element_keys_are_encoded() {
+        assert_eq!(encode_key("a.b$c d%"), "a%2Eb%24c%20d%25");
+        assert_eq!(encode_key("plain"), "plain");
+
+        let mut packages = ReportedArray::new("packages", "name").unwrap();
+
+        assert_eq!(
+            packages.upsert(json!({"name": "python3.10"})).unwrap(),
+            json!({"packages": {"python3%2E10": {"name": "python3.10"}}})
+        );
+        assert_eq!(
+            packages.remove("python3.10").unwrap(),
+            json!({"packages": {"python3%2E10": null}})
+        );
+        assert!(packages.is_empty());
+    }
+
+    #[test]
+    fn number_keys() {
+        let mut disks = ReportedArray::new("disks", "id").unwrap();
+
+        assert_eq!(
+            disks.append(vec![json!({"id": 7, "size": 64})]).unwrap(),
+            json!({"disks": {"7": {"id": 7, "size": 64}}})
+        );
+        assert_eq!(disks.remove("7").unwrap(), json!({"disks": {"7": null}}));
+    }
+
+    #[test]
+    fn elements_without_key_are_rejected() {
+        let mut packages = packages();
+
+        assert!(packages.upsert(json!({"version": "1.0"})).is_err());
+        assert!(packages.upsert(json!({"name": true})).is_err());
+        assert!(packages.upsert(json!("openssl")).is_err());
+        assert!(packages.upsert(json!({"name": ""})).is_err());
+        assert_eq!(packages.len(), 2);
+    }
+
+    #[test]
+    fn upsert_patches_changed_fields_only() {
+        let mut packages = packages();
+
+        assert_eq!(
+            packages
+                .upsert(json!({"name": "openssl", "version": "3.0.2"}))
+                .unwrap(),
+            json!({"packages": {}})
+        );
+        assert_eq!(
+            packages
+                .upsert(json!({"name": "libc6", "version": "2.35", "config": {"x": 1, "z": 3}}))
+                .unwrap(),
+            json!({"packages": {"libc6": {"config": {"y": null, "z": 3}}}})
+        );
+        assert_eq!(
+            packages
+                .upsert(json!({"name": "libc6", "version": "2.35", "config": 0}))
+                .unwrap(),
+            json!({"packages": {"libc6": {"config": 0}}})
+        );
+        assert_eq!(
+            packages.upsert(json!({"name": "libc6"})).unwrap(),
+            json!({"packages": {"libc6": {"version": null, "config": null}}})
+        );
+        assert_eq!(
+            packages.elements(),
+            vec![
+                json!({"name": "libc6"}),
+                json!({"name": "openssl", "version": "3.0.2"})
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_updates_keep_the_state() {
+        let mut packages = packages();
+        let expected = packages.clone();
+
+        assert!(packages
+            .append(vec![json!({"name": "curl"}), json!({"name": "openssl"})])
+            .is_err());
+        assert!(packages
+            .append(vec![json!({"name": "curl"}), json!({"name": "curl"})])
+            .is_err());
+        assert!(packages
+            .replace(vec![json!({"name": "curl"}), json!({"name": "curl"})])
+            .is_err());
+        assert!(packages
+            .replace(vec![json!({"name": "curl", "tags": ["network"]})])
+            .is_err());
+        assert!(packages.remove("curl").is_err());
+        assert_eq!(packages, expected);
+    }
+
+    #[test]
+    fn replace_with_empty_removes_all() {
+        let mut packages = packages();
+
+        assert_eq!(
+            packages.replace(vec![]).unwrap(),
+            json!({"packages": {"libc6": null, "openssl": null}})
+        );
+        assert!(packages.is_empty());
+    }
+
+    #[test]
+    fn from_reported() {
+        let reported = json!({
+            "packages": {
+                "openssl": {"name": "openssl", "version": "3.0.2"},
+                "python3%2E10": {"name": "python3.10"},
+                "curl": null,
+            },
+            "$version": 4,
+        });
+        let packages = ReportedArray::from_reported("packages", "name", &reported).unwrap();
+
+        assert_eq!(packages.property(), "packages");
+        assert_eq!(packages.len(), 2);
+
+        assert!(ReportedArray::from_reported("disks", "id", &reported)
+            .unwrap()
+            .is_empty());
+        assert!(
+            ReportedArray::from_reported("packages", "name", &json!({"packages": null}))
+                .unwrap()
+                .is_empty()
+        );
+        assert!(ReportedArray::from_reported(
+            "packages",
+            "name",
+            &json!({"packages": ["openssl"]})
+        )
+        .is_err());
+        assert!(ReportedArray::from_reported(
+            "packages",
+            "name",
+            &json!({"packages": {"curl": {"name": "openssl"}}})
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn validate_reported() {
+        assert!(ReportedArray::validate_reported(&json!({"status": "ok", "count": 1})).is_ok());
+        assert!(ReportedArray::validate_reported(&json!({"status": null})).is_err());
+        assert!(ReportedArray::validate_reported(&json!({"status": {"errors": []}})).is_err());
+        assert!(ReportedArray::validate_reported(&nested(MAX_TWIN_DEPTH)).is_ok());
+        assert!(ReportedArray::validate_reported(&nested(MAX_TWIN_DEPTH + 1)).is_err());
+    }
+
+    #[test]
+    fn elements_count_to_the_twin_depth() {
+        let mut packages = ReportedArray::new("packages", "name").unwrap();
+
+        // the elements are nested in the property object
+        assert!(packages
+            .upsert(json!({"name": "openssl", "config": nested(MAX_TWIN_DEPTH - 3)}))
+            .is_ok());
+        assert!(packages
+            .upsert(json!({"name": "openssl", "config": nested(MAX_TWIN_DEPTH - 2)}))
+            .is_err());
+    }
+}
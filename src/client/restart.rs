@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+/// Policy of the watchdog restarting the client by recreating the underlying azure-sdk-c handle with all
+/// callbacks and options applied. See [`crate::client::IotHubClientBuilder::restart_policy`].
+///
+/// The client is restarted if it stays unauthenticated for the stall duration. Optionally it is also restarted
+/// while authenticated if the connection quality, i.e. the share of succeeded confirmations of the last D2C
+/// messages and reported properties, drops below a threshold. See
+/// [`crate::client::IotHubClient::connection_quality`].
+/// ```rust
+/// use azure_iot_sdk::client::*;
+/// use std::time::Duration;
+///
+/// let policy = RestartPolicy::on_stall(Duration::from_secs(600)).on_quality_below(0.5);
+///
+/// assert_eq!(policy.stall_after(), Duration::from_secs(600));
+/// assert_eq!(policy.quality_below(), Some(0.5));
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RestartPolicy {
+    stall_after: Duration,
+    quality_below: Option<f64>,
+}
+
+impl RestartPolicy {
+    /// Get a policy restarting the client if it stays unauthenticated for `after`
+    pub fn on_stall(after: Duration) -> Self {
+        RestartPolicy {
+            stall_after: after,
+            quality_below: None,
+        }
+    }
+
+    /// Restart the client additionally if the connection quality drops below `threshold`, which is clamped
+    /// to 0.0..=1.0
+    pub fn on_quality_below(mut self, threshold: f64) -> Self {
+        self.quality_below = Some(threshold.clamp(0.0, 1.0));
+        self
+    }
+
+    /// unauthenticated duration after which the client is restarted
+    pub fn stall_after(&self) -> Duration {
+        self.stall_after
+    }
+
+    /// connection quality below which the client is restarted, if any
+    pub fn quality_below(&self) -> Option<f64> {
+        self.quality_below
+    }
+
+    /// true if `quality` is known and violates the policy
+    pub(crate) fn quality_violated(&self, quality: Option<f64>) -> bool {
+        matches!((self.quality_below, quality), (Some(threshold), Some(quality)) if quality < threshold)
+    }
+}
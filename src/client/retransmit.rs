@@ -0,0 +1,148 @@
+use crate::client::{
+    diagnostics::Diagnostics, offline_store, twin::SharedTwin, ConfirmationOutcome,
+    ConfirmationResult, DeadLetter, DeadLetterObserver, IotHubClient, IotMessage, MessageOverrides,
+    TraceId,
+};
+use log::{info, warn};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Policy of retransmitting D2C messages whose confirmation failed or timed out, see
+/// [`crate::client::IotHubClientBuilder::retransmit_failed_messages`].
+///
+/// A message is sent again up to the maximum number of retries. The backoff before the first retry is doubled
+/// for each further retry, but doesn't exceed the maximum backoff. Default backoff is 1s up to 60s.
+/// ```rust
+/// use azure_iot_sdk::client::*;
+/// use std::time::Duration;
+///
+/// let policy = RetransmitPolicy::new(5).backoff(Duration::from_millis(500), Duration::from_secs(10));
+///
+/// assert_eq!(policy.max_retries(), 5);
+/// assert_eq!(policy.initial_backoff(), Duration::from_millis(500));
+/// assert_eq!(policy.max_backoff(), Duration::from_secs(10));
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RetransmitPolicy {
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl RetransmitPolicy {
+    /// Get a policy retransmitting a message up to `max_retries` times
+    pub fn new(max_retries: u32) -> Self {
+        RetransmitPolicy {
+            max_retries,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+
+    /// Set the backoff before the first retry and its maximum, which is at least `initial`
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// maximum number of retries
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    /// backoff before the first retry
+    pub fn initial_backoff(&self) -> Duration {
+        self.initial_backoff
+    }
+
+    /// maximum backoff between retries
+    pub fn max_backoff(&self) -> Duration {
+        self.max_backoff
+    }
+}
+
+/// copy of a D2C message retransmitted if its confirmation fails
+pub(crate) struct Retransmission {
+    pub(crate) policy: RetransmitPolicy,
+    pub(crate) twin: Arc<SharedTwin>,
+    pub(crate) message: IotMessage,
+    // false if a message that might have been delivered must not be sent twice
+    pub(crate) retry_timed_out: bool,
+    pub(crate) tx_dead_letter: Option<DeadLetterObserver>,
+    pub(crate) diagnostics: Arc<Mutex<Diagnostics>>,
+}
+
+impl Retransmission {
+    /// retransmits the message until it is confirmed or the retries are exhausted. exhausted messages are
+    /// signaled as [`DeadLetter::D2cMessage`]. returns the outcome of the last attempt.
+    pub(crate) async fn run(
+        self,
+        trace_id: TraceId,
+        mut outcome: ConfirmationOutcome,
+        confirmation_timeout: Duration,
+    ) -> ConfirmationOutcome {
+        let mut backoff = self.policy.initial_backoff;
+
+        for retry in 1..=self.policy.max_retries {
+            if outcome == ConfirmationOutcome::TimedOut && !self.retry_timed_out {
+                warn!("confirmation({trace_id}): timed out, don't retransmit message that might be delivered");
+                break;
+            }
+
+            warn!(
+                "confirmation({trace_id}): {outcome:?}, retransmit {retry}/{} in {backoff:?}",
+                self.policy.max_retries
+            );
+
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(self.policy.max_backoff);
+
+            outcome = self.send(trace_id, confirmation_timeout).await;
+
+            if outcome == ConfirmationOutcome::Succeeded {
+                info!("confirmation({trace_id}): retransmission {retry} succeeded");
+                return outcome;
+            }
+        }
+
+        if outcome != ConfirmationOutcome::TimedOut || self.retry_timed_out {
+            warn!("confirmation({trace_id}): retransmissions exhausted");
+        }
+
+        IotHubClient::send_dead_letter(
+            &self.tx_dead_letter,
+            &self.diagnostics,
+            DeadLetter::D2cMessage {
+                message: self.message,
+                trace_id,
+                outcome,
+            },
+        );
+
+        outcome
+    }
+
+    async fn send(&self, trace_id: TraceId, confirmation_timeout: Duration) -> ConfirmationOutcome {
+        let rx = match self
+            .message
+            .with_overrides(&MessageOverrides::new())
+            .and_then(|message| offline_store::send(&self.twin, message, trace_id))
+        {
+            Ok(rx) => rx,
+            Err(e) => {
+                warn!("confirmation({trace_id}): cannot retransmit message: {e}");
+                return ConfirmationOutcome::Failed(ConfirmationResult::Error);
+            }
+        };
+
+        match tokio::time::timeout(confirmation_timeout, rx).await {
+            Ok(Ok(result)) if result.is_ok() => ConfirmationOutcome::Succeeded,
+            Ok(Ok(result)) => ConfirmationOutcome::Failed(result),
+            Ok(Err(_)) => ConfirmationOutcome::Failed(ConfirmationResult::Error),
+            Err(_) => ConfirmationOutcome::TimedOut,
+        }
+    }
+}
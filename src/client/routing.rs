@@ -0,0 +1,58 @@
+use crate::client::{IncomingMessageObserver, IotMessage};
+use azure_iot_sdk_sys::*;
+use std::ffi::CString;
+
+/// Filter used to route incoming cloud to device (C2D) messages to an [`IncomingMessageObserver`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MessageFilter {
+    /// matches messages having the property `key` with value `value`
+    Property {
+        /// property key
+        key: String,
+        /// property value
+        value: String,
+    },
+    /// matches messages received on the given module input.
+    /// ***Note***: device clients don't have inputs, thus their messages never match.
+    Input(String),
+    /// matches messages with the given content type, e.g. "application/json"
+    ContentType(String),
+}
+
+impl MessageFilter {
+    /// true if the incoming message matches all `filters`
+    pub(crate) fn match_all(filters: &[MessageFilter], handle: IOTHUB_MESSAGE_HANDLE) -> bool {
+        filters.iter().all(|filter| match filter {
+            MessageFilter::Property { key, value } => CString::new(key.as_str())
+                .ok()
+                .and_then(|key| IotMessage::incoming_property(handle, &key))
+                .is_some_and(|v| v == *value),
+            MessageFilter::Input(input) => {
+                IotMessage::incoming_input_name(handle).is_some_and(|i| i == *input)
+            }
+            MessageFilter::ContentType(content_type) => {
+                IotMessage::incoming_content_type(handle).is_some_and(|c| c == *content_type)
+            }
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct IncomingMessageRoute {
+    pub(crate) filters: Vec<MessageFilter>,
+    pub(crate) observer: IncomingMessageObserver,
+}
+
+impl IncomingMessageRoute {
+    /// a route matches if all of its filters match
+    pub(crate) fn matches(&self, handle: IOTHUB_MESSAGE_HANDLE) -> bool {
+        MessageFilter::match_all(&self.filters, handle)
+    }
+
+    pub(crate) fn inputs(&self) -> impl Iterator<Item = &str> {
+        self.filters.iter().filter_map(|filter| match filter {
+            MessageFilter::Input(input) => Some(input.as_str()),
+            _ => None,
+        })
+    }
+}
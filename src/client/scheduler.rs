@@ -0,0 +1,110 @@
+use crate::client::{IotHubClient, IotMessage, TraceId};
+use anyhow::Result;
+use log::debug;
+use tokio::{
+    sync::mpsc,
+    time::{timeout_at, Duration, Instant},
+};
+
+/// Scheduler coalescing telemetry datapoints into batched D2C messages, see
+/// [`IotHubClient::telemetry_scheduler`].
+///
+/// Datapoints are collected until the coalescing window elapses or the maximum number of datapoints is
+/// reached, whatever comes first. Then they are sent as one message with a JSON array of all datapoints as
+/// body, in the order they were enqueued. Empty windows aren't sent. Since the scheduler borrows the client,
+/// it must run on the task owning the client, while datapoints can be enqueued from any task via a channel.
+pub struct TelemetryScheduler<'a> {
+    client: &'a IotHubClient,
+    window: Duration,
+    max_datapoints: usize,
+    output_queue: Option<String>,
+    datapoints: Vec<serde_json::Value>,
+}
+
+impl<'a> TelemetryScheduler<'a> {
+    pub(crate) fn new(client: &'a IotHubClient, window: Duration, max_datapoints: usize) -> Self {
+        let max_datapoints = max_datapoints.max(1);
+
+        TelemetryScheduler {
+            client,
+            window,
+            max_datapoints,
+            output_queue: None,
+            datapoints: Vec::with_capacity(max_datapoints),
+        }
+    }
+
+    /// Set the output queue batched messages are sent to
+    pub fn output_queue(mut self, queue: impl Into<String>) -> Self {
+        self.output_queue = Some(queue.into());
+        self
+    }
+
+    /// number of datapoints not yet sent
+    pub fn pending(&self) -> usize {
+        self.datapoints.len()
+    }
+
+    /// Enqueue `datapoint` and send the batch if the maximum number of datapoints is reached. Returns the trace
+    /// id of the sent message, if any.
+    pub async fn enqueue(&mut self, datapoint: serde_json::Value) -> Result<Option<TraceId>> {
+        self.datapoints.push(datapoint);
+
+        if self.datapoints.len() < self.max_datapoints {
+            return Ok(None);
+        }
+
+        self.flush().await
+    }
+
+    /// Send all pending datapoints as one message. Returns the trace id of the sent message or `None` if there
+    /// was nothing to send. If sending fails, the datapoints are kept and sent by the next flush.
+    pub async fn flush(&mut self) -> Result<Option<TraceId>> {
+        if self.datapoints.is_empty() {
+            return Ok(None);
+        }
+
+        let mut builder = IotMessage::builder().set_json_body(&self.datapoints)?;
+
+        if let Some(queue) = &self.output_queue {
+            builder = builder.set_output_queue(queue);
+        }
+
+        let trace_id = self.client.send_d2c_message(builder.build()?).await?;
+
+        debug!(
+            "telemetry scheduler({trace_id}): {} datapoints sent",
+            self.datapoints.len()
+        );
+
+        self.datapoints.clear();
+
+        Ok(Some(trace_id))
+    }
+
+    /// Enqueue all datapoints received by `rx` and send them once per coalescing window or whenever the maximum
+    /// number of datapoints is reached. Returns after pending datapoints are flushed once all senders are
+    /// dropped. On error the datapoints are kept, so that the scheduler can be run again with the same
+    /// receiver.
+    pub async fn run(&mut self, rx: &mut mpsc::Receiver<serde_json::Value>) -> Result<()> {
+        let mut deadline = Instant::now() + self.window;
+
+        loop {
+            match timeout_at(deadline, rx.recv()).await {
+                Ok(Some(datapoint)) => {
+                    if self.enqueue(datapoint).await?.is_some() {
+                        deadline = Instant::now() + self.window;
+                    }
+                }
+                Ok(None) => {
+                    self.flush().await?;
+                    return Ok(());
+                }
+                Err(_) => {
+                    deadline = Instant::now() + self.window;
+                    self.flush().await?;
+                }
+            }
+        }
+    }
+}
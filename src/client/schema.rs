@@ -0,0 +1,373 @@
+use crate::client::{
+    event_hubs::{self, CONTENT_TYPE_JSON},
+    IotMessage,
+};
+use anyhow::{Context, Result};
+use log::debug;
+use serde_json::Value;
+use std::{collections::HashMap, ffi::CString};
+
+/// name of the message property declaring the schema name of a D2C message
+pub static SCHEMA_NAME_PROPERTY: &str = "schema-name";
+/// name of the message property declaring the schema version of a D2C message
+pub static SCHEMA_VERSION_PROPERTY: &str = "schema-version";
+// keywords without effect on validation
+static ANNOTATIONS: [&str; 7] = [
+    "$schema",
+    "$id",
+    "$comment",
+    "title",
+    "description",
+    "default",
+    "examples",
+];
+static TYPES: [&str; 7] = [
+    "object", "array", "string", "number", "integer", "boolean", "null",
+];
+
+/// Registry of versioned telemetry schemas, i.e. the contract between a device and its cloud pipeline.
+/// See [`crate::client::IotHubClientBuilder::telemetry_schemas`].
+///
+/// Messages declare their schema by [`crate::client::IotMessageBuilder::set_schema`], which is sent as
+/// properties [`SCHEMA_NAME_PROPERTY`] and [`SCHEMA_VERSION_PROPERTY`], so that consumers can select the
+/// matching deserializer. JSON bodies are validated against the registered
+/// [JSON schema](https://json-schema.org/) before they are sent, so that contract violations are caught on
+/// the device instead of in the cloud pipeline.
+///
+/// The following subset of JSON schema is supported and schemas using other keywords are rejected on
+/// registration: `type`, `enum`, `const`, `properties`, `required`, `additionalProperties`, `items`,
+/// `minItems`, `maxItems`, `minLength`, `maxLength`, `minimum`, `maximum`, `exclusiveMinimum` and
+/// `exclusiveMaximum` as well as annotations like `title` or `description`.
+/// ```rust
+/// use azure_iot_sdk::client::*;
+/// use serde_json::json;
+///
+/// let schemas = SchemaRegistry::new()
+///     .register(
+///         "temperature",
+///         "1",
+///         json!({
+///             "type": "object",
+///             "properties": {
+///                 "celsius": {"type": "number", "minimum": -273.15},
+///                 "sensor": {"type": "string"},
+///             },
+///             "required": ["celsius"],
+///             "additionalProperties": false,
+///         }),
+///     )
+///     .unwrap();
+///
+/// assert!(schemas.validate_body("temperature", "1", &json!({"celsius": 21.5})).is_ok());
+/// assert!(schemas.validate_body("temperature", "1", &json!({"celsius": -300})).is_err());
+/// assert!(schemas.validate_body("temperature", "1", &json!({"fahrenheit": 70})).is_err());
+/// assert!(schemas.validate_body("temperature", "2", &json!({"celsius": 21.5})).is_err());
+///
+/// let msg = IotMessage::builder()
+///     .set_body(br#"{"celsius": 21.5}"#.to_vec())
+///     .set_content_type("application/json")
+///     .set_schema("temperature", "1")
+///     .build()
+///     .unwrap();
+///
+/// assert!(schemas.validate(&msg).is_ok());
+/// ```
+#[derive(Clone, Debug)]
+pub struct SchemaRegistry {
+    // schemas by name and version
+    schemas: HashMap<(String, String), Value>,
+    validate_before_send: bool,
+    require_schema: bool,
+}
+
+impl Default for SchemaRegistry {
+    fn default() -> Self {
+        SchemaRegistry::new()
+    }
+}
+
+impl SchemaRegistry {
+    /// Get an empty registry that validates message bodies before send and accepts messages without schema
+    pub fn new() -> Self {
+        SchemaRegistry {
+            schemas: HashMap::new(),
+            validate_before_send: true,
+            require_schema: false,
+        }
+    }
+
+    /// Register JSON `schema` as version `version` of `name`, an already registered schema is replaced.
+    /// Fails if `schema` uses unsupported keywords.
+    pub fn register(
+        mut self,
+        name: impl Into<String>,
+        version: impl Into<String>,
+        schema: Value,
+    ) -> Result<Self> {
+        let name = name.into();
+        let version = version.into();
+
+        anyhow::ensure!(
+            !name.is_empty() && !version.is_empty(),
+            "schema name and version must not be empty"
+        );
+
+        check_schema(&schema, "#")
+            .with_context(|| format!("invalid schema {name} version {version}"))?;
+
+        self.schemas.insert((name, version), schema);
+
+        Ok(self)
+    }
+
+    /// Set whether message bodies are validated against their schema before send. Default is true.
+    /// If disabled, only the declared schema must be registered.
+    pub fn validate_before_send(mut self, validate: bool) -> Self {
+        self.validate_before_send = validate;
+        self
+    }
+
+    /// Set whether messages must declare a schema. Default is false.
+    pub fn require_schema(mut self, require: bool) -> Self {
+        self.require_schema = require;
+        self
+    }
+
+    /// true if version `version` of schema `name` is registered
+    pub fn is_registered(&self, name: &str, version: &str) -> bool {
+        self.schemas
+            .contains_key(&(name.to_string(), version.to_string()))
+    }
+
+    /// Validate `message` against this registry: the declared schema must be registered and, if validation
+    /// before send is enabled, a JSON body must be valid according to it. Bodies with a content type other
+    /// than "application/json" cannot be validated and are accepted.
+    pub fn validate(&self, message: &IotMessage) -> Result<()> {
+        let Some((name, version)) = message.schema() else {
+            anyhow::ensure!(!self.require_schema, "message declares no schema");
+            return Ok(());
+        };
+
+        anyhow::ensure!(
+            self.is_registered(&name, &version),
+            "schema {name} version {version} is not registered"
+        );
+
+        if !self.validate_before_send {
+            return Ok(());
+        }
+
+        let content_type = message
+            .system_properties
+            .get(CString::new("$.ct")?.as_c_str())
+            .map(|content_type| event_hubs::decode_property(&content_type.to_string_lossy()));
+
+        if content_type
+            .as_ref()
+            .is_some_and(|ct| ct != CONTENT_TYPE_JSON)
+        {
+            debug!("cannot validate body with content type {content_type:?} against schema {name}");
+            return Ok(());
+        }
+
+        let body = serde_json::from_slice(&message.body)
+            .with_context(|| format!("body of schema {name} version {version} is no valid json"))?;
+
+        self.validate_body(&name, &version, &body)
+    }
+
+    /// Validate `body` against version `version` of schema `name`
+    pub fn validate_body(&self, name: &str, version: &str, body: &Value) -> Result<()> {
+        let Some(schema) = self.schemas.get(&(name.to_string(), version.to_string())) else {
+            anyhow::bail!("schema {name} version {version} is not registered");
+        };
+
+        validate_value(schema, body, "#")
+            .with_context(|| format!("body violates schema {name} version {version}"))
+    }
+}
+
+/// checks that `schema` only uses supported keywords with valid values
+fn check_schema(schema: &Value, path: &str) -> Result<()> {
+    let schema = match schema {
+        Value::Bool(_) => return Ok(()),
+        Value::Object(schema) => schema,
+        _ => anyhow::bail!("schema at {path} must be an object or boolean"),
+    };
+
+    for (keyword, value) in schema {
+        let path = format!("{path}/{keyword}");
+
+        match keyword.as_str() {
+            keyword if ANNOTATIONS.contains(&keyword) => {}
+            "type" => {
+                let valid = |t: &Value| t.as_str().is_some_and(|t| TYPES.contains(&t));
+                let valid = match value {
+                    Value::Array(types) => types.iter().all(valid),
+                    t => valid(t),
+                };
+
+                anyhow::ensure!(valid, "unknown type at {path}");
+            }
+            "enum" => anyhow::ensure!(value.is_array(), "{path} must be an array"),
+            "const" => {}
+            "properties" => {
+                let Value::Object(properties) = value else {
+                    anyhow::bail!("{path} must be an object");
+                };
+
+                for (name, schema) in properties {
+                    check_schema(schema, &format!("{path}/{name}"))?;
+                }
+            }
+            "required" => anyhow::ensure!(
+                value
+                    .as_array()
+                    .is_some_and(|names| names.iter().all(Value::is_string)),
+                "{path} must be an array of strings"
+            ),
+            "additionalProperties" | "items" => check_schema(value, &path)?,
+            "minItems" | "maxItems" | "minLength" | "maxLength" => {
+                anyhow::ensure!(value.is_u64(), "{path} must be a non-negative integer")
+            }
+            "minimum" | "maximum" | "exclusiveMinimum" | "exclusiveMaximum" => {
+                anyhow::ensure!(value.is_number(), "{path} must be a number")
+            }
+            _ => anyhow::bail!("unsupported keyword at {path}"),
+        }
+    }
+
+    Ok(())
+}
+
+fn type_matches(name: &str, value: &Value) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => false,
+    }
+}
+
+/// validates `value` against checked `schema`, `path` is the location of `value` starting at `#`
+fn validate_value(schema: &Value, value: &Value, path: &str) -> Result<()> {
+    let schema = match schema {
+        Value::Bool(accepted) => {
+            anyhow::ensure!(*accepted, "{path} is not allowed");
+            return Ok(());
+        }
+        Value::Object(schema) => schema,
+        _ => return Ok(()),
+    };
+    let limit = |keyword: &str| schema.get(keyword).and_then(Value::as_u64);
+    let bound = |keyword: &str| schema.get(keyword).and_then(Value::as_f64);
+
+    if let Some(types) = schema.get("type") {
+        let matches = match types {
+            Value::Array(types) => types
+                .iter()
+                .any(|t| t.as_str().is_some_and(|t| type_matches(t, value))),
+            t => t.as_str().is_some_and(|t| type_matches(t, value)),
+        };
+
+        anyhow::ensure!(matches, "{path} must be of type {types}");
+    }
+
+    if let Some(Value::Array(values)) = schema.get("enum") {
+        anyhow::ensure!(values.contains(value), "{path} must be one of {values:?}");
+    }
+
+    if let Some(expected) = schema.get("const") {
+        anyhow::ensure!(expected == value, "{path} must be {expected}");
+    }
+
+    match value {
+        Value::Object(object) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+
+            for name in schema
+                .get("required")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+            {
+                anyhow::ensure!(object.contains_key(name), "{path}/{name} is required");
+            }
+
+            for (name, value) in object {
+                let path = format!("{path}/{name}");
+
+                match (
+                    properties.and_then(|p| p.get(name)),
+                    schema.get("additionalProperties"),
+                ) {
+                    (Some(schema), _) | (None, Some(schema)) => {
+                        validate_value(schema, value, &path)?
+                    }
+                    (None, None) => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(min) = limit("minItems") {
+                anyhow::ensure!(
+                    items.len() as u64 >= min,
+                    "{path} must have at least {min} items"
+                );
+            }
+
+            if let Some(max) = limit("maxItems") {
+                anyhow::ensure!(
+                    items.len() as u64 <= max,
+                    "{path} must have at most {max} items"
+                );
+            }
+
+            if let Some(schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate_value(schema, item, &format!("{path}/{i}"))?;
+                }
+            }
+        }
+        Value::String(string) => {
+            let len = string.chars().count() as u64;
+
+            if let Some(min) = limit("minLength") {
+                anyhow::ensure!(len >= min, "{path} must have at least {min} characters");
+            }
+
+            if let Some(max) = limit("maxLength") {
+                anyhow::ensure!(len <= max, "{path} must have at most {max} characters");
+            }
+        }
+        Value::Number(number) => {
+            let number = number.as_f64().unwrap_or_default();
+
+            if let Some(min) = bound("minimum") {
+                anyhow::ensure!(number >= min, "{path} must be at least {min}");
+            }
+
+            if let Some(max) = bound("maximum") {
+                anyhow::ensure!(number <= max, "{path} must be at most {max}");
+            }
+
+            if let Some(min) = bound("exclusiveMinimum") {
+                anyhow::ensure!(number > min, "{path} must be greater than {min}");
+            }
+
+            if let Some(max) = bound("exclusiveMaximum") {
+                anyhow::ensure!(number < max, "{path} must be less than {max}");
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
@@ -0,0 +1,173 @@
+use crate::client::{
+    diagnostics::{self, Diagnostics, ErrorEvent},
+    twin::Twin,
+    AuthenticationStatus, ConnectionStatusContext, IotHubClient, TraceId, Transport,
+};
+use anyhow::{Context, Result};
+use azure_iot_sdk_sys::*;
+use log::{debug, error, info};
+use serde_json::json;
+use std::{
+    ffi::{c_void, CString},
+    sync::{Arc, Mutex},
+};
+
+/// settings of an additional hub selected telemetry is published to
+#[derive(Clone)]
+pub(crate) struct SecondaryHubSetting {
+    pub(crate) name: String,
+    pub(crate) connection_string: String,
+    pub(crate) outputs: Vec<String>,
+}
+
+impl std::fmt::Debug for SecondaryHubSetting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SecondaryHubSetting")
+            .field("name", &self.name)
+            .field(
+                "connection_string",
+                &diagnostics::redact(&self.connection_string),
+            )
+            .field("outputs", &self.outputs)
+            .finish()
+    }
+}
+
+/// connection to an additional hub with its own connection state tracking
+pub(crate) struct SecondaryHub {
+    pub(crate) name: String,
+    connection_string: String,
+    outputs: Vec<CString>,
+    pub(crate) twin: Option<Box<dyn Twin>>,
+    connection_status_context: Box<ConnectionStatusContext>,
+}
+
+impl SecondaryHub {
+    pub(crate) fn new(setting: &SecondaryHubSetting) -> Result<Self> {
+        IotHubClient::validate_connection_string(&setting.connection_string)
+            .with_context(|| format!("secondary hub {}", setting.name))?;
+
+        Ok(SecondaryHub {
+            name: setting.name.clone(),
+            connection_string: setting.connection_string.clone(),
+            outputs: setting
+                .outputs
+                .iter()
+                .map(|o| CString::new(o.as_str()))
+                .collect::<Result<Vec<CString>, _>>()?,
+            twin: None,
+            connection_status_context: Box::new(ConnectionStatusContext {
+                observer: None,
+                retry: None,
+                diagnostics: Arc::new(Mutex::new(Diagnostics::default())),
+                sas_token_expired: None,
+                status_changed: None,
+                latency_profile: None,
+                #[cfg(feature = "systemd")]
+                systemd_readiness: None,
+            }),
+        })
+    }
+
+    /// creates the underlying handle and observes its connection status
+    pub(crate) fn create_twin(&mut self, transport: Transport) -> Result<Box<dyn Twin>> {
+        info!("secondary hub {}: create", self.name);
+
+        let twin =
+            IotHubClient::create_twin_from_connection_string(&self.connection_string, transport)?;
+
+        if let Err(e) = twin.set_connection_status_callback(
+            Some(IotHubClient::c_connection_status_callback),
+            self.connection_status_context.as_mut() as *mut ConnectionStatusContext as *mut c_void,
+        ) {
+            let mut twin = twin;
+            twin.destroy();
+            return Err(e);
+        }
+
+        Ok(twin)
+    }
+
+    pub(crate) fn publishes(&self, output: &CString) -> bool {
+        self.outputs.contains(output)
+    }
+
+    pub(crate) fn send(
+        &self,
+        handle: IOTHUB_MESSAGE_HANDLE,
+        queue: CString,
+        trace_id: TraceId,
+    ) -> Result<()> {
+        let Some(twin) = self.twin.as_deref() else {
+            anyhow::bail!("secondary hub {} not connected", self.name);
+        };
+
+        debug!("secondary hub {}: send_d2c_message({trace_id})", self.name);
+
+        twin.send_event_to_output_async(
+            handle,
+            queue,
+            Some(SecondaryHub::c_confirmation_callback),
+            Box::into_raw(Box::new((
+                self.connection_status_context.diagnostics.clone(),
+                trace_id,
+            ))) as *mut c_void,
+        )
+    }
+
+    pub(crate) fn connection_status(&self) -> Option<AuthenticationStatus> {
+        match self.connection_status_context.diagnostics.lock() {
+            Ok(diagnostics) => diagnostics.last_connection_status(),
+            Err(poisoned) => poisoned.into_inner().last_connection_status(),
+        }
+    }
+
+    pub(crate) fn support_bundle(&self) -> serde_json::Value {
+        let diagnostics = match self.connection_status_context.diagnostics.lock() {
+            Ok(diagnostics) => diagnostics,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let bundle = json!({
+            "name": self.name,
+            "outputs": self.outputs.iter().map(|o| o.to_string_lossy()).collect::<Vec<_>>(),
+            "connected": self.twin.is_some(),
+            "connection_history": diagnostics.connection_history_json(),
+            "last_errors": diagnostics.last_errors_json(),
+        });
+
+        bundle
+    }
+
+    pub(crate) fn destroy(&mut self) {
+        if let Some(mut twin) = self.twin.take() {
+            info!("secondary hub {}: destroy", self.name);
+            twin.destroy();
+        }
+
+        if let Ok(mut diagnostics) = self.connection_status_context.diagnostics.lock() {
+            diagnostics.reset_connection_status();
+        }
+    }
+
+    unsafe extern "C" fn c_confirmation_callback(
+        status: IOTHUB_CLIENT_CONFIRMATION_RESULT,
+        context: *mut std::ffi::c_void,
+    ) {
+        let (diagnostics, trace_id) =
+            *Box::from_raw(context as *mut (Arc<Mutex<Diagnostics>>, TraceId));
+
+        if status == IOTHUB_CLIENT_CONFIRMATION_RESULT_TAG_IOTHUB_CLIENT_CONFIRMATION_OK {
+            debug!("secondary hub confirmation({trace_id}): successfully received");
+            return;
+        }
+
+        if let Ok(mut diagnostics) = diagnostics.lock() {
+            diagnostics.report(ErrorEvent::SendFailure(format!(
+                "secondary hub confirmation({trace_id}): failed with {status}"
+            )));
+        }
+
+        error!("secondary hub confirmation({trace_id}): failed with {status}");
+    }
+}
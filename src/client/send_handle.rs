@@ -0,0 +1,37 @@
+use crate::client::{trace_id::Trace, IotHubClient, TraceId};
+use anyhow::Result;
+
+/// Handle of a D2C message sent by [`IotHubClient::send_d2c_message_cancellable`].
+///
+/// Cancelling removes the message from the internal queues, i.e. the suspend buffer and the offline store, and
+/// abandons the wait for its confirmation, e.g. if the data became stale like a superseded status update. A
+/// message already handed over to azure-sdk-c cannot be recalled and may still be delivered. Of a message
+/// split into chunks all chunks are cancelled.
+pub struct SendHandle<'a> {
+    client: &'a IotHubClient,
+    // traces of the message or of all its chunks, the first one is the trace of the message
+    traces: Vec<Trace>,
+}
+
+impl<'a> SendHandle<'a> {
+    pub(crate) fn new(client: &'a IotHubClient, traces: Vec<Trace>) -> Self {
+        SendHandle { client, traces }
+    }
+
+    /// trace id of the message, i.e. of its first chunk if it is split into chunks
+    pub fn trace_id(&self) -> TraceId {
+        self.traces[0].id
+    }
+
+    /// Cancel the message. Returns false if there was nothing to cancel, e.g. since the message is already
+    /// confirmed.
+    pub fn cancel(self) -> Result<bool> {
+        let mut cancelled = false;
+
+        for trace in self.traces {
+            cancelled |= self.client.cancel_d2c(trace)?;
+        }
+
+        Ok(cancelled)
+    }
+}
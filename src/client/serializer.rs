@@ -0,0 +1,206 @@
+use crate::client::{
+    event_hubs::{CONTENT_ENCODING_UTF8, CONTENT_TYPE_JSON},
+    IotMessage, IotMessageBuilder,
+};
+use anyhow::Result;
+use serde_json::Value;
+use std::{collections::HashMap, sync::Arc};
+
+/// name of the format of [`JsonSerializer`]
+pub static FORMAT_JSON: &str = "json";
+#[cfg(feature = "cbor")]
+/// name of the format of [`CborSerializer`]
+pub static FORMAT_CBOR: &str = "cbor";
+#[cfg(feature = "msgpack")]
+/// name of the format of [`MessagePackSerializer`]
+pub static FORMAT_MSGPACK: &str = "msgpack";
+
+/// Serializes message bodies into a wire format, registered in a [`SerializerRegistry`]
+pub trait Serializer: Send + Sync {
+    /// content type set for serialized bodies
+    fn content_type(&self) -> &str;
+    /// content encoding set for serialized bodies, if any
+    fn content_encoding(&self) -> Option<&str> {
+        None
+    }
+    /// serializes `body`
+    fn serialize(&self, body: &Value) -> Result<Vec<u8>>;
+}
+
+/// JSON encoded as utf-8, so that bodies can be queried by iothub message routing
+#[derive(Debug, Default)]
+pub struct JsonSerializer;
+
+impl Serializer for JsonSerializer {
+    fn content_type(&self) -> &str {
+        CONTENT_TYPE_JSON
+    }
+
+    fn content_encoding(&self) -> Option<&str> {
+        Some(CONTENT_ENCODING_UTF8)
+    }
+
+    fn serialize(&self, body: &Value) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(body)?)
+    }
+}
+
+#[cfg(feature = "cbor")]
+/// [CBOR](https://www.rfc-editor.org/rfc/rfc8949), e.g. for constrained links.<br>
+/// ***Note***: this serializer is only available with "cbor" feature enabled.
+#[derive(Debug, Default)]
+pub struct CborSerializer;
+
+#[cfg(feature = "cbor")]
+impl Serializer for CborSerializer {
+    fn content_type(&self) -> &str {
+        "application/cbor"
+    }
+
+    fn serialize(&self, body: &Value) -> Result<Vec<u8>> {
+        let mut buf = vec![];
+
+        ciborium::into_writer(body, &mut buf)
+            .map_err(|e| anyhow::anyhow!("cannot serialize body as cbor: {e}"))?;
+
+        Ok(buf)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+/// [MessagePack](https://msgpack.org), e.g. for constrained links.<br>
+/// ***Note***: this serializer is only available with "msgpack" feature enabled.
+#[derive(Debug, Default)]
+pub struct MessagePackSerializer;
+
+#[cfg(feature = "msgpack")]
+impl Serializer for MessagePackSerializer {
+    fn content_type(&self) -> &str {
+        "application/msgpack"
+    }
+
+    fn serialize(&self, body: &Value) -> Result<Vec<u8>> {
+        Ok(rmp_serde::to_vec(body)?)
+    }
+}
+
+/// Serializers selectable by format name, with a default format used if no format is given.
+/// Content type and content encoding of messages are set according to the selected serializer.
+/// See [`crate::client::IotHubClientBuilder::serializers`].
+/// ```rust
+/// use azure_iot_sdk::client::*;
+/// use serde_json::{json, Value};
+/// use std::sync::Arc;
+///
+/// struct Csv;
+///
+/// impl Serializer for Csv {
+///     fn content_type(&self) -> &str {
+///         "text/csv"
+///     }
+///
+///     fn serialize(&self, body: &Value) -> anyhow::Result<Vec<u8>> {
+///         let values: Vec<String> = body
+///             .as_array()
+///             .ok_or_else(|| anyhow::anyhow!("body must be an array"))?
+///             .iter()
+///             .map(Value::to_string)
+///             .collect();
+///
+///         Ok(values.join(",").into_bytes())
+///     }
+/// }
+///
+/// let registry = SerializerRegistry::new()
+///     .register("csv", Arc::new(Csv))
+///     .default_format("csv");
+///
+/// let msg = registry.message(None, &json!([1, 2, 3])).unwrap().build().unwrap();
+/// assert_eq!(&msg.body[..], b"1,2,3");
+///
+/// let msg = registry.message(Some("json"), &json!([1, 2, 3])).unwrap().build().unwrap();
+/// assert_eq!(&msg.body[..], b"[1,2,3]");
+/// ```
+#[derive(Clone)]
+pub struct SerializerRegistry {
+    serializers: HashMap<String, Arc<dyn Serializer>>,
+    default_format: String,
+}
+
+impl Default for SerializerRegistry {
+    fn default() -> Self {
+        SerializerRegistry::new()
+    }
+}
+
+impl std::fmt::Debug for SerializerRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut formats: Vec<&String> = self.serializers.keys().collect();
+        formats.sort();
+
+        f.debug_struct("SerializerRegistry")
+            .field("formats", &formats)
+            .field("default_format", &self.default_format)
+            .finish()
+    }
+}
+
+impl SerializerRegistry {
+    /// Get a registry with all built-in serializers registered and "json" as default format
+    pub fn new() -> Self {
+        let registry = SerializerRegistry {
+            serializers: HashMap::new(),
+            default_format: FORMAT_JSON.to_string(),
+        }
+        .register(FORMAT_JSON, Arc::new(JsonSerializer));
+
+        #[cfg(feature = "cbor")]
+        let registry = registry.register(FORMAT_CBOR, Arc::new(CborSerializer));
+
+        #[cfg(feature = "msgpack")]
+        let registry = registry.register(FORMAT_MSGPACK, Arc::new(MessagePackSerializer));
+
+        registry
+    }
+
+    /// Register `serializer` as `format`, an already registered serializer is replaced
+    pub fn register(mut self, format: &str, serializer: Arc<dyn Serializer>) -> Self {
+        self.serializers.insert(format.to_string(), serializer);
+        self
+    }
+
+    /// Set the format used if no format is given
+    pub fn default_format(mut self, format: &str) -> Self {
+        self.default_format = format.to_string();
+        self
+    }
+
+    /// validates that a serializer is registered for the default format
+    pub(crate) fn validate(&self) -> Result<()> {
+        anyhow::ensure!(
+            self.serializers.contains_key(&self.default_format),
+            "no serializer registered for default format {}",
+            self.default_format
+        );
+
+        Ok(())
+    }
+
+    /// Get a message builder with `body` serialized by the serializer of `format` or of the default format.
+    /// Content type and content encoding are set according to the serializer.
+    pub fn message(&self, format: Option<&str>, body: &Value) -> Result<IotMessageBuilder> {
+        let format = format.unwrap_or(&self.default_format);
+        let Some(serializer) = self.serializers.get(format) else {
+            anyhow::bail!("no serializer registered for format {format}");
+        };
+
+        let builder = IotMessage::builder()
+            .set_body(serializer.serialize(body)?)
+            .set_content_type(serializer.content_type());
+
+        Ok(match serializer.content_encoding() {
+            Some(encoding) => builder.set_content_encoding(encoding),
+            None => builder,
+        })
+    }
+}
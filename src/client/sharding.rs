@@ -0,0 +1,56 @@
+use crate::client::IotMessage;
+use std::{
+    collections::hash_map::DefaultHasher,
+    ffi::CString,
+    hash::{Hash, Hasher},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Strategy used to distribute messages across sharded output queues
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ShardingStrategy {
+    /// distribute messages in turn
+    RoundRobin,
+    /// select output queue by hash of the given message property value. messages without that property
+    /// are sent to the first output queue.
+    PropertyHash(String),
+}
+
+#[derive(Debug)]
+pub(crate) struct OutputSharding {
+    outputs: Vec<CString>,
+    strategy: ShardingStrategy,
+    next: AtomicUsize,
+}
+
+impl OutputSharding {
+    pub(crate) fn new(outputs: Vec<CString>, strategy: ShardingStrategy) -> Self {
+        OutputSharding {
+            outputs,
+            strategy,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn select(&self, message: &IotMessage) -> CString {
+        let index = match &self.strategy {
+            ShardingStrategy::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed),
+            ShardingStrategy::PropertyHash(key) => {
+                let value = CString::new(crate::client::message::urlencode(key))
+                    .ok()
+                    .and_then(|key| message.properties.get(&key));
+
+                match value {
+                    Some(value) => {
+                        let mut hasher = DefaultHasher::new();
+                        value.hash(&mut hasher);
+                        hasher.finish() as usize
+                    }
+                    None => 0,
+                }
+            }
+        };
+
+        self.outputs[index % self.outputs.len()].clone()
+    }
+}
@@ -0,0 +1,156 @@
+use crate::client::IotHubClient;
+use anyhow::Result;
+use log::{info, warn};
+use tokio::{sync::mpsc, time::Duration};
+
+/// Stages of a shutdown orchestrated by [`ShutdownCoordinator`], signaled in this order
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ShutdownStage {
+    /// new D2C messages and reported properties are rejected
+    SendsStopped,
+    /// pending confirmations are flushed, `pending` confirmations didn't arrive until the deadline
+    ConfirmationsFlushed {
+        /// number of confirmations still pending after the deadline
+        pending: usize,
+    },
+    /// the final "going offline" report was sent, `confirmed` is false if it wasn't confirmed until the deadline
+    OfflineReported {
+        /// true if the report was confirmed by iothub
+        confirmed: bool,
+    },
+    /// the connection to iothub is dropped
+    Disconnected,
+    /// the client is destroyed
+    Destroyed,
+}
+
+/// Sender used to signal the progress of a shutdown as [`ShutdownStage`]
+pub type ShutdownObserver = mpsc::Sender<ShutdownStage>;
+
+/// Sequences the shutdown of an [`IotHubClient`] for devices that must report planned downtime:
+/// stop accepting new sends → flush pending confirmations within a deadline → send a final "going offline"
+/// reported patch → disconnect → destroy. Every stage is signaled as [`ShutdownStage`].
+/// ```rust, no_run
+/// use azure_iot_sdk::client::*;
+/// use serde_json::json;
+/// use std::time::Duration;
+/// use tokio::sync::mpsc;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     #[cfg(feature = "edge_client")]
+///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+///     #[cfg(feature = "device_client")]
+///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+///     #[cfg(feature = "module_client")]
+///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+///
+///     let (tx_progress, mut rx_progress) = mpsc::channel(10);
+///
+///     tokio::spawn(async move {
+///         while let Some(stage) = rx_progress.recv().await {
+///             println!("shutdown: {stage:?}");
+///         }
+///     });
+///
+///     ShutdownCoordinator::new()
+///         .flush_deadline(Duration::from_secs(10))
+///         .offline_report(json!({"status": "planned downtime"}))
+///         .observe_progress(tx_progress)
+///         .run(client)
+///         .await
+///         .unwrap();
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct ShutdownCoordinator {
+    flush_deadline: Option<Duration>,
+    offline_report: Option<serde_json::Value>,
+    tx_progress: Option<ShutdownObserver>,
+}
+
+impl ShutdownCoordinator {
+    /// Get a coordinator without offline report that flushes within the confirmation timeout of the client
+    pub fn new() -> Self {
+        ShutdownCoordinator::default()
+    }
+
+    /// Set the deadline pending confirmations and the offline report are waited for.
+    /// Default is the confirmation timeout of the client.
+    pub fn flush_deadline(mut self, deadline: Duration) -> Self {
+        self.flush_deadline = Some(deadline);
+        self
+    }
+
+    /// Set the reported properties patch sent as final "going offline" report
+    pub fn offline_report(mut self, reported: serde_json::Value) -> Self {
+        self.offline_report = Some(reported);
+        self
+    }
+
+    /// Set the observer the progress of the shutdown is signaled to
+    pub fn observe_progress(mut self, tx_progress: ShutdownObserver) -> Self {
+        self.tx_progress = Some(tx_progress);
+        self
+    }
+
+    /// Run all stages of the shutdown and destroy `client`. All stages are run even if a stage fails.
+    /// An error is returned if confirmations were still pending after the deadline or the offline report
+    /// wasn't confirmed.
+    pub async fn run(self, mut client: IotHubClient) -> Result<()> {
+        let deadline = self
+            .flush_deadline
+            .unwrap_or(Duration::from_secs(client.confirmation_timeout_secs));
+        let mut errors = vec![];
+
+        info!("shutdown: stop sends");
+        client.sends_stopped.set(true);
+        self.progress(ShutdownStage::SendsStopped).await;
+
+        let pending = client.flush_confirmations(deadline).await;
+        if pending > 0 {
+            errors.push(format!("{pending} confirmations still pending"));
+        }
+        self.progress(ShutdownStage::ConfirmationsFlushed { pending })
+            .await;
+
+        if let Some(reported) = self.offline_report.clone() {
+            info!("shutdown: send offline report");
+
+            let confirmed = match client.send_reported(reported, client.trace_id.next()) {
+                Ok(_) => client.flush_confirmations(deadline).await == 0,
+                Err(e) => {
+                    warn!("shutdown: cannot send offline report: {e}");
+                    false
+                }
+            };
+
+            if !confirmed {
+                errors.push("offline report not confirmed".to_string());
+            }
+            self.progress(ShutdownStage::OfflineReported { confirmed })
+                .await;
+        }
+
+        client.abort_confirmations().await;
+        client.disconnect();
+        self.progress(ShutdownStage::Disconnected).await;
+
+        drop(client);
+        self.progress(ShutdownStage::Destroyed).await;
+
+        if !errors.is_empty() {
+            anyhow::bail!("shutdown: {}", errors.join("; "));
+        }
+
+        Ok(())
+    }
+
+    async fn progress(&self, stage: ShutdownStage) {
+        if let Some(tx) = &self.tx_progress {
+            if tx.send(stage).await.is_err() {
+                warn!("shutdown: progress observer dropped");
+            }
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use crate::client::{IotHubClient, IotMessage, TraceId};
+use anyhow::Result;
+use futures::{future::LocalBoxFuture, ready, FutureExt, Sink};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// [`Sink`] of D2C messages sending into an [`IotHubClient`], see [`IotHubClient::d2c_sink`].
+///
+/// Each message is handed over to the client by [`IotHubClient::send_d2c_message`] before the sink accepts the
+/// next one, so that the limit set by [`crate::client::IotHubClientBuilder::max_in_flight`] slows down the
+/// pipeline feeding the sink. Flushing waits until the last message is handed over, not for its confirmation.
+/// Since the sink borrows the client, it is neither `Send` nor `'static` and must be polled on the task owning
+/// the client.
+pub struct D2cSink<'a> {
+    client: &'a IotHubClient,
+    pending: Option<LocalBoxFuture<'a, Result<TraceId>>>,
+    last_trace_id: Option<TraceId>,
+}
+
+impl<'a> D2cSink<'a> {
+    pub(crate) fn new(client: &'a IotHubClient) -> Self {
+        D2cSink {
+            client,
+            pending: None,
+            last_trace_id: None,
+        }
+    }
+
+    /// trace id of the last message handed over, if any
+    pub fn last_trace_id(&self) -> Option<TraceId> {
+        self.last_trace_id
+    }
+
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if let Some(pending) = self.pending.as_mut() {
+            let result = ready!(pending.poll_unpin(cx));
+
+            self.pending = None;
+            self.last_trace_id = Some(result?);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Sink<IotMessage> for D2cSink<'_> {
+    type Error = anyhow::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut().poll_pending(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, message: IotMessage) -> Result<()> {
+        let sink = self.get_mut();
+
+        anyhow::ensure!(
+            sink.pending.is_none(),
+            "d2c sink: start_send called without poll_ready"
+        );
+
+        sink.pending = Some(sink.client.send_d2c_message(message).boxed_local());
+
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut().poll_pending(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut().poll_pending(cx)
+    }
+}
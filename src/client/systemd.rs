@@ -0,0 +1,77 @@
+use crate::client::{diagnostics::Diagnostics, AuthenticationStatus};
+use log::{debug, info, warn};
+use sd_notify::NotifyState;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// notifies systemd that the service is ready once the client is authenticated for the first time
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Readiness {
+    notified: bool,
+}
+
+impl Readiness {
+    pub(crate) fn authenticated(&mut self) {
+        if self.notified {
+            return;
+        }
+
+        info!("notify systemd: ready");
+
+        if let Err(e) = sd_notify::notify(false, &[NotifyState::Ready]) {
+            warn!("cannot notify systemd: {e}");
+        }
+
+        self.notified = true;
+    }
+}
+
+/// pets the systemd watchdog while the client is healthy, i.e. authenticated and without confirmations
+/// pending longer than `confirmation_timeout`. returns if the watchdog isn't enabled for the service.
+pub(crate) async fn run_watchdog(
+    diagnostics: Arc<Mutex<Diagnostics>>,
+    confirmation_timeout: Duration,
+) {
+    let mut usec = 0;
+
+    if !sd_notify::watchdog_enabled(false, &mut usec) {
+        debug!("systemd watchdog not enabled");
+        return;
+    }
+
+    // pet twice per watchdog interval as recommended by systemd
+    let interval = Duration::from_micros(usec / 2);
+
+    loop {
+        tokio::time::sleep(interval).await;
+
+        let (status, pending) = match diagnostics.lock() {
+            Ok(diagnostics) => (
+                diagnostics.last_connection_status(),
+                diagnostics.pending_confirmations(),
+            ),
+            Err(poisoned) => {
+                let diagnostics = poisoned.into_inner();
+                (
+                    diagnostics.last_connection_status(),
+                    diagnostics.pending_confirmations(),
+                )
+            }
+        };
+
+        if status != Some(AuthenticationStatus::Authenticated)
+            || pending
+                .oldest_age
+                .is_some_and(|age| age > confirmation_timeout)
+        {
+            warn!("systemd watchdog: unhealthy, status: {status:?}, {pending:?}");
+            continue;
+        }
+
+        if let Err(e) = sd_notify::notify(false, &[NotifyState::Watchdog]) {
+            warn!("cannot pet systemd watchdog: {e}");
+        }
+    }
+}
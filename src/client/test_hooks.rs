@@ -0,0 +1,161 @@
+use crate::client::{
+    platform::PlatformRef, AuthenticationStatus, IotHubClient, IotMessage, UnauthenticatedReason,
+};
+use anyhow::Result;
+use azure_iot_sdk_sys::*;
+use std::ffi::c_void;
+
+impl IotHubClient {
+    /// Call this function to simulate a connection status as if it was signaled by azure-sdk-c.
+    /// All consumers of the connection status, e.g. the connection state observer, are triggered.<br>
+    /// ***Note***: this function is only available with "test_hooks" feature enabled.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     client
+    ///         .simulate_connection_status(AuthenticationStatus::Authenticated)
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub async fn simulate_connection_status(&mut self, status: AuthenticationStatus) -> Result<()> {
+        let (connection_status, status_reason) = match status {
+            AuthenticationStatus::Authenticated => (
+                IOTHUB_CLIENT_CONNECTION_STATUS_TAG_IOTHUB_CLIENT_CONNECTION_AUTHENTICATED,
+                IOTHUB_CLIENT_CONNECTION_STATUS_REASON_TAG_IOTHUB_CLIENT_CONNECTION_OK,
+            ),
+            AuthenticationStatus::Unauthenticated(reason) => (
+                IOTHUB_CLIENT_CONNECTION_STATUS_TAG_IOTHUB_CLIENT_CONNECTION_UNAUTHENTICATED,
+                reason.raw(),
+            ),
+        };
+
+        // the callback blocks on sending to observers, thus it must not run on an async worker thread.
+        // the context outlives the blocking task since self is borrowed until it's joined.
+        let context = self.connection_status_context.as_mut() as *mut _ as usize;
+
+        tokio::task::spawn_blocking(move || unsafe {
+            IotHubClient::c_connection_status_callback(
+                connection_status,
+                status_reason,
+                context as *mut c_void,
+            )
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Call this function to simulate an expired SAS token without waiting for the token lifetime to elapse.
+    /// Consumers are signaled [`UnauthenticatedReason::ExpiredSasToken`] as if reported by azure-sdk-c.<br>
+    /// ***Note***: this function is only available with "test_hooks" feature enabled.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     #[cfg(feature = "edge_client")]
+    ///     let mut client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let mut client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let mut client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     client.simulate_sas_token_expiry().await.unwrap();
+    /// }
+    /// ```
+    pub async fn simulate_sas_token_expiry(&mut self) -> Result<()> {
+        self.simulate_connection_status(AuthenticationStatus::Unauthenticated(
+            UnauthenticatedReason::ExpiredSasToken,
+        ))
+        .await
+    }
+
+    /// Call this function to get the number of clients currently holding the azure-sdk-c platform
+    /// initialized by `IoTHub_Init()`. It is initialized by the first client creating a handle and
+    /// deinitialized by `IoTHub_Deinit()` when the last of these clients is dropped.<br>
+    /// ***Note***: this function is only available with "test_hooks" feature enabled.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     for _ in 0..3 {
+    ///         #[cfg(feature = "edge_client")]
+    ///         let client = IotHubClient::builder().build_edge_client().unwrap();
+    ///         #[cfg(feature = "device_client")]
+    ///         let client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///         #[cfg(feature = "module_client")]
+    ///         let client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///         assert_eq!(IotHubClient::platform_references(), 1);
+    ///
+    ///         drop(client);
+    ///
+    ///         assert_eq!(IotHubClient::platform_references(), 0);
+    ///     }
+    /// }
+    /// ```
+    pub fn platform_references() -> usize {
+        PlatformRef::count()
+    }
+
+    /// Call this function to get the number of times the azure-sdk-c platform was initialized by
+    /// `IoTHub_Init()`, e.g. to check that it is initialized again after the last client was dropped.<br>
+    /// ***Note***: this function is only available with "test_hooks" feature enabled.
+    /// ```rust, no_run
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let initializations = IotHubClient::platform_initializations();
+    ///
+    ///     #[cfg(feature = "edge_client")]
+    ///     let client = IotHubClient::builder().build_edge_client().unwrap();
+    ///     #[cfg(feature = "device_client")]
+    ///     let client = IotHubClient::builder().build_device_client("my-connection-string").unwrap();
+    ///     #[cfg(feature = "module_client")]
+    ///     let client = IotHubClient::builder().build_module_client("my-connection-string").unwrap();
+    ///
+    ///     assert_eq!(IotHubClient::platform_initializations(), initializations + 1);
+    /// }
+    /// ```
+    pub fn platform_initializations() -> usize {
+        PlatformRef::initializations()
+    }
+}
+
+impl IotMessage {
+    /// Call this function to get the message as a consumer receives it, i.e. its properties are set on an
+    /// azure-sdk-c message handle and read back from it, e.g. in order to check the mapping of
+    /// [`crate::client::event_hubs`] without iothub.<br>
+    /// ***Note***: this function is only available with "test_hooks" feature enabled.
+    /// ```rust
+    /// use azure_iot_sdk::client::*;
+    ///
+    /// let msg = IotMessage::builder()
+    ///     .set_body(vec![])
+    ///     .set_property("room name", "kitchen")
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let received = msg.simulate_wire_round_trip().unwrap();
+    ///
+    /// assert_eq!(event_hubs::application_properties(&received).unwrap()["room name"], "kitchen");
+    /// ```
+    pub fn simulate_wire_round_trip(mut self) -> Result<IotMessage> {
+        let handle = self.create_outgoing_handle()?;
+
+        // the handle is destroyed when self is dropped, thus the received message must not keep it
+        Ok(IotMessage::from_incoming_handle(handle, vec![])?.detached())
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    cell::RefCell,
+    time::{Duration, Instant},
+};
+
+// share of the maximum rate regained per succeeded confirmation
+static RATE_INCREASE: f64 = 0.05;
+
+/// Adaptive rate limit of D2C messages, see [`crate::client::IotHubClientBuilder::adaptive_rate_limit`].
+///
+/// Since azure-sdk-c doesn't signal throttling by iothub, e.g. if the quota of a unit is exhausted, a number of
+/// consecutive failed or timed out confirmations is taken as throttling. Then the rate is halved, but not below
+/// the minimum rate. Each succeeded confirmation raises the rate again by 5% of the maximum rate (AIMD).
+/// ```rust
+/// use azure_iot_sdk::client::*;
+///
+/// let limit = AdaptiveRateLimit::new(100.0).slow_down_to(0.5).after_failures(5);
+///
+/// assert_eq!(limit.max_rate(), 100.0);
+/// assert_eq!(limit.min_rate(), 0.5);
+/// assert_eq!(limit.failures(), 5);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AdaptiveRateLimit {
+    max_rate: f64,
+    min_rate: f64,
+    failures: u32,
+}
+
+impl AdaptiveRateLimit {
+    /// Get a limit of at most `max_rate` messages per second, slowed down to 1% of it at most after 3
+    /// consecutive failed or timed out confirmations
+    pub fn new(max_rate: f64) -> Self {
+        AdaptiveRateLimit {
+            max_rate,
+            min_rate: max_rate / 100.0,
+            failures: 3,
+        }
+    }
+
+    /// Set the rate in messages per second the limit doesn't drop below, which is clamped to the maximum rate
+    pub fn slow_down_to(mut self, min_rate: f64) -> Self {
+        self.min_rate = min_rate.min(self.max_rate);
+        self
+    }
+
+    /// Set the number of consecutive failed or timed out confirmations taken as throttling, at least 1
+    pub fn after_failures(mut self, failures: u32) -> Self {
+        self.failures = failures.max(1);
+        self
+    }
+
+    /// maximum rate in messages per second
+    pub fn max_rate(&self) -> f64 {
+        self.max_rate
+    }
+
+    /// minimum rate in messages per second
+    pub fn min_rate(&self) -> f64 {
+        self.min_rate
+    }
+
+    /// consecutive failed or timed out confirmations taken as throttling
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// true if both rates are positive and finite
+    pub(crate) fn is_valid(&self) -> bool {
+        self.max_rate.is_finite() && self.min_rate > 0.0
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    rate: f64,
+    next_send: Option<Instant>,
+    // totals of (succeeded, not succeeded) confirmations already adapted to
+    seen: (u64, u64),
+    // consecutive failed or timed out confirmations
+    streak: u64,
+}
+
+/// paces D2C messages at a rate adapted to their confirmations
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    limit: AdaptiveRateLimit,
+    state: RefCell<State>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit: AdaptiveRateLimit) -> Self {
+        RateLimiter {
+            limit,
+            state: RefCell::new(State {
+                rate: limit.max_rate,
+                next_send: None,
+                seen: (0, 0),
+                streak: 0,
+            }),
+        }
+    }
+
+    /// current rate in messages per second
+    pub(crate) fn rate(&self) -> f64 {
+        self.state.borrow().rate
+    }
+
+    /// adapts the rate to the `totals` of (succeeded, not succeeded) confirmations. returns the decreased
+    /// rate if throttling is detected.
+    pub(crate) fn adapt(&self, totals: (u64, u64)) -> Option<f64> {
+        let mut state = self.state.borrow_mut();
+        let succeeded = totals.0.saturating_sub(state.seen.0);
+        let failed = totals.1.saturating_sub(state.seen.1);
+
+        state.seen = totals;
+
+        if succeeded > 0 {
+            // the order of new outcomes is unknown, thus new failures start a new streak
+            state.streak = 0;
+            state.rate = (state.rate + self.limit.max_rate * RATE_INCREASE * succeeded as f64)
+                .min(self.limit.max_rate);
+        }
+
+        state.streak += failed;
+
+        if failed == 0 || state.streak < u64::from(self.limit.failures) {
+            return None;
+        }
+
+        state.streak = 0;
+        state.rate = (state.rate / 2.0).max(self.limit.min_rate);
+
+        Some(state.rate)
+    }
+
+    /// reserves the next send slot at or after `now` and returns the time to wait for it
+    pub(crate) fn reserve(&self, now: Instant) -> Duration {
+        let mut state = self.state.borrow_mut();
+        let slot = state.next_send.map_or(now, |next| next.max(now));
+
+        state.next_send = Some(slot + Duration::from_secs_f64(1.0 / state.rate));
+
+        slot - now
+    }
+}
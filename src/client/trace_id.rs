@@ -0,0 +1,137 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+static TENANT_PREFIX_SHIFT: u32 = 56;
+static TIME_ORDERED_SHIFT: u32 = 16;
+static UUID_V7_TIMESTAMP_SHIFT: u32 = 80;
+static UUID_V7_COUNTER_SHIFT: u32 = 64;
+static UUID_V7_VERSION: u128 = 0x7 << 76;
+static UUID_V7_VARIANT: u128 = 0b10 << 62;
+static UUID_V7_COUNTER_MASK: u64 = 0xfff;
+static UUID_V7_RANDOM_MASK: u128 = (1 << 62) - 1;
+static NEXT_KEY: AtomicU64 = AtomicU64::new(0);
+
+/// Trace id of a D2C message or reported properties, see [`TraceIdStrategy`]
+pub type TraceId = u128;
+
+/// Strategy used to generate the trace ids of D2C messages and reported properties
+#[derive(Clone, Default)]
+pub enum TraceIdStrategy {
+    /// sequential trace ids starting at 0
+    #[default]
+    Sequential,
+    /// the given tenant prefix in the upper 8 bits of 64 bits followed by a 56 bit sequence
+    TenantPrefixed(u8),
+    /// milliseconds since unix epoch in the upper 48 bits of 64 bits followed by a 16 bit sequence, so that
+    /// trace ids are ordered by time
+    TimeOrdered,
+    /// UUIDv7 according to RFC 9562 with a 12 bit sequence as counter, which can be displayed by
+    /// `uuid::Uuid::from_u128(trace_id)`
+    UuidV7,
+    /// custom generator. generated trace ids must be unique as long as confirmations are pending.
+    Custom(Arc<dyn Fn() -> TraceId + Send + Sync>),
+}
+
+impl std::fmt::Debug for TraceIdStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraceIdStrategy::Sequential => f.write_str("Sequential"),
+            TraceIdStrategy::TenantPrefixed(prefix) => {
+                f.debug_tuple("TenantPrefixed").field(prefix).finish()
+            }
+            TraceIdStrategy::TimeOrdered => f.write_str("TimeOrdered"),
+            TraceIdStrategy::UuidV7 => f.write_str("UuidV7"),
+            TraceIdStrategy::Custom(_) => f.write_str("Custom"),
+        }
+    }
+}
+
+/// trace id of a D2C message or reported properties together with a key that is unique within the process,
+/// so that pending confirmations can be told apart even if a custom generator repeats trace ids
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub(crate) struct Trace {
+    key: u64,
+    pub(crate) id: TraceId,
+}
+
+impl Trace {
+    pub(crate) fn new(id: TraceId) -> Self {
+        Trace {
+            key: NEXT_KEY.fetch_add(1, Ordering::Relaxed),
+            id,
+        }
+    }
+}
+
+impl std::fmt::Display for Trace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.id)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct TraceIdGenerator {
+    strategy: TraceIdStrategy,
+    sequence: AtomicU64,
+    last: Mutex<TraceId>,
+}
+
+impl TraceIdGenerator {
+    pub(crate) fn new(strategy: TraceIdStrategy) -> Self {
+        TraceIdGenerator {
+            strategy,
+            sequence: AtomicU64::new(0),
+            last: Mutex::new(0),
+        }
+    }
+
+    pub(crate) fn next(&self) -> Trace {
+        let trace_id = match &self.strategy {
+            TraceIdStrategy::Sequential => self.sequence.fetch_add(1, Ordering::Relaxed) as TraceId,
+            TraceIdStrategy::TenantPrefixed(prefix) => {
+                let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+                ((*prefix as u64) << TENANT_PREFIX_SHIFT
+                    | sequence & ((1 << TENANT_PREFIX_SHIFT) - 1)) as TraceId
+            }
+            TraceIdStrategy::TimeOrdered => {
+                let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+                (unix_millis() << TIME_ORDERED_SHIFT | sequence & ((1 << TIME_ORDERED_SHIFT) - 1))
+                    as TraceId
+            }
+            TraceIdStrategy::UuidV7 => {
+                let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+                (unix_millis() as TraceId) << UUID_V7_TIMESTAMP_SHIFT
+                    | UUID_V7_VERSION
+                    | ((sequence & UUID_V7_COUNTER_MASK) as TraceId) << UUID_V7_COUNTER_SHIFT
+                    | UUID_V7_VARIANT
+                    | Uuid::new_v4().as_u128() & UUID_V7_RANDOM_MASK
+            }
+            TraceIdStrategy::Custom(generator) => generator(),
+        };
+
+        *self.last.lock().unwrap_or_else(|e| e.into_inner()) = trace_id;
+
+        Trace::new(trace_id)
+    }
+
+    /// last generated trace id
+    pub(crate) fn last(&self) -> TraceId {
+        *self.last.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// milliseconds since unix epoch truncated to 48 bits
+fn unix_millis() -> u64 {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+
+    millis & ((1 << 48) - 1)
+}
@@ -0,0 +1,73 @@
+use crate::client::Transport;
+use log::{debug, warn};
+use std::{
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+static PROBE_TIMEOUT_IN_SECS: u64 = 5;
+
+impl Transport {
+    /// port the transport connects to
+    pub(crate) fn port(&self) -> u16 {
+        match self {
+            Transport::Mqtt => 8883,
+            Transport::Amqp => 5671,
+            Transport::MqttWebSocket | Transport::AmqpWebSocket | Transport::Http => 443,
+        }
+    }
+
+    /// websocket transport tunneling the same protocol via port 443
+    pub(crate) fn websocket_fallback(&self) -> Option<Transport> {
+        match self {
+            Transport::Mqtt => Some(Transport::MqttWebSocket),
+            Transport::Amqp => Some(Transport::AmqpWebSocket),
+            _ => None,
+        }
+    }
+}
+
+/// Probes a tcp connection to `host` on the port of `primary` and returns the transport to be used,
+/// together with the information whether the websocket fallback was chosen.
+/// If no host is known, `primary` is used without probing.
+pub(crate) fn select(primary: Transport, host: Option<&str>) -> (Transport, bool) {
+    let Some(fallback) = primary.websocket_fallback() else {
+        return (primary, false);
+    };
+
+    let Some(host) = host else {
+        warn!("transport fallback: no host name known, use {primary:?} without probing");
+        return (primary, false);
+    };
+
+    if reachable(host, primary.port()) {
+        (primary, false)
+    } else {
+        warn!(
+            "transport fallback: {host}:{} not reachable, fall back to {fallback:?}",
+            primary.port()
+        );
+        (fallback, true)
+    }
+}
+
+fn reachable(host: &str, port: u16) -> bool {
+    let addrs = match (host, port).to_socket_addrs() {
+        Ok(addrs) => addrs,
+        Err(e) => {
+            // name resolution doesn't depend on the port, so the fallback can't help either
+            warn!("transport fallback: cannot resolve {host}: {e}");
+            return true;
+        }
+    };
+
+    addrs.into_iter().any(|addr| {
+        match TcpStream::connect_timeout(&addr, Duration::from_secs(PROBE_TIMEOUT_IN_SECS)) {
+            Ok(_) => true,
+            Err(e) => {
+                debug!("transport fallback: cannot connect to {addr}: {e}");
+                false
+            }
+        }
+    })
+}
@@ -1,6 +1,11 @@
 use anyhow::Result;
 use azure_iot_sdk_sys::*;
-use std::ffi::{CStr, CString};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::{c_void, CStr, CString},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+};
 
 #[cfg(any(feature = "module_client", feature = "edge_client"))]
 #[derive(Default, Debug)]
@@ -14,8 +19,14 @@ pub struct DeviceTwin {
     handle: Option<IOTHUB_DEVICE_CLIENT_HANDLE>,
 }
 
+// handles of the azure-sdk-c convenience layer are thread safe
+#[cfg(any(feature = "module_client", feature = "edge_client"))]
+unsafe impl Send for ModuleTwin {}
+#[cfg(feature = "device_client")]
+unsafe impl Send for DeviceTwin {}
+
 /// type of client twin
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
 pub enum ClientType {
     /// edge module twin client
     Edge,
@@ -25,6 +36,34 @@ pub enum ClientType {
     Device,
 }
 
+/// transport protocol used to connect to iothub
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Transport {
+    /// MQTT via port 8883
+    #[default]
+    Mqtt,
+    /// MQTT over websockets via port 443
+    MqttWebSocket,
+    /// AMQP via port 5671
+    Amqp,
+    /// AMQP over websockets via port 443
+    AmqpWebSocket,
+    /// HTTP via port 443. Only supported by device clients. Twin and direct methods aren't available.
+    Http,
+}
+
+impl Transport {
+    pub(crate) fn protocol(&self) -> IOTHUB_CLIENT_TRANSPORT_PROVIDER {
+        match self {
+            Transport::Mqtt => Some(MQTT_Protocol),
+            Transport::MqttWebSocket => Some(MQTT_WebSocket_Protocol),
+            Transport::Amqp => Some(AMQP_Protocol),
+            Transport::AmqpWebSocket => Some(AMQP_Protocol_over_WebSocketsTls),
+            Transport::Http => Some(HTTP_Protocol),
+        }
+    }
+}
+
 pub(crate) fn sdk_version_string() -> String {
     unsafe {
         let version_string = IoTHubClient_GetVersionString();
@@ -40,12 +79,247 @@ pub(crate) fn sdk_version_string() -> String {
     }
 }
 
-pub trait Twin {
+#[derive(Default)]
+struct SharedTwinState {
+    twin: Option<Box<dyn Twin>>,
+    // callback contexts owned by a handle created by SharedTwin::renew
+    contexts: Option<Box<dyn Send>>,
+    borrows: Arc<Borrows>,
+    closed: bool,
+    // names of callbacks installed on the handle by foreign code, see IotHubClient::raw_handle
+    foreign_callbacks: Vec<String>,
+}
+
+impl SharedTwinState {
+    /// foreign callbacks are gone with the handle they were installed on
+    fn forget_foreign_callbacks(&mut self) {
+        if !self.foreign_callbacks.is_empty() {
+            warn!(
+                "foreign callbacks {:?} are not installed on a new handle",
+                self.foreign_callbacks
+            );
+            self.foreign_callbacks.clear();
+        }
+    }
+
+    fn take(&mut self) -> Option<RetiredTwin> {
+        let contexts = self.contexts.take();
+        let borrows = std::mem::take(&mut self.borrows);
+
+        self.twin.take().map(|twin| RetiredTwin {
+            twin,
+            contexts,
+            borrows,
+        })
+    }
+}
+
+/// number of [`BorrowedTwin`]s of a handle
+#[derive(Debug, Default)]
+struct Borrows {
+    count: Mutex<usize>,
+    released: Condvar,
+}
+
+impl Borrows {
+    fn count(&self) -> MutexGuard<'_, usize> {
+        self.count
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// blocks until all borrows are released
+    fn wait(&self) {
+        let mut count = self.count();
+
+        while *count > 0 {
+            count = self
+                .released
+                .wait(count)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+}
+
+/// handle borrowed without locking it, see [`SharedTwin::borrow`]
+pub(crate) struct BorrowedTwin {
+    handle: *mut c_void,
+    borrows: Arc<Borrows>,
+}
+
+impl BorrowedTwin {
+    pub(crate) fn raw_handle(&self) -> *mut c_void {
+        self.handle
+    }
+}
+
+impl Drop for BorrowedTwin {
+    fn drop(&mut self) {
+        *self.borrows.count() -= 1;
+        self.borrows.released.notify_all();
+    }
+}
+
+/// handle taken out of a [`SharedTwin`] together with the callback contexts it owns
+pub(crate) struct RetiredTwin {
+    twin: Box<dyn Twin>,
+    // dropped not before the handle is destroyed, since callbacks might be called until then
+    contexts: Option<Box<dyn Send>>,
+    borrows: Arc<Borrows>,
+}
+
+impl RetiredTwin {
+    /// destroys the handle as soon as it isn't borrowed anymore. destroying joins the azure-sdk-c worker thread,
+    /// thus it must neither be called with the [`SharedTwin`] locked nor on an async worker.
+    pub(crate) fn destroy(mut self) {
+        self.borrows.wait();
+        self.twin.destroy();
+        drop(self.contexts.take());
+    }
+}
+
+/// handle locked against replacement, e.g. while it is used by foreign code
+pub(crate) struct LockedTwin<'a>(MutexGuard<'a, SharedTwinState>);
+
+impl LockedTwin<'_> {
+    pub(crate) fn raw_handle(&self) -> Option<*mut c_void> {
+        self.0.twin.as_deref().map(|twin| twin.raw_handle())
+    }
+
+    pub(crate) fn add_foreign_callback(&mut self, name: &str) {
+        if !self.0.foreign_callbacks.iter().any(|n| n == name) {
+            self.0.foreign_callbacks.push(name.to_string());
+        }
+    }
+}
+
+/// underlying handle that can be replaced by background tasks, e.g. credential renewal
+#[derive(Default)]
+pub(crate) struct SharedTwin(Mutex<SharedTwinState>);
+
+impl SharedTwin {
+    fn state(&self) -> MutexGuard<'_, SharedTwinState> {
+        match self.0.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// calls `f` with the connected handle
+    pub(crate) fn with<T>(&self, f: impl FnOnce(&dyn Twin) -> Result<T>) -> Result<T> {
+        match self.state().twin.as_deref() {
+            Some(twin) => f(twin),
+            None => anyhow::bail!("client not connected, call connect() first"),
+        }
+    }
+
+    pub(crate) fn is_connected(&self) -> bool {
+        self.state().twin.is_some()
+    }
+
+    /// locks the handle, so that it is neither replaced nor used by others until the lock is dropped
+    pub(crate) fn lock(&self) -> LockedTwin<'_> {
+        LockedTwin(self.state())
+    }
+
+    /// borrows the handle without locking it, e.g. for long running blocking calls that must not stall other
+    /// operations. the handle might be replaced meanwhile, but it isn't destroyed until the borrow is dropped.
+    pub(crate) fn borrow(&self) -> Result<BorrowedTwin> {
+        let state = self.state();
+        let Some(twin) = state.twin.as_deref() else {
+            anyhow::bail!("client not connected, call connect() first");
+        };
+
+        *state.borrows.count() += 1;
+
+        Ok(BorrowedTwin {
+            handle: twin.raw_handle(),
+            borrows: state.borrows.clone(),
+        })
+    }
+
+    pub(crate) fn foreign_callbacks(&self) -> Vec<String> {
+        self.state().foreign_callbacks.clone()
+    }
+
+    /// sets `twin` and returns the previous handle, that must be destroyed by the caller
+    pub(crate) fn replace(&self, twin: Option<Box<dyn Twin>>) -> Option<RetiredTwin> {
+        let mut state = self.state();
+
+        state.forget_foreign_callbacks();
+
+        let retired = state.take();
+
+        state.twin = twin;
+
+        retired
+    }
+
+    /// creates a handle owning its callback `contexts` by `create` and replaces the current one, which is
+    /// returned and must be destroyed by the caller. the lock is neither held while creating nor while
+    /// destroying handles, since destroying joins the azure-sdk-c worker thread, which might wait for a
+    /// consumer using the handle. nothing is created once the handle is closed.
+    pub(crate) fn renew(
+        &self,
+        create: impl FnOnce() -> Result<(Box<dyn Twin>, Box<dyn Send>)>,
+    ) -> Result<Option<RetiredTwin>> {
+        if self.state().closed {
+            anyhow::bail!("client already dropped");
+        }
+
+        let (twin, contexts) = create()?;
+        let mut state = self.state();
+
+        // closed while the handle was created
+        if state.closed {
+            drop(state);
+            RetiredTwin {
+                twin,
+                contexts: Some(contexts),
+            }
+            .destroy();
+            anyhow::bail!("client already dropped");
+        }
+
+        state.forget_foreign_callbacks();
+
+        let retired = state.take();
+
+        state.twin = Some(twin);
+        state.contexts = Some(contexts);
+
+        Ok(retired)
+    }
+
+    /// destroys the handle and prevents any further renewal
+    pub(crate) fn close(&self) {
+        let retired = {
+            let mut state = self.state();
+
+            state.foreign_callbacks.clear();
+            state.closed = true;
+            state.take()
+        };
+
+        if let Some(retired) = retired {
+            retired.destroy();
+        }
+    }
+}
+
+pub trait Twin: Send {
     #[cfg(any(feature = "device_client", feature = "module_client"))]
-    fn create_from_connection_string(&mut self, connection_string: CString) -> Result<()>;
+    fn create_from_connection_string(
+        &mut self,
+        connection_string: CString,
+        transport: Transport,
+    ) -> Result<()>;
 
     fn destroy(&mut self);
 
+    /// underlying handle of azure-sdk-c
+    fn raw_handle(&self) -> *mut c_void;
+
     fn send_event_to_output_async(
         &self,
         message_handle: IOTHUB_MESSAGE_HANDLE,
@@ -70,6 +344,7 @@ pub trait Twin {
 
     fn set_input_message_callback(
         &self,
+        input_name: CString,
         callback: IOTHUB_CLIENT_MESSAGE_CALLBACK_ASYNC,
         ctx: *mut std::ffi::c_void,
     ) -> Result<()>;
@@ -103,9 +378,9 @@ pub trait Twin {
 
 #[cfg(feature = "edge_client")]
 impl ModuleTwin {
-    pub(crate) fn create_from_edge_environment(&mut self) -> Result<()> {
+    pub(crate) fn create_from_edge_environment(&mut self, transport: Transport) -> Result<()> {
         unsafe {
-            let handle = IoTHubModuleClient_CreateFromEnvironment(Some(MQTT_Protocol));
+            let handle = IoTHubModuleClient_CreateFromEnvironment(transport.protocol());
 
             if handle.is_null() {
                 anyhow::bail!("error while calling IoTHubModuleClient_CreateFromEnvironment()");
@@ -121,11 +396,15 @@ impl ModuleTwin {
 #[cfg(any(feature = "module_client", feature = "edge_client"))]
 impl Twin for ModuleTwin {
     #[cfg(feature = "module_client")]
-    fn create_from_connection_string(&mut self, connection_string: CString) -> Result<()> {
+    fn create_from_connection_string(
+        &mut self,
+        connection_string: CString,
+        transport: Transport,
+    ) -> Result<()> {
         unsafe {
             let handle = IoTHubModuleClient_CreateFromConnectionString(
                 connection_string.into_raw(),
-                Some(MQTT_Protocol),
+                transport.protocol(),
             );
 
             if handle.is_null() {
@@ -146,6 +425,10 @@ impl Twin for ModuleTwin {
         }
     }
 
+    fn raw_handle(&self) -> *mut c_void {
+        self.handle.expect("no handle") as *mut c_void
+    }
+
     fn send_event_to_output_async(
         &self,
         message_handle: IOTHUB_MESSAGE_HANDLE,
@@ -218,11 +501,11 @@ impl Twin for ModuleTwin {
 
     fn set_input_message_callback(
         &self,
+        input_name: CString,
         callback: IOTHUB_CLIENT_MESSAGE_CALLBACK_ASYNC,
         ctx: *mut std::ffi::c_void,
     ) -> Result<()> {
         unsafe {
-            let input_name = CString::new("input")?;
             if IOTHUB_CLIENT_RESULT_TAG_IOTHUB_CLIENT_OK
                 != IoTHubModuleClient_SetInputMessageCallback(
                     self.handle.expect("no handle"),
@@ -333,11 +616,15 @@ impl Twin for ModuleTwin {
 
 #[cfg(feature = "device_client")]
 impl Twin for DeviceTwin {
-    fn create_from_connection_string(&mut self, connection_string: CString) -> Result<()> {
+    fn create_from_connection_string(
+        &mut self,
+        connection_string: CString,
+        transport: Transport,
+    ) -> Result<()> {
         unsafe {
             let handle = IoTHubDeviceClient_CreateFromConnectionString(
                 connection_string.into_raw(),
-                Some(MQTT_Protocol),
+                transport.protocol(),
             );
 
             if handle.is_null() {
@@ -358,6 +645,10 @@ impl Twin for DeviceTwin {
         }
     }
 
+    fn raw_handle(&self) -> *mut c_void {
+        self.handle.expect("no handle") as *mut c_void
+    }
+
     fn send_event_to_output_async(
         &self,
         message_handle: IOTHUB_MESSAGE_HANDLE,
@@ -429,10 +720,12 @@ impl Twin for DeviceTwin {
 
     fn set_input_message_callback(
         &self,
+        _input_name: CString,
         callback: IOTHUB_CLIENT_MESSAGE_CALLBACK_ASYNC,
         ctx: *mut std::ffi::c_void,
     ) -> Result<()> {
         unsafe {
+            // device clients don't have inputs, all messages are received by a single callback
             if IOTHUB_CLIENT_RESULT_TAG_IOTHUB_CLIENT_OK
                 != IoTHubDeviceClient_SetMessageCallback(
                     self.handle.expect("no handle"),
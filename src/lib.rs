@@ -19,6 +19,7 @@
 //!     - use iot-identity-service (only for iot modules and device clients) (must be installed on device) to create the client and build the connection string. ***Note***:
 //!       This feature is currently [not supported for all combinations of identity type and authentication mechanism](https://azure.github.io/iot-identity-service/develop-an-agent.html#connecting-your-agent-to-iot-hub).
 //!     - use iot edge environment (only for iot edge module)
+//!     - connect as downstream device through an iot edge gateway (only for device clients)
 //! - [module twin](https://docs.microsoft.com/en-us/azure/iot-hub/iot-hub-devguide-module-twins) or [device twin](https://docs.microsoft.com/en-us/azure/iot-hub/iot-hub-devguide-device-twins) twin based communication with iothub:
 //!     - read/write tags
 //!     - receive desired properties
@@ -26,6 +27,15 @@
 //! - [direct methods](https://docs.microsoft.com/en-us/azure/iot-hub/iot-hub-devguide-direct-methods)
 //! - [device to cloud (D2C) messages](https://docs.microsoft.com/en-us/azure/iot-hub/iot-hub-devguide-messages-d2c)
 //! - [cloud to device (C2D) messages](https://docs.microsoft.com/en-us/azure/iot-hub/iot-hub-devguide-messages-c2d)
+//!
+//! Transport independent twin logic is provided by [`twin_state`]. It is also available without any client feature
+//! and without azure-sdk-c, e.g. in order to test business logic outside the device.
 
 /// A module providing code to establish a connection to iothub.
+#[cfg(any(
+    feature = "device_client",
+    feature = "module_client",
+    feature = "edge_client"
+))]
 pub mod client;
+pub mod twin_state;
@@ -0,0 +1,312 @@
+//! Transport independent twin logic.
+//!
+//! This module doesn't depend on azure-sdk-c. It compiles without any client feature enabled, e.g. in order
+//! to unit test or simulate business logic built on twin updates outside the device.
+//! ```rust
+//! use azure_iot_sdk::twin_state::*;
+//! use serde_json::json;
+//!
+//! let mut state = TwinState::default();
+//!
+//! state.apply(&TwinUpdate {
+//!     state: TwinUpdateState::Complete,
+//!     value: json!({"desired": {"interval": 10, "$version": 1}, "reported": {}}),
+//! });
+//! state.apply(&TwinUpdate {
+//!     state: TwinUpdateState::Partial,
+//!     value: json!({"interval": 20, "$version": 2}),
+//! });
+//!
+//! assert_eq!(state.desired()["interval"], json!(20));
+//! assert_eq!(state.desired_version(), Some(2));
+//!
+//! // only changed reported properties have to be sent
+//! assert_eq!(state.report(json!({"interval": 20})), Some(json!({"interval": 20})));
+//! assert_eq!(state.report(json!({"interval": 20})), None);
+//! ```
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+
+/// Indicates [type](https://docs.microsoft.com/en-us/azure/iot-hub/iot-hub-devguide-module-twins#back-end-operations) of desired properties update
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TwinUpdateState {
+    /// complete update of desired properties
+    Complete = 0,
+    /// partial update of desired properties
+    Partial = 1,
+}
+
+/// Used to update [desired properties](https://docs.microsoft.com/en-us/azure/iot-hub/iot-hub-devguide-module-twins#back-end-operations) to the client
+#[derive(Debug)]
+pub struct TwinUpdate {
+    /// type of update [`TwinUpdateState`]
+    pub state: TwinUpdateState,
+    /// value
+    pub value: serde_json::Value,
+}
+
+impl TwinUpdate {
+    /// Parses a raw twin payload as received from iothub
+    pub fn from_payload(state: TwinUpdateState, payload: &[u8]) -> Result<Self> {
+        Ok(TwinUpdate {
+            state,
+            value: serde_json::from_slice(payload)?,
+        })
+    }
+
+    /// Returns the complete twin as [`TwinDocument`]. Fails for partial updates, since they only contain
+    /// the changed desired properties.
+    pub fn document(&self) -> Result<TwinDocument> {
+        if self.state != TwinUpdateState::Complete {
+            anyhow::bail!("partial twin update doesn't contain the complete twin");
+        }
+
+        TwinDocument::try_from(self.value.clone())
+    }
+}
+
+/// Typed representation of the complete twin document
+/// ```rust
+/// use azure_iot_sdk::twin_state::*;
+/// use serde_json::json;
+///
+/// let twin = TwinDocument::try_from(json!({
+///     "desired": {"settings": {"interval": 10}, "$version": 3},
+///     "reported": {"$version": 7},
+/// }))
+/// .unwrap();
+///
+/// assert_eq!(twin.get_path("desired.settings.interval"), Some(&json!(10)));
+/// assert_eq!(twin.desired.get_path("settings.interval"), Some(&json!(10)));
+/// assert_eq!(twin.desired.version(), Some(3));
+/// assert_eq!(twin.reported.version(), Some(7));
+/// assert!(twin.tags.is_none());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TwinDocument {
+    /// desired properties
+    pub desired: Section,
+    /// reported properties
+    pub reported: Section,
+    /// version of the twin, if contained in the document
+    pub version: Option<u64>,
+    /// tags, if contained in the document. Tags aren't visible to devices and modules.
+    pub tags: Option<Section>,
+}
+
+impl TwinDocument {
+    /// Returns the value at the dot separated `path`, e.g. "desired.settings.interval"
+    pub fn get_path(&self, path: &str) -> Option<&Value> {
+        let (section, path) = path.split_once('.').unwrap_or((path, ""));
+
+        let section = match section {
+            "desired" => &self.desired,
+            "reported" => &self.reported,
+            "tags" => self.tags.as_ref()?,
+            _ => return None,
+        };
+
+        if path.is_empty() {
+            return Some(section.as_value());
+        }
+
+        section.get_path(path)
+    }
+
+    /// version of the twin, if contained in the document
+    pub fn version(&self) -> Option<u64> {
+        self.version
+    }
+}
+
+impl TryFrom<Value> for TwinDocument {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Value) -> Result<Self> {
+        let Value::Object(mut document) = value else {
+            anyhow::bail!("twin document must be an object");
+        };
+
+        let mut section = |key: &str| -> Result<Option<Section>> {
+            document.remove(key).map(Section::try_from).transpose()
+        };
+
+        Ok(TwinDocument {
+            desired: section("desired")?.unwrap_or_default(),
+            reported: section("reported")?.unwrap_or_default(),
+            tags: section("tags")?,
+            version: document.get("version").and_then(Value::as_u64),
+        })
+    }
+}
+
+/// Section of a [`TwinDocument`], e.g. desired or reported properties
+#[derive(Clone, Debug, PartialEq)]
+pub struct Section(Value);
+
+impl Default for Section {
+    fn default() -> Self {
+        Section(json!({}))
+    }
+}
+
+impl TryFrom<Value> for Section {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Value) -> Result<Self> {
+        if !value.is_object() {
+            anyhow::bail!("twin section must be an object");
+        }
+
+        Ok(Section(value))
+    }
+}
+
+impl Section {
+    /// Returns the value of the top level property `key`
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.0.get(key)
+    }
+
+    /// Returns the value at the dot separated `path`, e.g. "settings.interval"
+    pub fn get_path(&self, path: &str) -> Option<&Value> {
+        path.split('.')
+            .try_fold(&self.0, |value, key| value.get(key))
+    }
+
+    /// "$version" of the section
+    pub fn version(&self) -> Option<u64> {
+        self.0.get("$version").and_then(Value::as_u64)
+    }
+
+    /// the section as raw json
+    pub fn as_value(&self) -> &Value {
+        &self.0
+    }
+
+    /// consumes the section and returns the raw json
+    pub fn into_value(self) -> Value {
+        self.0
+    }
+}
+
+/// Local copy of desired and reported twin properties
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TwinState {
+    desired: Value,
+    reported: Value,
+}
+
+impl TwinState {
+    /// Applies a [`TwinUpdate`]. A complete update replaces all desired properties, a partial update
+    /// is merged into them, whereby `null` values remove properties.
+    pub fn apply(&mut self, update: &TwinUpdate) {
+        match update.state {
+            TwinUpdateState::Complete => {
+                self.desired = update.value.get("desired").cloned().unwrap_or(json!({}));
+            }
+            TwinUpdateState::Partial => merge(&mut self.desired, &update.value),
+        }
+    }
+
+    /// current desired properties
+    pub fn desired(&self) -> &Value {
+        &self.desired
+    }
+
+    /// "$version" of the desired properties
+    pub fn desired_version(&self) -> Option<u64> {
+        self.desired.get("$version").and_then(Value::as_u64)
+    }
+
+    /// reported properties sent so far
+    pub fn reported(&self) -> &Value {
+        &self.reported
+    }
+
+    /// Merges `reported` into the reported properties and returns the patch that has to be sent to iothub,
+    /// i.e. only properties that changed. Returns `None` if nothing changed.
+    pub fn report(&mut self, reported: Value) -> Option<Value> {
+        let mut updated = self.reported.clone();
+
+        merge(&mut updated, &reported);
+
+        let patch = diff(&self.reported, &updated);
+
+        self.reported = updated;
+
+        patch
+    }
+
+    /// Creates the reported properties acknowledging the writable property `name` according to the
+    /// [plug and play conventions](https://learn.microsoft.com/en-us/azure/iot/concepts-convention#writable-properties).
+    /// `code` is a HTTP like status code, e.g. 200 for success.
+    pub fn ack(&self, name: &str, code: u16, description: Option<&str>) -> Value {
+        let mut ack = json!({
+            "value": self.desired.get(name).cloned().unwrap_or(Value::Null),
+            "ac": code,
+            "av": self.desired_version(),
+        });
+
+        if let Some(description) = description {
+            ack["ad"] = json!(description);
+        }
+
+        json!({ name: ack })
+    }
+}
+
+/// Merges `patch` into `target` as [json merge patch](https://www.rfc-editor.org/rfc/rfc7386), that is used by
+/// iothub for partial twin updates: objects are merged recursively and `null` values remove properties.
+pub fn merge(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge(target.entry(key.as_str()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+/// Returns the json merge patch that transforms `from` into `to` or `None` if both are equal.
+pub fn diff(from: &Value, to: &Value) -> Option<Value> {
+    if from == to {
+        return None;
+    }
+
+    let (Value::Object(from), Value::Object(to)) = (from, to) else {
+        return Some(to.clone());
+    };
+
+    let mut patch = Map::new();
+
+    for (key, value) in to {
+        match from.get(key) {
+            Some(old) => {
+                if let Some(value) = diff(old, value) {
+                    patch.insert(key.clone(), value);
+                }
+            }
+            None => {
+                patch.insert(key.clone(), value.clone());
+            }
+        }
+    }
+
+    for key in from.keys().filter(|key| !to.contains_key(*key)) {
+        patch.insert(key.clone(), Value::Null);
+    }
+
+    Some(Value::Object(patch))
+}
@@ -0,0 +1,214 @@
+//! End-to-end tests against a live iothub, enabled by the `e2e-tests` feature together with one client type, e.g.
+//! `E2E_CONNECTION_STRING="HostName=..." cargo test --features device_client,e2e-tests --test e2e`.
+//!
+//! Configuration by environment variables:
+//! - `E2E_CONNECTION_STRING`: connection string of the device or module identity, not used by "edge_client"
+//!   which gets its identity from the iotedge runtime
+//! - `E2E_TIMEOUT_IN_SECS`: time to wait for each expected event, default 60s
+//! - `E2E_DIRECT_METHOD`: name of a direct method the test waits for, e.g. invoked by
+//!   `az iot hub invoke-device-method -n <hub> -d <device> --method-name <name> --method-payload '{"ping": 1}'`.
+//!   The method responds with its payload. The test is skipped if not set.
+//! - `E2E_C2D_BODY`: body of a C2D message the test waits for, e.g. sent by
+//!   `az iot device c2d-message send -n <hub> -d <device> --data <body>`. The test is skipped if not set.
+//!
+//! Since iothub only accepts one connection per identity, the tests are run one after the other.
+#![cfg(all(
+    feature = "e2e-tests",
+    any(
+        feature = "device_client",
+        feature = "module_client",
+        feature = "edge_client"
+    )
+))]
+
+use azure_iot_sdk::client::*;
+use serde_json::json;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::{
+    sync::{mpsc, Mutex, MutexGuard},
+    time::timeout,
+};
+
+static SERIAL: Mutex<()> = Mutex::const_new(());
+
+fn timeout_duration() -> Duration {
+    Duration::from_secs(
+        std::env::var("E2E_TIMEOUT_IN_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(60),
+    )
+}
+
+fn build(builder: IotHubClientBuilder) -> IotHubClient {
+    #[cfg(feature = "edge_client")]
+    let client = builder.build_edge_client();
+    #[cfg(feature = "device_client")]
+    let client = builder.build_device_client(&connection_string());
+    #[cfg(feature = "module_client")]
+    let client = builder.build_module_client(&connection_string());
+
+    client.expect("cannot build client")
+}
+
+#[cfg(any(feature = "device_client", feature = "module_client"))]
+fn connection_string() -> String {
+    std::env::var("E2E_CONNECTION_STRING").expect("E2E_CONNECTION_STRING not set")
+}
+
+/// builds an authenticated client and returns it with a guard serializing the tests
+async fn connect(builder: IotHubClientBuilder) -> (IotHubClient, MutexGuard<'static, ()>) {
+    let serial = SERIAL.lock().await;
+    let (tx, mut rx) = mpsc::channel(100);
+    let client = build(builder.observe_connection_state(tx));
+
+    timeout(timeout_duration(), async {
+        while let Some(status) = rx.recv().await {
+            if status == AuthenticationStatus::Authenticated {
+                return;
+            }
+        }
+    })
+    .await
+    .expect("client not authenticated in time");
+
+    (client, serial)
+}
+
+fn run_id() -> String {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
+        .to_string()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn send_and_confirm() {
+    let (client, _serial) = connect(IotHubClient::builder()).await;
+
+    let msg = IotMessage::builder()
+        .set_body(json!({"e2e": run_id()}).to_string().into_bytes())
+        .set_content_type("application/json")
+        .set_content_encoding("UTF-8")
+        .set_property("e2e property", "e2e value")
+        .build()
+        .unwrap();
+
+    timeout(timeout_duration(), client.send_d2c_message_confirmed(msg))
+        .await
+        .expect("message not confirmed in time")
+        .expect("message not confirmed");
+
+    let batch = (0..5)
+        .map(|i| {
+            IotMessage::builder()
+                .set_body(format!("e2e batch {i}").into_bytes())
+                .build()
+                .unwrap()
+        })
+        .collect();
+
+    timeout(timeout_duration(), client.send_d2c_batch(batch))
+        .await
+        .expect("batch not confirmed in time")
+        .expect("batch not confirmed");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn twin_round_trip() {
+    let (tx, mut rx) = mpsc::channel(100);
+    let (mut client, _serial) =
+        connect(IotHubClient::builder().observe_desired_properties(tx)).await;
+    let run = run_id();
+
+    client
+        .twin_report(json!({"e2e": {"run": run}}))
+        .expect("cannot report properties");
+
+    // the reported properties are visible in the complete twin once they are applied by iothub
+    timeout(timeout_duration(), async {
+        loop {
+            client.twin_async().expect("cannot request twin");
+
+            while let Some(update) = rx.recv().await {
+                if update.state == TwinUpdateState::Complete {
+                    if update.value["reported"]["e2e"]["run"] == run.as_str() {
+                        return;
+                    }
+                    break;
+                }
+            }
+
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    })
+    .await
+    .expect("reported properties not in twin in time");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn direct_method() {
+    let Ok(name) = std::env::var("E2E_DIRECT_METHOD") else {
+        eprintln!("E2E_DIRECT_METHOD not set, skip direct method test");
+        return;
+    };
+    let (tx, mut rx) = mpsc::channel(100);
+    let (_client, _serial) = connect(IotHubClient::builder().observe_direct_methods(tx)).await;
+
+    let method = timeout(timeout_duration(), async {
+        loop {
+            let method = rx.recv().await.expect("direct method channel closed");
+
+            if method.name == name {
+                return method;
+            }
+
+            method
+                .responder
+                .send(Err(anyhow::anyhow!("unexpected method")))
+                .unwrap();
+        }
+    })
+    .await
+    .expect("direct method not invoked in time");
+
+    method
+        .responder
+        .send(Ok(Some(method.payload.clone())))
+        .unwrap();
+
+    // give the client time to send the response before it is dropped
+    tokio::time::sleep(Duration::from_secs(2)).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn c2d_message() {
+    let Ok(body) = std::env::var("E2E_C2D_BODY") else {
+        eprintln!("E2E_C2D_BODY not set, skip C2D test");
+        return;
+    };
+    let (tx, mut rx) = mpsc::channel(100);
+    let (_client, _serial) = connect(
+        IotHubClient::builder().observe_incoming_messages(IncomingMessageObserver::new(tx, vec![])),
+    )
+    .await;
+
+    timeout(timeout_duration(), async {
+        loop {
+            let msg = rx.recv().await.expect("C2D channel closed");
+            let expected = msg.inner.body == body.as_bytes();
+
+            msg.responder.send(Ok(DispositionResult::Accepted)).unwrap();
+
+            if expected {
+                return;
+            }
+        }
+    })
+    .await
+    .expect("C2D message not received in time");
+
+    // give the client time to send the disposition before it is dropped
+    tokio::time::sleep(Duration::from_secs(2)).await;
+}
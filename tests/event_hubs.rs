@@ -0,0 +1,102 @@
+//! Tests of the mapping to consumers of the Event Hubs-compatible endpoint, enabled by the `test_hooks` feature
+//! together with a client, e.g. `cargo test --features device_client,test_hooks --test event_hubs`.
+//!
+//! Messages are passed through an azure-sdk-c message handle by [`IotMessage::simulate_wire_round_trip`], thus no
+//! iothub is required.
+#![cfg(all(
+    feature = "test_hooks",
+    any(
+        feature = "device_client",
+        feature = "module_client",
+        feature = "edge_client"
+    )
+))]
+
+use azure_iot_sdk::client::*;
+use serde_json::json;
+use std::collections::HashMap;
+
+#[test]
+fn application_properties_round_trip() {
+    let properties = [
+        ("room name", "kitchen & hall"),
+        ("unit", "°C"),
+        ("a+b", "50% off"),
+        ("query", "/path?key=value#fragment"),
+        ("%20", "%2B"),
+    ];
+    let msg = properties
+        .iter()
+        .fold(
+            IotMessage::builder().set_body(vec![]),
+            |builder, (key, value)| builder.set_property(*key, *value),
+        )
+        .build()
+        .unwrap();
+
+    let received = msg.simulate_wire_round_trip().unwrap();
+
+    assert_eq!(
+        event_hubs::application_properties(&received).unwrap(),
+        properties
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>()
+    );
+}
+
+#[test]
+fn system_properties_round_trip() {
+    let msg = event_hubs::json_message(&json!({"temperature": 21.5}))
+        .unwrap()
+        .set_id("my msg id")
+        .set_correlation_id("my/correlation+id")
+        .build()
+        .unwrap();
+
+    let received = msg.simulate_wire_round_trip().unwrap();
+
+    assert_eq!(
+        event_hubs::system_properties(&received).unwrap(),
+        HashMap::from([
+            ("message-id", "my msg id".to_string()),
+            ("correlation-id", "my/correlation+id".to_string()),
+            ("content-type", event_hubs::CONTENT_TYPE_JSON.to_string()),
+            (
+                "content-encoding",
+                event_hubs::CONTENT_ENCODING_UTF8.to_string()
+            ),
+        ])
+    );
+}
+
+#[test]
+fn unset_system_properties_are_missing() {
+    let msg = IotMessage::builder()
+        .set_body(b"plain".to_vec())
+        .set_content_type("text/plain")
+        .build()
+        .unwrap();
+
+    let received = msg.simulate_wire_round_trip().unwrap();
+
+    assert_eq!(
+        event_hubs::system_properties(&received).unwrap(),
+        HashMap::from([("content-type", "text/plain".to_string())])
+    );
+    assert!(event_hubs::application_properties(&received)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn json_body_round_trip() {
+    let body = json!({"temperature": 21.5, "room": "kitchen & hall", "unit": "°C"});
+    let msg = event_hubs::json_message(&body).unwrap().build().unwrap();
+    let sent = msg.body.clone();
+
+    let received = msg.simulate_wire_round_trip().unwrap();
+
+    assert_eq!(received.body, sent);
+    assert_eq!(received.body_as::<serde_json::Value>().unwrap(), body);
+}
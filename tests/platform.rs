@@ -0,0 +1,180 @@
+//! Tests of the reference-counted azure-sdk-c platform initialization, enabled by the `test_hooks` feature together
+//! with a device or module client, e.g. `cargo test --features device_client,test_hooks --test platform`.
+//!
+//! Clients are built with a syntactically valid connection string of a non-existing hub, thus handles are created
+//! but never authenticated.
+#![cfg(all(
+    feature = "test_hooks",
+    any(feature = "device_client", feature = "module_client")
+))]
+
+use azure_iot_sdk::client::*;
+use std::sync::{Arc, Barrier};
+use tokio::sync::{Mutex, MutexGuard};
+
+// the platform initialization is global, thus the tests are run one after the other
+static SERIAL: Mutex<()> = Mutex::const_new(());
+
+#[cfg(feature = "device_client")]
+static CONNECTION_STRING: &str =
+    "HostName=platform-test.azure-devices.net;DeviceId=platform-test;SharedAccessKey=cGxhdGZvcm0tdGVzdA==";
+#[cfg(feature = "module_client")]
+static CONNECTION_STRING: &str = "HostName=platform-test.azure-devices.net;DeviceId=platform-test;ModuleId=platform-test;SharedAccessKey=cGxhdGZvcm0tdGVzdA==";
+
+fn build() -> IotHubClient {
+    #[cfg(feature = "device_client")]
+    let client = IotHubClient::builder().build_device_client(CONNECTION_STRING);
+    #[cfg(feature = "module_client")]
+    let client = IotHubClient::builder().build_module_client(CONNECTION_STRING);
+
+    client.expect("cannot build client")
+}
+
+async fn serial() -> MutexGuard<'static, ()> {
+    let serial = SERIAL.lock().await;
+
+    assert_eq!(IotHubClient::platform_references(), 0);
+
+    serial
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn create_drop_cycles() {
+    let _serial = serial().await;
+
+    for _ in 0..3 {
+        let initializations = IotHubClient::platform_initializations();
+        let client = build();
+
+        assert_eq!(IotHubClient::platform_references(), 1);
+        assert_eq!(
+            IotHubClient::platform_initializations(),
+            initializations + 1
+        );
+
+        drop(client);
+
+        assert_eq!(IotHubClient::platform_references(), 0);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn init_deinit_cycles() {
+    let _serial = serial().await;
+    let cycles = 5;
+    let initializations = IotHubClient::platform_initializations();
+
+    for cycle in 1..=cycles {
+        #[cfg(feature = "device_client")]
+        let client = IotHubClient::builder().build_device_client_lazy(CONNECTION_STRING);
+        #[cfg(feature = "module_client")]
+        let client = IotHubClient::builder().build_module_client_lazy(CONNECTION_STRING);
+        let mut client = client.expect("cannot build client");
+
+        // the platform is initialized not until the handle is created
+        assert_eq!(IotHubClient::platform_references(), 0);
+
+        client.connect().await.expect("cannot connect client");
+
+        assert_eq!(IotHubClient::platform_references(), 1);
+        assert_eq!(
+            IotHubClient::platform_initializations(),
+            initializations + cycle
+        );
+
+        drop(client);
+
+        assert_eq!(IotHubClient::platform_references(), 0);
+    }
+
+    assert_eq!(IotHubClient::platform_references(), 0);
+    assert_eq!(
+        IotHubClient::platform_initializations(),
+        initializations + cycles
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sequential_clients_share_initialization() {
+    let _serial = serial().await;
+    let initializations = IotHubClient::platform_initializations();
+    let first = build();
+    let second = build();
+
+    assert_eq!(IotHubClient::platform_references(), 2);
+    assert_eq!(
+        IotHubClient::platform_initializations(),
+        initializations + 1
+    );
+
+    drop(first);
+
+    assert_eq!(IotHubClient::platform_references(), 1);
+
+    let third = build();
+
+    assert_eq!(IotHubClient::platform_references(), 2);
+    assert_eq!(
+        IotHubClient::platform_initializations(),
+        initializations + 1
+    );
+
+    drop(second);
+    drop(third);
+
+    assert_eq!(IotHubClient::platform_references(), 0);
+
+    let fourth = build();
+
+    assert_eq!(
+        IotHubClient::platform_initializations(),
+        initializations + 2
+    );
+
+    drop(fourth);
+
+    assert_eq!(IotHubClient::platform_references(), 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn concurrent_clients() {
+    let _serial = serial().await;
+    let clients = 4;
+    let initializations = IotHubClient::platform_initializations();
+
+    for _ in 0..3 {
+        let built = Arc::new(Barrier::new(clients));
+        let checked = Arc::new(Barrier::new(clients));
+        let tasks: Vec<_> = (0..clients)
+            .map(|_| {
+                let built = built.clone();
+                let checked = checked.clone();
+
+                // clients aren't Send, thus each one is built and dropped on a blocking thread of its own
+                tokio::task::spawn_blocking(move || {
+                    let client = build();
+
+                    built.wait();
+                    let references = IotHubClient::platform_references();
+                    checked.wait();
+
+                    drop(client);
+
+                    references
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            assert_eq!(task.await.expect("client task failed"), clients);
+        }
+
+        assert_eq!(IotHubClient::platform_references(), 0);
+    }
+
+    // the platform is initialized once per cycle
+    assert_eq!(
+        IotHubClient::platform_initializations(),
+        initializations + 3
+    );
+}